@@ -0,0 +1,78 @@
+//! Compares the throughput of streaming a file using different read buffer sizes,
+//! as configured by `server.download_buffer_bytes`.
+//!
+//! ```shell
+//! cargo bench --bench read_ahead
+//! ```
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::StreamExt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use tokio::runtime::Runtime;
+use tokio_util::io::ReaderStream;
+
+/// The size of the streamed file.
+const FILE_SIZE: usize = 64 * 1024 * 1024;
+
+/// The read buffer sizes to compare; 4 KiB is the default of [`ReaderStream::new`].
+const BUFFER_SIZES: &[usize] = &[4 * 1024, 64 * 1024, 256 * 1024, 1024 * 1024];
+
+/// Removes the file when the benchmark completes.
+struct TestFile(PathBuf);
+
+impl TestFile {
+    fn create() -> std::io::Result<Self> {
+        let path = std::env::temp_dir().join(format!("yy-read-ahead-{}", std::process::id()));
+        let mut file = std::fs::File::create(&path)?;
+        let chunk: Vec<u8> = (0..1024 * 1024).map(|i| i as u8).collect();
+        for _ in 0..FILE_SIZE / chunk.len() {
+            file.write_all(&chunk)?;
+        }
+        file.sync_all()?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for TestFile {
+    fn drop(&mut self) {
+        std::fs::remove_file(&self.0).ok();
+    }
+}
+
+async fn stream_file(path: &Path, capacity: usize) -> usize {
+    let file = tokio::fs::File::open(path)
+        .await
+        .expect("failed to open the file");
+    let mut stream = ReaderStream::with_capacity(file, capacity);
+    let mut total = 0;
+    while let Some(chunk) = stream.next().await {
+        total += chunk.expect("failed to read the file").len();
+    }
+    total
+}
+
+fn read_ahead(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("failed to create the runtime");
+    let file = TestFile::create().expect("failed to create the file");
+
+    let mut group = c.benchmark_group("read_ahead");
+    group.throughput(Throughput::Bytes(FILE_SIZE as u64));
+    group.sample_size(20);
+    for &capacity in BUFFER_SIZES {
+        group.bench_with_input(
+            BenchmarkId::from_parameter(capacity),
+            &capacity,
+            |b, &capacity| {
+                b.to_async(&runtime).iter(|| async {
+                    let total = stream_file(&file.0, capacity).await;
+                    assert_eq!(total, FILE_SIZE);
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, read_ahead);
+criterion_main!(benches);
@@ -0,0 +1,142 @@
+//! Contains the limits for accepting connections.
+
+use app_config::server::ServerConfig;
+use metrics::connections::{ConnectionMetrics, ThrottleReason};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use tokio::time::{Instant, Sleep};
+use tokio_util::sync::PollSemaphore;
+
+/// Limits the rate and the number of concurrent connections accepted by all listeners.
+///
+/// While a limit is exceeded, the listeners pause accepting, leaving new connections
+/// in the backlog of the listening sockets instead of spawning tasks for them.
+#[derive(Clone)]
+pub struct AcceptLimits {
+    /// The slots for open connections; `None` if the number of connections is unlimited.
+    connections: Option<Arc<Semaphore>>,
+    /// The rate limit; `None` if connections are accepted as fast as they arrive.
+    rate: Option<Arc<Mutex<TokenBucket>>>,
+}
+
+/// The accept limits of a single listener; see [`AcceptLimits::acceptor`].
+pub struct Acceptor {
+    connections: Option<PollSemaphore>,
+    rate: Option<Arc<Mutex<TokenBucket>>>,
+    /// The connection slot reserved for the next connection.
+    permit: Option<OwnedSemaphorePermit>,
+    /// Whether a token of the rate limit was reserved for the next connection.
+    token: bool,
+    /// The time until the rate limit allows the next connection.
+    delay: Option<Pin<Box<Sleep>>>,
+    /// Whether accepting is paused until a connection slot becomes available.
+    waiting_for_slot: bool,
+}
+
+/// A token bucket allowing short bursts of up to one second worth of connections.
+struct TokenBucket {
+    /// The number of connections per second.
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl AcceptLimits {
+    /// Creates the limits configured by `server.max_connections` and `server.max_accept_rate`.
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            connections: config
+                .max_connections
+                .map(|max| Arc::new(Semaphore::new(max as usize))),
+            rate: config
+                .max_accept_rate
+                .map(|rate| Arc::new(Mutex::new(TokenBucket::new(rate)))),
+        }
+    }
+
+    /// Creates the state for a single listener sharing these limits.
+    pub fn acceptor(&self) -> Acceptor {
+        Acceptor {
+            connections: self.connections.clone().map(PollSemaphore::new),
+            rate: self.rate.clone(),
+            permit: None,
+            token: false,
+            delay: None,
+            waiting_for_slot: false,
+        }
+    }
+}
+
+impl Acceptor {
+    /// Waits until the limits allow accepting another connection and reserves it.
+    pub fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        if let Some(rate) = &self.rate {
+            while !self.token {
+                if let Some(delay) = &mut self.delay {
+                    ready!(delay.as_mut().poll(cx));
+                    self.delay = None;
+                }
+
+                let now = Instant::now();
+                match rate.lock().unwrap_or_else(|e| e.into_inner()).take(now) {
+                    Ok(()) => self.token = true,
+                    Err(wait) => {
+                        ConnectionMetrics::track_throttled(ThrottleReason::Rate);
+                        self.delay = Some(Box::pin(tokio::time::sleep_until(now + wait)));
+                    }
+                }
+            }
+        }
+
+        if let Some(connections) = &mut self.connections {
+            if self.permit.is_none() {
+                if connections.available_permits() == 0 && !self.waiting_for_slot {
+                    ConnectionMetrics::track_throttled(ThrottleReason::MaxConnections);
+                    self.waiting_for_slot = true;
+                }
+
+                // The semaphore is never closed.
+                self.permit = ready!(connections.poll_acquire(cx));
+                self.waiting_for_slot = false;
+            }
+        }
+
+        Poll::Ready(())
+    }
+
+    /// Consumes the reservation for an accepted connection. The returned permit,
+    /// if any, must be held for as long as the connection is open.
+    pub fn accepted(&mut self) -> Option<OwnedSemaphorePermit> {
+        self.token = false;
+        self.permit.take()
+    }
+}
+
+impl TokenBucket {
+    fn new(rate: u32) -> Self {
+        let rate = f64::from(rate.max(1));
+        Self {
+            rate,
+            tokens: rate,
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Takes a token, or returns the time until the next token is available.
+    fn take(&mut self, now: Instant) -> Result<(), Duration> {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.rate);
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
@@ -1,14 +1,27 @@
-use app_config::AppConfig;
+use crate::circuit_breaker::CircuitBreakers;
+use app_config::circuit_breaker::CircuitBreakerConfig;
+use app_config::mirror::MirrorBackendConfig;
+use app_config::routing::{RoutedFile, RoutingRule};
+use app_config::{AppConfig, RejectionPolicy};
+use backbone::CleanupGuard;
 use backend_traits::{
-    Backend, BackendCommand, BackendCommandSender, BackendRegistration, RegisterBackendError,
-    TryCreateFromConfig,
+    Backend, BackendCommand, BackendCommandSender, BackendRegistration, DeleteFileError,
+    DistributionError, DistributionState, DistributionTracker, MirrorBackend, MirrorRole,
+    QuorumState, RegisterBackendError, TryCreateFromConfig,
 };
-use file_distribution::FileProvider;
+use file_distribution::{FileProvider, FileReaderTrait, GetFile, WriteSummary};
+use futures::future::join_all;
+use metrics::distribution::DistributionMetrics;
 use rendezvous::RendezvousGuard;
+use shortguid::ShortGuid;
 use std::cell::Cell;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::task::{JoinError, JoinHandle};
+use tokio::sync::Semaphore;
+use tokio::task::{AbortHandle, JoinError, JoinHandle};
 use tracing::{debug, error, info, warn};
 
 const EVENT_BUFFER_SIZE: usize = 64;
@@ -16,82 +29,300 @@ const EVENT_BUFFER_SIZE: usize = 64;
 pub struct BackendRegistry {
     handle: JoinHandle<()>,
     sender: Cell<Option<Sender<BackendCommand>>>,
+    tracker: DistributionTracker,
+    backends: Arc<[Backend]>,
 }
 
 impl BackendRegistry {
     pub fn builder(
         cleanup_rendezvous: RendezvousGuard,
         file_accessor: FileProvider,
+        distribution_timeout: Duration,
     ) -> BackendRegistryBuilder {
-        BackendRegistryBuilder::new(cleanup_rendezvous, file_accessor)
+        BackendRegistryBuilder::new(cleanup_rendezvous, file_accessor, distribution_timeout)
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn new(
         cleanup_rendezvous: RendezvousGuard,
         backends: Vec<Backend>,
         file_accessor: FileProvider,
+        distribution_timeout: Duration,
+        write_quorum: Option<usize>,
+        routing: Vec<RoutingRule>,
+        circuit_breakers: Option<CircuitBreakers>,
+        distribution_limit: Option<Semaphore>,
+        rejection_policy: RejectionPolicy,
     ) -> Self {
         let (sender, receiver) = mpsc::channel(EVENT_BUFFER_SIZE);
+        let tracker = DistributionTracker::new(backends.iter().map(|b| b.tag().to_string()))
+            .with_write_quorum(write_quorum);
+        let backends: Arc<[Backend]> = backends.into();
+        let distribution = Arc::new(Distribution {
+            backends: backends.clone(),
+            file_accessor,
+            tracker: tracker.clone(),
+            timeout: distribution_timeout,
+            routing: routing.into(),
+            circuit_breakers: circuit_breakers.map(Mutex::new),
+            limit: distribution_limit,
+            rejection_policy,
+        });
         let handle = tokio::spawn(Self::handle_events(
-            backends,
+            distribution,
             receiver,
-            cleanup_rendezvous,
-            file_accessor,
+            CleanupGuard::new(cleanup_rendezvous),
         ));
         Self {
             handle,
             sender: Cell::new(Some(sender)),
+            tracker,
+            backends,
         }
     }
 
     pub(crate) fn get_sender(&self) -> Option<BackendCommandSender> {
-        self.sender.take().map(BackendCommandSender::from)
+        self.sender
+            .take()
+            .map(|sender| BackendCommandSender::new(sender, self.tracker.clone()))
+    }
+
+    /// Gets the tracker recording the per-backend distribution outcomes of files.
+    pub(crate) fn distribution_tracker(&self) -> DistributionTracker {
+        self.tracker.clone()
+    }
+
+    /// Gets the registered backends, e.g. for reading files back from them.
+    pub(crate) fn backends(&self) -> Arc<[Backend]> {
+        self.backends.clone()
     }
 
+    /// Gets a handle for checking whether the event loop is still running.
+    /// While it is, it holds its rendezvous guard.
+    pub(crate) fn event_loop(&self) -> AbortHandle {
+        self.handle.abort_handle()
+    }
+
+    #[allow(dead_code)]
     pub async fn join(self) -> Result<(), JoinError> {
         self.handle.await
     }
 
     async fn handle_events(
-        backends: Vec<Backend>,
+        distribution: Arc<Distribution>,
         mut receiver: Receiver<BackendCommand>,
-        cleanup_rendezvous: RendezvousGuard,
-        file_accessor: FileProvider,
+        cleanup_rendezvous: CleanupGuard,
     ) {
+        // The running distributions of each file, so that deleting it waits for them.
+        let mut in_flight: HashMap<ShortGuid, Vec<JoinHandle<()>>> = HashMap::new();
         while let Some(event) = receiver.recv().await {
+            in_flight.retain(|_, tasks| {
+                tasks.retain(|task| !task.is_finished());
+                !tasks.is_empty()
+            });
+
             match event {
                 BackendCommand::DistributeFile(id, summary) => {
-                    // TODO: Handle file distribution
                     debug!(file_id = %id, "Handling distribution of file {id}", id = id);
+                    distribution.tracker.begin(id, summary.expires);
 
-                    // TODO: Spawn distribution tasks in background
-
-                    // TODO: Initiate tasks in priority order?
-                    for backend in &backends {
-                        match backend
-                            .distribute_file(id, summary.clone(), file_accessor.clone())
-                            .await
-                        {
-                            Ok(_) => {}
-                            Err(e) => {
-                                warn!(file_id = %id, "Failed to distribute file using backend {tag}: {error}", tag = backend.tag(), error = e);
-                            }
-                        }
-                    }
+                    // Files are distributed concurrently; each task holds a fork of the
+                    // rendezvous guard so that shutdown waits for running distributions.
+                    let distribution = distribution.clone();
+                    let guard = cleanup_rendezvous.fork();
+                    let task = tokio::spawn(async move {
+                        distribution.distribute(id, summary).await;
+                        guard.completed();
+                    });
+                    in_flight.entry(id).or_default().push(task);
+                }
+                BackendCommand::DeleteFile(id) => {
+                    debug!(file_id = %id, "Handling deletion of file {id}");
+                    let running = in_flight.remove(&id).unwrap_or_default();
+                    let distribution = distribution.clone();
+                    let guard = cleanup_rendezvous.fork();
+                    tokio::spawn(async move {
+                        // Otherwise, a running distribution could store the file again.
+                        join_all(running).await;
+                        distribution.delete(id).await;
+                        guard.completed();
+                    });
                 }
             }
         }
 
-        // TODO: Wait until all currently running tasks have finished.
         debug!("Closing backend event loop");
         cleanup_rendezvous.completed();
     }
 }
 
+/// The state shared by the distributions of all files.
+struct Distribution {
+    backends: Arc<[Backend]>,
+    file_accessor: FileProvider,
+    tracker: DistributionTracker,
+    /// The time after which a distribution to a single backend is abandoned.
+    timeout: Duration,
+    routing: Arc<[RoutingRule]>,
+    circuit_breakers: Option<Mutex<CircuitBreakers>>,
+    /// The limit of distributions in progress across all files and backends.
+    limit: Option<Semaphore>,
+    rejection_policy: RejectionPolicy,
+}
+
+impl Distribution {
+    /// Deletes a file from all backends concurrently.
+    async fn delete(&self, id: ShortGuid) {
+        join_all(self.backends.iter().map(|backend| async move {
+            let result = tokio::time::timeout(self.timeout, backend.delete_file(id))
+                .await
+                .unwrap_or(Err(DeleteFileError::Timeout(self.timeout)));
+
+            match result {
+                Ok(()) => {
+                    debug!(file_id = %id, "Deleted file {id} from backend {tag}", tag = backend.tag());
+                }
+                Err(DeleteFileError::NotSupported(_) | DeleteFileError::UnknownFile(_)) => {}
+                Err(e) => {
+                    warn!(file_id = %id, "Failed to delete file {id} from backend {tag}: {e}", tag = backend.tag());
+                }
+            }
+        }))
+        .await;
+    }
+
+    /// Stores a file on all eligible backends concurrently. The outcome of each backend is
+    /// tracked as soon as it is known, so that waiting uploads don't wait for the slowest one.
+    async fn distribute(&self, id: ShortGuid, summary: Arc<WriteSummary>) {
+        let content_type = if self.routing.iter().any(RoutingRule::needs_content_type) {
+            Self::content_type(&self.file_accessor, id).await
+        } else {
+            None
+        };
+        let file = RoutedFile {
+            content_type: content_type.as_deref(),
+            size: summary.file_size_bytes as u64,
+            file_name: summary.file_name.as_deref(),
+        };
+
+        // TODO: Initiate tasks in priority order?
+        let mut eligible = Vec::with_capacity(self.backends.len());
+        for backend in self.backends.iter() {
+            if !Self::is_eligible(&self.routing, backend.tag(), &file) {
+                debug!(file_id = %id, "Skipping backend {tag} for file {id} since none of its routing rules match", tag = backend.tag());
+                self.tracker
+                    .update(id, backend.tag(), DistributionState::NotEligible);
+                continue;
+            }
+
+            if !self.circuit_breakers.as_ref().map_or(true, |breakers| {
+                breakers
+                    .lock()
+                    .expect("failed to lock circuit breakers")
+                    .allow(backend.tag())
+            }) {
+                debug!(file_id = %id, "Skipping backend {tag} for file {id} since its circuit is open", tag = backend.tag());
+                DistributionMetrics::track_circuit_open_skip(backend.tag());
+                self.tracker
+                    .update(id, backend.tag(), DistributionState::CircuitOpen);
+                continue;
+            }
+
+            eligible.push(backend);
+        }
+
+        // The backends store the file concurrently, bounded by the global limit.
+        join_all(
+            eligible
+                .into_iter()
+                .map(|backend| self.distribute_to(backend, id, summary.clone())),
+        )
+        .await;
+
+        if self.rejection_policy != RejectionPolicy::Ignore && self.tracker.is_rejected(&id) {
+            error!(file_id = %id, "File {id} was rejected and not stored by any backend; it is only held locally until it expires");
+            DistributionMetrics::track_unstored_rejection();
+        }
+
+        if self.tracker.quorum_state(&id) == Some(QuorumState::Failed) {
+            warn!(file_id = %id, "File {id} was not stored on enough backends to meet the write quorum");
+            DistributionMetrics::track_quorum_failure();
+        }
+    }
+
+    /// Stores a file on a single backend and records the outcome.
+    async fn distribute_to(&self, backend: &Backend, id: ShortGuid, summary: Arc<WriteSummary>) {
+        let _permit = match &self.limit {
+            Some(limit) => limit.acquire().await.ok(),
+            None => None,
+        };
+
+        // A hanging backend must not stall the distribution indefinitely.
+        let result = tokio::time::timeout(
+            self.timeout,
+            backend.distribute_file(id, summary, self.file_accessor.clone()),
+        )
+        .await
+        .unwrap_or_else(|_| {
+            DistributionMetrics::track_timeout(backend.tag());
+            Err(DistributionError::Timeout(self.timeout))
+        });
+
+        // The error is not `Send` and thus can't be held across an await point.
+        let state = match result {
+            Ok(_) => DistributionState::Stored,
+            Err(e) => {
+                warn!(file_id = %id, "Failed to distribute file using backend {tag}: {error}", tag = backend.tag(), error = e);
+                match e {
+                    DistributionError::BackendRejected(_) => DistributionState::Rejected,
+                    _ => DistributionState::Failed,
+                }
+            }
+        };
+
+        // Rejections are deliberate and don't indicate a failing backend.
+        if let Some(breakers) = &self.circuit_breakers {
+            let mut breakers = breakers.lock().expect("failed to lock circuit breakers");
+            if state == DistributionState::Failed {
+                breakers.record_failure(backend.tag());
+            } else {
+                breakers.record_success(backend.tag());
+            }
+        }
+        self.tracker.update(id, backend.tag(), state);
+    }
+
+    /// Indicates whether a file is to be distributed to the backend with the specified tag,
+    /// i.e. whether the backend has no routing rules or any of them matches.
+    fn is_eligible(routing: &[RoutingRule], tag: &str, file: &RoutedFile) -> bool {
+        let mut rules = routing.iter().filter(|rule| rule.backend == tag).peekable();
+        rules.peek().is_none() || rules.any(|rule| rule.matches(file))
+    }
+
+    /// Gets the content type of a file for evaluating the routing rules.
+    async fn content_type(file_accessor: &FileProvider, id: ShortGuid) -> Option<String> {
+        match file_accessor.get_file(id).await {
+            Ok(reader) => reader
+                .content_type()
+                .map(|content_type| content_type.into_owned()),
+            Err(e) => {
+                warn!(file_id = %id, "Failed to determine the content type of file {id} for routing: {e}");
+                None
+            }
+        }
+    }
+}
+
 pub struct BackendRegistryBuilder {
     backends: Vec<Backend>,
     cleanup_rendezvous: RendezvousGuard,
     file_accessor: FileProvider,
+    distribution_timeout: Duration,
+    write_quorum: Option<usize>,
+    routing: Vec<RoutingRule>,
+    circuit_breakers: Option<CircuitBreakers>,
+    max_concurrent_distributions: Option<usize>,
+    rejection_policy: RejectionPolicy,
 }
 
 impl BackendRegistration for BackendRegistryBuilder {
@@ -105,16 +336,97 @@ impl BackendRegistration for BackendRegistryBuilder {
 }
 
 impl BackendRegistryBuilder {
-    fn new(cleanup_rendezvous: RendezvousGuard, file_accessor: FileProvider) -> Self {
+    fn new(
+        cleanup_rendezvous: RendezvousGuard,
+        file_accessor: FileProvider,
+        distribution_timeout: Duration,
+    ) -> Self {
         Self {
             backends: Vec::default(),
             cleanup_rendezvous,
             file_accessor,
+            distribution_timeout,
+            write_quorum: None,
+            routing: Vec::default(),
+            circuit_breakers: None,
+            max_concurrent_distributions: None,
+            rejection_policy: RejectionPolicy::default(),
+        }
+    }
+
+    /// Requires files to be stored on at least `quorum` backends.
+    pub fn with_write_quorum(mut self, quorum: Option<usize>) -> Self {
+        if let Some(quorum) = quorum {
+            if quorum > self.backends.len() {
+                warn!(
+                    "The write quorum of {quorum} backends can never be met with {count} backends registered",
+                    count = self.backends.len()
+                );
+            }
+        }
+
+        self.write_quorum = quorum;
+        self
+    }
+
+    /// Restricts which files are distributed to which backends.
+    pub fn with_routing(mut self, rules: &[RoutingRule]) -> Self {
+        for rule in rules {
+            if !self
+                .backends
+                .iter()
+                .any(|backend| backend.tag() == rule.backend)
+            {
+                warn!(
+                    "A routing rule refers to the backend {tag}, which is not registered",
+                    tag = rule.backend
+                );
+            }
         }
+
+        self.routing = rules.to_vec();
+        self
+    }
+
+    /// Skips backends for a while after repeated failed distributions.
+    pub fn with_circuit_breaker(mut self, config: Option<&CircuitBreakerConfig>) -> Self {
+        self.circuit_breakers = config.map(|config| {
+            CircuitBreakers::new(config, self.backends.iter().map(|backend| backend.tag()))
+        });
+        self
+    }
+
+    /// Limits the number of files stored on backends at the same time, across all files
+    /// and backends. Further distributions wait until one completes.
+    pub fn with_max_concurrent_distributions(mut self, max: Option<usize>) -> Self {
+        self.max_concurrent_distributions = max.map(|max| max.max(1));
+        self
+    }
+
+    /// Sets how files are handled that no backend stored because a backend rejected them.
+    pub fn with_rejection_policy(mut self, policy: RejectionPolicy) -> Self {
+        self.rejection_policy = policy;
+        self
+    }
+
+    /// Tests each registered backend by distributing a small file and reading it back.
+    /// Returns `true` if the test passed for all backends.
+    pub async fn self_test(&self) -> bool {
+        crate::self_test::run(&self.backends).await
     }
 
     pub fn build(self) -> BackendRegistry {
-        BackendRegistry::new(self.cleanup_rendezvous, self.backends, self.file_accessor)
+        BackendRegistry::new(
+            self.cleanup_rendezvous,
+            self.backends,
+            self.file_accessor,
+            self.distribution_timeout,
+            self.write_quorum,
+            self.routing,
+            self.circuit_breakers,
+            self.max_concurrent_distributions.map(Semaphore::new),
+            self.rejection_policy,
+        )
     }
 
     /// Adds backends to the application.
@@ -173,12 +485,52 @@ impl BackendRegistryBuilder {
                 }
             }
             Err(e) => {
-                error!("Failed to initialize Memcached backends: {}", e);
+                error!(
+                    "Failed to initialize {backend} backends: {error}",
+                    backend = T::backend_name(),
+                    error = e
+                );
                 Err(e)
             }
         }
     }
 
+    /// Combines registered backends into mirrors writing files to all of them at once.
+    /// The mirrored backends only receive files through their mirror.
+    pub fn add_mirrors(
+        mut self,
+        configs: &[MirrorBackendConfig],
+    ) -> Result<BackendRegistryBuilder, RegisterBackendError> {
+        for config in configs {
+            let mut mirror = MirrorBackend::new(&config.tag);
+            for (role, tags) in [
+                (MirrorRole::Primary, &config.primary),
+                (MirrorRole::Secondary, &config.secondary),
+            ] {
+                for tag in tags {
+                    let Some(index) = self.backends.iter().position(|b| b.tag() == tag) else {
+                        let e = RegisterBackendError::UnknownMirroredBackend {
+                            mirror: config.tag.clone(),
+                            tag: tag.clone(),
+                        };
+                        error!("Failed to initialize mirror backends: {e}");
+                        return Err(e);
+                    };
+                    mirror = mirror.with_backend(role, self.backends.remove(index));
+                }
+            }
+
+            info!(
+                "Registering mirror backend {tag} of {primary} primary and {secondary} secondary backends",
+                tag = config.tag,
+                primary = config.primary.len(),
+                secondary = config.secondary.len()
+            );
+            self.backends.push(Backend::wrap(mirror));
+        }
+        Ok(self)
+    }
+
     /// Registers multiple backends.
     fn add_backends_from_iter<I: IntoIterator<Item = Backend>>(
         mut self,
@@ -188,3 +540,233 @@ impl BackendRegistryBuilder {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use async_trait::async_trait;
+    use backend_traits::{DistributeFile, ReceiveFile};
+    use file_distribution::{BoxedFileReader, FileAccessorError, FileHashes, GetFileReaderError};
+    use rendezvous::Rendezvous;
+    use std::collections::BTreeMap;
+    use std::time::SystemTime;
+    use tokio::time::Instant;
+
+    /// A backend that stores files immediately, or hangs until the gate is closed.
+    struct TestBackend {
+        tag: &'static str,
+        gate: Option<Arc<Semaphore>>,
+    }
+
+    #[async_trait]
+    impl DistributeFile for TestBackend {
+        fn tag(&self) -> &str {
+            self.tag
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<(), DistributionError> {
+            if let Some(gate) = &self.gate {
+                gate.acquire().await.ok();
+            }
+            Ok(())
+        }
+    }
+
+    impl ReceiveFile for TestBackend {}
+
+    /// A backend recording what happened to its files, storing them once the gate opens.
+    struct RecordingBackend {
+        gate: Arc<Semaphore>,
+        log: Arc<Mutex<Vec<&'static str>>>,
+    }
+
+    #[async_trait]
+    impl DistributeFile for RecordingBackend {
+        fn tag(&self) -> &str {
+            "recording"
+        }
+
+        async fn distribute_file(
+            &self,
+            _id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<(), DistributionError> {
+            self.gate.acquire().await.ok();
+            self.log.lock().unwrap().push("stored");
+            Ok(())
+        }
+
+        async fn delete_file(&self, _id: ShortGuid) -> Result<(), DeleteFileError> {
+            self.log.lock().unwrap().push("deleted");
+            Ok(())
+        }
+    }
+
+    impl ReceiveFile for RecordingBackend {}
+
+    struct NoFiles;
+
+    #[async_trait]
+    impl GetFile for NoFiles {
+        async fn get_file(&self, id: ShortGuid) -> Result<BoxedFileReader, FileAccessorError> {
+            Err(GetFileReaderError::UnknownFile(id).into())
+        }
+    }
+
+    fn summary() -> Arc<WriteSummary> {
+        Arc::new(WriteSummary {
+            created: SystemTime::now(),
+            expires: Instant::now() + Duration::from_secs(60),
+            hashes: FileHashes::new(None, None, None),
+            file_name: None,
+            file_size_bytes: 0,
+            metadata: BTreeMap::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn quorum_is_met_while_a_backend_hangs() {
+        let rendezvous = Rendezvous::new();
+        let gate = Arc::new(Semaphore::new(0));
+        let registry = BackendRegistry::builder(
+            rendezvous.fork_guard(),
+            FileProvider::wrap(Arc::new(NoFiles)),
+            Duration::from_secs(300),
+        )
+        .add_backends_from_iter([
+            Backend::wrap(TestBackend {
+                tag: "fast",
+                gate: None,
+            }),
+            Backend::wrap(TestBackend {
+                tag: "hanging",
+                gate: Some(gate.clone()),
+            }),
+        ])
+        .with_write_quorum(Some(1))
+        .build();
+        let sender = registry.get_sender().expect("sender was taken");
+        let tracker = registry.distribution_tracker();
+
+        // The second file is distributed while the first one still waits for the hanging backend.
+        for id in [ShortGuid::new_random(), ShortGuid::new_random()] {
+            sender
+                .send(BackendCommand::DistributeFile(id, summary()))
+                .await
+                .expect("failed to send command");
+
+            let quorum = tokio::time::timeout(Duration::from_secs(1), tracker.wait_for_quorum(&id))
+                .await
+                .expect("the quorum wait was blocked by the hanging backend");
+            assert_eq!(quorum, Some(QuorumState::Met));
+
+            let states = tracker.get_or_pending(&id);
+            let state = |tag| states.iter().find(|b| b.tag == tag).map(|b| b.state);
+            assert_eq!(state("fast"), Some(DistributionState::Stored));
+            assert_eq!(state("hanging"), Some(DistributionState::Pending));
+        }
+
+        // Shutting down waits for the running distributions.
+        gate.close();
+        drop(sender);
+        drop(registry);
+        tokio::time::timeout(Duration::from_secs(1), rendezvous.rendezvous_async())
+            .await
+            .expect("the distributions did not complete")
+            .expect("failed to wait for the distributions");
+    }
+
+    #[tokio::test]
+    async fn mirrored_backends_are_replaced_by_their_mirror() {
+        let rendezvous = Rendezvous::new();
+        let builder = || {
+            BackendRegistry::builder(
+                rendezvous.fork_guard(),
+                FileProvider::wrap(Arc::new(NoFiles)),
+                Duration::from_secs(300),
+            )
+            .add_backends_from_iter(
+                ["a", "b", "c"].map(|tag| Backend::wrap(TestBackend { tag, gate: None })),
+            )
+        };
+        let mirror = MirrorBackendConfig {
+            tag: String::from("mirrored"),
+            primary: vec![String::from("a")],
+            secondary: vec![String::from("c")],
+        };
+
+        let registry = builder()
+            .add_mirrors(std::slice::from_ref(&mirror))
+            .expect("failed to register the mirror");
+        let tags: Vec<_> = registry.backends.iter().map(|b| b.tag()).collect();
+        assert_eq!(tags, ["b", "mirrored"]);
+
+        let unknown = MirrorBackendConfig {
+            tag: String::from("unknown"),
+            primary: vec![String::from("a"), String::from("d")],
+            secondary: Vec::default(),
+        };
+        assert!(matches!(
+            builder().add_mirrors(&[unknown]),
+            Err(RegisterBackendError::UnknownMirroredBackend { tag, .. }) if tag == "d"
+        ));
+    }
+
+    #[tokio::test]
+    async fn deletion_waits_for_the_running_distribution() {
+        let rendezvous = Rendezvous::new();
+        let gate = Arc::new(Semaphore::new(0));
+        let log = Arc::new(Mutex::new(Vec::new()));
+        let registry = BackendRegistry::builder(
+            rendezvous.fork_guard(),
+            FileProvider::wrap(Arc::new(NoFiles)),
+            Duration::from_secs(300),
+        )
+        .add_backends_from_iter([Backend::wrap(RecordingBackend {
+            gate: gate.clone(),
+            log: log.clone(),
+        })])
+        .build();
+        let sender = registry.get_sender().expect("sender was taken");
+
+        let id = ShortGuid::new_random();
+        sender
+            .send(BackendCommand::DistributeFile(id, summary()))
+            .await
+            .expect("failed to send command");
+        sender
+            .send(BackendCommand::DeleteFile(id))
+            .await
+            .expect("failed to send command");
+
+        // The event loop keeps handling commands while the deletion waits.
+        let other = ShortGuid::new_random();
+        sender
+            .send(BackendCommand::DeleteFile(other))
+            .await
+            .expect("failed to send command");
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while log.lock().unwrap().is_empty() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the deletion of another file was blocked");
+        assert_eq!(*log.lock().unwrap(), ["deleted"]);
+
+        gate.add_permits(1);
+        drop(sender);
+        drop(registry);
+        tokio::time::timeout(Duration::from_secs(1), rendezvous.rendezvous_async())
+            .await
+            .expect("the deletion did not complete")
+            .expect("failed to wait for the deletion");
+        assert_eq!(*log.lock().unwrap(), ["deleted", "stored", "deleted"]);
+    }
+}
@@ -0,0 +1,77 @@
+//! Contains the collection of the backends' storage statistics.
+
+use backend_traits::{Backend, BackendStats, BackendStatsError};
+use futures::future::join_all;
+use metrics::backends::BackendMetrics;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tracing::{debug, warn};
+
+/// The time each backend may take to report its statistics.
+const STATS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The storage statistics reported by a backend.
+pub struct CollectedStats<'a> {
+    /// The backend.
+    pub backend: &'a Backend,
+    /// The statistics, or `None` if the backend can't report them.
+    pub stats: Result<Option<BackendStats>, BackendStatsError>,
+}
+
+/// Collects the statistics of all backends concurrently and updates the metrics.
+pub async fn collect(backends: &[Backend]) -> Vec<CollectedStats<'_>> {
+    let collected = join_all(backends.iter().map(|backend| async move {
+        let stats = tokio::time::timeout(STATS_TIMEOUT, backend.stats())
+            .await
+            .unwrap_or(Err(BackendStatsError::Timeout(STATS_TIMEOUT)));
+        CollectedStats { backend, stats }
+    }))
+    .await;
+
+    for entry in &collected {
+        match &entry.stats {
+            Ok(Some(stats)) => BackendMetrics::set_stats(
+                entry.backend.tag(),
+                stats.items,
+                stats.bytes_used,
+                stats.bytes_limit,
+            ),
+            Ok(None) => {}
+            Err(e) => {
+                warn!(
+                    "Failed to collect the storage statistics of backend {tag}: {e}",
+                    tag = entry.backend.tag()
+                );
+            }
+        }
+    }
+
+    collected
+}
+
+/// Collects the statistics of all backends periodically until a shutdown is initiated.
+pub fn spawn_refresh(
+    backends: Arc<[Backend]>,
+    interval: Duration,
+    shutdown_tx: &broadcast::Sender<()>,
+) {
+    if backends.is_empty() {
+        return;
+    }
+
+    let mut shutdown_rx = shutdown_tx.subscribe();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(interval);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                _ = interval.tick() => {
+                    collect(&backends).await;
+                }
+                _ = shutdown_rx.recv() => break,
+            }
+        }
+        debug!("Stopped collecting backend storage statistics");
+    });
+}
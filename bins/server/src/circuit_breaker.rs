@@ -0,0 +1,237 @@
+//! Contains the per-backend circuit breakers skipping persistently failing backends.
+
+use app_config::circuit_breaker::CircuitBreakerConfig;
+use metrics::distribution::DistributionMetrics;
+use std::collections::HashMap;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+/// The circuit breakers of all backends, keyed by backend tag.
+pub struct CircuitBreakers {
+    failure_threshold: u32,
+    window: Duration,
+    cooldown: Duration,
+    circuits: HashMap<String, Circuit>,
+}
+
+enum Circuit {
+    /// Distributions are attempted.
+    Closed {
+        /// The number of consecutive failures.
+        failures: u32,
+        /// The time of the first of the consecutive failures.
+        first_failure: Option<Instant>,
+    },
+    /// Distributions are skipped until the cool-down ends.
+    Open { until: Instant },
+    /// A single distribution tests whether the backend recovered; others are skipped
+    /// until it completed. If its outcome is never recorded, e.g. because it was cancelled,
+    /// another test is admitted after the cool-down.
+    HalfOpen { probe_started: Instant },
+}
+
+impl CircuitBreakers {
+    /// Creates closed circuits for the backends identified by the specified tags.
+    pub fn new<'a, I>(config: &CircuitBreakerConfig, tags: I) -> Self
+    where
+        I: IntoIterator<Item = &'a str>,
+    {
+        let circuits = tags
+            .into_iter()
+            .map(|tag| {
+                DistributionMetrics::set_backend_healthy(tag, true);
+                (tag.to_string(), Circuit::closed())
+            })
+            .collect();
+
+        Self {
+            failure_threshold: config.failure_threshold(),
+            window: config.window(),
+            cooldown: config.cooldown(),
+            circuits,
+        }
+    }
+
+    /// Indicates whether a distribution to the backend is to be attempted.
+    /// Once the cool-down of an open circuit ended, the circuit becomes half-open and
+    /// only the first caller is admitted until the outcome of its distribution is recorded.
+    pub fn allow(&mut self, tag: &str) -> bool {
+        let Some(circuit) = self.circuits.get_mut(tag) else {
+            return true;
+        };
+
+        let now = Instant::now();
+        match circuit {
+            Circuit::Closed { .. } => true,
+            Circuit::Open { until } if now < *until => false,
+            Circuit::HalfOpen { probe_started } if now < *probe_started + self.cooldown => false,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => {
+                info!("Testing whether backend {tag} recovered");
+                *circuit = Circuit::HalfOpen { probe_started: now };
+                true
+            }
+        }
+    }
+
+    /// Records a successful distribution, closing the circuit.
+    pub fn record_success(&mut self, tag: &str) {
+        let Some(circuit) = self.circuits.get_mut(tag) else {
+            return;
+        };
+
+        if matches!(circuit, Circuit::HalfOpen { .. } | Circuit::Open { .. }) {
+            info!("Backend {tag} recovered, resuming distributions");
+            DistributionMetrics::set_backend_healthy(tag, true);
+        }
+        *circuit = Circuit::closed();
+    }
+
+    /// Records a failed distribution, opening the circuit if the threshold is reached
+    /// or the backend failed the test of a half-open circuit.
+    pub fn record_failure(&mut self, tag: &str) {
+        let Some(circuit) = self.circuits.get_mut(tag) else {
+            return;
+        };
+
+        let now = Instant::now();
+        let open = match circuit {
+            Circuit::Closed {
+                failures,
+                first_failure,
+            } => {
+                // Failures spread out wider than the window start a new series.
+                match first_failure {
+                    Some(first) if now.duration_since(*first) <= self.window => *failures += 1,
+                    _ => {
+                        *failures = 1;
+                        *first_failure = Some(now);
+                    }
+                }
+                *failures >= self.failure_threshold
+            }
+            Circuit::HalfOpen { .. } => true,
+            Circuit::Open { .. } => false,
+        };
+
+        if open {
+            warn!(
+                "Skipping backend {tag} for {cooldown} seconds after repeated failures",
+                cooldown = self.cooldown.as_secs()
+            );
+            DistributionMetrics::set_backend_healthy(tag, false);
+            *circuit = Circuit::Open {
+                until: now + self.cooldown,
+            };
+        }
+    }
+}
+
+impl Circuit {
+    fn closed() -> Self {
+        Self::Closed {
+            failures: 0,
+            first_failure: None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    const TAG: &str = "test";
+
+    fn breakers(failure_threshold: u32, cooldown_sec: u64) -> CircuitBreakers {
+        let config = CircuitBreakerConfig {
+            failure_threshold: Some(failure_threshold),
+            window_sec: Some(60),
+            cooldown_sec: Some(cooldown_sec),
+        };
+        CircuitBreakers::new(&config, [TAG])
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn circuit_opens_after_repeated_failures_and_closes_after_a_successful_test() {
+        let mut breakers = breakers(2, 30);
+        breakers.record_failure(TAG);
+        assert!(breakers.allow(TAG));
+
+        breakers.record_failure(TAG);
+        assert!(!breakers.allow(TAG));
+
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(breakers.allow(TAG), "the test distribution was skipped");
+        assert!(
+            !breakers.allow(TAG),
+            "a second test distribution was admitted"
+        );
+
+        breakers.record_success(TAG);
+        assert!(breakers.allow(TAG));
+        assert!(breakers.allow(TAG));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failed_test_opens_the_circuit_again() {
+        let mut breakers = breakers(1, 30);
+        breakers.record_failure(TAG);
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(breakers.allow(TAG));
+
+        breakers.record_failure(TAG);
+        assert!(!breakers.allow(TAG));
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(!breakers.allow(TAG));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(breakers.allow(TAG));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn failures_outside_the_window_keep_the_circuit_closed() {
+        let mut breakers = breakers(2, 30);
+        breakers.record_failure(TAG);
+        tokio::time::advance(Duration::from_secs(61)).await;
+        breakers.record_failure(TAG);
+        assert!(breakers.allow(TAG));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn lost_test_is_replaced_after_the_cool_down() {
+        let mut breakers = breakers(1, 30);
+        breakers.record_failure(TAG);
+        tokio::time::advance(Duration::from_secs(30)).await;
+        assert!(breakers.allow(TAG));
+
+        tokio::time::advance(Duration::from_secs(29)).await;
+        assert!(!breakers.allow(TAG));
+        tokio::time::advance(Duration::from_secs(1)).await;
+        assert!(breakers.allow(TAG));
+    }
+
+    #[test]
+    fn concurrent_callers_admit_a_single_test() {
+        // The cool-down of the open circuit just ended.
+        let mut breakers = breakers(1, 30);
+        breakers.circuits.insert(
+            TAG.to_string(),
+            Circuit::Open {
+                until: Instant::now(),
+            },
+        );
+        let breakers = Mutex::new(breakers);
+
+        let admitted = std::thread::scope(|scope| {
+            let callers: Vec<_> = (0..8)
+                .map(|_| scope.spawn(|| breakers.lock().unwrap().allow(TAG)))
+                .collect();
+            callers
+                .into_iter()
+                .map(|caller| caller.join().unwrap())
+                .filter(|&allowed| allowed)
+                .count()
+        });
+        assert_eq!(admitted, 1);
+    }
+}
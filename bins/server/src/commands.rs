@@ -42,7 +42,12 @@ pub fn build_command() -> Command {
                 .value_parser(valid_file)
                 .value_hint(clap::ValueHint::FilePath)
                 .help("The config file to load")
-                .help_heading("Configuration"),
+                .help_heading("Configuration")
+                .global(true),
+        )
+        .subcommand(
+            Command::new("validate-config")
+                .about("Validates the configuration and exits without starting the server"),
         )
 }
 
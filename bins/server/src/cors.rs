@@ -0,0 +1,78 @@
+//! Contains the construction of the CORS layer.
+
+use app_config::cors::CorsConfig;
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowOrigin, CorsLayer};
+
+/// The wildcard value allowing any origin or header.
+const WILDCARD: &str = "*";
+
+/// Builds the CORS layer from the configuration.
+pub fn build_layer(config: &CorsConfig) -> Result<CorsLayer, InvalidCorsConfig> {
+    let origins = if config
+        .allowed_origins
+        .iter()
+        .any(|origin| origin == WILDCARD)
+    {
+        AllowOrigin::any()
+    } else {
+        let origins = config
+            .allowed_origins
+            .iter()
+            .map(|origin| {
+                HeaderValue::from_str(origin).map_err(|_| InvalidCorsConfig::Origin(origin.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    let methods = config
+        .allowed_methods()
+        .into_iter()
+        .map(|method| {
+            method
+                .parse::<Method>()
+                .map_err(|_| InvalidCorsConfig::Method(method.to_string()))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let allowed_headers = config.allowed_headers();
+    let allowed_headers = if allowed_headers.contains(&WILDCARD) {
+        AllowHeaders::any()
+    } else {
+        AllowHeaders::list(parse_header_names(&allowed_headers)?)
+    };
+
+    let exposed_headers = parse_header_names(&config.exposed_headers())?;
+
+    let layer = CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(allowed_headers)
+        .expose_headers(exposed_headers);
+
+    Ok(match config.max_age() {
+        Some(max_age) => layer.max_age(max_age),
+        None => layer,
+    })
+}
+
+fn parse_header_names(names: &[&str]) -> Result<Vec<HeaderName>, InvalidCorsConfig> {
+    names
+        .iter()
+        .map(|name| {
+            HeaderName::from_bytes(name.as_bytes())
+                .map_err(|_| InvalidCorsConfig::Header(name.to_string()))
+        })
+        .collect()
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidCorsConfig {
+    #[error("Invalid CORS origin: {0}")]
+    Origin(String),
+    #[error("Invalid CORS method: {0}")]
+    Method(String),
+    #[error("Invalid CORS header: {0}")]
+    Header(String),
+}
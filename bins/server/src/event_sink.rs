@@ -0,0 +1,178 @@
+//! Contains the delivery of structured transfer events to the configured sink.
+
+use crate::handlers::Hashes;
+use app_config::events::{EventSinkConfig, EventsConfig};
+use file_distribution::FileHashes;
+use metrics::events::EventMetrics;
+use serde::Serialize;
+use shortguid::ShortGuid;
+use std::net::IpAddr;
+use tokio::fs::OpenOptions;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tracing::{error, warn};
+
+/// Emits transfer events to the configured sink without blocking. Can be cheaply cloned.
+#[derive(Clone)]
+pub struct EventSink {
+    sender: mpsc::Sender<TransferEvent>,
+}
+
+/// A structured record of a `/yeet` or `/yoink` request.
+#[derive(Serialize)]
+pub struct TransferEvent {
+    /// The time the request was received, in RFC 3339 format.
+    pub timestamp: String,
+    /// The kind of transfer.
+    pub operation: TransferOperation,
+    /// The matched route, e.g. `/yoink/:id`.
+    pub route: String,
+    /// The ID of the file; omitted if the request failed before a file was known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<ShortGuid>,
+    /// The file size in bytes, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<u64>,
+    /// The hashes of the file, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hashes: Option<Hashes>,
+    /// The content type of the file, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    /// The IP of the client, as resolved through trusted proxies.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<IpAddr>,
+    /// The time from receiving the request until the response was sent, in milliseconds.
+    pub duration_ms: u64,
+    /// The HTTP status code of the response.
+    pub status: u16,
+    /// Whether the response body was transmitted completely.
+    pub completed: bool,
+}
+
+/// The kind of transfer.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferOperation {
+    /// A file was uploaded.
+    Yeet,
+    /// A file was downloaded.
+    Yoink,
+}
+
+/// The details of a transferred file, attached by the handlers to their responses
+/// as an extension for inclusion in the [`TransferEvent`].
+#[derive(Debug, Clone)]
+pub struct TransferDetails {
+    /// The ID of the file.
+    pub id: ShortGuid,
+    /// The file size in bytes, if known.
+    pub size_bytes: Option<u64>,
+    /// The hashes of the file, if known.
+    pub hashes: Option<FileHashes>,
+    /// The content type of the file, if known.
+    pub content_type: Option<String>,
+}
+
+impl EventSink {
+    /// Creates the sink and spawns the task delivering the events.
+    pub fn spawn(config: &EventsConfig) -> Self {
+        let (sender, receiver) = mpsc::channel(config.buffer_size());
+        tokio::spawn(deliver(config.sink.clone(), receiver));
+        Self { sender }
+    }
+
+    /// Emits an event without waiting. The event is dropped if the buffer is full.
+    pub fn emit(&self, event: TransferEvent) {
+        if self.sender.try_send(event).is_err() {
+            EventMetrics::track_dropped();
+        }
+    }
+}
+
+impl TransferOperation {
+    /// Determines the kind of transfer handled by a route, or `None` if the route
+    /// doesn't transfer files.
+    pub fn of_route(route: &str) -> Option<Self> {
+        match route {
+            "/yeet" => Some(Self::Yeet),
+            "/yoink/:id" | "/yoink/:id/url" | "/by-sha256/:hex" => Some(Self::Yoink),
+            _ => None,
+        }
+    }
+}
+
+/// Delivers the events until all senders are dropped.
+async fn deliver(config: EventSinkConfig, mut receiver: mpsc::Receiver<TransferEvent>) {
+    let mut writer = match EventWriter::open(&config).await {
+        Ok(writer) => writer,
+        Err(e) => {
+            error!("Unable to open the event sink {config:?}: {e}");
+            return;
+        }
+    };
+
+    while let Some(event) = receiver.recv().await {
+        match writer.write(&event).await {
+            Ok(()) => EventMetrics::track_emitted(),
+            Err(e) => {
+                warn!("Failed to deliver transfer event: {e}");
+                EventMetrics::track_dropped();
+            }
+        }
+    }
+}
+
+/// Writes the events to the configured destination.
+enum EventWriter {
+    /// Writes JSON lines to standard output or a file.
+    Lines(Box<dyn AsyncWrite + Send + Unpin>),
+    /// Posts each event to the URL.
+    Webhook(reqwest::Client, String),
+}
+
+impl EventWriter {
+    async fn open(config: &EventSinkConfig) -> Result<Self, std::io::Error> {
+        Ok(match config {
+            EventSinkConfig::Stdout => Self::Lines(Box::new(tokio::io::stdout())),
+            EventSinkConfig::File { path } => Self::Lines(Box::new(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await?,
+            )),
+            EventSinkConfig::Webhook { url } => Self::Webhook(reqwest::Client::new(), url.clone()),
+        })
+    }
+
+    async fn write(&mut self, event: &TransferEvent) -> Result<(), EventSinkError> {
+        match self {
+            Self::Lines(writer) => {
+                let mut line = serde_json::to_vec(event)?;
+                line.push(b'\n');
+                writer.write_all(&line).await?;
+                writer.flush().await?;
+            }
+            Self::Webhook(client, url) => {
+                client
+                    .post(url.as_str())
+                    .json(event)
+                    .send()
+                    .await?
+                    .error_for_status()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+enum EventSinkError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serialization(#[from] serde_json::Error),
+    #[error(transparent)]
+    Webhook(#[from] reqwest::Error),
+}
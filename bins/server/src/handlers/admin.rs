@@ -0,0 +1,179 @@
+//! Contains the `/admin` endpoint filters.
+
+use crate::handlers::file_id::FileId;
+use crate::{backend_stats, AppState};
+use axum::body::HttpBody;
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use backbone::{FileIdFormat, RedistributeError};
+use backend_traits::BackendCommand;
+use hyper::StatusCode;
+use serde::Serialize;
+use tracing::info;
+
+pub trait AdminRoutes {
+    /// Provides administrative APIs.
+    ///
+    /// ```http
+    /// POST /admin/redistribute/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// POST /admin/delete/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// POST /admin/uploads/pause HTTP/1.1
+    /// POST /admin/uploads/resume HTTP/1.1
+    /// GET /admin/backends/stats HTTP/1.1
+    /// ```
+    fn map_admin_endpoints(self) -> Self;
+}
+
+impl<B> AdminRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + Sync + 'static,
+{
+    fn map_admin_endpoints(self) -> Self {
+        self.route("/admin/redistribute/:id", post(do_redistribute))
+            .route("/admin/delete/:id", post(do_delete))
+            .route("/admin/uploads/pause", post(do_pause_uploads))
+            .route("/admin/uploads/resume", post(do_resume_uploads))
+            .route("/admin/backends/stats", get(do_backend_stats))
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct BackendStatsResponse {
+    backends: Vec<BackendStatsEntry>,
+}
+
+#[derive(Debug, Serialize)]
+struct BackendStatsEntry {
+    /// The tag of the backend.
+    backend: String,
+    /// The statistics, or `None` if the backend can't report them or failed to.
+    stats: Option<Stats>,
+    /// Why the backend failed to report its statistics.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Stats {
+    items: Option<u64>,
+    bytes_used: Option<u64>,
+    bytes_limit: Option<u64>,
+}
+
+/// Reports the storage statistics of each backend, as far as it is able to.
+async fn do_backend_stats(State(state): State<AppState>) -> Json<BackendStatsResponse> {
+    let backends = backend_stats::collect(&state.backends)
+        .await
+        .into_iter()
+        .map(|entry| {
+            let (stats, error) = match entry.stats {
+                Ok(stats) => (stats, None),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            BackendStatsEntry {
+                backend: entry.backend.tag().to_string(),
+                stats: stats.map(|stats| Stats {
+                    items: stats.items,
+                    bytes_used: stats.bytes_used,
+                    bytes_limit: stats.bytes_limit,
+                }),
+                error,
+            }
+        })
+        .collect();
+
+    Json(BackendStatsResponse { backends })
+}
+
+/// Stops accepting new uploads; uploads in progress are completed.
+async fn do_pause_uploads(State(state): State<AppState>) -> StatusCode {
+    if !state.set_uploads_paused(true) {
+        info!("Pausing new uploads");
+    }
+    StatusCode::NO_CONTENT
+}
+
+/// Accepts new uploads again after they were paused.
+async fn do_resume_uploads(State(state): State<AppState>) -> StatusCode {
+    if state.set_uploads_paused(false) {
+        info!("Resuming new uploads");
+    }
+    StatusCode::NO_CONTENT
+}
+
+/// Schedules a locally held file for distribution to the backends again.
+async fn do_redistribute(
+    FileId(id): FileId,
+    State(state): State<AppState>,
+) -> Result<Response, StatusCode> {
+    match state.backbone.redistribute(id).await {
+        Ok(()) => Ok(StatusCode::ACCEPTED.into_response()),
+        Err(e) => Ok(map_redistribute_error_to_response(
+            e,
+            state.backbone.id_format(),
+        )),
+    }
+}
+
+/// Schedules the deletion of a file from all backends; a locally held copy is kept until
+/// it expires.
+async fn do_delete(FileId(id): FileId, State(state): State<AppState>) -> Response {
+    info!(file_id = %id, "Scheduling deletion of file {id} from the backends");
+    match state
+        .backend_sender
+        .send(BackendCommand::DeleteFile(id))
+        .await
+    {
+        Ok(()) => StatusCode::ACCEPTED.into_response(),
+        Err(_) => {
+            let id = state.backbone.id_format().format(id);
+            problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .with_title("Deletion failed")
+                .with_detail(format!(
+                    "Failed to schedule the deletion of the file with ID {id}"
+                ))
+                .with_instance(format!("/admin/delete/{id}"))
+                .with_value("id", id)
+                .into_response()
+        }
+    }
+}
+
+fn map_redistribute_error_to_response(
+    value: RedistributeError,
+    id_format: &FileIdFormat,
+) -> Response {
+    match value {
+        RedistributeError::UnknownFile(id) => {
+            let id = id_format.format(id);
+            problemdetails::new(StatusCode::NOT_FOUND)
+                .with_title("File not found")
+                .with_detail(format!("The file with ID {id} is not held locally"))
+                .with_instance(format!("/admin/redistribute/{id}"))
+                .with_value("id", id)
+                .into_response()
+        }
+        RedistributeError::NotReady(id) => {
+            let id = id_format.format(id);
+            problemdetails::new(StatusCode::CONFLICT)
+                .with_title("File not ready")
+                .with_detail(format!("The file with ID {id} is still being written"))
+                .with_instance(format!("/admin/redistribute/{id}"))
+                .with_value("id", id)
+                .into_response()
+        }
+        RedistributeError::BackboneCommunicationFailed(id) => {
+            let id = id_format.format(id);
+            problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .with_title("Redistribution failed")
+                .with_detail(format!(
+                    "Failed to schedule the redistribution of the file with ID {id}"
+                ))
+                .with_instance(format!("/admin/redistribute/{id}"))
+                .with_value("id", id)
+                .into_response()
+        }
+    }
+}
@@ -0,0 +1,323 @@
+//! Contains the `/yoink-bundle` endpoint filter.
+
+use crate::handlers::shutting_down_response;
+use crate::AppState;
+use app_config::server::BundleMissingFiles;
+use axum::body::{HttpBody, StreamBody};
+use axum::extract::State;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use axum::{Json, Router};
+use file_distribution::{BoxedFileReader, FileReaderTrait, GetFileReaderError};
+use futures::StreamExt;
+use hyper::StatusCode;
+use metrics::transfer::{TransferMethod, TransferMetrics};
+use mime_db::extension;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::oneshot;
+use tokio_tar::{Builder, EntryType, Header};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, warn};
+
+/// The size of the buffer between the archive writer and the response body.
+const BUNDLE_BUFFER_SIZE: usize = 64 * 1024;
+
+/// The name of the manifest entry.
+const MANIFEST_NAME: &str = "manifest.json";
+
+pub trait BundleRoutes {
+    /// Provides an API for retrieving several files at once as a tar archive.
+    ///
+    /// ```http
+    /// POST /yoink-bundle HTTP/1.1
+    /// Content-Type: application/json
+    ///
+    /// ["KmC6e8laTnK3dioUSMpM0Q", "ZRkA0OTPSt-6tr6ER8hT7g"]
+    /// ```
+    ///
+    /// Each file is named after its stored file name. Files that are unknown, expired or
+    /// of unknown size are skipped; depending on the configuration, they are listed in a
+    /// `manifest.json` entry at the start of the archive.
+    fn map_bundle_endpoint(self) -> Self;
+}
+
+impl<B> BundleRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + 'static,
+    <B as HttpBody>::Data: Send,
+    <B as HttpBody>::Error: std::error::Error + Send + Sync,
+{
+    fn map_bundle_endpoint(self) -> Self {
+        self.route("/yoink-bundle", post(do_yoink_bundle))
+    }
+}
+
+/// The `manifest.json` entry of a bundle.
+#[derive(Serialize)]
+struct Manifest {
+    /// The files included in the archive.
+    files: Vec<BundledFile>,
+    /// The requested files that are not included.
+    missing: Vec<MissingFile>,
+}
+
+#[derive(Serialize)]
+struct BundledFile {
+    id: String,
+    /// The name of the entry in the archive.
+    name: String,
+    size_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct MissingFile {
+    id: String,
+    /// Why the file is not included, i.e. `not-found`, `expired`, `incomplete` or `failed`.
+    reason: &'static str,
+}
+
+/// A file to add to the archive.
+struct BundleEntry {
+    name: String,
+    size: u64,
+    modified: u64,
+    file: BoxedFileReader,
+}
+
+#[axum::debug_handler]
+async fn do_yoink_bundle(
+    State(state): State<AppState>,
+    Json(ids): Json<Vec<String>>,
+) -> Result<Response, StatusCode> {
+    if state.is_shutting_down() {
+        return Ok(shutting_down_response());
+    }
+
+    let max_files = state.config.server.max_bundle_files();
+    if ids.len() > max_files {
+        return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+            .with_title("Too many files")
+            .with_detail(format!(
+                "At most {max_files} files can be requested at once, got {count}",
+                count = ids.len()
+            ))
+            .into_response());
+    }
+
+    let id_format = *state.backbone.id_format();
+    let mut parsed_ids = Vec::with_capacity(ids.len());
+    for value in ids {
+        let Some(id) = id_format.parse(&value) else {
+            return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                .with_title("Invalid file ID")
+                .with_detail(format!("{value:?} is not a valid file ID"))
+                .with_value("id", value)
+                .into_response());
+        };
+        parsed_ids.push(id);
+    }
+
+    // All files are opened up front so that the manifest is known before streaming.
+    let mut entries = Vec::new();
+    let mut manifest = Manifest {
+        files: Vec::new(),
+        missing: Vec::new(),
+    };
+    let mut names = HashSet::from([MANIFEST_NAME.to_string()]);
+    for id in parsed_ids {
+        let display_id = id_format.format(id);
+        if manifest.files.iter().any(|file| file.id == display_id) {
+            continue;
+        }
+
+        state.backbone.extend_lease(id).await;
+        let file = match state.backbone.get_file(id).await {
+            Ok(file) => file,
+            Err(e) => {
+                let reason = match e {
+                    GetFileReaderError::UnknownFile(_) => "not-found",
+                    GetFileReaderError::FileExpired(_) => "expired",
+                    e => {
+                        warn!(file_id = %id, "Failed to open file {id} for a bundle: {e}");
+                        "failed"
+                    }
+                };
+                manifest.missing.push(MissingFile {
+                    id: display_id,
+                    reason,
+                });
+                continue;
+            }
+        };
+
+        // Tar headers require the size up front.
+        let Some(size) = file.final_file_size() else {
+            manifest.missing.push(MissingFile {
+                id: display_id,
+                reason: "incomplete",
+            });
+            continue;
+        };
+
+        let name = unique_name(&mut names, &display_id, entry_name(&display_id, &file));
+
+        let modified = file
+            .summary()
+            .as_ref()
+            .and_then(|summary| summary.created.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default()
+            .as_secs();
+        manifest.files.push(BundledFile {
+            id: display_id,
+            name: name.clone(),
+            size_bytes: size as u64,
+        });
+        entries.push(BundleEntry {
+            name,
+            size: size as u64,
+            modified,
+            file,
+        });
+    }
+
+    debug!(
+        "Bundling {included} files, {missing} requested files are missing",
+        included = manifest.files.len(),
+        missing = manifest.missing.len()
+    );
+    for _ in &entries {
+        TransferMetrics::track_transfer(TransferMethod::Fetch);
+    }
+
+    let manifest = match state.config.server.bundle_missing_files {
+        BundleMissingFiles::Manifest => serde_json::to_vec_pretty(&manifest).ok(),
+        BundleMissingFiles::Skip => None,
+    };
+
+    // The archive is written into one end of a pipe while the other end is streamed
+    // to the client. Failures abort the response instead of truncating the archive.
+    let (writer, reader) = tokio::io::duplex(BUNDLE_BUFFER_SIZE);
+    let (error_sender, error_receiver) = oneshot::channel();
+    tokio::spawn(async move {
+        if let Err(e) = write_bundle(writer, manifest, entries).await {
+            warn!("Failed to write bundle: {e}");
+            let _ = error_sender.send(e);
+        }
+    });
+
+    let failure = futures::stream::once(error_receiver)
+        .filter_map(|result| async move { result.ok().map(Err) });
+    let body = StreamBody::new(ReaderStream::new(reader).chain(failure));
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/x-tar"),
+            (
+                header::CONTENT_DISPOSITION,
+                "attachment; filename=\"bundle.tar\"",
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+/// Writes the archive, starting with the manifest if there is one.
+async fn write_bundle<W>(
+    writer: W,
+    manifest: Option<Vec<u8>>,
+    entries: Vec<BundleEntry>,
+) -> std::io::Result<()>
+where
+    W: AsyncWrite + Unpin + Send + 'static,
+{
+    let mut builder = Builder::new_non_terminated(writer);
+    if let Some(manifest) = manifest {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let mut header = entry_header(manifest.len() as u64, now);
+        builder
+            .append_data(&mut header, MANIFEST_NAME, manifest.as_slice())
+            .await?;
+    }
+
+    for entry in entries {
+        let mut header = entry_header(entry.size, entry.modified);
+        builder
+            .append_data(&mut header, &entry.name, entry.file)
+            .await?;
+    }
+
+    builder.finish().await?;
+    builder.into_inner().await?.shutdown().await
+}
+
+fn entry_header(size: u64, modified: u64) -> Header {
+    let mut header = Header::new_gnu();
+    header.set_entry_type(EntryType::Regular);
+    header.set_mode(0o644);
+    header.set_size(size);
+    header.set_mtime(modified);
+    header
+}
+
+/// Gets the name of a file in the archive, i.e. the stored file name without any
+/// directories, or the ID with an extension matching the content type.
+fn entry_name(id: &str, file: &BoxedFileReader) -> String {
+    let stored = file
+        .summary()
+        .as_ref()
+        .and_then(|summary| summary.file_name.as_deref())
+        .and_then(|name| name.rsplit(['/', '\\']).next())
+        .filter(|name| !name.is_empty() && *name != "." && *name != "..");
+    if let Some(name) = stored {
+        return name.to_string();
+    }
+
+    match file.content_type().as_deref().and_then(extension) {
+        Some(ext) if !ext.is_empty() => format!("{id}.{ext}"),
+        _ => id.to_string(),
+    }
+}
+
+/// Reserves a name in the archive for the file with the given ID. Names that are already
+/// taken are prefixed with the ID, and then with a counter, until they are unique.
+fn unique_name(names: &mut HashSet<String>, id: &str, name: String) -> String {
+    if names.insert(name.clone()) {
+        return name;
+    }
+
+    let mut candidate = format!("{id}-{name}");
+    let mut counter = 1;
+    while !names.insert(candidate.clone()) {
+        counter += 1;
+        candidate = format!("{id}-{counter}-{name}");
+    }
+    candidate
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colliding_names_are_made_unique() {
+        let mut names = HashSet::from([MANIFEST_NAME.to_string()]);
+        assert_eq!(unique_name(&mut names, "a", "x.txt".into()), "x.txt");
+        assert_eq!(unique_name(&mut names, "b", "x.txt".into()), "b-x.txt");
+
+        // A stored file name may itself collide with a prefixed name.
+        assert_eq!(unique_name(&mut names, "c", "b-x.txt".into()), "c-b-x.txt");
+        assert_eq!(unique_name(&mut names, "b", "x.txt".into()), "b-2-x.txt");
+        assert_eq!(unique_name(&mut names, "b", "x.txt".into()), "b-3-x.txt");
+        assert_eq!(
+            unique_name(&mut names, "d", MANIFEST_NAME.into()),
+            format!("d-{MANIFEST_NAME}")
+        );
+    }
+}
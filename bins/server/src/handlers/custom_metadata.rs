@@ -0,0 +1,76 @@
+//! Contains the `x-meta-*` headers carrying client-supplied file metadata.
+
+use axum::http::{HeaderMap, HeaderName};
+use std::collections::BTreeMap;
+
+/// The prefix of the headers carrying metadata. The remainder of the name is the key.
+pub const METADATA_HEADER_PREFIX: &str = "x-meta-";
+
+/// Collects the metadata from the `x-meta-*` headers of an upload, keyed by the header
+/// name without the prefix. Repeated headers are combined into a comma separated value.
+pub fn parse_metadata(
+    headers: &HeaderMap,
+    max_entries: usize,
+    max_bytes: usize,
+) -> Result<BTreeMap<String, String>, MetadataError> {
+    let mut metadata = BTreeMap::<String, String>::new();
+    for (name, value) in headers {
+        let Some(key) = name.as_str().strip_prefix(METADATA_HEADER_PREFIX) else {
+            continue;
+        };
+        if key.is_empty() {
+            continue;
+        }
+
+        let value = value
+            .to_str()
+            .map_err(|_| MetadataError::InvalidValue(name.to_string()))?;
+        metadata
+            .entry(key.to_string())
+            .and_modify(|existing| {
+                existing.push_str(", ");
+                existing.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+
+    if metadata.len() > max_entries {
+        return Err(MetadataError::TooManyEntries {
+            count: metadata.len(),
+            max: max_entries,
+        });
+    }
+
+    let bytes = metadata
+        .iter()
+        .map(|(key, value)| key.len() + value.len())
+        .sum();
+    if bytes > max_bytes {
+        return Err(MetadataError::TooLarge {
+            bytes,
+            max: max_bytes,
+        });
+    }
+
+    Ok(metadata)
+}
+
+/// Gets the `x-meta-*` headers echoing the metadata of a file.
+pub fn metadata_headers(
+    metadata: &BTreeMap<String, String>,
+) -> impl Iterator<Item = (HeaderName, String)> + '_ {
+    metadata.iter().filter_map(|(key, value)| {
+        let name = HeaderName::try_from(format!("{METADATA_HEADER_PREFIX}{key}")).ok()?;
+        Some((name, value.clone()))
+    })
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum MetadataError {
+    #[error("The value of the {0} header contains invalid characters")]
+    InvalidValue(String),
+    #[error("The upload has {count} metadata entries, exceeding the limit of {max}")]
+    TooManyEntries { count: usize, max: usize },
+    #[error("The metadata of the upload has {bytes} bytes, exceeding the limit of {max}")]
+    TooLarge { bytes: usize, max: usize },
+}
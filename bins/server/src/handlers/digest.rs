@@ -0,0 +1,140 @@
+//! Contains the `Digest` and `Want-Digest` headers of RFC 3230.
+
+use axum::http::{HeaderMap, HeaderName};
+use base64::Engine;
+use file_distribution::FileHashes;
+
+/// The header carrying the instance digests of a file.
+pub static DIGEST_HEADER: HeaderName = HeaderName::from_static("digest");
+
+/// The header requesting instance digests.
+pub static WANT_DIGEST_HEADER: HeaderName = HeaderName::from_static("want-digest");
+
+/// The digest algorithms supported in the `Digest` and `Want-Digest` headers.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DigestAlgorithm {
+    Md5,
+    Sha256,
+}
+
+impl DigestAlgorithm {
+    /// Gets the algorithm by its case-insensitive name, e.g. `SHA-256`.
+    fn from_name(name: &str) -> Option<Self> {
+        if name.eq_ignore_ascii_case("md5") {
+            Some(Self::Md5)
+        } else if name.eq_ignore_ascii_case("sha-256") {
+            Some(Self::Sha256)
+        } else {
+            None
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "md5",
+            DigestAlgorithm::Sha256 => "sha-256",
+        }
+    }
+}
+
+/// The hashes announced in a `Digest` request header.
+#[derive(Debug, Default)]
+pub struct ExpectedDigests {
+    pub md5: Option<[u8; 16]>,
+    pub sha256: Option<[u8; 32]>,
+}
+
+/// Gets the supported algorithms requested by the `Want-Digest` header, most preferred first.
+/// Algorithms with a quality of `0` are excluded.
+pub fn wanted_digests(headers: &HeaderMap) -> Vec<DigestAlgorithm> {
+    let mut wanted: Vec<(DigestAlgorithm, f32)> = Vec::new();
+    let entries = headers
+        .get_all(&WANT_DIGEST_HEADER)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+    for entry in entries {
+        let mut params = entry.split(';');
+        let Some(algorithm) = DigestAlgorithm::from_name(params.next().unwrap_or_default().trim())
+        else {
+            continue;
+        };
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+        if quality > 0.0 && !wanted.iter().any(|(a, _)| *a == algorithm) {
+            wanted.push((algorithm, quality));
+        }
+    }
+
+    // The sort is stable, so equally preferred algorithms keep their order.
+    wanted.sort_by(|a, b| b.1.total_cmp(&a.1));
+    wanted.into_iter().map(|(algorithm, _)| algorithm).collect()
+}
+
+/// Formats the `Digest` header for the requested algorithms, omitting those whose
+/// hash was not calculated. Returns `None` if none of them was.
+pub fn format_digests(algorithms: &[DigestAlgorithm], hashes: &FileHashes) -> Option<String> {
+    let engine = &base64::engine::general_purpose::STANDARD;
+    let digests: Vec<String> = algorithms
+        .iter()
+        .filter_map(|algorithm| {
+            let value = match algorithm {
+                DigestAlgorithm::Md5 => engine.encode(&hashes.md5.as_ref()?[..]),
+                DigestAlgorithm::Sha256 => engine.encode(&hashes.sha256.as_ref()?[..]),
+            };
+            Some(format!("{name}={value}", name = algorithm.name()))
+        })
+        .collect();
+
+    (!digests.is_empty()).then(|| digests.join(","))
+}
+
+/// Parses the hashes of the supported algorithms from the `Digest` header.
+/// Other algorithms are ignored.
+pub fn parse_digest_header(headers: &HeaderMap) -> Result<ExpectedDigests, String> {
+    let mut expected = ExpectedDigests::default();
+    for value in headers.get_all(&DIGEST_HEADER) {
+        let value = value.to_str().map_err(|e| e.to_string())?;
+        for entry in value.split(',') {
+            let Some((name, digest)) = entry.trim().split_once('=') else {
+                return Err(format!("Expected <algorithm>=<digest>, got {entry:?}"));
+            };
+            let Some(algorithm) = DigestAlgorithm::from_name(name.trim()) else {
+                continue;
+            };
+
+            let digest = base64::engine::general_purpose::STANDARD
+                .decode(digest.trim())
+                .map_err(|e| format!("Invalid {name} digest: {e}"))?;
+            let invalid_length = |_| {
+                format!(
+                    "Invalid {name} digest: unexpected length of {length} bytes",
+                    length = digest.len()
+                )
+            };
+            match algorithm {
+                DigestAlgorithm::Md5 => {
+                    expected.md5 = Some(digest.as_slice().try_into().map_err(invalid_length)?)
+                }
+                DigestAlgorithm::Sha256 => {
+                    expected.sha256 = Some(digest.as_slice().try_into().map_err(invalid_length)?)
+                }
+            }
+        }
+    }
+    Ok(expected)
+}
+
+/// Combines a hash announced in the `Digest` header with one announced in another header,
+/// failing if they disagree.
+pub fn merge_expected<const N: usize>(
+    digest: Option<[u8; N]>,
+    other: Option<[u8; N]>,
+) -> Result<Option<[u8; N]>, ()> {
+    match (digest, other) {
+        (Some(digest), Some(other)) if digest != other => Err(()),
+        (digest, other) => Ok(digest.or(other)),
+    }
+}
@@ -0,0 +1,41 @@
+//! Contains the extractor for file IDs in the configured format.
+
+use crate::handlers::negotiate_problem;
+use crate::AppState;
+use async_trait::async_trait;
+use axum::extract::{FromRequestParts, Path};
+use axum::http::request::Parts;
+use axum::response::{IntoResponse, Response};
+use hyper::StatusCode;
+use shortguid::ShortGuid;
+
+/// Extracts the `:id` path parameter, which must be in the configured ID format.
+///
+/// Malformed IDs are rejected with `400 Bad Request`.
+#[derive(Debug, Copy, Clone)]
+pub struct FileId(pub ShortGuid);
+
+#[async_trait]
+impl FromRequestParts<AppState> for FileId {
+    type Rejection = Response;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        state: &AppState,
+    ) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<String>::from_request_parts(parts, state)
+            .await
+            .map_err(IntoResponse::into_response)?;
+
+        match state.backbone.id_format().parse(&value) {
+            Some(id) => Ok(Self(id)),
+            None => {
+                let problem = problemdetails::new(StatusCode::BAD_REQUEST)
+                    .with_title("Invalid file ID")
+                    .with_detail(format!("{value:?} is not a valid file ID"))
+                    .with_value("id", value);
+                Err(negotiate_problem(problem, &parts.headers))
+            }
+        }
+    }
+}
@@ -1,11 +1,16 @@
 //! Contains the `/health` endpoint filter.
 
-use crate::health::HealthState;
+use crate::health::{AvailableSpace, HealthState};
+use crate::AppState;
 use axum::body::HttpBody;
+use axum::extract::State;
 use axum::response::{IntoResponse, Response};
 use axum::routing::{get, MethodRouter};
 use axum::Router;
+use hyper::StatusCode;
 use std::convert::Infallible;
+use tokio::task::spawn_blocking;
+use tracing::warn;
 
 /// Defines a type of health check.
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
@@ -53,16 +58,17 @@ pub trait HealthRoutes {
     /// ```http
     /// GET /healthz HTTP/1.1
     /// ```
+    ///
+    /// Readiness and combined health probes fail with `503 Service Unavailable` if the
+    /// temporary directory runs short of the configured free space or inodes.
     fn map_health_endpoints(self) -> Self;
 }
 
-impl<S, B> HealthRoutes for Router<S, B>
+impl<B> HealthRoutes for Router<AppState, B>
 where
-    S: Clone + Send + Sync + 'static,
     B: HttpBody + Send + 'static,
 {
     fn map_health_endpoints(self) -> Self {
-        // Ensure HttpCallMetricTracker is updated.
         self.route(
             "/health",
             health_endpoint(HealthCheck::Full(HealthCheckFormat::Compact)),
@@ -82,12 +88,11 @@ where
 /// ## Arguments
 /// * `path` - The path on which to host the handler, e.g. `health`, `readyz`, etc.
 /// * `checks` - The type of health check to run on that path.
-fn health_endpoint<S, B>(checks: HealthCheck) -> MethodRouter<S, B, Infallible>
+fn health_endpoint<B>(checks: HealthCheck) -> MethodRouter<AppState, B, Infallible>
 where
-    S: Clone + Send + Sync + 'static,
     B: HttpBody + Send + 'static,
 {
-    get(move || handle_health(checks))
+    get(move |State(state): State<AppState>| handle_health(checks, state))
 }
 
 /// Performs a health check.
@@ -95,19 +100,69 @@ where
 /// ```http
 /// GET /health
 /// ```
-async fn handle_health(checks: HealthCheck) -> Result<HealthState, Infallible> {
+async fn handle_health(checks: HealthCheck, state: AppState) -> Result<Response, Infallible> {
     // TODO: Actually implement health checks!
     match checks {
-        HealthCheck::Startup => Ok(HealthState::Healthy),
-        HealthCheck::Readiness => Ok(HealthState::Healthy),
-        HealthCheck::Liveness => Ok(HealthState::Healthy),
-        HealthCheck::Full(HealthCheckFormat::Compact) => Ok(HealthState::Healthy),
-        HealthCheck::Full(HealthCheckFormat::Complex) => Ok(HealthState::Healthy),
+        HealthCheck::Startup => Ok(HealthState::Healthy.into_response()),
+        HealthCheck::Readiness => Ok(check_temp_dir(&state).await.0.into_response()),
+        HealthCheck::Liveness => Ok(HealthState::Healthy.into_response()),
+        HealthCheck::Full(HealthCheckFormat::Compact) => {
+            Ok(check_temp_dir(&state).await.0.into_response())
+        }
+        HealthCheck::Full(HealthCheckFormat::Complex) => {
+            let (health, space) = check_temp_dir(&state).await;
+            let space = space.map_or_else(|| String::from("unknown"), |space| space.to_string());
+            let details = format!(
+                "{health}\ntemporary directory {path:?}: {space}",
+                path = std::env::temp_dir()
+            );
+            Ok((health.status_code(), details).into_response())
+        }
+    }
+}
+
+/// Checks whether the temporary directory has the configured space available.
+async fn check_temp_dir(state: &AppState) -> (HealthState, Option<AvailableSpace>) {
+    let config = &state.config.backbone;
+    let path = std::env::temp_dir();
+
+    // Querying the file system may block, e.g. on network mounts.
+    let space = spawn_blocking({
+        let path = path.clone();
+        move || AvailableSpace::of(&path)
+    })
+    .await
+    .unwrap_or_else(|e| Err(std::io::Error::new(std::io::ErrorKind::Other, e)));
+    let space = match space {
+        Ok(space) => space,
+        Err(e) => {
+            // Without the information, the node is assumed to be healthy.
+            warn!("Failed to determine the available space in {path:?}: {e}");
+            return (HealthState::Healthy, None);
+        }
+    };
+
+    let below = |available: u64, min: Option<u64>| matches!(min, Some(min) if available < min);
+    if below(space.bytes, config.min_free_bytes) || below(space.inodes, config.min_free_inodes) {
+        warn!("The temporary directory {path:?} is running out of space: {space}");
+        return (HealthState::Failed, Some(space));
+    }
+
+    (HealthState::Healthy, Some(space))
+}
+
+impl HealthState {
+    /// Gets the status code reported for the health state.
+    fn status_code(&self) -> StatusCode {
+        match self {
+            HealthState::Healthy | HealthState::Degraded => StatusCode::OK,
+            HealthState::Failed => StatusCode::SERVICE_UNAVAILABLE,
+        }
     }
 }
 
 impl IntoResponse for HealthState {
     fn into_response(self) -> Response {
-        format!("{}", self).into_response()
+        (self.status_code(), format!("{}", self)).into_response()
     }
 }
@@ -0,0 +1,104 @@
+//! Contains the `/meta` endpoint filter.
+
+use crate::expiration_as_rfc1123;
+use crate::handlers::file_id::FileId;
+use crate::handlers::yeet::{Distribution, Hashes};
+use crate::handlers::yoink::map_file_reader_error_to_response;
+use crate::AppState;
+use axum::body::HttpBody;
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use file_distribution::FileReaderTrait;
+use hyper::StatusCode;
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+pub trait MetaRoutes {
+    /// Provides an API for obtaining file metadata, including the
+    /// per-backend distribution status.
+    ///
+    /// ```http
+    /// GET /meta/KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// ```
+    fn map_meta_endpoint(self) -> Self;
+}
+
+impl<B> MetaRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + Sync + 'static,
+{
+    fn map_meta_endpoint(self) -> Self {
+        self.route("/meta/:id", get(do_meta))
+    }
+}
+
+async fn do_meta(
+    FileId(id): FileId,
+    State(state): State<AppState>,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    let file = match state.backbone.get_file(id).await {
+        Ok(file) => file,
+        Err(e) => {
+            return Ok(map_file_reader_error_to_response(
+                e,
+                state.backbone.id_format(),
+                &request_headers,
+            ))
+        }
+    };
+
+    let summary = file.summary();
+    let response = FileMetadataResponse {
+        id: state.backbone.id_format().format(id),
+        file_size_bytes: summary.as_ref().map(|s| s.file_size_bytes),
+        file_name: summary.as_ref().and_then(|s| s.file_name.clone()),
+        content_type: file.content_type().map(|c| c.to_string()),
+        expires: expiration_as_rfc1123(&file.expiration_date()),
+        hashes: summary.as_ref().map(|s| (&s.hashes).into()),
+        metadata: summary
+            .as_ref()
+            .map(|s| s.metadata.clone())
+            .unwrap_or_default(),
+        distribution: state
+            .distribution
+            .get_or_pending(&id)
+            .iter()
+            .map(Distribution::from)
+            .collect(),
+        quorum: state
+            .distribution
+            .quorum_state(&id)
+            .map(|quorum| quorum.to_string()),
+    };
+
+    Ok(axum::Json(response).into_response())
+}
+
+#[derive(Serialize)]
+struct FileMetadataResponse {
+    /// The ID of the file, in the configured format.
+    id: String,
+    /// The file size in bytes; `None` while the file is still being written.
+    file_size_bytes: Option<usize>,
+    /// The optional file name.
+    file_name: Option<String>,
+    /// The optional content type.
+    content_type: Option<String>,
+    /// The expiration date of the file in RFC 1123 format.
+    expires: String,
+    /// The hashes of the file; `None` while the file is still being written.
+    hashes: Option<Hashes>,
+    /// The metadata supplied in the `x-meta-*` headers on upload, keyed by name;
+    /// empty while the file is still being written.
+    metadata: BTreeMap<String, String>,
+    /// The per-backend distribution state of the file.
+    distribution: Vec<Distribution>,
+    /// Whether the write quorum was met, i.e. `pending`, `met` or `failed`;
+    /// omitted if no quorum is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quorum: Option<String>,
+}
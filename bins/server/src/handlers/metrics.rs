@@ -1,9 +1,17 @@
 //! Contains the `/metrics` endpoint filter.
 
+use crate::AppState;
 use axum::body::HttpBody;
+use axum::extract::State;
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
 use axum::routing::get;
 use axum::Router;
+use flate2::write::GzEncoder;
+use flate2::Compression;
 use metrics::Metrics;
+use std::io::Write;
+use tracing::warn;
 
 pub trait MetricsRoutes {
     /// Provides an API for Prometheus/OpenMetrics metrics.
@@ -11,20 +19,82 @@ pub trait MetricsRoutes {
     /// ```http
     /// GET /metrics HTTP/1.1
     /// ```
+    ///
+    /// Responses of at least `server.metrics_gzip_min_bytes` are gzip compressed
+    /// if the scraper sends `Accept-Encoding: gzip`.
     fn map_metrics_endpoint(self) -> Self;
 }
 
-impl<S, B> MetricsRoutes for Router<S, B>
+impl<B> MetricsRoutes for Router<AppState, B>
 where
-    S: Clone + Send + Sync + 'static,
     B: HttpBody + Send + 'static,
 {
-    // Ensure HttpCallMetricTracker is updated.
     fn map_metrics_endpoint(self) -> Self {
         self.route("/metrics", get(render_metrics))
     }
 }
 
-async fn render_metrics() -> String {
-    Metrics::get().encode()
+async fn render_metrics(State(state): State<AppState>, request_headers: HeaderMap) -> Response {
+    let metrics = Metrics::get().encode();
+    let vary = (header::VARY, HeaderValue::from_static("accept-encoding"));
+
+    if metrics.len() < state.config.server.metrics_gzip_min_bytes()
+        || !accepts_gzip(&request_headers)
+    {
+        return ([vary], metrics).into_response();
+    }
+
+    match gzip(metrics.as_bytes()) {
+        Ok(compressed) => (
+            [
+                vary,
+                (
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("text/plain; charset=utf-8"),
+                ),
+                (header::CONTENT_ENCODING, HeaderValue::from_static("gzip")),
+            ],
+            compressed,
+        )
+            .into_response(),
+        Err(e) => {
+            warn!("Failed to compress the metrics; sending them uncompressed: {e}");
+            ([vary], metrics).into_response()
+        }
+    }
+}
+
+/// Determines whether the `Accept-Encoding` header accepts gzip, either explicitly
+/// or using the `*` wildcard.
+fn accepts_gzip(request_headers: &HeaderMap) -> bool {
+    let mut gzip = None;
+    let mut wildcard = None;
+
+    let codings = request_headers
+        .get_all(header::ACCEPT_ENCODING)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
+    for coding in codings {
+        let mut params = coding.split(';');
+        let name = params.next().unwrap_or_default().trim();
+        let quality = params
+            .filter_map(|param| param.trim().strip_prefix("q="))
+            .find_map(|q| q.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if name.eq_ignore_ascii_case("gzip") || name.eq_ignore_ascii_case("x-gzip") {
+            gzip = Some(quality);
+        } else if name == "*" {
+            wildcard = Some(quality);
+        }
+    }
+
+    gzip.or(wildcard).map_or(false, |quality| quality > 0.0)
+}
+
+fn gzip(data: &[u8]) -> std::io::Result<Vec<u8>> {
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 4), Compression::fast());
+    encoder.write_all(data)?;
+    encoder.finish()
 }
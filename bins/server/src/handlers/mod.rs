@@ -1,15 +1,33 @@
 //! Contains warp filters.
 
+mod admin;
+mod bundle;
+mod custom_metadata;
+mod digest;
+mod file_id;
 mod health;
+mod meta;
 mod metrics;
+mod negotiation;
+mod openapi;
+mod progress;
 mod shutdown;
 mod yeet;
 mod yoink;
 
+pub use admin::AdminRoutes;
+use axum::response::{IntoResponse, Response};
+pub use bundle::BundleRoutes;
 use chrono::{DateTime, Utc};
 pub use health::HealthRoutes;
+use hyper::StatusCode;
+pub use meta::MetaRoutes;
 pub use metrics::MetricsRoutes;
+pub(crate) use negotiation::negotiate_problem;
+pub use openapi::OpenApiRoutes;
+pub use progress::ProgressRoutes;
 pub use shutdown::ShutdownRoutes;
+pub(crate) use yeet::Hashes;
 pub use yeet::YeetRoutes;
 pub use yoink::YoinkRoutes;
 
@@ -21,3 +39,12 @@ pub fn expiration_as_rfc1123(expires: &tokio::time::Instant) -> String {
         .format("%a, %d %b %Y %H:%M:%S GMT")
         .to_string()
 }
+
+/// Rejects a request arriving while the server drains its connections during shutdown,
+/// so that the client can retry with another instance.
+pub fn shutting_down_response() -> Response {
+    problemdetails::new(StatusCode::SERVICE_UNAVAILABLE)
+        .with_title("Shutting down")
+        .with_detail("The service is shutting down; please retry with another instance")
+        .into_response()
+}
@@ -0,0 +1,142 @@
+//! Contains the content negotiation of error responses.
+
+use axum::http::{header, HeaderMap, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use problemdetails::Problem;
+
+/// The representations of an error response, in order of preference if the client
+/// accepts several of them equally.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ErrorFormat {
+    /// Problem details as `application/problem+json`.
+    ProblemJson,
+    /// A minimal HTML page.
+    Html,
+    /// A plain text message.
+    PlainText,
+}
+
+impl ErrorFormat {
+    /// Selects the representation preferred by the client's `Accept` header, falling back
+    /// to problem details if it accepts none of them or sent no `Accept` header.
+    pub fn from_headers(request_headers: &HeaderMap) -> Self {
+        let mut best = (Self::ProblemJson, 0.0);
+
+        let ranges = request_headers
+            .get_all(header::ACCEPT)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','));
+        for range in ranges {
+            let mut params = range.split(';');
+            let media_range = params
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .to_ascii_lowercase();
+            let quality = params
+                .filter_map(|param| param.trim().strip_prefix("q="))
+                .find_map(|q| q.trim().parse::<f32>().ok())
+                .unwrap_or(1.0);
+            if quality <= 0.0 {
+                continue;
+            }
+
+            let format = match media_range.as_str() {
+                "application/problem+json" | "application/json" | "application/*" | "*/*" => {
+                    Self::ProblemJson
+                }
+                "text/html" | "application/xhtml+xml" => Self::Html,
+                "text/plain" => Self::PlainText,
+                _ => continue,
+            };
+
+            // Ties are resolved in favor of the earlier variant.
+            if quality > best.1 || (quality == best.1 && (format as u8) < (best.0 as u8)) {
+                best = (format, quality);
+            }
+        }
+
+        best.0
+    }
+}
+
+/// Renders the problem in the representation preferred by the client.
+///
+/// Since the response depends on the `Accept` request header, `Vary` is always set.
+pub fn negotiate_problem(problem: Problem, request_headers: &HeaderMap) -> Response {
+    let format = ErrorFormat::from_headers(request_headers);
+    let mut response = match format {
+        ErrorFormat::ProblemJson => problem.into_response(),
+        ErrorFormat::Html => render_html(&problem),
+        ErrorFormat::PlainText => render_text(&problem),
+    };
+
+    response
+        .headers_mut()
+        .append(header::VARY, HeaderValue::from_static("accept"));
+    response
+}
+
+/// Gets the title and detail of the problem, defaulting to the reason phrase of the status.
+fn title_and_detail(problem: &Problem) -> (String, Option<&str>) {
+    let title = problem
+        .body
+        .get("title")
+        .and_then(|value| value.as_str())
+        .map(String::from)
+        .or_else(|| problem.status_code.canonical_reason().map(String::from))
+        .unwrap_or_default();
+    let detail = problem.body.get("detail").and_then(|value| value.as_str());
+    (title, detail)
+}
+
+fn render_html(problem: &Problem) -> Response {
+    let (title, detail) = title_and_detail(problem);
+    let status = problem.status_code.as_u16();
+    let title = escape_html(&title);
+    let detail = detail
+        .map(|detail| format!("<p>{}</p>", escape_html(detail)))
+        .unwrap_or_default();
+    let body = format!(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>{status} {title}</title></head>\
+         <body><h1>{title}</h1>{detail}</body></html>\n"
+    );
+
+    (
+        problem.status_code,
+        [(header::CONTENT_TYPE, "text/html; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn render_text(problem: &Problem) -> Response {
+    let (title, detail) = title_and_detail(problem);
+    let body = match detail {
+        Some(detail) => format!("{title}\n\n{detail}\n"),
+        None => format!("{title}\n"),
+    };
+
+    (
+        problem.status_code,
+        [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+        body,
+    )
+        .into_response()
+}
+
+fn escape_html(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
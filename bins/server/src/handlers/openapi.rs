@@ -0,0 +1,245 @@
+//! Contains the `/openapi.json` endpoint filter.
+
+use crate::handlers::yeet::{Hashes, ReservationResponse, SuccessfulUploadResponse};
+use axum::body::HttpBody;
+use axum::routing::get;
+use axum::{Json, Router};
+use schemars::gen::SchemaSettings;
+use schemars::JsonSchema;
+use serde_json::{json, Value};
+
+pub trait OpenApiRoutes {
+    /// Provides an OpenAPI 3 description of the API.
+    ///
+    /// ```http
+    /// GET /openapi.json HTTP/1.1
+    /// ```
+    fn map_openapi_endpoint(self) -> Self;
+}
+
+impl<S, B> OpenApiRoutes for Router<S, B>
+where
+    S: Clone + Send + Sync + 'static,
+    B: HttpBody + Send + 'static,
+{
+    fn map_openapi_endpoint(self) -> Self {
+        self.route("/openapi.json", get(render_openapi))
+    }
+}
+
+/// An RFC 7807 problem details object, as returned for all errors.
+///
+/// Some errors add further members, e.g. `expected_bytes` and `received_bytes`
+/// for uploads not matching their `Content-Length`.
+#[derive(JsonSchema)]
+#[allow(dead_code)]
+struct ProblemDetails {
+    /// A URI identifying the problem type.
+    #[serde(rename = "type")]
+    problem_type: Option<String>,
+    /// A short summary of the problem type.
+    title: Option<String>,
+    /// The HTTP status code.
+    status: Option<u16>,
+    /// An explanation specific to this occurrence of the problem.
+    detail: Option<String>,
+    /// A URI identifying this occurrence of the problem.
+    instance: Option<String>,
+}
+
+async fn render_openapi() -> Json<Value> {
+    Json(document())
+}
+
+/// Builds the OpenAPI document, deriving the schemas from the response types.
+fn document() -> Value {
+    let mut generator = SchemaSettings::openapi3().into_generator();
+    let upload_response = generator.subschema_for::<SuccessfulUploadResponse>();
+    let reservation_response = generator.subschema_for::<ReservationResponse>();
+    let hashes = generator.subschema_for::<Hashes>();
+    let problem = generator.subschema_for::<ProblemDetails>();
+    let schemas = generator.take_definitions();
+
+    let problem_response = |description: &str| {
+        json!({
+            "description": description,
+            "content": { "application/problem+json": { "schema": problem } }
+        })
+    };
+    let string_header =
+        |description: &str| json!({ "description": description, "schema": { "type": "string" } });
+    let binary = json!({ "schema": { "type": "string", "format": "binary" } });
+    let text = json!({ "schema": { "type": "string" } });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "yeet-yoink",
+            "description": env!("CARGO_PKG_DESCRIPTION"),
+            "version": env!("CARGO_PKG_VERSION")
+        },
+        "paths": {
+            "/yeet": {
+                "post": {
+                    "summary": "Stores a file",
+                    "parameters": [
+                        {
+                            "name": "id",
+                            "in": "query",
+                            "description": "The ID reserved for the upload using `/yeet/reserve`",
+                            "schema": { "type": "string" }
+                        },
+                        {
+                            "name": "yy-digests",
+                            "in": "header",
+                            "description": "The digests to calculate, overriding the configured ones, e.g. `sha256`",
+                            "schema": { "type": "string" }
+                        },
+                        {
+                            "name": "yy-expected-sha256",
+                            "in": "header",
+                            "description": "The hex encoded SHA-256 hash the upload is verified against",
+                            "schema": { "type": "string" }
+                        },
+                        {
+                            "name": "Digest",
+                            "in": "header",
+                            "description": "The base64 encoded `md5` and/or `sha-256` digests the upload is verified against, e.g. `sha-256=...`",
+                            "schema": { "type": "string" }
+                        },
+                        {
+                            "name": "x-meta-*",
+                            "in": "header",
+                            "description": "Metadata stored with the file and returned on download, e.g. `x-meta-owner`",
+                            "schema": { "type": "string" }
+                        }
+                    ],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "*/*": binary }
+                    },
+                    "responses": {
+                        "201": {
+                            "description": "The file was stored",
+                            "headers": {
+                                "yy-id": string_header("The ID of the file"),
+                                "Expires": string_header("The time at which the file expires")
+                            },
+                            "content": { "application/json": { "schema": upload_response } }
+                        },
+                        "400": problem_response("The request or its headers are invalid, the body is malformed, or the content doesn't match its `Content-Length` or expected hash"),
+                        "404": problem_response("The requested ID is not reserved or its reservation expired"),
+                        "409": problem_response("A file with the assigned ID already exists or is being written"),
+                        "413": problem_response("The file exceeds the maximum file size"),
+                        "415": problem_response("The content type is not accepted"),
+                        "431": problem_response("The `x-meta-*` headers exceed the configured limits"),
+                        "500": problem_response("The file could not be stored"),
+                        "503": problem_response("The service is overloaded, shutting down or not accepting uploads")
+                    }
+                }
+            },
+            "/yeet/reserve": {
+                "post": {
+                    "summary": "Reserves an ID for an upload, e.g. to follow its progress at `/yeet/{id}/progress` while it is streamed",
+                    "responses": {
+                        "201": {
+                            "description": "The ID was reserved",
+                            "headers": {
+                                "yy-id": string_header("The reserved ID"),
+                                "Expires": string_header("The time until which the upload must be started")
+                            },
+                            "content": { "application/json": { "schema": reservation_response } }
+                        },
+                        "503": problem_response("Too many IDs are reserved or the service is shutting down")
+                    }
+                }
+            },
+            "/yoink/{id}": {
+                "get": {
+                    "summary": "Retrieves a file",
+                    "parameters": [
+                        {
+                            "name": "id",
+                            "in": "path",
+                            "required": true,
+                            "description": "The ID of the file",
+                            "schema": { "type": "string" }
+                        },
+                        {
+                            "name": "Want-Digest",
+                            "in": "header",
+                            "description": "The digests to send in the `Digest` header, e.g. `sha-256, md5;q=0.5`",
+                            "schema": { "type": "string" }
+                        },
+                        {
+                            "name": "raw",
+                            "in": "query",
+                            "description": "Whether to send only the `Content-Type` and `Content-Length` headers",
+                            "schema": { "type": "boolean", "default": false }
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The file contents",
+                            "headers": {
+                                "ETag": string_header("The entity tag of the file"),
+                                "Last-Modified": string_header("The time at which the file was created"),
+                                "Expires": string_header("The time at which the file expires"),
+                                "Digest": string_header("The base64 encoded digests requested by `Want-Digest`, if calculated"),
+                                "yy-file-md5": string_header("The hex encoded MD5 hash of the file"),
+                                "yy-file-sha256": string_header("The hex encoded SHA-256 hash of the file"),
+                                "x-meta-*": string_header("The metadata supplied on upload")
+                            },
+                            "content": { "*/*": binary }
+                        },
+                        "304": { "description": "The file matches the `If-None-Match` header or was not modified since the `If-Modified-Since` date" },
+                        "404": problem_response("The file is not known"),
+                        "410": problem_response("The file has expired"),
+                        "500": problem_response("The file could not be read")
+                    }
+                }
+            },
+            "/yoink/{id}/hashes": {
+                "get": {
+                    "summary": "Retrieves the digests of a file",
+                    "parameters": [
+                        {
+                            "name": "id",
+                            "in": "path",
+                            "required": true,
+                            "description": "The ID of the file",
+                            "schema": { "type": "string" }
+                        }
+                    ],
+                    "responses": {
+                        "200": {
+                            "description": "The hex encoded digests calculated for the file",
+                            "content": { "application/json": { "schema": hashes } }
+                        },
+                        "202": { "description": "The file is still being uploaded" },
+                        "404": problem_response("The file is not known"),
+                        "410": problem_response("The file has expired")
+                    }
+                }
+            },
+            "/health": {
+                "get": {
+                    "summary": "Checks the health of the service",
+                    "responses": {
+                        "200": { "description": "The service is healthy", "content": { "text/plain": text } },
+                        "503": { "description": "The service is unhealthy", "content": { "text/plain": text } }
+                    }
+                }
+            },
+            "/metrics": {
+                "get": {
+                    "summary": "Renders the metrics in the OpenMetrics text format",
+                    "responses": {
+                        "200": { "description": "The metrics", "content": { "text/plain": text } }
+                    }
+                }
+            }
+        },
+        "components": { "schemas": schemas }
+    })
+}
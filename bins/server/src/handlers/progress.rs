@@ -0,0 +1,118 @@
+//! Contains the `/yeet/:id/progress` endpoint filter.
+
+use crate::handlers::file_id::FileId;
+use crate::AppState;
+use axum::body::HttpBody;
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::State;
+use axum::response::{IntoResponse, Response};
+use axum::routing::get;
+use axum::Router;
+use backbone::{UploadProgress, UploadProgressReceiver};
+use hyper::StatusCode;
+use serde::Serialize;
+use shortguid::ShortGuid;
+use tracing::debug;
+
+pub trait ProgressRoutes {
+    /// Provides a WebSocket API for observing the progress of an upload.
+    ///
+    /// Each message is a JSON object describing the current state of the upload;
+    /// the socket is closed once the upload completed or failed.
+    ///
+    /// ```http
+    /// GET /yeet/KmC6e8laTnK3dioUSMpM0Q/progress HTTP/1.1
+    /// Connection: Upgrade
+    /// Upgrade: websocket
+    /// ```
+    fn map_progress_endpoint(self) -> Self;
+}
+
+impl<B> ProgressRoutes for Router<AppState, B>
+where
+    B: HttpBody + Send + Sync + 'static,
+{
+    fn map_progress_endpoint(self) -> Self {
+        self.route("/yeet/:id/progress", get(do_progress))
+    }
+}
+
+async fn do_progress(
+    FileId(id): FileId,
+    State(state): State<AppState>,
+    ws: WebSocketUpgrade,
+) -> Response {
+    let Some(progress) = state.backbone.subscribe_progress(id).await else {
+        let id = state.backbone.id_format().format(id);
+        return problemdetails::new(StatusCode::NOT_FOUND)
+            .with_title("File not found")
+            .with_detail(format!("The file with ID {id} could not be found"))
+            .with_instance(format!("/yeet/{id}/progress"))
+            .with_value("id", id)
+            .into_response();
+    };
+
+    ws.on_upgrade(move |socket| stream_progress(id, socket, progress))
+}
+
+/// Sends the progress of the upload until it completes, fails or the client disconnects.
+async fn stream_progress(
+    id: ShortGuid,
+    mut socket: WebSocket,
+    mut progress: UploadProgressReceiver,
+) {
+    loop {
+        let current = *progress.borrow_and_update();
+        let message = serde_json::to_string(&ProgressMessage::from(current))
+            .expect("failed to serialize progress");
+        if socket.send(Message::Text(message)).await.is_err() {
+            debug!(file_id = %id, "Progress subscriber disconnected");
+            return;
+        }
+
+        if !matches!(current, UploadProgress::Pending { .. }) {
+            break;
+        }
+
+        // The sender is dropped without a final update only if the upload was abandoned.
+        if progress.changed().await.is_err() {
+            break;
+        }
+    }
+
+    socket.close().await.ok();
+}
+
+#[derive(Serialize)]
+#[serde(tag = "state", rename_all = "lowercase")]
+enum ProgressMessage {
+    /// The upload is in progress.
+    Pending {
+        /// The number of bytes buffered so far.
+        buffered_bytes: u64,
+        /// The announced size of the upload, if known.
+        expected_bytes: Option<u64>,
+    },
+    /// The upload completed.
+    Completed {
+        /// The file size in bytes.
+        file_size_bytes: u64,
+    },
+    /// The upload failed.
+    Failed,
+}
+
+impl From<UploadProgress> for ProgressMessage {
+    fn from(value: UploadProgress) -> Self {
+        match value {
+            UploadProgress::Pending { buffered, expected } => Self::Pending {
+                buffered_bytes: buffered,
+                expected_bytes: expected,
+            },
+            UploadProgress::Completed(size) => Self::Completed {
+                file_size_bytes: size,
+            },
+            UploadProgress::Failed => Self::Failed,
+        }
+    }
+}
@@ -1,29 +1,48 @@
 //! Contains the `/yeet` endpoint filter.
 
+use crate::event_sink::TransferDetails;
 use crate::expiration_as_rfc1123;
+use crate::handlers::custom_metadata::{self, MetadataError};
+use crate::handlers::digest::{self, DIGEST_HEADER};
+use crate::handlers::shutting_down_response;
 use crate::AppState;
+use app_config::RejectionPolicy;
 use axum::body::HttpBody;
 use axum::extract::{BodyStream, Query, State, TypedHeader};
 use axum::headers::{ContentLength, ContentType};
-use axum::http::{HeaderName, HeaderValue};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
 use axum::response::{IntoResponse, Response};
 use axum::routing::post;
 use axum::Router;
-use backbone::{CompletionMode, NewFileError};
+use backbone::{CompletionMode, ExpectedContent, FileIdFormat, FinalizationError, NewFileError};
+use backend_traits::{BackendDistribution, QuorumState};
+use bytes::{Bytes, BytesMut};
+use file_distribution::hash::DigestSet;
 use file_distribution::FileHashes;
 use headers_content_md5::ContentMd5;
 use hyper::body::Buf;
-use hyper::header::EXPIRES;
+use hyper::header::{EXPECT, EXPIRES, RETRY_AFTER};
 use hyper::StatusCode;
 use metrics::transfer::TransferMethod;
 use metrics::transfer::TransferMetrics;
+use schemars::JsonSchema;
 use serde::Serialize;
-use shortguid::ShortGuid;
+use std::time::Duration;
+use tokio::time::Instant;
 use tokio_stream::StreamExt;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 
 static ID_HEADER: HeaderName = HeaderName::from_static("yy-id");
 
+/// The header selecting the digests to calculate for the upload, e.g. `yy-digests: sha256`.
+static DIGESTS_HEADER: HeaderName = HeaderName::from_static("yy-digests");
+
+/// The header specifying the hex encoded SHA-256 hash the upload is verified against.
+static EXPECTED_SHA256_HEADER: HeaderName = HeaderName::from_static("yy-expected-sha256");
+
+/// The number of seconds after which clients should retry uploads rejected due to overload.
+const OVERLOADED_RETRY_AFTER_SECS: u32 = 1;
+
 pub trait YeetRoutes {
     /// Provides an API for storing files.
     ///
@@ -34,6 +53,26 @@ pub trait YeetRoutes {
     ///
     /// your-data
     /// ```
+    ///
+    /// The optional `yy-digests` header overrides the configured digests to calculate,
+    /// e.g. `yy-digests: sha256` to skip the MD5 digest.
+    ///
+    /// The optional `yy-expected-sha256` header specifies the hex encoded SHA-256 hash of the
+    /// content; uploads not matching it are rejected. If the `Content-Length` is known as well,
+    /// the hash is verified as soon as the announced number of bytes was received.
+    ///
+    /// Headers prefixed with `x-meta-` are stored with the file and returned on download.
+    ///
+    /// To follow the progress of the upload while it is streamed, reserve an ID first and
+    /// subscribe to `/yeet/:id/progress` before uploading to it:
+    ///
+    /// ```http
+    /// POST /yeet/reserve HTTP/1.1
+    /// ```
+    ///
+    /// ```http
+    /// POST /yeet?id=KmC6e8laTnK3dioUSMpM0Q HTTP/1.1
+    /// ```
     fn map_yeet_endpoint(self) -> Self;
 }
 
@@ -43,15 +82,57 @@ where
     axum::body::Bytes: From<<B as HttpBody>::Data>,
     <B as HttpBody>::Error: std::error::Error + Send + Sync,
 {
-    // Ensure HttpCallMetricTracker is updated.
     fn map_yeet_endpoint(self) -> Self {
         self.route("/yeet", post(do_yeet))
+            .route("/yeet/reserve", post(do_reserve))
     }
 }
 
 #[derive(Debug, serde::Deserialize)]
 struct QueryParams {
     file_name: Option<String>,
+    /// The ID reserved for the upload using `/yeet/reserve`.
+    id: Option<String>,
+}
+
+/// Reserves an ID for an upload, so that its progress can be subscribed to before the
+/// upload starts.
+async fn do_reserve(State(state): State<AppState>) -> Response {
+    if state.is_shutting_down() {
+        return shutting_down_response();
+    }
+
+    let Some((id, expires)) = state.backbone.reserve_id() else {
+        let mut response = problemdetails::new(StatusCode::SERVICE_UNAVAILABLE)
+            .with_title("Too many reservations")
+            .with_detail("Too many IDs are reserved at the moment; please retry later")
+            .into_response();
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(OVERLOADED_RETRY_AFTER_SECS));
+        return response;
+    };
+
+    let id = state.backbone.id_format().format(id);
+    let mut response = (
+        StatusCode::CREATED,
+        axum::Json(ReservationResponse {
+            progress: format!("/yeet/{id}/progress"),
+            id: id.clone(),
+        }),
+    )
+        .into_response();
+    let headers = response.headers_mut();
+    headers.insert(
+        EXPIRES,
+        HeaderValue::from_str(&expiration_as_rfc1123(&expires))
+            .expect("invalid time input provided"),
+    );
+    headers.insert(
+        &ID_HEADER,
+        HeaderValue::from_str(&id).expect("invalid ID input provided"),
+    );
+    response
 }
 
 #[axum::debug_handler]
@@ -59,12 +140,44 @@ async fn do_yeet(
     content_length: Option<TypedHeader<ContentLength>>,
     content_type: Option<TypedHeader<ContentType>>,
     content_md5: Option<TypedHeader<ContentMd5>>,
+    headers: HeaderMap,
     State(state): State<AppState>,
     query: Query<QueryParams>,
     stream: BodyStream,
 ) -> Result<Response, StatusCode> {
+    if state.is_shutting_down() {
+        return Ok(shutting_down_response());
+    }
+
+    if state.are_uploads_paused() {
+        return Ok(problemdetails::new(StatusCode::SERVICE_UNAVAILABLE)
+            .with_title("Uploads paused")
+            .with_detail("The service currently doesn't accept new uploads; please retry later or with another instance")
+            .into_response());
+    }
+
     TransferMetrics::track_transfer(TransferMethod::Store);
 
+    // Clients sending `Expect: 100-continue` wait for a provisional response before
+    // transmitting the body. Since `100 Continue` is only sent once the body is first
+    // read, all checks up to the start of streaming reject the upload without the body
+    // being transferred.
+    if let Some(expect) = headers.get(EXPECT) {
+        if !expect.as_bytes().eq_ignore_ascii_case(b"100-continue") {
+            return Ok(problemdetails::new(StatusCode::EXPECTATION_FAILED)
+                .with_title("Expectation failed")
+                .with_detail("Only the 100-continue expectation is supported")
+                .into_response());
+        }
+    }
+
+    if state.config.server.require_backend && !state.backbone.has_backends() {
+        return Ok(problemdetails::new(StatusCode::SERVICE_UNAVAILABLE)
+            .with_title("No backend available")
+            .with_detail("No backend is registered to durably store the file")
+            .into_response());
+    }
+
     let content_length = if let Some(TypedHeader(ContentLength(n))) = content_length {
         trace!("Expecting {value} bytes", value = n);
         Some(n)
@@ -72,13 +185,38 @@ async fn do_yeet(
         None
     };
 
+    // Uploads of known size can be rejected before reading any data. For chunked uploads,
+    // the limit is enforced while streaming.
+    let max_upload_bytes = state.config.server.max_upload_bytes;
+    if let (Some(max), Some(n)) = (max_upload_bytes, content_length) {
+        if n > max {
+            return Ok(payload_too_large_response(None, max));
+        }
+    }
+
     let content_type = if let Some(TypedHeader(content_type)) = content_type {
         trace!("Expecting MIME type {value}", value = content_type);
-        Some(content_type)
+        content_type
     } else {
-        None
+        trace!(
+            "No MIME type specified, assuming {value}",
+            value = state.default_content_type
+        );
+        state.default_content_type.clone()
     };
 
+    if !state
+        .config
+        .server
+        .is_content_type_allowed(&content_type.to_string())
+    {
+        return Ok(problemdetails::new(StatusCode::UNSUPPORTED_MEDIA_TYPE)
+            .with_title("Unsupported media type")
+            .with_detail(format!("Uploads of type {content_type} are not accepted"))
+            .with_value("content_type", content_type.to_string())
+            .into_response());
+    }
+
     let content_md5 = if let Some(TypedHeader(ContentMd5(md5))) = content_md5 {
         trace!("Expecting content MD5 {value}", value = hex::encode(md5));
         Some(md5)
@@ -86,7 +224,91 @@ async fn do_yeet(
         None
     };
 
-    let id = ShortGuid::new_random();
+    let digests = match parse_digests(&headers) {
+        Ok(digests) => digests,
+        Err(e) => {
+            return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                .with_title("Invalid digest selection")
+                .with_detail(format!("The {DIGESTS_HEADER} header is invalid: {e}"))
+                .with_value("error", e)
+                .into_response())
+        }
+    };
+
+    let expected_sha256 = match parse_expected_sha256(&headers) {
+        Ok(sha256) => sha256,
+        Err(e) => {
+            return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                .with_title("Invalid expected hash")
+                .with_detail(format!(
+                    "The {EXPECTED_SHA256_HEADER} header is invalid: {e}"
+                ))
+                .with_value("error", e)
+                .into_response())
+        }
+    };
+
+    let expected_digests = match digest::parse_digest_header(&headers) {
+        Ok(digests) => digests,
+        Err(e) => {
+            return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                .with_title("Invalid expected hash")
+                .with_detail(format!("The {DIGEST_HEADER} header is invalid: {e}"))
+                .with_value("error", e)
+                .into_response())
+        }
+    };
+
+    let (Ok(content_md5), Ok(expected_sha256)) = (
+        digest::merge_expected(expected_digests.md5, content_md5),
+        digest::merge_expected(expected_digests.sha256, expected_sha256),
+    ) else {
+        return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+            .with_title("Conflicting expected hashes")
+            .with_detail(format!(
+                "The {DIGEST_HEADER} header disagrees with the Content-MD5 or {EXPECTED_SHA256_HEADER} header"
+            ))
+            .into_response());
+    };
+
+    let metadata = match custom_metadata::parse_metadata(
+        &headers,
+        state.config.server.max_metadata_entries(),
+        state.config.server.max_metadata_bytes(),
+    ) {
+        Ok(metadata) => metadata,
+        Err(e @ MetadataError::InvalidValue(_)) => {
+            return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                .with_title("Invalid metadata")
+                .with_detail(e.to_string())
+                .into_response())
+        }
+        Err(e) => {
+            return Ok(
+                problemdetails::new(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+                    .with_title("Too much metadata")
+                    .with_detail(e.to_string())
+                    .into_response(),
+            )
+        }
+    };
+
+    let id_format = *state.backbone.id_format();
+    let id = match query.id.as_deref() {
+        Some(value) => match id_format.parse(value) {
+            Some(id) if state.backbone.is_reserved(id) => id,
+            _ => {
+                return Ok(problemdetails::new(StatusCode::NOT_FOUND)
+                    .with_title("Reservation not found")
+                    .with_detail(format!(
+                        "The ID {value:?} is not reserved or its reservation expired"
+                    ))
+                    .with_value("id", value)
+                    .into_response())
+            }
+        },
+        None => state.backbone.new_id(),
+    };
 
     // TODO: Allow capacity? Test whether we have enough resources?
 
@@ -94,32 +316,116 @@ async fn do_yeet(
         .backbone
         .new_file(
             id,
-            content_length,
-            content_type,
-            content_md5,
+            ExpectedContent {
+                size: content_length,
+                md5: content_md5,
+                sha256: expected_sha256,
+            },
+            Some(content_type.clone()),
             query.file_name.clone(),
+            metadata,
+            digests,
         )
         .await
     {
         Ok(writer) => writer,
-        Err(e) => return Ok(map_new_file_error_to_response(e)),
+        Err(e) => return Ok(map_new_file_error_to_response(e, &id_format)),
     };
 
     let mut stream = Box::pin(stream);
 
+    // The timeout applies to each chunk, so it only triggers if no data arrives for that long.
+    let body_read_timeout = state.config.server.body_read_timeout();
+
+    // Small chunks are collected and written at once if configured.
+    let upload_buffer_bytes = state.config.server.upload_buffer_bytes;
+    let mut buffer = BytesMut::new();
+
     let mut bytes_written = 0;
-    while let Some(result) = stream.next().await {
-        let mut data = match result {
-            Ok(data) => data,
+    let mut first_chunk_received = None;
+    loop {
+        let result = match body_read_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, stream.next()).await {
+                Ok(result) => result,
+                Err(_) => {
+                    // Dropping the writer discards the partially written file.
+                    warn!(file_id = %id, "No data was received for {timeout:?}; aborting the upload");
+                    return Ok(problemdetails::new(StatusCode::REQUEST_TIMEOUT)
+                        .with_title("Request timeout")
+                        .with_detail(format!(
+                            "No data was received for {secs} seconds",
+                            secs = timeout.as_secs()
+                        ))
+                        .with_value("id", id_format.format(id))
+                        .into_response());
+                }
+            },
+            None => stream.next().await,
+        };
+
+        // The buffered data is written once the stream ended.
+        let ended = result.is_none();
+        if ended && buffer.is_empty() {
+            break;
+        }
+
+        let data = match result.unwrap_or(Ok(Bytes::new())) {
+            Ok(data) => {
+                first_chunk_received.get_or_insert_with(Instant::now);
+                data
+            }
+            Err(e) if is_client_body_error(&e) => {
+                // Dropping the writer discards the partially written file.
+                warn!(file_id = %id, "The request body is malformed or incomplete; aborting the upload: {e}");
+                return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                    .with_title("Malformed request body")
+                    .with_detail(format!("The request body could not be decoded: {e}"))
+                    .with_value("id", id_format.format(id))
+                    .into_response());
+            }
             Err(e) => {
-                return Ok((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to obtain data from the read stream: {e}"),
-                )
+                return Ok(problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+                    .with_title("Failed to read request body")
+                    .with_detail(format!("Failed to obtain data from the read stream: {e}"))
+                    .with_value("id", id_format.format(id))
+                    .with_value("error", e.to_string())
                     .into_response())
             }
         };
 
+        if let Some(max) = max_upload_bytes {
+            if (bytes_written + buffer.len() + data.remaining()) as u64 > max {
+                // Dropping the writer discards the partially written file.
+                return Ok(payload_too_large_response(Some(id_format.format(id)), max));
+            }
+        }
+
+        if let Some(n) = content_length {
+            if (bytes_written + buffer.len() + data.remaining()) as u64 > n {
+                // Dropping the writer discards the partially written file.
+                return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                    .with_title("Unexpected trailing data")
+                    .with_detail(format!("The upload exceeds the announced {n} bytes"))
+                    .with_value("id", id_format.format(id))
+                    .into_response());
+            }
+        }
+
+        // With both the size and hash known, the hash is verified as soon as the
+        // announced number of bytes was received, without waiting for the stream to end.
+        let complete = expected_sha256.is_some()
+            && content_length == Some((bytes_written + buffer.len() + data.remaining()) as u64);
+
+        let mut data = if buffer.is_empty() && data.remaining() >= upload_buffer_bytes {
+            data
+        } else {
+            buffer.extend_from_slice(&data);
+            if buffer.len() < upload_buffer_bytes && !(ended || complete) {
+                continue;
+            }
+            buffer.split().freeze()
+        };
+
         while data.has_remaining() {
             let chunk = data.chunk();
             match writer.write(chunk).await {
@@ -129,10 +435,11 @@ async fn do_yeet(
                     data.advance(n);
                 }
                 Err(e) => {
-                    return Ok((
-                        StatusCode::INTERNAL_SERVER_ERROR,
-                        format!("Failed to write to temporary file: {e}"),
-                    )
+                    return Ok(problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+                        .with_title("Failed to write file")
+                        .with_detail(format!("Failed to write to temporary file: {e}"))
+                        .with_value("id", id_format.format(id))
+                        .with_value("error", e.to_string())
                         .into_response())
                 }
             }
@@ -141,46 +448,169 @@ async fn do_yeet(
         match writer.sync_data().await {
             Ok(_) => {}
             Err(e) => {
-                return Ok((
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to flush data to temporary file: {e}"),
-                )
+                return Ok(problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+                    .with_title("Failed to flush file")
+                    .with_detail(format!("Failed to flush data to temporary file: {e}"))
+                    .with_value("id", id_format.format(id))
+                    .with_value("error", e.to_string())
                     .into_response())
             }
         }
+
+        if ended || complete {
+            break;
+        }
     }
 
     // The file was already synced to disk in the last iteration, so
     // we can skip the sync here.
-    // TODO: Add server-side validation of MD5 value if header is present.
     let write_result = match writer.finalize(CompletionMode::NoSync).await {
         Ok(write_result) => write_result,
+        Err(e @ FinalizationError::IntegrityCheckFailed(..)) => {
+            warn!(file_id = %id, "Rejecting upload: {e}");
+            return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                .with_title("Integrity check failed")
+                .with_detail(e.to_string())
+                .with_value("id", id_format.format(id))
+                .into_response());
+        }
+        Err(FinalizationError::InvalidFileLength(expected, received)) => {
+            warn!(file_id = %id, "Rejecting upload: announced {expected} bytes, but received {received} bytes");
+            return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+                .with_title("Content length mismatch")
+                .with_detail(format!(
+                    "The Content-Length header announced {expected} bytes, but {received} bytes were received"
+                ))
+                .with_value("id", id_format.format(id))
+                .with_value("expected_bytes", expected)
+                .with_value("received_bytes", received)
+                .into_response());
+        }
         Err(e) => {
-            return Ok((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                format!("Failed to complete writing to temporary file: {e}"),
-            )
+            return Ok(problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
+                .with_title("Failed to finalize file")
+                .with_detail(format!("Failed to complete writing to temporary file: {e}"))
+                .with_value("id", id_format.format(id))
+                .with_value("error", e.to_string())
                 .into_response())
         }
     };
 
+    TransferMetrics::track_upload_duration(
+        bytes_written,
+        first_chunk_received.map_or(Duration::ZERO, |received| received.elapsed()),
+    );
+
     debug!(
         file_id = %id,
         "Stream ended, buffered {bytes} bytes to disk; {hashes}",
         bytes = bytes_written,
-        hashes = write_result.hashes
+        hashes = write_result.summary.hashes
     );
 
+    // If the file was deduplicated, we continue with the existing file.
+    let deduplicated = write_result.deduplicated;
+    let tracked = write_result.tracked;
+    let id = write_result.id;
+    let write_result = write_result.summary;
+
+    if !tracked {
+        warn!(file_id = %id, "Upload {id} was written, but is not tracked and won't be available for download");
+    }
+
+    // Distribution only starts after the configured delay, so the waits below start then too.
+    let distribution_delay = state
+        .config
+        .backbone
+        .distribution_delay()
+        .unwrap_or_default();
+
+    // Optionally hold the response back until the file is durably stored.
+    // Untracked files are never distributed, so there is nothing to wait for.
+    if let Some(wait) = state
+        .config
+        .backends
+        .write_quorum_wait()
+        .filter(|_| tracked)
+    {
+        let failure = match tokio::time::timeout(
+            distribution_delay + wait,
+            state.distribution.wait_for_quorum(&id),
+        )
+        .await
+        {
+            Ok(Some(QuorumState::Failed)) => Some((
+                StatusCode::BAD_GATEWAY,
+                "Write quorum not met",
+                String::from("Too many backends failed to store the file"),
+            )),
+            Err(_) => Some((
+                StatusCode::GATEWAY_TIMEOUT,
+                "Write quorum not met in time",
+                format!("The file was not stored on enough backends within {wait:?}"),
+            )),
+            Ok(_) => None,
+        };
+
+        if let Some((status, title, detail)) = failure {
+            warn!(file_id = %id, "Rejecting upload: {detail}");
+            return Ok(problemdetails::new(status)
+                .with_title(title)
+                .with_detail(detail)
+                .with_value("id", id_format.format(id))
+                .into_response());
+        }
+    }
+
+    // Optionally fail the upload if no backend accepted the file.
+    if tracked && state.config.backends.on_rejection == RejectionPolicy::FailUpload {
+        // Each backend is bounded by the distribution timeout; if the distribution is still
+        // queued after it, the file is not known to be rejected.
+        tokio::time::timeout(
+            distribution_delay + state.config.backends.distribution_timeout(),
+            state.distribution.wait_for_completion(&id),
+        )
+        .await
+        .ok();
+
+        if state.distribution.is_rejected(&id) {
+            warn!(file_id = %id, "Rejecting upload {id} since no backend accepted it");
+            return Ok(problemdetails::new(StatusCode::INSUFFICIENT_STORAGE)
+                .with_title("File rejected by backends")
+                .with_detail("No backend accepted the file, e.g. because it is too large")
+                .with_value("id", id_format.format(id))
+                .into_response());
+        }
+    }
+
     let mut response = axum::Json(SuccessfulUploadResponse {
-        id,
+        id: id_format.format(id),
         file_size_bytes: write_result.file_size_bytes,
         hashes: (&write_result.hashes).into(),
+        deduplicated,
+        tracked,
+        distribution: state
+            .distribution
+            .get_or_pending(&id)
+            .iter()
+            .map(Distribution::from)
+            .collect(),
+        quorum: state
+            .distribution
+            .quorum_state(&id)
+            .map(|quorum| quorum.to_string()),
     })
     .into_response();
 
     let expiration_date = expiration_as_rfc1123(&write_result.expires);
 
     *response.status_mut() = StatusCode::CREATED;
+    response.extensions_mut().insert(TransferDetails {
+        id,
+        size_bytes: Some(write_result.file_size_bytes as u64),
+        hashes: Some(write_result.hashes.clone()),
+        content_type: Some(content_type.to_string()),
+    });
     let headers = response.headers_mut();
 
     // Set the file expiration.
@@ -189,7 +619,7 @@ async fn do_yeet(
         .or_insert(HeaderValue::from_str(&expiration_date).expect("invalid time input provided"));
 
     // Add the ID as a separate header to simplify testing.
-    let id = format!("{id}");
+    let id = id_format.format(id);
     headers
         .entry(&ID_HEADER)
         .or_insert(HeaderValue::from_str(&id).expect("invalid ID input provided"));
@@ -197,40 +627,150 @@ async fn do_yeet(
     Ok(response)
 }
 
-#[derive(Serialize)]
-struct SuccessfulUploadResponse {
-    /// The ID of the file.
-    id: ShortGuid,
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct ReservationResponse {
+    /// The reserved ID, in the configured format.
+    id: String,
+    /// The path of the WebSocket streaming the progress of the upload.
+    progress: String,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct SuccessfulUploadResponse {
+    /// The ID of the file, in the configured format.
+    id: String,
     /// The file size in bytes.
     file_size_bytes: usize,
     /// The hashes of the file.
     hashes: Hashes,
+    /// Whether an identical file was already stored and its ID is returned instead.
+    deduplicated: bool,
+    /// Whether the file is held for download; `false` if it was written completely,
+    /// but the server could no longer take it over, e.g. because it is shutting down.
+    tracked: bool,
+    /// The backends the file was (or will be) distributed to.
+    distribution: Vec<Distribution>,
+    /// Whether the write quorum was met, i.e. `pending`, `met` or `failed`;
+    /// omitted if no quorum is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    quorum: Option<String>,
+}
+
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct Hashes {
+    /// The MD5 hash in hex encoding; omitted if the digest was not calculated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    md5: Option<String>,
+    /// The SHA-256 hash in hex encoding; omitted if the hash was not calculated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sha256: Option<String>,
+    /// The CRC32C checksum in big-endian hex encoding; omitted if it was not calculated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    crc32c: Option<String>,
 }
 
-#[derive(Serialize)]
-struct Hashes {
-    /// The MD5 hash in hex encoding.
-    md5: String,
-    /// The SHA-256 hash in hex encoding
-    sha256: String,
+/// The distribution state of a file for a single backend.
+#[derive(Serialize, JsonSchema)]
+pub(crate) struct Distribution {
+    /// The tag of the backend.
+    backend: String,
+    /// The distribution state, i.e. `pending`, `stored`, `rejected`, `failed`, `not-eligible`
+    /// or `circuit-open`.
+    status: String,
+}
+
+impl From<&BackendDistribution> for Distribution {
+    fn from(value: &BackendDistribution) -> Self {
+        Self {
+            backend: value.tag.clone(),
+            status: value.state.to_string(),
+        }
+    }
 }
 
 impl From<&FileHashes> for Hashes {
     fn from(value: &FileHashes) -> Self {
         Self {
-            md5: hex::encode(value.md5.as_slice()),
-            sha256: hex::encode(value.sha256),
+            md5: value.md5.map(|md5| hex::encode(md5.as_slice())),
+            sha256: value.sha256.map(hex::encode),
+            crc32c: value.crc32c.map(|crc32c| format!("{crc32c:08x}")),
+        }
+    }
+}
+
+/// Parses the optional digest selection of the upload.
+fn parse_digests(headers: &HeaderMap) -> Result<Option<DigestSet>, String> {
+    let Some(value) = headers.get(&DIGESTS_HEADER) else {
+        return Ok(None);
+    };
+
+    let digests = value
+        .to_str()
+        .map_err(|e| e.to_string())?
+        .parse::<DigestSet>()
+        .map_err(|e| e.to_string())?;
+    trace!("Calculating the digests {digests}");
+    Ok(Some(digests))
+}
+
+/// Parses the optional hex encoded SHA-256 hash the upload is verified against.
+fn parse_expected_sha256(headers: &HeaderMap) -> Result<Option<[u8; 32]>, String> {
+    let Some(value) = headers.get(&EXPECTED_SHA256_HEADER) else {
+        return Ok(None);
+    };
+
+    let mut sha256 = [0u8; 32];
+    hex::decode_to_slice(value.as_bytes(), &mut sha256).map_err(|e| e.to_string())?;
+    trace!("Expecting SHA-256 {value}", value = hex::encode(sha256));
+    Ok(Some(sha256))
+}
+
+/// Determines whether reading the request body failed because of the client, e.g. due to
+/// a malformed chunked encoding or a connection closed before the body was complete,
+/// rather than because of the server.
+fn is_client_body_error(error: &axum::Error) -> bool {
+    let mut source: Option<&(dyn std::error::Error + 'static)> = Some(error);
+    while let Some(e) = source {
+        if let Some(e) = e.downcast_ref::<hyper::Error>() {
+            if e.is_parse() || e.is_incomplete_message() {
+                return true;
+            }
+        }
+        // Decoding errors of the transfer coding, e.g. an invalid chunk size, are reported
+        // by hyper as I/O errors.
+        if let Some(e) = e.downcast_ref::<std::io::Error>() {
+            return matches!(
+                e.kind(),
+                std::io::ErrorKind::InvalidInput
+                    | std::io::ErrorKind::InvalidData
+                    | std::io::ErrorKind::UnexpectedEof
+            );
         }
+        source = e.source();
+    }
+    false
+}
+
+fn payload_too_large_response(id: Option<String>, max_upload_bytes: u64) -> Response {
+    let response = problemdetails::new(StatusCode::PAYLOAD_TOO_LARGE)
+        .with_title("Payload too large")
+        .with_detail(format!(
+            "The upload exceeds the maximum size of {max_upload_bytes} bytes"
+        ))
+        .with_value("max_upload_bytes", max_upload_bytes);
+    match id {
+        Some(id) => response.with_value("id", id).into_response(),
+        None => response.into_response(),
     }
 }
 
-fn map_new_file_error_to_response(value: NewFileError) -> Response {
+fn map_new_file_error_to_response(value: NewFileError, id_format: &FileIdFormat) -> Response {
     match value {
         NewFileError::FailedCreatingFile(id, e) => {
             problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
                 .with_title("File not found")
                 .with_detail(format!("Failed to create temporary file: {e}"))
-                .with_value("id", id.to_string())
+                .with_value("id", id_format.format(id))
                 .with_value("error", e.to_string())
                 .into_response()
         }
@@ -240,16 +780,36 @@ fn map_new_file_error_to_response(value: NewFileError) -> Response {
                 .with_detail(format!(
                     "Failed to create a writer for the temporary file: {e}"
                 ))
-                .with_value("id", id.to_string())
+                .with_value("id", id_format.format(id))
                 .with_value("error", e.to_string())
                 .into_response()
         }
-        NewFileError::InternalErrorMayRetry(id) => {
-            problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
-                .with_title("File not found")
-                .with_detail("Failed to create temporary file - ID already in use".to_string())
-                .with_value("id", id.to_string())
-                .into_response()
+        NewFileError::Overloaded(id) => {
+            let mut response = problemdetails::new(StatusCode::SERVICE_UNAVAILABLE)
+                .with_title("Service overloaded")
+                .with_detail("The service is overloaded; please retry later")
+                .with_value("id", id_format.format(id))
+                .into_response();
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(OVERLOADED_RETRY_AFTER_SECS));
+            response
+        }
+        NewFileError::TooManyOpenFiles(id) => {
+            let mut response = problemdetails::new(StatusCode::SERVICE_UNAVAILABLE)
+                .with_title("Too many open files")
+                .with_detail("The service holds too many files at the moment; please retry later")
+                .with_value("id", id_format.format(id))
+                .into_response();
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(OVERLOADED_RETRY_AFTER_SECS));
+            response
         }
+        NewFileError::AlreadyExists(id) => problemdetails::new(StatusCode::CONFLICT)
+            .with_title("File already exists")
+            .with_detail("A file with this ID already exists or is being written")
+            .with_value("id", id_format.format(id))
+            .into_response(),
     }
 }
@@ -1,23 +1,39 @@
 //! Contains the `/yoink` endpoint filter.
 
+use crate::event_sink::TransferDetails;
 use crate::expiration_as_rfc1123;
+use crate::handlers::custom_metadata::metadata_headers;
+use crate::handlers::digest::{format_digests, wanted_digests, DIGEST_HEADER, WANT_DIGEST_HEADER};
+use crate::handlers::file_id::FileId;
+use crate::handlers::yeet::Hashes;
+use crate::handlers::{negotiate_problem, shutting_down_response};
+use crate::services::{adjust_encoded_headers, compression_layer, Uncompressed};
+use crate::trailer_body::Sha256TrailerBody;
 use crate::AppState;
-use axum::body::{HttpBody, StreamBody};
-use axum::extract::{Path, State};
-use axum::http::{header, HeaderName};
+use app_config::server::ETagFormat;
+use axum::body::{boxed, HttpBody, StreamBody};
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, HeaderName, HeaderValue, Version};
 use axum::response::{AppendHeaders, IntoResponse, Response};
 use axum::routing::get;
-use axum::Router;
+use axum::{Json, Router};
+use backbone::FileIdFormat;
+use backend_traits::ReceiveFileError;
 use base64::Engine;
-use file_distribution::{FileReaderTrait, GetFileReaderError};
+use chrono::{DateTime, Utc};
+use file_distribution::hash::Sha256Digest;
+use file_distribution::{BoxedFileReader, FileHashes, FileReaderTrait, GetFileReaderError};
 use hyper::StatusCode;
 use metrics::transfer::{TransferMethod, TransferMetrics};
 use mime_db::extension;
 use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
-use shared_files::FileSize;
 use shortguid::ShortGuid;
-use std::borrow::Borrow;
+use std::borrow::Cow;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
 use tokio_util::io::ReaderStream;
+use tower::ServiceBuilder;
+use tracing::{debug, warn};
 
 /// Escape control set for URL/hex-encoding file names in the Content-Disposition header.
 static ASCII_CONTROLS: AsciiSet = CONTROLS
@@ -32,6 +48,28 @@ static ASCII_CONTROLS: AsciiSet = CONTROLS
     .add(b'|')
     .add(b'}');
 
+/// The order of the headers of file responses, independent of which of them are present:
+/// the representation, then the caching and finally the integrity headers.
+///
+/// If the MD5 digest of a file was calculated, both `Content-MD5` (base64) and
+/// `yy-file-md5` (hex) are sent; otherwise neither is.
+const HEADER_ORDER: &[&str] = &[
+    "content-type",
+    "content-length",
+    "content-disposition",
+    "etag",
+    "last-modified",
+    "age",
+    "expires",
+    "warning",
+    "vary",
+    "digest",
+    "content-md5",
+    "yy-file-md5",
+    "yy-file-sha256",
+    "yy-file-crc32c",
+];
+
 pub trait YoinkRoutes {
     /// Provides an API for storing files.
     ///
@@ -43,6 +81,25 @@ pub trait YoinkRoutes {
     ///
     /// your-data
     /// ```
+    ///
+    /// HTTP/2 clients sending `TE: trailers` additionally receive the SHA-256 hash
+    /// of the transmitted data in the `yy-file-sha256` trailer. The trailer is not
+    /// announced in a `Trailer` header.
+    ///
+    /// Responses are compressed using gzip or zstd if the client accepts it via
+    /// `Accept-Encoding`, unless the file is small or already compressed.
+    ///
+    /// With `?raw=true`, the file is served with only the `Content-Type` and
+    /// `Content-Length` headers, e.g. for clients choking on `Content-Disposition`.
+    ///
+    /// `GET /yoink/:id/url` redirects to a presigned backend URL when a backend
+    /// supports it, and otherwise serves the file like `GET /yoink/:id`.
+    ///
+    /// `GET /yoink/:id/hashes` returns only the hex encoded digests of the file as JSON,
+    /// or `202 Accepted` while the file is still being uploaded.
+    ///
+    /// `GET /by-sha256/:hex` serves the file with the specified SHA-256 hash like
+    /// `GET /yoink/:id`, looking it up locally first and then in the backends.
     fn map_yoink_endpoint(self) -> Self;
 }
 
@@ -52,65 +109,367 @@ where
     axum::body::Bytes: From<<B as HttpBody>::Data>,
     <B as HttpBody>::Error: std::error::Error + Send + Sync,
 {
-    // Ensure HttpCallMetricTracker is updated.
     fn map_yoink_endpoint(self) -> Self {
-        self.route("/yoink/:id", get(do_yoink))
+        // Downloads are compressed if the client accepts it.
+        let compression = ServiceBuilder::new()
+            .map_response(adjust_encoded_headers)
+            .layer(compression_layer());
+
+        self.route("/yoink/:id", get(do_yoink).layer(compression.clone()))
+            .route(
+                "/yoink/:id/url",
+                get(do_yoink_url).layer(compression.clone()),
+            )
+            .route("/yoink/:id/hashes", get(do_yoink_hashes))
+            .route(
+                "/by-sha256/:hex",
+                get(do_yoink_by_sha256).layer(compression),
+            )
+    }
+}
+
+#[derive(Debug, Default, serde::Deserialize)]
+struct QueryParams {
+    /// Whether to omit all headers but `Content-Type` and `Content-Length`.
+    #[serde(default)]
+    raw: bool,
+}
+
+/// Returns the digests of a file from its write summary, without reading the file.
+async fn do_yoink_hashes(
+    FileId(id): FileId,
+    State(state): State<AppState>,
+    request_headers: HeaderMap,
+) -> Response {
+    match state.backbone.get_summary(id).await {
+        Ok(Some(summary)) => Json(Hashes::from(&summary.hashes)).into_response(),
+        Ok(None) => StatusCode::ACCEPTED.into_response(),
+        Err(e) => {
+            map_file_reader_error_to_response(e, state.backbone.id_format(), &request_headers)
+        }
+    }
+}
+
+/// Redirects to a presigned URL of the first backend able to issue one,
+/// falling back to streaming the file through the service.
+#[axum::debug_handler]
+async fn do_yoink_url(
+    FileId(id): FileId,
+    State(state): State<AppState>,
+    version: Version,
+    query: Query<QueryParams>,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if state.is_shutting_down() {
+        return Ok(shutting_down_response());
+    }
+
+    for backend in state.backends.iter() {
+        if let Some(url) = backend.presign_get(id).await {
+            debug!(file_id = %id, "Redirecting to presigned URL of backend {tag}", tag = backend.tag());
+            TransferMetrics::track_transfer(TransferMethod::Fetch);
+            return Ok((
+                StatusCode::TEMPORARY_REDIRECT,
+                [(header::LOCATION, url.to_string())],
+            )
+                .into_response());
+        }
     }
+
+    do_yoink(FileId(id), State(state), version, query, request_headers).await
+}
+
+/// Serves the file with the specified SHA-256 hash, looking it up locally first
+/// and then in the backends supporting hash-keyed lookups.
+#[axum::debug_handler]
+async fn do_yoink_by_sha256(
+    Path(hex): Path<String>,
+    State(state): State<AppState>,
+    version: Version,
+    request_headers: HeaderMap,
+) -> Result<Response, StatusCode> {
+    if state.is_shutting_down() {
+        return Ok(shutting_down_response());
+    }
+
+    let mut sha256 = Sha256Digest::default();
+    if let Err(e) = hex::decode_to_slice(&hex, sha256.as_mut_slice()) {
+        return Ok(problemdetails::new(StatusCode::BAD_REQUEST)
+            .with_title("Invalid hash")
+            .with_detail(format!(
+                "The SHA-256 hash must be 64 hexadecimal characters: {e}"
+            ))
+            .with_instance(format!("/by-sha256/{hex}"))
+            .into_response());
+    }
+
+    if let Some(id) = state.backbone.find_by_sha256(&sha256) {
+        state.backbone.extend_lease(id).await;
+        match state.backbone.get_file(id).await {
+            Ok(file) => {
+                return Ok(serve_file(
+                    &state,
+                    id,
+                    file,
+                    version,
+                    &request_headers,
+                    ResponseKind::Full,
+                ))
+            }
+            Err(e) => {
+                debug!(file_id = %id, "Unable to read the file with SHA-256 hash {hex} locally: {e}")
+            }
+        }
+    }
+
+    let range = byte_range(&request_headers);
+    for backend in state.backends.iter() {
+        let Some(id) = backend.find_by_sha256(&sha256).await else {
+            continue;
+        };
+
+        // Ranges are fetched from the backend directly instead of downloading the whole file.
+        let result = match range {
+            Some((start, end)) => match backend.receive_range(id, start, end).await {
+                Err(ReceiveFileError::NotSupported(_)) => {
+                    backend.receive_file(id).await.map(|file| (file, None))
+                }
+                result => result.map(|file| (file, Some((start, end)))),
+            },
+            None => backend.receive_file(id).await.map(|file| (file, None)),
+        };
+
+        match result {
+            Ok((file, None)) => {
+                debug!(file_id = %id, "Serving the file with SHA-256 hash {hex} from backend {tag}", tag = backend.tag());
+                return Ok(serve_file(
+                    &state,
+                    id,
+                    file,
+                    version,
+                    &request_headers,
+                    ResponseKind::Full,
+                ));
+            }
+            Ok((file, Some((start, _)))) => {
+                debug!(file_id = %id, "Serving a range of the file with SHA-256 hash {hex} from backend {tag}", tag = backend.tag());
+                return Ok(serve_range(
+                    &state,
+                    id,
+                    file,
+                    start,
+                    version,
+                    &request_headers,
+                ));
+            }
+            Err(ReceiveFileError::RangeNotSatisfiable(_)) => {
+                return Ok(problemdetails::new(StatusCode::RANGE_NOT_SATISFIABLE)
+                    .with_title("Range not satisfiable")
+                    .with_detail("The requested range starts past the end of the file")
+                    .with_instance(format!("/by-sha256/{hex}"))
+                    .into_response());
+            }
+            Err(e) => {
+                warn!(file_id = %id, "Failed to read the file with SHA-256 hash {hex} from backend {tag}: {e}", tag = backend.tag());
+            }
+        }
+    }
+
+    let problem = problemdetails::new(StatusCode::NOT_FOUND)
+        .with_title("File not found")
+        .with_detail(format!(
+            "No file with the SHA-256 hash {hex} could be found"
+        ))
+        .with_instance(format!("/by-sha256/{hex}"));
+    Ok(negotiate_problem(problem, &request_headers))
 }
 
 #[axum::debug_handler]
 async fn do_yoink(
-    Path(id): Path<ShortGuid>,
+    FileId(id): FileId,
     State(state): State<AppState>,
+    version: Version,
+    Query(query): Query<QueryParams>,
+    request_headers: HeaderMap,
 ) -> Result<Response, StatusCode> {
+    if state.is_shutting_down() {
+        return Ok(shutting_down_response());
+    }
+
+    state.backbone.extend_lease(id).await;
     let file = match state.backbone.get_file(id).await {
         Ok(file) => file,
-        Err(e) => return Ok(map_file_reader_error_to_response(e)),
+        Err(e) => {
+            return Ok(map_file_reader_error_to_response(
+                e,
+                state.backbone.id_format(),
+                &request_headers,
+            ))
+        }
+    };
+
+    Ok(serve_file(
+        &state,
+        id,
+        file,
+        version,
+        &request_headers,
+        if query.raw {
+            ResponseKind::Raw
+        } else {
+            ResponseKind::Full
+        },
+    ))
+}
+
+/// How a file is represented in a response.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ResponseKind {
+    /// The complete file, described by all of its headers.
+    Full,
+    /// The complete file, described only by the headers describing the body.
+    Raw,
+    /// A byte range of the file, without the digests of the complete file.
+    Partial,
+}
+
+/// Serves a local or backend file, including its metadata headers unless it is
+/// served [raw](ResponseKind::Raw).
+fn serve_file(
+    state: &AppState,
+    id: ShortGuid,
+    file: BoxedFileReader,
+    version: Version,
+    request_headers: &HeaderMap,
+    kind: ResponseKind,
+) -> Response {
+    let details = TransferDetails {
+        id,
+        size_bytes: file.final_file_size().map(|size| size as u64),
+        hashes: file
+            .summary()
+            .as_ref()
+            .map(|summary| summary.hashes.clone()),
+        content_type: file.content_type().map(Cow::into_owned),
     };
 
+    let mut response = file_response(state, id, file, version, request_headers, kind);
+    response.extensions_mut().insert(details);
+    response
+}
+
+/// Serves a byte range of a backend file as partial content.
+fn serve_range(
+    state: &AppState,
+    id: ShortGuid,
+    file: BoxedFileReader,
+    start: u64,
+    version: Version,
+    request_headers: &HeaderMap,
+) -> Response {
+    let length = file.final_file_size().unwrap_or_default() as u64;
+    let total = file
+        .summary()
+        .as_ref()
+        .map_or_else(|| String::from("*"), |s| s.file_size_bytes.to_string());
+
+    let mut response = serve_file(
+        state,
+        id,
+        file,
+        version,
+        request_headers,
+        ResponseKind::Partial,
+    );
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+    let headers = response.headers_mut();
+    let end = (start + length).saturating_sub(1);
+    let content_range = format!("bytes {start}-{end}/{total}");
+    if let Ok(value) = HeaderValue::try_from(content_range) {
+        headers.insert(header::CONTENT_RANGE, value);
+    }
+    response
+}
+
+/// Parses a `Range` header requesting a single byte range, i.e. `bytes=start-end` or
+/// `bytes=start-`, as an inclusive range. Other ranges are ignored.
+fn byte_range(headers: &HeaderMap) -> Option<(u64, u64)> {
+    let value = headers.get(header::RANGE)?.to_str().ok()?;
+    let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.trim().parse().ok()?;
+    let end = match end.trim() {
+        "" => u64::MAX,
+        end => end.parse().ok()?,
+    };
+    (start <= end).then_some((start, end))
+}
+
+/// Produces the response for a file. Raw responses only carry the headers describing
+/// the body and are never answered as not modified or with trailers. Partial responses
+/// carry neither the digests nor the trailer, since they describe the complete file
+/// rather than the transmitted part.
+fn file_response(
+    state: &AppState,
+    id: ShortGuid,
+    file: BoxedFileReader,
+    version: Version,
+    request_headers: &HeaderMap,
+    kind: ResponseKind,
+) -> Response {
     TransferMetrics::track_transfer(TransferMethod::Fetch);
+    let raw = kind == ResponseKind::Raw;
+    let partial = kind == ResponseKind::Partial;
 
     let summary = file.summary();
+    let display_id = state.backbone.id_format().format(id);
 
+    // Files that are still being written are streamed as the data arrives. Their size is
+    // only known if it was declared on upload; if the write fails, the transfer is aborted.
     let mut headers = Vec::new();
-    if let FileSize::Exactly(size) = file.file_size() {
+    if let Some(size) = file.final_file_size() {
         headers.push((header::CONTENT_LENGTH, size.to_string()));
     }
 
-    // The content type specified on file creation, or an empty string.
+    // The content type specified on file creation, or the configured default.
     let content_type = file
         .content_type()
-        .map_or(String::default(), |c| c.to_string());
+        .map_or_else(|| state.default_content_type.to_string(), |c| c.to_string());
 
-    // Add ETag from SHA-256 hash, etc.
-    if let Some(summary) = summary {
-        headers.push((
-            header::ETAG,
-            base64::engine::general_purpose::STANDARD.encode(&summary.hashes.sha256[..]),
-        ));
+    // Add ETag in the configured format, hashes, etc.
+    let etag = summary
+        .as_ref()
+        .and_then(|summary| etag(state.config.server.etag, &summary.hashes));
+    if let Some(etag) = &etag {
+        headers.push((header::ETAG, etag.clone()));
+    }
 
-        headers.push((
-            HeaderName::from_static("content-md5"),
-            base64::engine::general_purpose::STANDARD.encode(&summary.hashes.md5[..]),
-        ));
+    // Files are immutable, so they were last modified when they were created.
+    let created = summary.as_ref().map(|summary| summary.created);
+    if let Some(created) = created {
+        headers.push((header::LAST_MODIFIED, http_date(created)));
+    }
 
-        headers.push((
-            HeaderName::from_static("yy-file-md5"),
-            hex::encode(&summary.hashes.md5[..]),
-        ));
+    // The digests requested by `Want-Digest`, as far as they were calculated.
+    headers.push((header::VARY, WANT_DIGEST_HEADER.to_string()));
+    if let Some(summary) = summary {
+        // The digests describe the complete file rather than the transmitted part.
+        if !partial {
+            headers.extend(digest_headers(request_headers, &summary.hashes));
+        }
 
-        headers.push((
-            HeaderName::from_static("yy-file-sha256"),
-            hex::encode(&summary.hashes.sha256[..]),
-        ));
+        headers.extend(metadata_headers(&summary.metadata));
 
         let file_name = &summary.file_name;
 
-        let header = content_disposition_from_optional_name(id, &content_type, file_name);
+        let header = content_disposition_from_optional_name(&display_id, &content_type, file_name);
         headers.push(header);
     } else {
         // Use a default file name when none is known.
-        let header = default_content_disposition_header(id, &content_type);
+        let header = default_content_disposition_header(&display_id, &content_type);
         headers.push(header);
     }
 
@@ -124,24 +483,217 @@ async fn do_yoink(
     let expiration_date = expiration_as_rfc1123(&file.expiration_date());
     headers.push((header::EXPIRES, expiration_date));
 
-    let stream = ReaderStream::new(file);
-    let body = StreamBody::new(stream);
+    // Files read during the grace period after their expiration are stale.
+    if file.expiration_date() <= Instant::now() {
+        headers.push((header::WARNING, String::from("110 - \"Response is Stale\"")));
+    }
+
+    sort_headers(&mut headers);
+
+    // TODO: Announce the content headers via `103 Early Hints` once the server stack
+    //       supports sending informational responses; hyper 0.14 doesn't.
+
+    if raw {
+        headers.retain(|(name, _)| name == header::CONTENT_TYPE || name == header::CONTENT_LENGTH);
+    }
+
+    // Clients holding the current version of the file don't need it transmitted again.
+    // As required, `If-Modified-Since` is only evaluated without `If-None-Match`.
+    let not_modified = if raw {
+        false
+    } else if request_headers.contains_key(header::IF_NONE_MATCH) {
+        etag.as_ref()
+            .map_or(false, |etag| if_none_match(request_headers, etag))
+    } else {
+        created.map_or(false, |created| !modified_since(request_headers, created))
+    };
+    if not_modified {
+        headers.retain(|(name, _)| {
+            name == header::ETAG
+                || name == header::LAST_MODIFIED
+                || name == header::EXPIRES
+                || name == header::AGE
+        });
+        return (StatusCode::NOT_MODIFIED, AppendHeaders(headers)).into_response();
+    }
+
+    // Completely buffered files can be sent by the reverse proxy directly from disk.
+    if let Some(accel_redirect) = &state.config.server.accel_redirect {
+        let file_name = file
+            .local_path()
+            .and_then(|path| path.file_name())
+            .and_then(|name| name.to_str());
+        if let (Some(file_name), Some(_)) = (file_name, summary) {
+            let location = format!(
+                "{location}/{file_name}",
+                location = accel_redirect.location.trim_end_matches('/')
+            );
+            headers.retain(|(name, _)| name != header::CONTENT_LENGTH);
+            headers.push((HeaderName::from_static("x-accel-redirect"), location));
+
+            // The proxy opens the file only after receiving the response. The reader keeps
+            // the temporary file on disk until then, even if the lease expires meanwhile.
+            let hold = accel_redirect.hold();
+            tokio::spawn(async move {
+                tokio::time::sleep(hold).await;
+                drop(file);
+            });
+            return AppendHeaders(headers).into_response();
+        }
+    }
+
+    let stream = ReaderStream::with_capacity(file, state.config.server.download_buffer_bytes());
+    if raw || partial || !accepts_trailers(version, request_headers) {
+        let body = StreamBody::new(stream);
+        let headers = AppendHeaders(headers);
+        let mut response = (headers, body).into_response();
+        // The raw body is sent exactly as it was uploaded.
+        if raw {
+            response.extensions_mut().insert(Uncompressed);
+        }
+        return response;
+    }
 
+    // The trailer is not announced in a `Trailer` header since hyper treats it as a
+    // connection header and strips it from HTTP/2 responses with a warning; clients
+    // requesting trailers must expect it regardless.
+    let body = boxed(Sha256TrailerBody::new(stream));
     let headers = AppendHeaders(headers);
-    Ok((headers, body).into_response())
+    (headers, body).into_response()
+}
+
+/// Produces the headers carrying the digests of a file, including those requested by `Want-Digest`.
+fn digest_headers(request_headers: &HeaderMap, hashes: &FileHashes) -> Vec<(HeaderName, String)> {
+    let mut headers = Vec::new();
+    let wanted = wanted_digests(request_headers);
+    if let Some(digest) = format_digests(&wanted, hashes) {
+        headers.push((DIGEST_HEADER.clone(), digest));
+    }
+
+    if let Some(md5) = &hashes.md5 {
+        headers.push((
+            HeaderName::from_static("content-md5"),
+            base64::engine::general_purpose::STANDARD.encode(&md5[..]),
+        ));
+
+        headers.push((
+            HeaderName::from_static("yy-file-md5"),
+            hex::encode(&md5[..]),
+        ));
+    }
+
+    if let Some(sha256) = &hashes.sha256 {
+        headers.push((
+            HeaderName::from_static("yy-file-sha256"),
+            hex::encode(&sha256[..]),
+        ));
+    }
+
+    if let Some(crc32c) = hashes.crc32c {
+        headers.push((
+            HeaderName::from_static("yy-file-crc32c"),
+            format!("{crc32c:08x}"),
+        ));
+    }
+
+    headers
+}
+
+/// Sorts the headers of a file response into the order given by [`HEADER_ORDER`].
+fn sort_headers(headers: &mut [(HeaderName, String)]) {
+    let position = |name: &HeaderName| {
+        HEADER_ORDER
+            .iter()
+            .position(|ordered| *ordered == name.as_str())
+            .unwrap_or(HEADER_ORDER.len())
+    };
+    headers.sort_by_key(|(name, _)| position(name));
+}
+
+/// Produces the quoted `ETag` of a file in the configured format, or `None` if the required
+/// hash was not calculated.
+fn etag(format: ETagFormat, hashes: &FileHashes) -> Option<String> {
+    let engine = &base64::engine::general_purpose::STANDARD;
+    match format {
+        ETagFormat::Sha256 => hashes
+            .sha256
+            .map(|sha256| format!("\"{}\"", engine.encode(&sha256[..]))),
+        ETagFormat::Md5 => hashes
+            .md5
+            .map(|md5| format!("\"{}\"", engine.encode(&md5[..]))),
+        ETagFormat::Weak => hashes
+            .sha256
+            .map(|sha256| format!("W/\"{}\"", engine.encode(&sha256[..]))),
+    }
+}
+
+/// Determines whether the `If-None-Match` header of the request matches the `ETag`.
+///
+/// As required for `If-None-Match`, the weak comparison is used, i.e. the `W/` prefix of
+/// weak validators is ignored. This also matches the `ETag` of compressed responses.
+fn if_none_match(headers: &HeaderMap, etag: &str) -> bool {
+    let opaque = |tag: &str| {
+        tag.trim()
+            .trim_start_matches("W/")
+            .trim_matches('"')
+            .to_owned()
+    };
+    let etag = opaque(etag);
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|tag| tag.trim() == "*" || opaque(tag) == etag)
+}
+
+/// Determines whether the file was modified after the date in the `If-Modified-Since`
+/// header. Missing or invalid dates are treated as if the file was modified.
+fn modified_since(headers: &HeaderMap, created: SystemTime) -> bool {
+    let Some(since) = headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value.trim()).ok())
+    else {
+        return true;
+    };
+
+    // HTTP dates have a resolution of one second.
+    let created = created
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    created > since.timestamp()
+}
+
+/// Formats a point in time as an HTTP date.
+fn http_date(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+/// Determines whether trailers can be sent to the client.
+///
+/// Trailers are only sent if the client indicates their support using `TE: trailers`.
+/// Since the server only emits trailers over HTTP/2, HTTP/1 clients don't receive them.
+fn accepts_trailers(version: Version, headers: &HeaderMap) -> bool {
+    version == Version::HTTP_2
+        && headers
+            .get_all(header::TE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|value| value.trim().eq_ignore_ascii_case("trailers"))
 }
 
 /// Attempts to generate a `Content-Disposition` header from the optionally specified
 /// file name. If no name was set, falls back to a generated file name based on the ID.
-fn content_disposition_from_optional_name<I>(
-    id: I,
+fn content_disposition_from_optional_name(
+    id: &str,
     content_type: &String,
     file_name: &Option<String>,
-) -> (HeaderName, String)
-where
-    I: Borrow<ShortGuid>,
-{
-    let id = id.borrow();
+) -> (HeaderName, String) {
     if let Some(file_name) = file_name {
         let file_name = utf8_percent_encode(file_name, &ASCII_CONTROLS).to_string();
         (
@@ -155,11 +707,7 @@ where
 
 /// Generates a `Content-Disposition` header based on the ID. If the `Content-Type` was specified,
 /// a default extension will be appended to the file.
-fn default_content_disposition_header<I>(id: I, content_type: &String) -> (HeaderName, String)
-where
-    I: Borrow<ShortGuid>,
-{
-    let id = id.borrow();
+fn default_content_disposition_header(id: &str, content_type: &String) -> (HeaderName, String) {
     if content_type.is_empty() {
         (
             header::CONTENT_DISPOSITION,
@@ -182,28 +730,92 @@ where
     }
 }
 
-fn map_file_reader_error_to_response(value: GetFileReaderError) -> Response {
-    match value {
-        GetFileReaderError::UnknownFile(id) => problemdetails::new(StatusCode::NOT_FOUND)
-            .with_title("File not found")
-            .with_detail(format!("The file with ID {id} could not be found"))
-            .with_instance(format!("/yoink/{id}"))
-            .with_value("id", id.to_string())
-            .into_response(),
-        GetFileReaderError::FileExpired(id) => problemdetails::new(StatusCode::GONE)
-            .with_title("File not found")
-            .with_detail(format!("The file with ID {id} has expired"))
-            .with_instance(format!("/yoink/{id}"))
-            .with_value("id", id.to_string())
-            .into_response(),
+/// Maps the error to a problem in the representation preferred by the client.
+pub(crate) fn map_file_reader_error_to_response(
+    value: GetFileReaderError,
+    id_format: &FileIdFormat,
+    request_headers: &HeaderMap,
+) -> Response {
+    let problem = match value {
+        GetFileReaderError::UnknownFile(id) => {
+            let id = id_format.format(id);
+            problemdetails::new(StatusCode::NOT_FOUND)
+                .with_title("File not found")
+                .with_detail(format!("The file with ID {id} could not be found"))
+                .with_instance(format!("/yoink/{id}"))
+                .with_value("id", id)
+        }
+        GetFileReaderError::FileExpired(id) => {
+            let id = id_format.format(id);
+            problemdetails::new(StatusCode::GONE)
+                .with_title("File not found")
+                .with_detail(format!("The file with ID {id} has expired"))
+                .with_instance(format!("/yoink/{id}"))
+                .with_value("id", id)
+        }
         GetFileReaderError::FileError(id, e) => {
+            let id = id_format.format(id);
             problemdetails::new(StatusCode::INTERNAL_SERVER_ERROR)
                 .with_title("File not found")
                 .with_detail(format!("Unable to process file: {e}"))
                 .with_instance(format!("/yoink/{id}"))
-                .with_value("id", id.to_string())
+                .with_value("id", id)
                 .with_value("error", e.to_string())
-                .into_response()
         }
+    };
+    negotiate_problem(problem, request_headers)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_distribution::protobuf::Hashes;
+
+    fn hashes() -> FileHashes {
+        Hashes {
+            md5: vec![0xAB; 16],
+            sha256: vec![0xCD; 32],
+            ..Default::default()
+        }
+        .to_file_hashes()
+        .unwrap()
+    }
+
+    #[test]
+    fn etags_are_quoted() {
+        let sha256 = "zc3Nzc3Nzc3Nzc3Nzc3Nzc3Nzc3Nzc3Nzc3Nzc3Nzc0=";
+        assert_eq!(
+            etag(ETagFormat::Sha256, &hashes()),
+            Some(format!("\"{sha256}\""))
+        );
+        assert_eq!(
+            etag(ETagFormat::Md5, &hashes()),
+            Some(String::from("\"q6urq6urq6urq6urq6urqw==\""))
+        );
+        assert_eq!(
+            etag(ETagFormat::Weak, &hashes()),
+            Some(format!("W/\"{sha256}\""))
+        );
+        assert_eq!(
+            etag(ETagFormat::Sha256, &FileHashes::new(None, None, None)),
+            None
+        );
+    }
+
+    #[test]
+    fn if_none_match_uses_the_weak_comparison() {
+        let matches = |value: &str, etag: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+            if_none_match(&headers, etag)
+        };
+
+        assert!(matches("\"abc\"", "\"abc\""));
+        assert!(matches("W/\"abc\"", "\"abc\""));
+        assert!(matches("\"abc\"", "W/\"abc\""));
+        assert!(matches("\"xyz\", W/\"abc\"", "\"abc\""));
+        assert!(matches("*", "\"abc\""));
+        assert!(!matches("\"xyz\"", "\"abc\""));
+        assert!(!if_none_match(&HeaderMap::new(), "\"abc\""));
     }
 }
@@ -1,4 +1,5 @@
 use std::fmt::{Display, Formatter};
+use std::path::Path;
 
 #[derive(Debug, Copy, Clone, Eq, PartialEq)]
 #[allow(dead_code)]
@@ -17,3 +18,41 @@ impl Display for HealthState {
         }
     }
 }
+
+/// The space available in a directory's file system.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct AvailableSpace {
+    /// The number of bytes available to unprivileged users.
+    pub bytes: u64,
+    /// The number of inodes available to unprivileged users.
+    pub inodes: u64,
+}
+
+impl AvailableSpace {
+    /// Determines the space available in the file system containing the specified path.
+    #[cfg(unix)]
+    pub fn of(path: &Path) -> std::io::Result<Self> {
+        let stat = nix::sys::statvfs::statvfs(path)?;
+        Ok(Self {
+            bytes: stat.blocks_available() as u64 * stat.fragment_size() as u64,
+            inodes: stat.files_available() as u64,
+        })
+    }
+
+    /// Determines the space available in the file system containing the specified path.
+    #[cfg(not(unix))]
+    pub fn of(_path: &Path) -> std::io::Result<Self> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+impl Display for AvailableSpace {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{bytes} bytes, {inodes} inodes available",
+            bytes = self.bytes,
+            inodes = self.inodes
+        )
+    }
+}
@@ -0,0 +1,382 @@
+//! Contains a connection wrapper closing idle connections.
+
+use crate::listener::Connection;
+use axum::body::HttpBody;
+use axum::http::{HeaderMap, Request, Response};
+use futures::task::AtomicWaker;
+use hyper::service::Service;
+use pin_project::pin_project;
+use std::future::Future;
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{ready, Context, Poll, Waker};
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::time::{Instant, Sleep};
+
+/// Wraps a connection and signals end-of-stream to the reader once neither
+/// reads nor writes happened for the configured timeout.
+///
+/// Since any transferred byte counts as activity, ongoing uploads and downloads
+/// are not affected; only connections that are silent in both directions are closed.
+/// While a request on the connection is in progress, the timeout is paused, so that
+/// requests waiting for their response, e.g. uploads waiting for the write quorum,
+/// are not cut off.
+#[pin_project]
+pub struct IdleTimeoutStream<S> {
+    #[pin]
+    inner: S,
+    timeout: Option<Duration>,
+    last_activity: Instant,
+    sleep: Pin<Box<Sleep>>,
+    active_requests: ActiveRequests,
+    /// Whether requests were in progress when the connection was last polled.
+    busy: bool,
+}
+
+impl<S> IdleTimeoutStream<S> {
+    pub fn new(inner: S, timeout: Option<Duration>) -> Self {
+        let now = Instant::now();
+        Self {
+            inner,
+            timeout,
+            last_activity: now,
+            sleep: Box::pin(tokio::time::sleep_until(now + timeout.unwrap_or_default())),
+            active_requests: ActiveRequests::default(),
+            busy: false,
+        }
+    }
+
+    /// Gets a reference to the wrapped connection.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Gets the requests in progress on the connection.
+    pub fn active_requests(&self) -> &ActiveRequests {
+        &self.active_requests
+    }
+}
+
+/// The number of requests in progress on a connection, including the transfer of
+/// their response bodies.
+#[derive(Clone, Default)]
+pub struct ActiveRequests(Arc<ActiveRequestsInner>);
+
+#[derive(Default)]
+struct ActiveRequestsInner {
+    count: AtomicUsize,
+    /// Wakes the reader of the connection once the last request completed,
+    /// so that the idle timeout resumes.
+    waker: AtomicWaker,
+}
+
+/// Marks a request as in progress until it is dropped.
+pub struct ActiveRequestGuard(ActiveRequests);
+
+impl ActiveRequests {
+    /// Marks a request as in progress.
+    pub fn begin(&self) -> ActiveRequestGuard {
+        self.0.count.fetch_add(1, Ordering::AcqRel);
+        ActiveRequestGuard(self.clone())
+    }
+
+    /// Indicates whether any requests are in progress.
+    fn any(&self) -> bool {
+        self.0.count.load(Ordering::Acquire) > 0
+    }
+
+    fn register(&self, waker: &Waker) {
+        self.0.waker.register(waker);
+    }
+}
+
+impl Drop for ActiveRequestGuard {
+    fn drop(&mut self) {
+        if self.0 .0.count.fetch_sub(1, Ordering::AcqRel) == 1 {
+            self.0 .0.waker.wake();
+        }
+    }
+}
+
+impl<S> AsyncRead for IdleTimeoutStream<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+        let filled = buf.filled().len();
+        match this.inner.poll_read(cx, buf) {
+            Poll::Ready(result) => {
+                if buf.filled().len() > filled {
+                    *this.last_activity = Instant::now();
+                }
+                Poll::Ready(result)
+            }
+            Poll::Pending => {
+                let Some(timeout) = *this.timeout else {
+                    return Poll::Pending;
+                };
+
+                // The waker is registered before checking, so that the completion
+                // of the last request can't be missed.
+                this.active_requests.register(cx.waker());
+                if this.active_requests.any() {
+                    *this.busy = true;
+                    return Poll::Pending;
+                }
+
+                // The connection only becomes idle once the last request completed.
+                if std::mem::take(this.busy) {
+                    *this.last_activity = Instant::now();
+                }
+
+                // The deadline moves with every activity, so the timer only
+                // elapses if there was no activity within the timeout.
+                let deadline = *this.last_activity + timeout;
+                if this.sleep.deadline() != deadline {
+                    this.sleep.as_mut().reset(deadline);
+                }
+
+                match this.sleep.as_mut().poll(cx) {
+                    // Reporting end-of-stream lets hyper close the connection.
+                    Poll::Ready(()) => Poll::Ready(Ok(())),
+                    Poll::Pending => Poll::Pending,
+                }
+            }
+        }
+    }
+}
+
+impl<S> AsyncWrite for IdleTimeoutStream<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let this = self.project();
+        let result = this.inner.poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = result {
+            if n > 0 {
+                *this.last_activity = Instant::now();
+            }
+        }
+        result
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let this = self.project();
+        let result = this.inner.poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(n)) = result {
+            if n > 0 {
+                *this.last_activity = Instant::now();
+            }
+        }
+        result
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
+
+/// Marks the requests on each connection as in progress, pausing its idle timeout
+/// until their response was sent.
+#[derive(Clone)]
+pub struct ActiveRequestsMakeService<M> {
+    inner: M,
+}
+
+/// Marks each request as in progress until its response body was dropped.
+#[derive(Clone)]
+pub struct ActiveRequestsService<S> {
+    inner: S,
+    active_requests: ActiveRequests,
+}
+
+impl<M> ActiveRequestsMakeService<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, M> Service<&'a Connection> for ActiveRequestsMakeService<M>
+where
+    M: Service<&'a Connection>,
+{
+    type Response = ActiveRequestsService<M::Response>;
+    type Error = M::Error;
+    type Future = ActiveRequestsMakeFuture<M::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, connection: &'a Connection) -> Self::Future {
+        ActiveRequestsMakeFuture {
+            active_requests: Some(connection.tcp().active_requests().clone()),
+            inner: self.inner.call(connection),
+        }
+    }
+}
+
+/// Resolves to the [`ActiveRequestsService`] of a connection.
+#[pin_project]
+pub struct ActiveRequestsMakeFuture<F> {
+    #[pin]
+    inner: F,
+    active_requests: Option<ActiveRequests>,
+}
+
+impl<F, S, E> Future for ActiveRequestsMakeFuture<F>
+where
+    F: Future<Output = Result<S, E>>,
+{
+    type Output = Result<ActiveRequestsService<S>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let service = ready!(this.inner.poll(cx))?;
+        Poll::Ready(Ok(ActiveRequestsService {
+            inner: service,
+            active_requests: this
+                .active_requests
+                .take()
+                .expect("future polled after completion"),
+        }))
+    }
+}
+
+impl<S, B, ResBody> Service<Request<B>> for ActiveRequestsService<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+{
+    type Response = Response<ActiveRequestBody<ResBody>>;
+    type Error = S::Error;
+    type Future = ActiveRequestFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        ActiveRequestFuture {
+            guard: Some(self.active_requests.begin()),
+            inner: self.inner.call(request),
+        }
+    }
+}
+
+/// Resolves to the response of a request, handing the guard over to its body.
+#[pin_project]
+pub struct ActiveRequestFuture<F> {
+    #[pin]
+    inner: F,
+    guard: Option<ActiveRequestGuard>,
+}
+
+impl<F, B, E> Future for ActiveRequestFuture<F>
+where
+    F: Future<Output = Result<Response<B>, E>>,
+{
+    type Output = Result<Response<ActiveRequestBody<B>>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let response = ready!(this.inner.poll(cx))?;
+        let guard = this.guard.take();
+        Poll::Ready(Ok(response.map(|inner| ActiveRequestBody {
+            inner,
+            _guard: guard,
+        })))
+    }
+}
+
+/// A response body keeping its request marked as in progress until it is dropped.
+#[pin_project]
+pub struct ActiveRequestBody<B> {
+    #[pin]
+    inner: B,
+    _guard: Option<ActiveRequestGuard>,
+}
+
+impl<B> HttpBody for ActiveRequestBody<B>
+where
+    B: HttpBody,
+{
+    type Data = B::Data;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        self.project().inner.poll_data(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{duplex, AsyncReadExt};
+
+    #[tokio::test(start_paused = true)]
+    async fn timeout_is_paused_while_requests_are_active() {
+        let (_client, server) = duplex(64);
+        let mut stream = IdleTimeoutStream::new(server, Some(Duration::from_secs(60)));
+        let guard = stream.active_requests().begin();
+
+        // A request waiting for its response for longer than the timeout is not cut off.
+        let mut buf = [0; 16];
+        let read = tokio::time::timeout(Duration::from_secs(300), stream.read(&mut buf)).await;
+        assert!(read.is_err(), "the connection was closed during a request");
+
+        // Once the request completed, the connection is closed after the timeout.
+        drop(guard);
+        let started = Instant::now();
+        let read = tokio::time::timeout(Duration::from_secs(120), stream.read(&mut buf))
+            .await
+            .expect("the idle connection was not closed");
+        assert_eq!(read.expect("failed to read"), 0);
+        assert_eq!(started.elapsed(), Duration::from_secs(60));
+    }
+}
@@ -0,0 +1,157 @@
+//! Contains the creation of the listening sockets.
+
+use crate::idle_timeout::IdleTimeoutStream;
+use crate::metered_stream::MeteredStream;
+use crate::tls::ClientCertificate;
+use app_config::server::ServerConfig;
+use axum::extract::connect_info::Connected;
+use hyper::server::conn::{AddrIncoming, AddrStream};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::io::IoSlice;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio_rustls::server::TlsStream;
+
+/// An accepted TCP connection, tracked in the metrics and closed when idle for too long.
+pub type TcpConnection = IdleTimeoutStream<MeteredStream<AddrStream>>;
+
+/// An accepted connection, secured by TLS if configured.
+pub enum Connection {
+    Plain(TcpConnection),
+    Tls {
+        stream: Box<TlsStream<TcpConnection>>,
+        /// The verified certificate presented by the client, if any.
+        client_certificate: Option<Arc<ClientCertificate>>,
+    },
+}
+
+impl Connection {
+    /// Gets the underlying TCP connection.
+    pub fn tcp(&self) -> &TcpConnection {
+        match self {
+            Connection::Plain(stream) => stream,
+            Connection::Tls { stream, .. } => stream.get_ref().0,
+        }
+    }
+
+    /// Gets the verified certificate presented by the client, if any.
+    pub fn client_certificate(&self) -> Option<&Arc<ClientCertificate>> {
+        match self {
+            Connection::Plain(_) => None,
+            Connection::Tls {
+                client_certificate, ..
+            } => client_certificate.as_ref(),
+        }
+    }
+}
+
+/// Provides the address of the client to the handlers via
+/// [`ConnectInfo`](axum::extract::ConnectInfo).
+impl Connected<&Connection> for SocketAddr {
+    fn connect_info(target: &Connection) -> Self {
+        target.tcp().get_ref().get_ref().remote_addr()
+    }
+}
+
+impl AsyncRead for Connection {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Connection::Tls { stream, .. } => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for Connection {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Connection::Tls { stream, .. } => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Connection::Tls { stream, .. } => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Connection::Tls { stream, .. } => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            Connection::Plain(stream) => Pin::new(stream).poll_write_vectored(cx, bufs),
+            Connection::Tls { stream, .. } => Pin::new(stream).poll_write_vectored(cx, bufs),
+        }
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        match self {
+            Connection::Plain(stream) => stream.is_write_vectored(),
+            Connection::Tls { stream, .. } => stream.is_write_vectored(),
+        }
+    }
+}
+
+/// Binds a listening socket to the specified address, applying the configured
+/// backlog and socket options.
+pub fn bind(addr: &SocketAddr, config: &ServerConfig) -> Result<AddrIncoming, BindError> {
+    let socket = Socket::new(
+        Domain::for_address(*addr),
+        Type::STREAM,
+        Some(Protocol::TCP),
+    )?;
+    socket.set_reuse_address(config.reuse_address())?;
+    if config.reuse_port {
+        set_reuse_port(&socket)?;
+    }
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&(*addr).into())?;
+    socket.listen(config.listen_backlog().min(i32::MAX as u32) as i32)?;
+
+    let listener = tokio::net::TcpListener::from_std(socket.into())?;
+    Ok(AddrIncoming::from_listener(listener)?)
+}
+
+#[cfg(unix)]
+fn set_reuse_port(socket: &Socket) -> std::io::Result<()> {
+    socket.set_reuse_port(true)
+}
+
+#[cfg(not(unix))]
+fn set_reuse_port(_socket: &Socket) -> std::io::Result<()> {
+    Err(std::io::Error::new(
+        std::io::ErrorKind::Unsupported,
+        "SO_REUSEPORT is not supported on this platform",
+    ))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BindError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Hyper(#[from] hyper::Error),
+}
@@ -3,39 +3,103 @@
 // the `docsrs` configuration attribute is defined
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+use crate::accept_limit::AcceptLimits;
+use crate::event_sink::EventSink;
 use crate::handlers::*;
-use app_config::AppConfig;
+use crate::idle_timeout::{ActiveRequestsMakeService, IdleTimeoutStream};
+use crate::metered_stream::MeteredStream;
+use app_config::{AppConfig, SelfTestMode};
+use axum::headers::ContentType;
 use axum::Router;
 use backbone::{Backbone, FileAccessorBridge};
+use backend_traits::{Backend, BackendCommandSender, DistributionTracker};
 use clap::ArgMatches;
 use directories::ProjectDirs;
 use futures::stream::FuturesUnordered;
 use futures::StreamExt;
+use hyper::server::accept::{self, Accept};
 use hyper::Server;
+use metrics::http::HttpMetrics;
 use rendezvous::Rendezvous;
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::process::ExitCode;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
+use std::task::ready;
+use std::time::Duration;
 use tokio::sync::broadcast;
+use tokio::time::Instant;
 use tower::ServiceBuilder;
+use tower_http::cors::CorsLayer;
 use tracing::{debug, error, info, warn};
 
 use crate::backend_registry::BackendRegistry;
+#[cfg(feature = "gcs")]
+use backend_gcs::GcsBackend;
 #[cfg(feature = "memcache")]
-use backend_memcache::MemcacheBackend;
+use backend_memcache::{MemcacheBackend, StripedMemcacheBackend};
+#[cfg(feature = "memory")]
+use backend_memory::MemoryBackend;
+#[cfg(feature = "sftp")]
+use backend_sftp::SftpBackend;
 use file_distribution::FileProvider;
 
+mod accept_limit;
 mod backend_registry;
+mod backend_stats;
+mod circuit_breaker;
 mod commands;
+mod cors;
+mod event_sink;
 mod handlers;
 mod health;
+mod idle_timeout;
+mod listener;
 mod logging;
+mod metered_stream;
+mod self_test;
 mod services;
+mod state_dump;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+mod tls;
+mod trailer_body;
+mod validate;
 
 #[derive(Clone)]
 pub struct AppState {
     shutdown_tx: broadcast::Sender<()>,
     backbone: Arc<Backbone>,
+    distribution: DistributionTracker,
+    /// Sends commands to the backend event loop, e.g. to delete files from the backends.
+    backend_sender: BackendCommandSender,
+    /// The registered backends, shared with the backend event loop.
+    backends: Arc<[Backend]>,
+    config: Arc<AppConfig>,
+    /// The content type to assume for files that were uploaded without one.
+    default_content_type: ContentType,
+    /// Set once a shutdown was initiated; new transfers are rejected while draining.
+    shutting_down: Arc<AtomicBool>,
+    /// Set while new uploads are paused for maintenance; downloads are unaffected.
+    uploads_paused: Arc<AtomicBool>,
+}
+
+impl AppState {
+    /// Indicates whether a shutdown was initiated.
+    pub fn is_shutting_down(&self) -> bool {
+        self.shutting_down.load(Ordering::Relaxed)
+    }
+
+    /// Indicates whether new uploads are paused.
+    pub fn are_uploads_paused(&self) -> bool {
+        self.uploads_paused.load(Ordering::Relaxed)
+    }
+
+    /// Pauses or resumes accepting new uploads. Returns whether uploads were paused before.
+    pub fn set_uploads_paused(&self, paused: bool) -> bool {
+        self.uploads_paused.swap(paused, Ordering::Relaxed)
+    }
 }
 
 #[tokio::main]
@@ -55,12 +119,134 @@ async fn main() -> ExitCode {
     };
 
     let cfg = match AppConfig::load(dirs.config_local_dir(), &matches) {
-        Ok(config) => config,
+        Ok(config) => Arc::new(config),
+        Err(_) => {
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if matches.subcommand_matches("validate-config").is_some() {
+        return validate::run(&cfg);
+    }
+
+    let problems = cfg.validate();
+    if !problems.is_empty() {
+        for problem in &problems {
+            error!("Invalid configuration: {problem}");
+        }
+        return ExitCode::from(exitcode::CONFIG as u8);
+    }
+
+    let default_content_type = match cfg.server.default_content_type().parse::<ContentType>() {
+        Ok(content_type) => content_type,
         Err(_) => {
+            error!(
+                "Invalid default content type: {content_type}",
+                content_type = cfg.server.default_content_type()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    HttpMetrics::set_max_path_labels(cfg.server.max_metric_paths());
+    if !cfg.server.http_duration_buckets.is_empty() {
+        if let Err(e) = HttpMetrics::set_duration_buckets(&cfg.server.http_duration_buckets) {
+            error!("Invalid HTTP duration buckets: {e}");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let cors = match cfg.server.cors.as_ref().map(cors::build_layer).transpose() {
+        Ok(cors) => cors,
+        Err(e) => {
+            error!("Invalid CORS configuration: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let client_ip = match services::ClientIpLayer::try_new(
+        &cfg.server.trusted_proxies,
+        cfg.server.forwarded_header,
+    ) {
+        Ok(client_ip) => client_ip,
+        Err(e) => {
+            error!("Invalid trusted proxy configuration: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if client_ip.trusted_proxies() > 0 {
+        info!(
+            "Resolving client IPs of requests from {count} trusted prox{plural}",
+            count = client_ip.trusted_proxies(),
+            plural = if client_ip.trusted_proxies() == 1 {
+                "y"
+            } else {
+                "ies"
+            }
+        );
+    }
+
+    let response_headers = match services::ResponseHeadersLayer::try_new(
+        &cfg.server.response_headers,
+        cfg.server.server_header.as_deref(),
+    ) {
+        Ok(response_headers) => response_headers,
+        Err(e) => {
+            error!("Invalid response header configuration: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let chaos = match cfg
+        .server
+        .chaos
+        .as_ref()
+        .map(services::ChaosLayer::try_new)
+        .transpose()
+    {
+        Ok(chaos) => chaos,
+        Err(e) => {
+            error!("Invalid chaos mode configuration: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let upload_rate_limit = match cfg
+        .server
+        .upload_rate_limit
+        .as_ref()
+        .map(services::UploadRateLimitLayer::try_new)
+        .transpose()
+    {
+        Ok(upload_rate_limit) => upload_rate_limit,
+        Err(e) => {
+            error!("Invalid upload rate limit configuration: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let tls = match cfg
+        .server
+        .tls
+        .as_ref()
+        .map(tls::TlsAcceptor::try_new)
+        .transpose()
+    {
+        Ok(tls) => tls,
+        Err(e) => {
+            error!("Invalid TLS configuration: {e}");
             return ExitCode::FAILURE;
         }
     };
 
+    if let Some(chaos) = &chaos {
+        warn!(
+            "Chaos mode is enabled, {description}; do not use this in production",
+            description = chaos.describe()
+        );
+    }
+
     // Provide a signal that can be used to shut down the server.
     let (shutdown_tx, _) = broadcast::channel::<()>(1);
     register_shutdown_handler(shutdown_tx.clone());
@@ -71,8 +257,11 @@ async fn main() -> ExitCode {
     let file_accessor = Arc::new(FileAccessorBridge::default());
 
     // TODO: Create and register backends.
-    let registry =
-        BackendRegistry::builder(rendezvous.fork_guard(), FileProvider::wrap(&file_accessor));
+    let registry = BackendRegistry::builder(
+        rendezvous.fork_guard(),
+        FileProvider::wrap(&file_accessor),
+        cfg.backends.distribution_timeout(),
+    );
 
     // TODO: This currently blocks if the Memcached instance is unavailable.
     //       We would prefer a solution where we can gracefully react to this in order to
@@ -83,33 +272,152 @@ async fn main() -> ExitCode {
         Err(_) => return ExitCode::FAILURE,
     };
 
-    let registry = registry.build();
+    #[cfg(feature = "memcache")]
+    let registry = match registry.add_backends::<StripedMemcacheBackend>(&cfg) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    #[cfg(feature = "gcs")]
+    let registry = match registry.add_backends::<GcsBackend>(&cfg) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    #[cfg(feature = "memory")]
+    let registry = match registry.add_backends::<MemoryBackend>(&cfg) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    #[cfg(feature = "sftp")]
+    let registry = match registry.add_backends::<SftpBackend>(&cfg) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    let registry = match registry.add_mirrors(&cfg.backends.mirror) {
+        Ok(registry) => registry,
+        Err(_) => return ExitCode::FAILURE,
+    };
+
+    if let Some(mode) = cfg.backends.self_test {
+        if !registry.self_test().await && mode == SelfTestMode::Abort {
+            error!("Aborting startup due to failed backend self-test");
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let registry = registry
+        .with_write_quorum(cfg.backends.write_quorum)
+        .with_routing(&cfg.backends.routing)
+        .with_circuit_breaker(cfg.backends.circuit_breaker.as_ref())
+        .with_max_concurrent_distributions(cfg.backends.max_concurrent_distributions)
+        .with_rejection_policy(cfg.backends.on_rejection)
+        .build();
     let backend_sender = registry.get_sender().expect("failed to get backend sender");
+    let distribution = registry.distribution_tracker();
+    let backends = registry.backends();
+    if let Some(interval) = cfg.backends.stats_interval() {
+        backend_stats::spawn_refresh(backends.clone(), interval, &shutdown_tx);
+    }
 
-    let backbone = Arc::new(Backbone::new(backend_sender, rendezvous.fork_guard()));
+    if !backend_sender.has_backends() {
+        if cfg.server.require_backend {
+            warn!("No backends are registered; uploads will be rejected");
+        } else {
+            warn!("No backends are registered; uploaded files will only be held locally until they expire");
+        }
+    }
+
+    let backbone = match Backbone::try_new(
+        backend_sender.clone(),
+        rendezvous.fork_guard(),
+        &cfg.backbone,
+    ) {
+        Ok(backbone) => Arc::new(backbone),
+        Err(e) => {
+            error!("Invalid backbone configuration: {e}");
+            return ExitCode::from(exitcode::CONFIG as u8);
+        }
+    };
     file_accessor.set_backbone(&backbone);
+    state_dump::spawn_handler(Arc::downgrade(&backbone), registry.event_loop());
+
+    // Files left over from a crash are buffered again before new uploads are accepted.
+    backbone.recover_from_wal().await;
 
     // The application state is shared with the Axum servers.
     let app_state = AppState {
         shutdown_tx: shutdown_tx.clone(),
         backbone: backbone.clone(),
+        distribution,
+        backend_sender,
+        backends,
+        config: cfg.clone(),
+        default_content_type,
+        shutting_down: Arc::default(),
+        uploads_paused: Arc::default(),
     };
 
-    let exit_code = serve_requests(matches, app_state).await.err();
+    let drain_timeout = cfg.server.drain_timeout();
+    let exit_code = serve_requests(
+        matches,
+        app_state,
+        cors,
+        client_ip,
+        response_headers,
+        chaos,
+        upload_rate_limit,
+        tls,
+        drain_timeout,
+    )
+    .await
+    .err();
 
     // If all servers are shut down, ensure the news is broadcast as well.
     stop_all_servers(shutdown_tx);
 
     // TODO: Ensure registry is dropped, backbone is halted, ...
     shut_down_backbone(backbone);
-    rendezvous.rendezvous_async().await.ok();
+
+    // Connections that outlived the drain timeout keep the backbone alive, so we can't
+    // wait for its tasks indefinitely. Remaining connections are closed when we exit.
+    if tokio::time::timeout(drain_timeout, rendezvous.rendezvous_async())
+        .await
+        .is_err()
+    {
+        warn!("Timed out waiting for background tasks to complete");
+    }
 
     info!("Bye. 👋");
     exit_code.unwrap_or(ExitCode::SUCCESS)
 }
 
+/// Builds the router serving all endpoints, without any middleware.
+fn routes(app_state: AppState) -> Router {
+    Router::new()
+        .map_metrics_endpoint()
+        .map_openapi_endpoint()
+        .map_shutdown_endpoint()
+        .map_yeet_endpoint()
+        .map_progress_endpoint()
+        .map_yoink_endpoint()
+        .map_bundle_endpoint()
+        .map_meta_endpoint()
+        .map_admin_endpoints()
+        .map_health_endpoints()
+        .with_state(app_state)
+}
+
 fn shut_down_backbone(backbone: Arc<Backbone>) {
-    assert_eq!(Arc::strong_count(&backbone), 1);
+    let references = Arc::strong_count(&backbone);
+    if references > 1 {
+        warn!(
+            "The backbone is still referenced by {count} open connections; they will be closed forcibly",
+            count = references - 1
+        );
+    }
 }
 
 fn stop_all_servers(shutdown_tx: broadcast::Sender<()>) {
@@ -117,19 +425,86 @@ fn stop_all_servers(shutdown_tx: broadcast::Sender<()>) {
     shutdown_tx.send(()).ok();
 }
 
-async fn serve_requests(matches: ArgMatches, app_state: AppState) -> Result<(), ExitCode> {
+#[allow(clippy::too_many_arguments)]
+async fn serve_requests(
+    matches: ArgMatches,
+    app_state: AppState,
+    cors: Option<CorsLayer>,
+    client_ip: services::ClientIpLayer,
+    response_headers: services::ResponseHeadersLayer,
+    chaos: Option<services::ChaosLayer>,
+    upload_rate_limit: Option<services::UploadRateLimitLayer>,
+    tls: Option<tls::TlsAcceptor>,
+    drain_timeout: Duration,
+) -> Result<(), ExitCode> {
     let shutdown_tx = app_state.shutdown_tx.clone();
+    let mut drain_rx = shutdown_tx.subscribe();
+    let shutting_down = app_state.shutting_down.clone();
+
+    let config = app_state.config.clone();
+    let idle_timeout = app_state.config.server.idle_timeout();
+    let header_read_timeout = app_state.config.server.header_read_timeout();
+    let keep_alive_interval = app_state.config.server.keep_alive_interval();
+    let metrics_layer = services::HttpCallMetricsLayer::new(&app_state.config.server.access_log);
+    let header_limits_layer = services::HeaderLimitsLayer::new(&app_state.config.server);
+    let max_header_bytes = app_state.config.server.max_header_bytes();
+    let http1_max_buf_size = services::http1_max_buf_size(&app_state.config.server);
+
+    let app = routes(app_state);
+
+    // The chaos layer is only added when enabled so that it has no overhead otherwise.
+    let app = match chaos {
+        Some(chaos) => app.layer(chaos),
+        None => app,
+    };
 
-    let app = Router::new()
-        .map_metrics_endpoint()
-        .map_shutdown_endpoint()
-        .map_yeet_endpoint()
-        .map_yoink_endpoint()
-        .map_health_endpoints()
-        .with_state(app_state)
-        .layer(services::HttpCallMetricsLayer);
+    // Likewise, transfer events are only tracked if a sink is configured.
+    let app = match &config.server.events {
+        Some(events) => {
+            info!("Emitting transfer events to {sink:?}", sink = events.sink);
+            app.layer(services::TransferEventsLayer::new(EventSink::spawn(events)))
+        }
+        None => app,
+    };
+
+    // Uploads are limited per client, and thus after the client IP was resolved.
+    let app = match upload_rate_limit {
+        Some(upload_rate_limit) => app.layer(upload_rate_limit),
+        None => app,
+    };
 
-    let make_svc = app.into_make_service();
+    // Load is shed before any other work is done for the request.
+    let app = match &config.server.load_shedding {
+        Some(load_shedding) => {
+            info!(
+                "Shedding requests while more than {max} requests are in flight",
+                max = load_shedding.max_in_flight
+            );
+            app.layer(services::LoadSheddingLayer::new(load_shedding))
+        }
+        None => app,
+    };
+
+    // The client IP is resolved first so that it is available to the logs.
+    let app = app
+        .layer(header_limits_layer)
+        .layer(metrics_layer)
+        .layer(client_ip);
+
+    // Preflight requests are answered by the CORS layer before reaching the routes.
+    let app = match cors {
+        Some(cors) => app.layer(cors),
+        None => app,
+    };
+
+    // Static headers are added last so that they are part of every response.
+    let app = app.layer(response_headers);
+
+    // The verified client certificate of each connection is provided to the handlers,
+    // and the idle timeout of a connection is paused while its requests are in progress.
+    let make_svc = tls::ClientCertificateMakeService::new(ActiveRequestsMakeService::new(
+        app.into_make_service_with_connect_info::<SocketAddr>(),
+    ));
 
     let service_builder = ServiceBuilder::new().service(make_svc);
 
@@ -141,14 +516,23 @@ async fn serve_requests(matches: ArgMatches, app_state: AppState) -> Result<(),
         .cloned()
         .collect();
 
+    let accept_limits = AcceptLimits::new(&config.server);
+    if let Some(max) = config.server.max_accept_rate {
+        info!("Accepting at most {max} connections per second");
+    }
+    if let Some(max) = config.server.max_connections {
+        info!("Accepting at most {max} concurrent connections");
+    }
+
     let mut servers = FuturesUnordered::new();
     for addr in http_sockets {
         let mut shutdown_rx = shutdown_tx.subscribe();
 
-        let builder = match Server::try_bind(&addr) {
-            Ok(builder) => {
-                info!("Now listening on http://{addr}", addr = addr);
-                builder
+        let mut incoming = match listener::bind(&addr, &config.server) {
+            Ok(incoming) => {
+                let scheme = if tls.is_some() { "https" } else { "http" };
+                info!("Now listening on {scheme}://{addr}", addr = addr);
+                incoming
             }
             Err(e) => {
                 error!("Unable to bind to {addr}: {error}", addr = addr, error = e);
@@ -161,6 +545,30 @@ async fn serve_requests(matches: ArgMatches, app_state: AppState) -> Result<(),
             }
         };
 
+        incoming.set_keepalive(keep_alive_interval);
+
+        // Wrap each connection such that it is tracked in the metrics and closed
+        // when idle for too long. Accepting pauses while the accept limits are exceeded.
+        let mut acceptor = accept_limits.acceptor();
+        let incoming = accept::poll_fn(move |cx| {
+            ready!(acceptor.poll_ready(cx));
+            Pin::new(&mut incoming).poll_accept(cx).map_ok(|stream| {
+                let permit = acceptor.accepted();
+                IdleTimeoutStream::new(MeteredStream::new(stream, permit), idle_timeout)
+            })
+        });
+        let incoming = tls::Incoming::new(incoming, tls.clone());
+
+        // Oversized headers are refused while decoding, before they are buffered: HTTP/2
+        // header lists by their size, HTTP/1 requests by the size of the read buffer.
+        let mut builder = Server::builder(incoming)
+            .http1_max_buf_size(http1_max_buf_size)
+            .http2_keep_alive_interval(keep_alive_interval)
+            .http2_max_header_list_size(max_header_bytes);
+        if let Some(timeout) = header_read_timeout {
+            builder = builder.http1_header_read_timeout(timeout);
+        }
+
         let server = builder
             .serve(service_builder.clone())
             .with_graceful_shutdown(async move {
@@ -170,9 +578,32 @@ async fn serve_requests(matches: ArgMatches, app_state: AppState) -> Result<(),
         servers.push(server);
     }
 
-    // Wait for all servers to stop.
+    // Wait for all servers to stop. Once a shutdown is initiated, the servers stop accepting
+    // new connections and drain the open ones; we only wait for the drain timeout to elapse.
     let mut exit_code = None;
-    while let Some(result) = servers.next().await {
+    let mut drain_deadline: Option<Instant> = None;
+    loop {
+        let result = tokio::select! {
+            result = servers.next() => match result {
+                Some(result) => result,
+                None => break,
+            },
+            _ = drain_rx.recv(), if drain_deadline.is_none() => {
+                shutting_down.store(true, Ordering::Relaxed);
+                info!("Draining open connections for up to {timeout:?}", timeout = drain_timeout);
+                drain_deadline = Some(Instant::now() + drain_timeout);
+                continue;
+            },
+            _ = sleep_until_deadline(drain_deadline), if drain_deadline.is_some() => {
+                warn!(
+                    "Connections were not drained within {timeout:?}; closing {count} server(s) forcibly",
+                    timeout = drain_timeout,
+                    count = servers.len()
+                );
+                break;
+            }
+        };
+
         match result {
             Ok(()) => {
                 debug!("A server stopped")
@@ -199,6 +630,36 @@ async fn serve_requests(matches: ArgMatches, app_state: AppState) -> Result<(),
     }
 }
 
+async fn sleep_until_deadline(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => futures::future::pending().await,
+    }
+}
+
+/// Registers handlers for SIGTERM and SIGINT (e.g. CTRL-C) that initiate a graceful shutdown.
+#[cfg(unix)]
+fn register_shutdown_handler(shutdown_tx: broadcast::Sender<()>) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigterm =
+        signal(SignalKind::terminate()).expect("Error setting SIGTERM termination handler");
+    let mut sigint =
+        signal(SignalKind::interrupt()).expect("Error setting SIGINT termination handler");
+
+    tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                _ = sigterm.recv() => warn!("Initiating shutdown from OS (SIGTERM)"),
+                _ = sigint.recv() => warn!("Initiating shutdown from OS (SIGINT)"),
+            }
+            shutdown_tx.send(()).ok();
+        }
+    });
+}
+
+/// Registers a handler for CTRL-C and similar that initiates a graceful shutdown.
+#[cfg(not(unix))]
 fn register_shutdown_handler(shutdown_tx: broadcast::Sender<()>) {
     ctrlc::set_handler(move || {
         warn!("Initiating shutdown from OS");
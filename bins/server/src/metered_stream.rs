@@ -0,0 +1,124 @@
+//! Contains a connection wrapper tracking connection metrics.
+
+use metrics::connections::{CloseOutcome, ConnectionMetrics};
+use pin_project::pin_project;
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+use tokio::sync::OwnedSemaphorePermit;
+
+/// Wraps a connection and tracks it in the [`ConnectionMetrics`] from
+/// construction until it is dropped.
+#[pin_project]
+pub struct MeteredStream<S> {
+    #[pin]
+    inner: S,
+    tracker: ConnectionTracker,
+}
+
+/// Tracks the connection as closed on drop.
+///
+/// We require this helper type because [`MeteredStream`] cannot imply [`Drop`]
+/// due to the use of [`pin_project`](pin_project::pin_project).
+struct ConnectionTracker {
+    /// Whether an I/O error occurred on the connection.
+    failed: bool,
+    /// The slot of the connection if the number of connections is limited;
+    /// released when the connection is closed.
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+impl<S> MeteredStream<S> {
+    /// Wraps the connection, holding its connection slot until it is closed.
+    pub fn new(inner: S, permit: Option<OwnedSemaphorePermit>) -> Self {
+        ConnectionMetrics::track_accepted();
+        Self {
+            inner,
+            tracker: ConnectionTracker {
+                failed: false,
+                _permit: permit,
+            },
+        }
+    }
+
+    /// Gets a reference to the wrapped connection.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+}
+
+impl ConnectionTracker {
+    fn observe<T>(&mut self, result: Poll<std::io::Result<T>>) -> Poll<std::io::Result<T>> {
+        if let Poll::Ready(Err(_)) = &result {
+            self.failed = true;
+        }
+        result
+    }
+}
+
+impl Drop for ConnectionTracker {
+    fn drop(&mut self) {
+        let outcome = if self.failed {
+            CloseOutcome::Error
+        } else {
+            CloseOutcome::Clean
+        };
+        ConnectionMetrics::track_closed(outcome);
+    }
+}
+
+impl<S> AsyncRead for MeteredStream<S>
+where
+    S: AsyncRead,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+        this.tracker.observe(this.inner.poll_read(cx, buf))
+    }
+}
+
+impl<S> AsyncWrite for MeteredStream<S>
+where
+    S: AsyncWrite,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let this = self.project();
+        this.tracker.observe(this.inner.poll_write(cx, buf))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Result<(), std::io::Error>> {
+        let this = self.project();
+        this.tracker.observe(this.inner.poll_flush(cx))
+    }
+
+    fn poll_shutdown(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<(), std::io::Error>> {
+        let this = self.project();
+        this.tracker.observe(this.inner.poll_shutdown(cx))
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<Result<usize, std::io::Error>> {
+        let this = self.project();
+        this.tracker
+            .observe(this.inner.poll_write_vectored(cx, bufs))
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.inner.is_write_vectored()
+    }
+}
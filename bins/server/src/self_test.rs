@@ -0,0 +1,126 @@
+//! Contains the startup self-test of the backends.
+
+use async_trait::async_trait;
+use backend_traits::{Backend, ReceiveFileError};
+use file_distribution::hash::{HashMd5, HashSha256};
+use file_distribution::{
+    BoxedFileReader, BytesFileReader, FileAccessorError, FileHashes, FileProvider, GetFile,
+    GetFileReaderError, WriteSummary,
+};
+use hyper::body::Bytes;
+use shortguid::ShortGuid;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::io::AsyncReadExt;
+use tokio::time::Instant;
+use tracing::{error, info, warn};
+
+/// The lifetime of the synthetic file.
+const SELF_TEST_LEASE: Duration = Duration::from_secs(60);
+
+/// Distributes a small synthetic file to each backend and reads it back,
+/// verifying its SHA-256 hash.
+///
+/// Backends that don't support reading files back only have their distribution tested.
+/// Returns `true` if the test passed for all backends.
+pub async fn run(backends: &[Backend]) -> bool {
+    let file = Arc::new(SelfTestFile::new());
+    let provider = FileProvider::wrap(&file);
+
+    let mut passed = true;
+    for backend in backends {
+        let tag = backend.tag();
+        if let Err(e) = backend
+            .distribute_file(file.id, file.summary.clone(), provider.clone())
+            .await
+        {
+            error!(file_id = %file.id, "Self-test failed for backend {tag}: unable to distribute the file: {e}");
+            passed = false;
+            continue;
+        }
+
+        let mut reader = match backend.receive_file(file.id).await {
+            Ok(reader) => reader,
+            Err(ReceiveFileError::NotSupported(_)) => {
+                info!(file_id = %file.id, "Self-test passed for backend {tag} (reading back is not supported)");
+                continue;
+            }
+            Err(e) => {
+                error!(file_id = %file.id, "Self-test failed for backend {tag}: unable to read back the file: {e}");
+                passed = false;
+                continue;
+            }
+        };
+
+        let mut data = Vec::new();
+        if let Err(e) = reader.read_to_end(&mut data).await {
+            error!(file_id = %file.id, "Self-test failed for backend {tag}: unable to read back the file: {e}");
+            passed = false;
+            continue;
+        }
+
+        let mut sha256 = HashSha256::new();
+        sha256.update(&data);
+        if Some(sha256.finalize()) != file.summary.hashes.sha256 {
+            error!(file_id = %file.id, "Self-test failed for backend {tag}: the file read back differs from the original");
+            passed = false;
+            continue;
+        }
+
+        info!(file_id = %file.id, "Self-test passed for backend {tag}");
+    }
+
+    if !passed {
+        warn!("The backend self-test failed");
+    }
+
+    passed
+}
+
+/// A synthetic file used for testing the backends.
+struct SelfTestFile {
+    id: ShortGuid,
+    data: Bytes,
+    summary: Arc<WriteSummary>,
+}
+
+impl SelfTestFile {
+    fn new() -> Self {
+        let id = ShortGuid::new_random();
+        let data = Bytes::from(format!("yeet-yoink self-test {id}"));
+
+        let mut md5 = HashMd5::new();
+        let mut sha256 = HashSha256::new();
+        md5.update(&data);
+        sha256.update(&data);
+
+        let summary = Arc::new(WriteSummary {
+            created: SystemTime::now(),
+            expires: Instant::now() + SELF_TEST_LEASE,
+            hashes: FileHashes::new(Some(md5.finalize()), Some(sha256.finalize()), None),
+            file_name: Some(String::from("self-test.txt")),
+            file_size_bytes: data.len(),
+            metadata: BTreeMap::default(),
+        });
+
+        Self { id, data, summary }
+    }
+}
+
+#[async_trait]
+impl GetFile for SelfTestFile {
+    async fn get_file(&self, id: ShortGuid) -> Result<BoxedFileReader, FileAccessorError> {
+        if id != self.id {
+            return Err(GetFileReaderError::UnknownFile(id).into());
+        }
+
+        Ok(BoxedFileReader::new(BytesFileReader::new(
+            self.data.clone(),
+            Some(String::from("text/plain")),
+            Duration::ZERO,
+            self.summary.expires,
+            Some(self.summary.clone()),
+        )))
+    }
+}
@@ -0,0 +1,176 @@
+use app_config::chaos::ChaosConfig;
+use axum::body::BoxBody;
+use axum::extract::MatchedPath;
+use axum::http::Response;
+use axum::response::IntoResponse;
+use futures::future::BoxFuture;
+use hyper::service::Service;
+use hyper::{Request, StatusCode};
+use rand::Rng;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tower::Layer;
+use tracing::debug;
+
+/// The route prefixes affected by the chaos mode.
+const AFFECTED_ROUTES: &[&str] = &["/yeet", "/yoink"];
+
+/// A middleware injecting latency and errors into `/yeet` and `/yoink` requests
+/// for testing the retry logic of clients.
+#[derive(Clone)]
+pub struct Chaos<S> {
+    inner: S,
+    settings: Arc<ChaosSettings>,
+}
+
+/// A layer for injecting chaos. Uses [`Chaos`].
+#[derive(Clone)]
+pub struct ChaosLayer {
+    settings: Arc<ChaosSettings>,
+}
+
+#[derive(Debug)]
+struct ChaosSettings {
+    delay_rate: f64,
+    delay_min: Duration,
+    delay_max: Duration,
+    error_rate: f64,
+    error_status_codes: Vec<StatusCode>,
+}
+
+impl ChaosLayer {
+    /// Creates a new [`ChaosLayer`] from the configuration.
+    pub fn try_new(config: &ChaosConfig) -> Result<Self, InvalidChaosConfig> {
+        let error_status_codes = config
+            .error_status_codes()
+            .iter()
+            .map(|&code| match StatusCode::from_u16(code) {
+                Ok(status) if status.is_client_error() || status.is_server_error() => Ok(status),
+                _ => Err(InvalidChaosConfig::StatusCode(code)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        if error_status_codes.is_empty() && config.error_rate() > 0.0 {
+            return Err(InvalidChaosConfig::NoStatusCodes);
+        }
+
+        Ok(Self {
+            settings: Arc::new(ChaosSettings {
+                delay_rate: config.delay_rate(),
+                delay_min: config.delay_min(),
+                delay_max: config.delay_max(),
+                error_rate: config.error_rate(),
+                error_status_codes,
+            }),
+        })
+    }
+
+    /// Describes the injected chaos for logging.
+    pub fn describe(&self) -> String {
+        let settings = &self.settings;
+        format!(
+            "delaying {delay:.1}% of requests by {min:?} to {max:?}, failing {error:.1}% of requests with {codes:?}",
+            delay = settings.delay_rate * 100.0,
+            min = settings.delay_min,
+            max = settings.delay_max,
+            error = settings.error_rate * 100.0,
+            codes = settings
+                .error_status_codes
+                .iter()
+                .map(StatusCode::as_u16)
+                .collect::<Vec<_>>()
+        )
+    }
+}
+
+impl<S> Layer<S> for ChaosLayer {
+    type Service = Chaos<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Chaos {
+            inner,
+            settings: self.settings.clone(),
+        }
+    }
+}
+
+impl ChaosSettings {
+    /// Randomly picks the delay to inject, if any.
+    fn pick_delay(&self) -> Option<Duration> {
+        let mut rng = rand::thread_rng();
+        if !rng.gen_bool(self.delay_rate) {
+            return None;
+        }
+        Some(rng.gen_range(self.delay_min..=self.delay_max))
+    }
+
+    /// Randomly picks the error to inject, if any.
+    fn pick_error(&self) -> Option<StatusCode> {
+        let mut rng = rand::thread_rng();
+        if self.error_status_codes.is_empty() || !rng.gen_bool(self.error_rate) {
+            return None;
+        }
+        Some(self.error_status_codes[rng.gen_range(0..self.error_status_codes.len())])
+    }
+}
+
+impl<S, B> Service<Request<B>> for Chaos<S>
+where
+    S: Service<Request<B>> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let affected = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or(false, |path| {
+                AFFECTED_ROUTES
+                    .iter()
+                    .any(|route| path.as_str().starts_with(route))
+            });
+
+        let (delay, error) = if affected {
+            (self.settings.pick_delay(), self.settings.pick_error())
+        } else {
+            (None, None)
+        };
+
+        // The service that was polled ready must be the one handling the request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            if let Some(delay) = delay {
+                debug!("Chaos mode: delaying request by {delay:?}");
+                tokio::time::sleep(delay).await;
+            }
+
+            if let Some(status) = error {
+                debug!("Chaos mode: failing request with {status}");
+                return Ok(problemdetails::new(status)
+                    .with_title("Injected failure")
+                    .with_detail("The failure was injected by the chaos mode")
+                    .into_response());
+            }
+
+            inner.call(request).await.map(IntoResponse::into_response)
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidChaosConfig {
+    #[error("Invalid error status code {0}; only 4xx and 5xx codes are supported")]
+    StatusCode(u16),
+    #[error("No error status codes were specified")]
+    NoStatusCodes,
+}
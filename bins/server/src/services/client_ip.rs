@@ -0,0 +1,269 @@
+use app_config::server::ForwardedHeader;
+use axum::extract::ConnectInfo;
+use hyper::header::{HeaderName, FORWARDED};
+use hyper::service::Service;
+use hyper::{HeaderMap, Request};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::Layer;
+
+/// The `X-Forwarded-For` header.
+static X_FORWARDED_FOR: HeaderName = HeaderName::from_static("x-forwarded-for");
+
+/// The IP address of the client, as a request extension.
+///
+/// For requests from trusted proxies, this is the address reported by them;
+/// otherwise, it is the address of the peer.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct ClientIp(pub IpAddr);
+
+/// A middleware resolving the [`ClientIp`] of requests.
+#[derive(Clone)]
+pub struct ClientIpService<S> {
+    inner: S,
+    trusted_proxies: Arc<[IpNet]>,
+    header: ForwardedHeader,
+}
+
+/// A layer for resolving client IPs. Uses [`ClientIpService`].
+#[derive(Clone)]
+pub struct ClientIpLayer {
+    trusted_proxies: Arc<[IpNet]>,
+    header: ForwardedHeader,
+}
+
+impl ClientIpLayer {
+    /// Creates a new [`ClientIpLayer`] trusting the specified addresses or CIDR ranges
+    /// to report the client IP in the specified header.
+    pub fn try_new(
+        trusted_proxies: &[String],
+        header: ForwardedHeader,
+    ) -> Result<Self, InvalidTrustedProxy> {
+        let trusted_proxies = trusted_proxies
+            .iter()
+            .map(|proxy| {
+                proxy
+                    .parse::<IpNet>()
+                    .or_else(|_| proxy.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| InvalidTrustedProxy(proxy.clone()))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self {
+            trusted_proxies: trusted_proxies.into(),
+            header,
+        })
+    }
+
+    /// Gets the number of trusted addresses or CIDR ranges.
+    pub fn trusted_proxies(&self) -> usize {
+        self.trusted_proxies.len()
+    }
+}
+
+impl<S> Layer<S> for ClientIpLayer {
+    type Service = ClientIpService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ClientIpService {
+            inner,
+            trusted_proxies: self.trusted_proxies.clone(),
+            header: self.header,
+        }
+    }
+}
+
+impl<S, B> Service<Request<B>> for ClientIpService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        let peer = request
+            .extensions()
+            .get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip());
+        if let Some(peer) = peer {
+            let client_ip = self.resolve(peer, request.headers());
+            request.extensions_mut().insert(ClientIp(client_ip));
+        }
+
+        self.inner.call(request)
+    }
+}
+
+impl<S> ClientIpService<S> {
+    /// Resolves the client IP by following the forwarded addresses from the nearest
+    /// hop on for as long as they are trusted proxies. Addresses reported by untrusted
+    /// hops are never followed, and only the configured header is read, so clients
+    /// can't spoof their IP.
+    fn resolve(&self, peer: IpAddr, headers: &HeaderMap) -> IpAddr {
+        if !self.is_trusted(&peer) {
+            return peer;
+        }
+
+        let forwarded = match self.header {
+            ForwardedHeader::XForwardedFor => x_forwarded_for(headers),
+            ForwardedHeader::Forwarded => forwarded_for(headers),
+        };
+
+        let mut client_ip = peer;
+        for addr in forwarded.iter().rev() {
+            // Obfuscated or unknown addresses end the chain at the proxy reporting them.
+            let Some(addr) = addr else {
+                break;
+            };
+
+            client_ip = *addr;
+            if !self.is_trusted(addr) {
+                break;
+            }
+        }
+
+        client_ip
+    }
+
+    fn is_trusted(&self, addr: &IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(addr))
+    }
+}
+
+/// Gets the addresses of the `X-Forwarded-For` headers, from the farthest to the nearest hop.
+fn x_forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all(&X_FORWARDED_FOR)
+        .iter()
+        .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+        .map(|addr| parse_addr(addr.trim()))
+        .collect()
+}
+
+/// Gets the `for` addresses of the `Forwarded` headers (RFC 7239),
+/// from the farthest to the nearest hop.
+fn forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all(FORWARDED)
+        .iter()
+        .flat_map(|value| value.to_str().unwrap_or_default().split(','))
+        .map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for")
+                    .then(|| parse_addr(value.trim_matches('"')))
+            })
+        })
+        .map(Option::flatten)
+        .collect()
+}
+
+/// Parses an address that may include a port, e.g. `192.0.2.60:4711` or `[2001:db8::17]:4711`.
+fn parse_addr(addr: &str) -> Option<IpAddr> {
+    addr.parse::<IpAddr>()
+        .or_else(|_| addr.parse::<SocketAddr>().map(|addr| addr.ip()))
+        .ok()
+        .or_else(|| {
+            let addr = addr.strip_prefix('[')?;
+            addr[..addr.find(']')?].parse().ok()
+        })
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid trusted proxy address or CIDR range: {0}")]
+pub struct InvalidTrustedProxy(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use hyper::header::HeaderValue;
+
+    fn service(header: ForwardedHeader) -> ClientIpService<()> {
+        let trusted = ["10.0.0.0/8".to_string(), "192.0.2.1".to_string()];
+        ClientIpLayer::try_new(&trusted, header)
+            .expect("invalid trusted proxies")
+            .layer(())
+    }
+
+    fn headers(entries: &[(&str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in entries {
+            headers.append(
+                HeaderName::from_bytes(name.as_bytes()).unwrap(),
+                HeaderValue::from_str(value).unwrap(),
+            );
+        }
+        headers
+    }
+
+    fn ip(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn untrusted_peers_are_not_followed() {
+        let service = service(ForwardedHeader::XForwardedFor);
+        let headers = headers(&[("x-forwarded-for", "203.0.113.7")]);
+        assert_eq!(
+            service.resolve(ip("198.51.100.1"), &headers),
+            ip("198.51.100.1")
+        );
+    }
+
+    #[test]
+    fn spoofed_forwarded_header_is_ignored() {
+        let service = service(ForwardedHeader::XForwardedFor);
+        let headers = headers(&[
+            ("forwarded", "for=10.1.2.3"),
+            ("x-forwarded-for", "203.0.113.7"),
+        ]);
+        assert_eq!(service.resolve(ip("10.0.0.1"), &headers), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn spoofed_x_forwarded_for_header_is_ignored() {
+        let service = service(ForwardedHeader::Forwarded);
+        let headers = headers(&[
+            ("forwarded", "for=\"203.0.113.7:4711\""),
+            ("x-forwarded-for", "10.1.2.3"),
+        ]);
+        assert_eq!(service.resolve(ip("10.0.0.1"), &headers), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn multi_hop_chains_stop_at_the_first_untrusted_hop() {
+        let service = service(ForwardedHeader::XForwardedFor);
+
+        // The client prepends a spoofed address, which the proxies append to.
+        let headers = headers(&[
+            ("x-forwarded-for", "10.9.9.9, 203.0.113.7"),
+            ("x-forwarded-for", "192.0.2.1"),
+        ]);
+        assert_eq!(service.resolve(ip("10.0.0.1"), &headers), ip("203.0.113.7"));
+
+        let headers = self::headers(&[("x-forwarded-for", "203.0.113.7, 10.2.0.1, 192.0.2.1")]);
+        assert_eq!(service.resolve(ip("10.0.0.1"), &headers), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn unknown_addresses_end_the_chain() {
+        let service = service(ForwardedHeader::Forwarded);
+        let headers = headers(&[("forwarded", "for=203.0.113.7, for=unknown;proto=https")]);
+        assert_eq!(service.resolve(ip("10.0.0.1"), &headers), ip("10.0.0.1"));
+    }
+
+    #[test]
+    fn missing_header_resolves_to_the_peer() {
+        let service = service(ForwardedHeader::XForwardedFor);
+        assert_eq!(
+            service.resolve(ip("10.0.0.1"), &HeaderMap::new()),
+            ip("10.0.0.1")
+        );
+    }
+}
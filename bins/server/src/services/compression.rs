@@ -0,0 +1,100 @@
+use axum::body::HttpBody;
+use axum::http::{header, HeaderValue, Response, StatusCode};
+use tower_http::compression::predicate::{DefaultPredicate, Predicate};
+use tower_http::compression::CompressionLayer;
+
+/// Content types that are already compressed and gain nothing from being compressed again.
+const COMPRESSED_CONTENT_TYPES: &[&str] = &[
+    "application/gzip",
+    "application/x-gzip",
+    "application/zstd",
+    "application/zip",
+    "application/x-7z-compressed",
+    "application/x-bzip2",
+    "application/x-xz",
+    "application/vnd.rar",
+    "application/x-rar-compressed",
+    "audio/",
+    "video/",
+    "font/woff",
+];
+
+/// Creates a layer compressing responses according to the client's `Accept-Encoding` header.
+///
+/// Compressed responses are sent without a `Content-Length`, i.e. using chunked transfer
+/// encoding. Use together with [`adjust_encoded_headers`] to keep the representation headers
+/// consistent with the transmitted data.
+pub fn compression_layer() -> CompressionLayer<CompressiblePredicate> {
+    CompressionLayer::new().compress_when(CompressiblePredicate::default())
+}
+
+/// A response extension marking a response that is sent as is, without being compressed
+/// by the [`compression_layer`], e.g. because the client asked for the raw body.
+#[derive(Debug, Copy, Clone)]
+pub struct Uncompressed;
+
+/// Adjusts the headers of a response that may have been compressed by the [`compression_layer`].
+///
+/// Since the response depends on the `Accept-Encoding` request header, `Vary` is set unless
+/// the response is marked as [`Uncompressed`]. If the response was compressed, the `ETag`
+/// is weakened and `Content-MD5` and `Digest` are removed, as they describe the
+/// uncompressed data.
+pub fn adjust_encoded_headers<B>(mut response: Response<B>) -> Response<B> {
+    if response.extensions().get::<Uncompressed>().is_some() {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    headers.append(header::VARY, HeaderValue::from_static("accept-encoding"));
+
+    if !headers.contains_key(header::CONTENT_ENCODING) {
+        return response;
+    }
+
+    headers.remove("content-md5");
+    headers.remove("digest");
+    if let Some(etag) = headers.get(header::ETAG) {
+        if !etag.as_bytes().starts_with(b"W/") {
+            let mut weak = b"W/".to_vec();
+            weak.extend_from_slice(etag.as_bytes());
+            if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                headers.insert(header::ETAG, weak);
+            }
+        }
+    }
+
+    response
+}
+
+/// Compresses responses unless they are small, partial, images, of an already compressed
+/// content type or marked as [`Uncompressed`].
+#[derive(Clone, Default)]
+pub struct CompressiblePredicate {
+    default: DefaultPredicate,
+}
+
+impl Predicate for CompressiblePredicate {
+    fn should_compress<B>(&self, response: &Response<B>) -> bool
+    where
+        B: HttpBody,
+    {
+        // The `Content-Range` of partial responses refers to the uncompressed data.
+        if response.status() == StatusCode::PARTIAL_CONTENT
+            || response.extensions().get::<Uncompressed>().is_some()
+            || !self.default.should_compress(response)
+        {
+            return false;
+        }
+
+        let content_type = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        !COMPRESSED_CONTENT_TYPES
+            .iter()
+            .any(|compressed| content_type.starts_with(compressed))
+    }
+}
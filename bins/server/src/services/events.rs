@@ -0,0 +1,197 @@
+use crate::event_sink::{EventSink, TransferDetails, TransferEvent, TransferOperation};
+use crate::services::ClientIp;
+use axum::body::{boxed, BoxBody, Bytes};
+use axum::extract::MatchedPath;
+use axum::http::{header, Response};
+use axum::response::IntoResponse;
+use chrono::Utc;
+use futures::future::BoxFuture;
+use hyper::body::HttpBody;
+use hyper::service::Service;
+use hyper::{HeaderMap, Request};
+use pin_project::pin_project;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use tokio::time::Instant;
+use tower::Layer;
+
+/// A middleware emitting a [`TransferEvent`] to the [`EventSink`] for every `/yeet`
+/// and `/yoink` request once its response was transmitted.
+#[derive(Clone)]
+pub struct TransferEvents<S> {
+    inner: S,
+    sink: EventSink,
+}
+
+/// A layer for emitting transfer events. Uses [`TransferEvents`].
+#[derive(Clone)]
+pub struct TransferEventsLayer {
+    sink: EventSink,
+}
+
+impl TransferEventsLayer {
+    /// Creates a new [`TransferEventsLayer`] emitting to the specified sink.
+    pub fn new(sink: EventSink) -> Self {
+        Self { sink }
+    }
+}
+
+impl<S> Layer<S> for TransferEventsLayer {
+    type Service = TransferEvents<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TransferEvents {
+            inner,
+            sink: self.sink.clone(),
+        }
+    }
+}
+
+impl<S, B> Service<Request<B>> for TransferEvents<S>
+where
+    S: Service<Request<B>> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map(|path| path.as_str().to_string());
+        let operation = route.as_deref().and_then(TransferOperation::of_route);
+        let client_ip = request
+            .extensions()
+            .get::<ClientIp>()
+            .map(|ClientIp(ip)| *ip);
+        let timestamp = Utc::now().to_rfc3339();
+        let start = Instant::now();
+
+        // The service that was polled ready must be the one handling the request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let sink = self.sink.clone();
+        Box::pin(async move {
+            let response = inner.call(request).await?.into_response();
+            let (Some(operation), Some(route)) = (operation, route) else {
+                return Ok(response);
+            };
+
+            let details = response.extensions().get::<TransferDetails>().cloned();
+            let event = TransferEvent {
+                timestamp,
+                operation,
+                route,
+                id: details.as_ref().map(|details| details.id),
+                size_bytes: details.as_ref().and_then(|details| details.size_bytes),
+                hashes: details
+                    .as_ref()
+                    .and_then(|details| details.hashes.as_ref())
+                    .map(Into::into),
+                content_type: details.and_then(|details| details.content_type),
+                client_ip,
+                duration_ms: 0,
+                status: response.status().as_u16(),
+                completed: false,
+            };
+
+            let content_length = response
+                .headers()
+                .get(header::CONTENT_LENGTH)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.parse::<u64>().ok());
+            Ok(response.map(|body| {
+                let completed = body.is_end_stream();
+                boxed(EventBody {
+                    remaining: content_length.or(body.size_hint().exact()),
+                    inner: body,
+                    pending: PendingEvent {
+                        sink,
+                        start,
+                        event: Some(event),
+                        completed,
+                    },
+                })
+            }))
+        })
+    }
+}
+
+/// A response body emitting the event once the body was transmitted or dropped.
+#[pin_project]
+struct EventBody<B> {
+    #[pin]
+    inner: B,
+    /// The number of bytes left to transmit, if known. The server stops polling
+    /// a body once its `Content-Length` was transmitted.
+    remaining: Option<u64>,
+    pending: PendingEvent,
+}
+
+/// Emits the event on drop.
+///
+/// We require this helper type because [`EventBody`] cannot imply [`Drop`]
+/// due to the use of [`pin_project`](pin_project::pin_project).
+struct PendingEvent {
+    sink: EventSink,
+    start: Instant,
+    event: Option<TransferEvent>,
+    /// Whether the body was transmitted completely.
+    completed: bool,
+}
+
+impl<B> HttpBody for EventBody<B>
+where
+    B: HttpBody<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let mut this = self.project();
+        let result = ready!(this.inner.as_mut().poll_data(cx));
+        if let (Some(remaining), Some(Ok(data))) = (this.remaining.as_mut(), &result) {
+            *remaining = remaining.saturating_sub(data.len() as u64);
+        }
+        if result.is_none() || *this.remaining == Some(0) || this.inner.is_end_stream() {
+            this.pending.completed = true;
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for PendingEvent {
+    fn drop(&mut self) {
+        if let Some(mut event) = self.event.take() {
+            event.duration_ms = self.start.elapsed().as_millis() as u64;
+            event.completed = self.completed;
+            self.sink.emit(event);
+        }
+    }
+}
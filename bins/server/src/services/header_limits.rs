@@ -0,0 +1,137 @@
+use app_config::server::ServerConfig;
+use axum::body::BoxBody;
+use axum::http::Response;
+use axum::response::IntoResponse;
+use futures::future::{Either, MapOk};
+use futures::TryFutureExt;
+use hyper::service::Service;
+use hyper::{HeaderMap, Request, StatusCode};
+use std::future::{ready, Ready};
+use std::task::{Context, Poll};
+use tower::Layer;
+use tracing::debug;
+
+/// The room left in the HTTP/1 read buffer for the request line.
+const HTTP1_REQUEST_LINE_BYTES: usize = 8 * 1024;
+
+/// The bytes each HTTP/1 header line takes in addition to its name and value, i.e. `: ` and CRLF.
+const HTTP1_HEADER_OVERHEAD_BYTES: usize = 4;
+
+/// The smallest read buffer hyper accepts for HTTP/1 connections.
+const HTTP1_MIN_BUF_SIZE: usize = 8 * 1024;
+
+/// Gets the size of the HTTP/1 read buffer that fits the request line and the largest
+/// permitted headers, so that larger headers are refused before they are parsed.
+pub fn http1_max_buf_size(config: &ServerConfig) -> usize {
+    let headers = config.max_header_bytes() as usize
+        + config.max_header_count() as usize * HTTP1_HEADER_OVERHEAD_BYTES;
+    (HTTP1_REQUEST_LINE_BYTES + headers).max(HTTP1_MIN_BUF_SIZE)
+}
+
+/// A middleware rejecting requests with too many or too large headers
+/// with `431 Request Header Fields Too Large`.
+#[derive(Clone)]
+pub struct HeaderLimits<S> {
+    inner: S,
+    limits: Limits,
+}
+
+/// A layer limiting the request headers. Uses [`HeaderLimits`].
+#[derive(Clone)]
+pub struct HeaderLimitsLayer {
+    limits: Limits,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Limits {
+    /// The maximum total size of the header names and values in bytes.
+    max_bytes: usize,
+    /// The maximum number of headers.
+    max_count: usize,
+}
+
+impl HeaderLimitsLayer {
+    /// Creates a new [`HeaderLimitsLayer`] using the limits from the configuration.
+    pub fn new(config: &ServerConfig) -> Self {
+        Self {
+            limits: Limits {
+                max_bytes: config.max_header_bytes() as _,
+                max_count: config.max_header_count() as _,
+            },
+        }
+    }
+}
+
+impl<S> Layer<S> for HeaderLimitsLayer {
+    type Service = HeaderLimits<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        HeaderLimits {
+            inner,
+            limits: self.limits,
+        }
+    }
+}
+
+impl<S, B> Service<Request<B>> for HeaderLimits<S>
+where
+    S: Service<Request<B>>,
+    S::Response: IntoResponse,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = Either<
+        Ready<Result<Self::Response, Self::Error>>,
+        MapOk<S::Future, fn(S::Response) -> Self::Response>,
+    >;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        if let Err(detail) = self.limits.check(request.headers()) {
+            debug!("Rejecting request: {detail}");
+            return Either::Left(ready(Ok(too_large_response(detail))));
+        }
+
+        Either::Right(
+            self.inner
+                .call(request)
+                .map_ok(IntoResponse::into_response as fn(_) -> _),
+        )
+    }
+}
+
+impl Limits {
+    /// Checks the headers against the limits, returning a description of the violation.
+    fn check(&self, headers: &HeaderMap) -> Result<(), String> {
+        let count = headers.len();
+        if count > self.max_count {
+            return Err(format!(
+                "The request contains {count} headers, exceeding the maximum of {max}",
+                max = self.max_count
+            ));
+        }
+
+        let size: usize = headers
+            .iter()
+            .map(|(name, value)| name.as_str().len() + value.len())
+            .sum();
+        if size > self.max_bytes {
+            return Err(format!(
+                "The request headers have a size of {size} bytes, exceeding the maximum of {max} bytes",
+                max = self.max_bytes
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn too_large_response(detail: String) -> Response<BoxBody> {
+    problemdetails::new(StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE)
+        .with_title("Request header fields too large")
+        .with_detail(detail)
+        .into_response()
+}
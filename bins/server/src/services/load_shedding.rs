@@ -0,0 +1,193 @@
+use app_config::load_shedding::LoadSheddingConfig;
+use axum::body::{boxed, BoxBody, Bytes};
+use axum::extract::MatchedPath;
+use axum::http::{HeaderValue, Response};
+use axum::response::IntoResponse;
+use futures::future::BoxFuture;
+use hyper::body::HttpBody;
+use hyper::header::RETRY_AFTER;
+use hyper::service::Service;
+use hyper::{HeaderMap, Request, StatusCode};
+use metrics::http::HttpMetrics;
+use pin_project::pin_project;
+use std::collections::HashSet;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tower::Layer;
+use tracing::debug;
+
+/// A middleware rejecting new requests with `503 Service Unavailable` while too many
+/// requests are in flight.
+#[derive(Clone)]
+pub struct LoadShedding<S> {
+    inner: S,
+    shedder: Arc<Shedder>,
+}
+
+/// A layer for shedding load. Uses [`LoadShedding`].
+#[derive(Clone)]
+pub struct LoadSheddingLayer {
+    shedder: Arc<Shedder>,
+}
+
+struct Shedder {
+    /// The number of requests in flight above which new requests are shed.
+    max_in_flight: usize,
+    /// The number of seconds after which clients are asked to retry.
+    retry_after_sec: u64,
+    /// The routes that are never shed.
+    exempt_routes: HashSet<String>,
+    /// The number of requests currently in flight.
+    in_flight: AtomicUsize,
+}
+
+impl LoadSheddingLayer {
+    /// Creates a new [`LoadSheddingLayer`] from the configuration.
+    pub fn new(config: &LoadSheddingConfig) -> Self {
+        Self {
+            shedder: Arc::new(Shedder {
+                max_in_flight: config.max_in_flight,
+                retry_after_sec: config.retry_after().as_secs(),
+                exempt_routes: config
+                    .exempt_routes()
+                    .into_iter()
+                    .map(String::from)
+                    .collect(),
+                in_flight: AtomicUsize::new(0),
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for LoadSheddingLayer {
+    type Service = LoadShedding<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        LoadShedding {
+            inner,
+            shedder: self.shedder.clone(),
+        }
+    }
+}
+
+impl Shedder {
+    /// Admits a request to the route, returning the guard keeping it in flight,
+    /// or `None` if the request is shed.
+    fn admit(self: &Arc<Self>, route: &str) -> Option<InFlightGuard> {
+        let in_flight = self.in_flight.fetch_add(1, Ordering::AcqRel);
+        let guard = InFlightGuard {
+            shedder: self.clone(),
+        };
+        if in_flight >= self.max_in_flight && !self.exempt_routes.contains(route) {
+            return None;
+        }
+        Some(guard)
+    }
+
+    fn overloaded_response(&self) -> Response<BoxBody> {
+        let mut response = problemdetails::new(StatusCode::SERVICE_UNAVAILABLE)
+            .with_title("Service overloaded")
+            .with_detail(format!(
+                "Too many requests are in flight; retry after {retry_after} seconds",
+                retry_after = self.retry_after_sec
+            ))
+            .into_response();
+        response
+            .headers_mut()
+            .insert(RETRY_AFTER, HeaderValue::from(self.retry_after_sec));
+        response
+    }
+}
+
+impl<S, B> Service<Request<B>> for LoadShedding<S>
+where
+    S: Service<Request<B>> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let route = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or("", |path| path.as_str());
+        let Some(guard) = self.shedder.admit(route) else {
+            debug!("Shedding request to {route} since too many requests are in flight");
+            HttpMetrics::track_shed(route);
+            let response = self.shedder.overloaded_response();
+            return Box::pin(async move { Ok(response) });
+        };
+
+        // The service that was polled ready must be the one handling the request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let response = inner.call(request).await?.into_response();
+            Ok(response.map(|body| {
+                boxed(InFlightBody {
+                    inner: body,
+                    _guard: guard,
+                })
+            }))
+        })
+    }
+}
+
+/// A response body keeping its request in flight until it was transmitted or dropped.
+#[pin_project]
+struct InFlightBody<B> {
+    #[pin]
+    inner: B,
+    _guard: InFlightGuard,
+}
+
+/// Counts a request as in flight until dropped.
+struct InFlightGuard {
+    shedder: Arc<Shedder>,
+}
+
+impl<B> HttpBody for InFlightBody<B>
+where
+    B: HttpBody<Data = Bytes>,
+{
+    type Data = Bytes;
+    type Error = B::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        self.project().inner.poll_data(cx)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        self.project().inner.poll_trailers(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> hyper::body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.shedder.in_flight.fetch_sub(1, Ordering::AcqRel);
+    }
+}
@@ -2,10 +2,16 @@ use hyper::service::Service;
 use hyper::{Request, StatusCode, Version};
 use pin_project::pin_project;
 
+use crate::services::ClientIp;
+use crate::tls::ClientCertificate;
+use app_config::server::AccessLogConfig;
 use axum::body::BoxBody;
+use axum::extract::MatchedPath;
 use axum::http::Response;
 use axum::response::IntoResponse;
 use hyper::body::HttpBody;
+use hyper::header::CONTENT_LENGTH;
+use hyper::HeaderMap;
 use metrics::http::HttpMetrics;
 use std::cell::Cell;
 use std::future::Future;
@@ -16,20 +22,46 @@ use tokio::time::Instant;
 use tower::Layer;
 use tracing::debug;
 
+/// The path label used for requests that did not match any route.
+const UNMATCHED_PATH: &str = "<unmatched>";
+
 /// A middleware for call metrics. Uses [`HttpMetrics`].
 #[derive(Clone)]
 pub struct HttpCallMetrics<S> {
     inner: S,
+    sampling: AccessLogSampling,
 }
 
 /// A layer for call metrics. Uses [`HttpCallMetrics`].
 #[derive(Clone, Default)]
-pub struct HttpCallMetricsLayer;
+pub struct HttpCallMetricsLayer {
+    sampling: AccessLogSampling,
+}
+
+/// Decides which requests are logged. Metrics are tracked for every request regardless.
+#[derive(Debug, Clone, Copy)]
+pub struct AccessLogSampling {
+    /// The fraction of successful requests to log.
+    sample_rate: f64,
+    /// The duration above which successful requests are always logged.
+    min_duration: Option<Duration>,
+    /// The body size above which successful requests are always logged.
+    min_size_bytes: Option<u64>,
+}
 
 impl<S> HttpCallMetrics<S> {
     /// Creates a new [`HttpCallMetrics`]
-    pub fn new(inner: S) -> Self {
-        Self { inner }
+    pub fn new(inner: S, sampling: AccessLogSampling) -> Self {
+        Self { inner, sampling }
+    }
+}
+
+impl HttpCallMetricsLayer {
+    /// Creates a new [`HttpCallMetricsLayer`] logging requests according to the configuration.
+    pub fn new(config: &AccessLogConfig) -> Self {
+        Self {
+            sampling: AccessLogSampling::from(config),
+        }
     }
 }
 
@@ -37,8 +69,51 @@ impl<S> Layer<S> for HttpCallMetricsLayer {
     type Service = HttpCallMetrics<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
-        HttpCallMetrics::new(inner)
+        HttpCallMetrics::new(inner, self.sampling)
+    }
+}
+
+impl Default for AccessLogSampling {
+    fn default() -> Self {
+        Self {
+            sample_rate: 1.0,
+            min_duration: None,
+            min_size_bytes: None,
+        }
+    }
+}
+
+impl From<&AccessLogConfig> for AccessLogSampling {
+    fn from(config: &AccessLogConfig) -> Self {
+        Self {
+            sample_rate: config.sample_rate(),
+            min_duration: config.min_duration(),
+            min_size_bytes: config.min_size_bytes,
+        }
+    }
+}
+
+impl AccessLogSampling {
+    /// Randomly decides whether a successful request is sampled.
+    fn sample(&self) -> bool {
+        self.sample_rate >= 1.0 || rand::random::<f64>() < self.sample_rate
     }
+
+    /// Determines whether a successful request exceeds any of the thresholds.
+    fn exceeds_threshold(&self, duration: Duration, size_bytes: Option<u64>) -> bool {
+        let exceeds_duration = matches!(self.min_duration, Some(min) if duration >= min);
+        let exceeds_size =
+            matches!((self.min_size_bytes, size_bytes), (Some(min), Some(size)) if size >= min);
+        exceeds_duration || exceeds_size
+    }
+}
+
+/// Gets the value of the `Content-Length` header, if any.
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
 }
 
 impl<S, B> Service<Request<B>> for HttpCallMetrics<S>
@@ -56,7 +131,7 @@ where
     }
 
     fn call(&mut self, request: Request<B>) -> Self::Future {
-        let tracker = HttpCallMetricTracker::start(&request);
+        let tracker = HttpCallMetricTracker::start(&request, self.sampling);
 
         // We start tracking request time before the first call to the future.
         HttpCallMetricsFuture::new(self.inner.call(request), tracker)
@@ -106,6 +181,8 @@ where
         let result = match response {
             Ok(reply) => {
                 let response = reply.into_response();
+                this.tracker
+                    .set_response_size(content_length(response.headers()));
                 this.tracker
                     .set_state(ResultState::Result(response.status(), response.version()));
                 Ok(response)
@@ -131,6 +208,14 @@ struct HttpCallMetricTracker {
     start: Instant,
     state: Cell<ResultState>,
     path_full: String,
+    /// The resolved IP of the client, or `-` if unknown, followed by the subject
+    /// of its verified certificate if any.
+    client_ip: String,
+    sampling: AccessLogSampling,
+    /// Whether the request was picked for logging regardless of its outcome.
+    sampled: bool,
+    /// The larger of the request and response body sizes, if known.
+    size_bytes: Option<u64>,
 }
 
 pub enum ResultState {
@@ -145,23 +230,36 @@ pub enum ResultState {
 }
 
 impl HttpCallMetricTracker {
-    fn start<B>(request: &Request<B>) -> Self {
+    fn start<B>(request: &Request<B>, sampling: AccessLogSampling) -> Self {
         let method = request.method().clone();
         let path = request.uri().path();
         let version = request.version();
 
         // Ensure we don't create a new metric for every file name, i.e.
-        // /yoink/4d6DOAMKQ5uhlE6eXKM_dQ should be tracked as /yoink.
+        // /yoink/4d6DOAMKQ5uhlE6eXKM_dQ should be tracked as /yoink/:id.
+        // Requests not matching any route are tracked together.
         let path_str = path.to_string();
-        let path_base = match path[1..].find('/') {
-            None => path_str.clone(),
-            Some(pos) => String::from(&path[0..(pos + 1)]),
-        };
+        let path_base = request
+            .extensions()
+            .get::<MatchedPath>()
+            .map_or(UNMATCHED_PATH, |path| path.as_str())
+            .to_string();
+
+        let mut client_ip = request
+            .extensions()
+            .get::<ClientIp>()
+            .map_or_else(|| String::from("-"), |ClientIp(ip)| ip.to_string());
+        if let Some(certificate) = request.extensions().get::<ClientCertificate>() {
+            client_ip = format!("{client_ip} ({certificate})");
+        }
 
-        debug!(
-            "Start processing {version:?} {method} {path} (tracking as {path_base})",
-            path = path_str
-        );
+        let sampled = sampling.sample();
+        if sampled {
+            debug!(
+                "Start processing {version:?} {method} {path} from {client_ip} (tracking as {path_base})",
+                path = path_str
+            );
+        }
         HttpMetrics::inc_in_flight(path_base.as_str());
         let start = Instant::now();
         Self {
@@ -169,8 +267,12 @@ impl HttpCallMetricTracker {
             method,
             path_full: path_str,
             path_base,
+            client_ip,
             start,
             state: Cell::new(ResultState::Started),
+            sampling,
+            sampled,
+            size_bytes: content_length(request.headers()),
         }
     }
 
@@ -178,6 +280,19 @@ impl HttpCallMetricTracker {
         self.state.set(state)
     }
 
+    fn set_response_size(&mut self, size_bytes: Option<u64>) {
+        self.size_bytes = self.size_bytes.max(size_bytes);
+    }
+
+    /// Determines whether a request with the given outcome should be logged.
+    /// Errors are always logged.
+    fn should_log(&self, status: Option<StatusCode>, duration: Duration) -> bool {
+        let is_error = status.map_or(true, |status| {
+            status.is_client_error() || status.is_server_error()
+        });
+        is_error || self.sampled || self.sampling.exceeds_threshold(duration, self.size_bytes)
+    }
+
     fn duration(&self) -> Duration {
         Instant::now() - self.start
     }
@@ -196,26 +311,32 @@ impl Drop for HttpCallMetricTracker {
             }
             ResultState::Failed => {
                 let duration = self.duration();
-                debug!(
-                    "Fail processing {version:?} {method} {path} - {duration:?}",
-                    version = self.version,
-                    method = self.method,
-                    path = self.path_full,
-                    duration = duration
-                );
+                if self.should_log(None, duration) {
+                    debug!(
+                        "Fail processing {version:?} {method} {path} from {client_ip} - {duration:?}",
+                        version = self.version,
+                        method = self.method,
+                        path = self.path_full,
+                        client_ip = self.client_ip,
+                        duration = duration
+                    );
+                }
                 HttpMetrics::track(&self.path_base, self.method.clone(), 0, duration);
             }
             ResultState::Result(status, version) => {
                 let duration = self.duration();
-                debug!(
-                        "Done processing {version:?} {method} {path}: {response_version:?} {response_status} - {duration:?}",
+                if self.should_log(Some(status), duration) {
+                    debug!(
+                        "Done processing {version:?} {method} {path} from {client_ip}: {response_version:?} {response_status} - {duration:?}",
                         version = self.version,
                         method = self.method,
                         path = self.path_full,
+                        client_ip = self.client_ip,
                         duration = duration,
                         response_version = version,
                         response_status = status
                     );
+                }
                 HttpMetrics::track(
                     &self.path_base,
                     self.method.clone(),
@@ -1,5 +1,25 @@
 //! Contains Tower services.
+//!
+//! The optional layers, i.e. [`ChaosLayer`], [`TransferEventsLayer`], [`LoadSheddingLayer`]
+//! and [`UploadRateLimitLayer`], are only meant to be added if their feature is configured,
+//! so that they have no overhead otherwise.
 
+mod chaos;
+mod client_ip;
+mod compression;
+mod events;
+mod header_limits;
+mod load_shedding;
 mod metrics;
+mod response_headers;
+mod upload_rate_limit;
 
+pub use chaos::ChaosLayer;
+pub use client_ip::{ClientIp, ClientIpLayer};
+pub use compression::{adjust_encoded_headers, compression_layer, Uncompressed};
+pub use events::TransferEventsLayer;
+pub use header_limits::{http1_max_buf_size, HeaderLimitsLayer};
+pub use load_shedding::LoadSheddingLayer;
 pub use metrics::HttpCallMetricsLayer;
+pub use response_headers::ResponseHeadersLayer;
+pub use upload_rate_limit::UploadRateLimitLayer;
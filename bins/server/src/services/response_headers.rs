@@ -0,0 +1,169 @@
+use axum::http::{HeaderName, HeaderValue, Response};
+use hyper::header::SERVER;
+use hyper::service::Service;
+use hyper::{HeaderMap, Request};
+use pin_project::pin_project;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use tower::Layer;
+
+/// The security headers added to all responses unless overridden by the configuration.
+const DEFAULT_HEADERS: &[(&str, &str)] = &[
+    ("x-content-type-options", "nosniff"),
+    ("x-frame-options", "DENY"),
+    ("referrer-policy", "no-referrer"),
+];
+
+/// A middleware adding static headers to all responses that don't already contain them.
+#[derive(Clone)]
+pub struct ResponseHeaders<S> {
+    inner: S,
+    headers: Arc<HeaderMap>,
+    server: ServerHeader,
+}
+
+/// A layer adding static response headers. Uses [`ResponseHeaders`].
+#[derive(Clone)]
+pub struct ResponseHeadersLayer {
+    headers: Arc<HeaderMap>,
+    server: ServerHeader,
+}
+
+/// How the `Server` header of responses is treated.
+#[derive(Clone)]
+enum ServerHeader {
+    /// The header is left as is.
+    Keep,
+    /// The header is set to the value, replacing any other.
+    Set(HeaderValue),
+    /// The header is removed.
+    Remove,
+}
+
+impl ResponseHeadersLayer {
+    /// Creates a new [`ResponseHeadersLayer`] adding the configured headers on top of the
+    /// default security headers. Configuring a default header with an empty value removes it.
+    ///
+    /// The `Server` header is set to `server_header` on all responses, or removed from them
+    /// if it is empty.
+    pub fn try_new(
+        configured: &BTreeMap<String, String>,
+        server_header: Option<&str>,
+    ) -> Result<Self, InvalidResponseHeader> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in DEFAULT_HEADERS {
+            headers.insert(
+                HeaderName::from_static(name),
+                HeaderValue::from_static(value),
+            );
+        }
+
+        for (name, value) in configured {
+            let name = HeaderName::try_from(name.as_str())
+                .map_err(|_| InvalidResponseHeader(name.clone()))?;
+            if value.is_empty() {
+                headers.remove(&name);
+                continue;
+            }
+
+            let value = HeaderValue::try_from(value.as_str())
+                .map_err(|_| InvalidResponseHeader(name.to_string()))?;
+            headers.insert(name, value);
+        }
+
+        let server = match server_header {
+            None => ServerHeader::Keep,
+            Some("") => ServerHeader::Remove,
+            Some(value) => ServerHeader::Set(
+                HeaderValue::try_from(value)
+                    .map_err(|_| InvalidResponseHeader(SERVER.to_string()))?,
+            ),
+        };
+
+        Ok(Self {
+            headers: Arc::new(headers),
+            server,
+        })
+    }
+}
+
+impl<S> Layer<S> for ResponseHeadersLayer {
+    type Service = ResponseHeaders<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        ResponseHeaders {
+            inner,
+            headers: self.headers.clone(),
+            server: self.server.clone(),
+        }
+    }
+}
+
+impl<S, B, ResBody> Service<Request<B>> for ResponseHeaders<S>
+where
+    S: Service<Request<B>, Response = Response<ResBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = ResponseHeadersFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        ResponseHeadersFuture {
+            inner: self.inner.call(request),
+            headers: self.headers.clone(),
+            server: self.server.clone(),
+        }
+    }
+}
+
+/// The response future of [`ResponseHeaders`].
+#[pin_project]
+pub struct ResponseHeadersFuture<F> {
+    #[pin]
+    inner: F,
+    headers: Arc<HeaderMap>,
+    server: ServerHeader,
+}
+
+impl<F, ResBody, E> Future for ResponseHeadersFuture<F>
+where
+    F: Future<Output = Result<Response<ResBody>, E>>,
+{
+    type Output = F::Output;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let mut response = ready!(this.inner.poll(cx))?;
+
+        // Headers set by the handlers take precedence.
+        let headers = response.headers_mut();
+        for (name, value) in this.headers.iter() {
+            if !headers.contains_key(name) {
+                headers.insert(name, value.clone());
+            }
+        }
+
+        match this.server {
+            ServerHeader::Keep => {}
+            ServerHeader::Set(value) => {
+                headers.insert(SERVER, value.clone());
+            }
+            ServerHeader::Remove => {
+                headers.remove(SERVER);
+            }
+        }
+
+        Poll::Ready(Ok(response))
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid response header name or value: {0}")]
+pub struct InvalidResponseHeader(String);
@@ -0,0 +1,428 @@
+use crate::event_sink::TransferDetails;
+use crate::services::ClientIp;
+use app_config::rate_limit::{RateLimitKey, UploadRateLimitConfig};
+use axum::body::BoxBody;
+use axum::extract::MatchedPath;
+use axum::http::{HeaderMap, HeaderValue, Method, Response};
+use axum::response::IntoResponse;
+use futures::future::BoxFuture;
+use hyper::header::{HeaderName, CONTENT_LENGTH, RETRY_AFTER};
+use hyper::service::Service;
+use hyper::{Request, StatusCode};
+use metrics::transfer::TransferMetrics;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::time::Instant;
+use tower::Layer;
+use tracing::debug;
+
+/// The route whose requests are limited.
+const LIMITED_ROUTE: &str = "/yeet";
+
+/// The maximum number of clients tracked at once. Further clients share a single limit
+/// until tracked clients were idle for long enough to be forgotten.
+const MAX_CLIENTS: usize = 100_000;
+
+/// A middleware limiting the number and total size of uploads per client.
+#[derive(Clone)]
+pub struct UploadRateLimit<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+/// A layer for limiting uploads per client. Uses [`UploadRateLimit`].
+#[derive(Clone)]
+pub struct UploadRateLimitLayer {
+    limiter: Arc<Limiter>,
+}
+
+struct Limiter {
+    key: RateLimitKey,
+    api_key_header: HeaderName,
+    /// The API keys limited on their own.
+    api_keys: HashSet<String>,
+    interval: Duration,
+    max_uploads: Option<u32>,
+    max_bytes: Option<u64>,
+    clients: Mutex<Clients>,
+}
+
+/// The token buckets of the clients that uploaded recently.
+struct Clients {
+    buckets: HashMap<ClientKey, ClientBuckets>,
+    /// The time at which clients with full buckets were last removed.
+    last_sweep: Instant,
+}
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq)]
+enum ClientKey {
+    Ip(IpAddr),
+    ApiKey(String),
+    /// The clients beyond [`MAX_CLIENTS`].
+    Overflow,
+}
+
+struct ClientBuckets {
+    uploads: TokenBucket,
+    bytes: TokenBucket,
+}
+
+/// A token bucket holding up to one interval worth of tokens. Its tokens may become
+/// negative when more bytes were uploaded than admitted up front.
+struct TokenBucket {
+    capacity: f64,
+    /// The number of tokens added per second.
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl UploadRateLimitLayer {
+    /// Creates a new [`UploadRateLimitLayer`] from the configuration.
+    pub fn try_new(config: &UploadRateLimitConfig) -> Result<Self, InvalidRateLimitConfig> {
+        let api_key_header = HeaderName::from_bytes(config.api_key_header().as_bytes())
+            .map_err(|_| InvalidRateLimitConfig(config.api_key_header().to_string()))?;
+
+        Ok(Self {
+            limiter: Arc::new(Limiter {
+                key: config.key,
+                api_key_header,
+                api_keys: config.api_keys.iter().cloned().collect(),
+                interval: config.interval(),
+                max_uploads: config.max_uploads,
+                max_bytes: config.max_bytes,
+                clients: Mutex::new(Clients {
+                    buckets: HashMap::new(),
+                    last_sweep: Instant::now(),
+                }),
+            }),
+        })
+    }
+}
+
+impl<S> Layer<S> for UploadRateLimitLayer {
+    type Service = UploadRateLimit<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        UploadRateLimit {
+            inner,
+            limiter: self.limiter.clone(),
+        }
+    }
+}
+
+impl Limiter {
+    /// Identifies the client of an upload, or returns `None` if the request is not limited.
+    fn client_key<B>(&self, request: &Request<B>) -> Option<ClientKey> {
+        let is_upload = request.method() == Method::POST
+            && request
+                .extensions()
+                .get::<MatchedPath>()
+                .map_or(false, |path| path.as_str() == LIMITED_ROUTE);
+        if !is_upload {
+            return None;
+        }
+
+        let ip = request
+            .extensions()
+            .get::<ClientIp>()
+            .map(|ClientIp(ip)| *ip);
+        self.identify(request.headers(), ip)
+    }
+
+    /// Identifies a client by its API key if it is one of the configured keys, or by its IP.
+    fn identify(&self, headers: &HeaderMap, ip: Option<IpAddr>) -> Option<ClientKey> {
+        if self.key == RateLimitKey::ApiKey {
+            let api_key = headers
+                .get(&self.api_key_header)
+                .and_then(|value| value.to_str().ok())
+                .filter(|value| self.api_keys.contains(*value));
+            if let Some(api_key) = api_key {
+                return Some(ClientKey::ApiKey(api_key.to_string()));
+            }
+        }
+
+        ip.map(ClientKey::Ip)
+    }
+
+    /// Admits an upload of the specified size, if known, or returns the time after which
+    /// the client may retry. Returns the key of the limit the upload is counted against.
+    fn admit(&self, key: &ClientKey, content_length: Option<u64>) -> Result<ClientKey, Duration> {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        self.sweep(&mut clients, now, false);
+
+        let mut key = key;
+        if !clients.buckets.contains_key(key) && clients.buckets.len() >= MAX_CLIENTS {
+            self.sweep(&mut clients, now, true);
+            if clients.buckets.len() >= MAX_CLIENTS {
+                key = &ClientKey::Overflow;
+            }
+        }
+
+        let buckets = clients
+            .buckets
+            .entry(key.clone())
+            .or_insert_with(|| ClientBuckets {
+                uploads: TokenBucket::new(self.max_uploads.map(f64::from), self.interval, now),
+                bytes: TokenBucket::new(self.max_bytes.map(|max| max as f64), self.interval, now),
+            });
+        buckets.uploads.refill(now);
+        buckets.bytes.refill(now);
+
+        // Uploads larger than the limit are admitted once the bucket is full.
+        let bytes = content_length.map_or(1.0, |length| length as f64);
+        let bytes = bytes.clamp(1.0, buckets.bytes.capacity.max(1.0));
+        let wait = buckets
+            .uploads
+            .time_until(1.0)
+            .max(buckets.bytes.time_until(bytes));
+        if !wait.is_zero() {
+            return Err(wait);
+        }
+
+        buckets.uploads.tokens -= 1.0;
+        Ok(key.clone())
+    }
+
+    /// Deducts the bytes of a completed upload.
+    fn complete(&self, key: &ClientKey, bytes: u64) {
+        let now = Instant::now();
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(buckets) = clients.buckets.get_mut(key) {
+            buckets.bytes.refill(now);
+            buckets.bytes.tokens -= bytes as f64;
+        }
+    }
+
+    /// Removes the clients whose buckets are full again, as they are indistinguishable
+    /// from clients that didn't upload at all. Unless `force`d, this happens at most once
+    /// per interval.
+    fn sweep(&self, clients: &mut Clients, now: Instant, force: bool) {
+        if !force && now.saturating_duration_since(clients.last_sweep) < self.interval {
+            return;
+        }
+
+        clients.last_sweep = now;
+        clients.buckets.retain(|_, buckets| {
+            buckets.uploads.refill(now);
+            buckets.bytes.refill(now);
+            !buckets.uploads.is_full() || !buckets.bytes.is_full()
+        });
+    }
+}
+
+impl TokenBucket {
+    /// Creates a full bucket, or an unlimited one if `capacity` is `None`.
+    fn new(capacity: Option<f64>, interval: Duration, now: Instant) -> Self {
+        let capacity = capacity.unwrap_or(f64::INFINITY);
+        Self {
+            capacity,
+            rate: capacity / interval.as_secs_f64(),
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() * self.rate).min(self.capacity);
+        self.last_refill = now;
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.capacity
+    }
+
+    /// Gets the time until the bucket holds the specified number of tokens.
+    fn time_until(&self, tokens: f64) -> Duration {
+        if self.tokens >= tokens {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((tokens - self.tokens) / self.rate)
+        }
+    }
+}
+
+impl<S, B> Service<Request<B>> for UploadRateLimit<S>
+where
+    S: Service<Request<B>> + Clone + Send + 'static,
+    S::Response: IntoResponse,
+    S::Future: Send,
+    B: Send + 'static,
+{
+    type Response = Response<BoxBody>;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        // The bytes of the upload are deducted from the limit it was counted against.
+        let key = match self.limiter.client_key(&request) {
+            Some(client) => {
+                let content_length = request
+                    .headers()
+                    .get(CONTENT_LENGTH)
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.parse().ok());
+                match self.limiter.admit(&client, content_length) {
+                    Ok(counted) => Some(counted),
+                    Err(wait) => {
+                        debug!("Rejecting upload from {client:?}, which exceeded its upload rate limit");
+                        TransferMetrics::track_rate_limited();
+                        let retry_after = wait.as_secs_f64().ceil().max(1.0) as u64;
+                        let mut response = problemdetails::new(StatusCode::TOO_MANY_REQUESTS)
+                            .with_title("Too many uploads")
+                            .with_detail(format!(
+                                "The upload rate limit was exceeded; retry after {retry_after} seconds"
+                            ))
+                            .into_response();
+                        response
+                            .headers_mut()
+                            .insert(RETRY_AFTER, HeaderValue::from(retry_after));
+                        return Box::pin(async move { Ok(response) });
+                    }
+                }
+            }
+            None => None,
+        };
+
+        // The service that was polled ready must be the one handling the request.
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let limiter = self.limiter.clone();
+        Box::pin(async move {
+            let response = inner.call(request).await?.into_response();
+            if let Some(key) = &key {
+                let bytes = response
+                    .extensions()
+                    .get::<TransferDetails>()
+                    .and_then(|details| details.size_bytes);
+                if let Some(bytes) = bytes {
+                    limiter.complete(key, bytes);
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Invalid API key header name: {0}")]
+pub struct InvalidRateLimitConfig(String);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(max_uploads: Option<u32>, max_bytes: Option<u64>) -> Arc<Limiter> {
+        let config = UploadRateLimitConfig {
+            key: RateLimitKey::ApiKey,
+            api_keys: vec![String::from("known")],
+            interval_sec: Some(10),
+            max_uploads,
+            max_bytes,
+            ..Default::default()
+        };
+        UploadRateLimitLayer::try_new(&config)
+            .expect("invalid rate limit config")
+            .limiter
+    }
+
+    fn ip(last: u8) -> IpAddr {
+        IpAddr::from([192, 0, 2, last])
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn bursts_are_admitted_up_to_the_limit_and_refilled_over_time() {
+        let limiter = limiter(Some(2), None);
+        let client = ClientKey::Ip(ip(1));
+        assert!(limiter.admit(&client, None).is_ok());
+        assert!(limiter.admit(&client, None).is_ok());
+        assert_eq!(
+            limiter.admit(&client, None),
+            Err(Duration::from_secs(5)),
+            "the third upload of the burst was admitted"
+        );
+
+        // Other clients have their own limit.
+        assert!(limiter.admit(&ClientKey::Ip(ip(2)), None).is_ok());
+
+        // One upload is refilled every five seconds.
+        tokio::time::advance(Duration::from_secs(5)).await;
+        assert!(limiter.admit(&client, None).is_ok());
+        assert!(limiter.admit(&client, None).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn completed_bytes_are_deducted() {
+        let limiter = limiter(None, Some(100));
+        let client = ClientKey::Ip(ip(1));
+        assert!(limiter.admit(&client, Some(100)).is_ok());
+        limiter.complete(&client, 100);
+        assert_eq!(
+            limiter.admit(&client, Some(50)),
+            Err(Duration::from_secs(5))
+        );
+
+        // Uploads larger than the limit only need a full bucket.
+        tokio::time::advance(Duration::from_secs(10)).await;
+        assert!(limiter.admit(&client, Some(1000)).is_ok());
+    }
+
+    #[test]
+    fn unknown_api_keys_are_limited_by_ip() {
+        let limiter = limiter(Some(1), None);
+        let mut headers = HeaderMap::new();
+        headers.insert("x-api-key", HeaderValue::from_static("known"));
+        assert_eq!(
+            limiter.identify(&headers, Some(ip(1))),
+            Some(ClientKey::ApiKey(String::from("known")))
+        );
+
+        // Rotating unknown keys doesn't evade the limit of the client.
+        for key in ["rotated-1", "rotated-2", ""] {
+            headers.insert("x-api-key", HeaderValue::from_static(key));
+            assert_eq!(
+                limiter.identify(&headers, Some(ip(1))),
+                Some(ClientKey::Ip(ip(1)))
+            );
+        }
+        assert_eq!(
+            limiter.identify(&HeaderMap::new(), Some(ip(1))),
+            Some(ClientKey::Ip(ip(1)))
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn clients_beyond_the_maximum_share_a_limit() {
+        let limiter = limiter(Some(1), None);
+        {
+            let mut clients = limiter.clients.lock().unwrap();
+            let now = Instant::now();
+            for index in 0..MAX_CLIENTS {
+                let mut buckets = ClientBuckets {
+                    uploads: TokenBucket::new(Some(1.0), limiter.interval, now),
+                    bytes: TokenBucket::new(None, limiter.interval, now),
+                };
+                buckets.uploads.tokens = 0.0;
+                clients
+                    .buckets
+                    .insert(ClientKey::ApiKey(index.to_string()), buckets);
+            }
+        }
+
+        assert!(limiter.admit(&ClientKey::Ip(ip(1)), None).is_ok());
+        assert!(limiter.admit(&ClientKey::Ip(ip(2)), None).is_err());
+        assert_eq!(
+            limiter.clients.lock().unwrap().buckets.len(),
+            MAX_CLIENTS + 1
+        );
+    }
+}
@@ -0,0 +1,107 @@
+//! Contains the dump of the server state to the log on `SIGUSR1`, for diagnosing
+//! a stuck instance, e.g. one that hangs during shutdown.
+
+// Without signals, the state can't be requested.
+#![cfg_attr(not(unix), allow(dead_code, unused_imports))]
+
+use backbone::{Backbone, CleanupGuard, SNAPSHOT_LOCK_TIMEOUT};
+use metrics::connections::ConnectionMetrics;
+use metrics::http::HttpMetrics;
+use std::sync::Weak;
+use tokio::task::AbortHandle;
+use tracing::{info, warn};
+
+/// Logs the state of the server whenever the process receives `SIGUSR1`.
+///
+/// The handler keeps running during a shutdown, and only holds weak references so that
+/// it doesn't keep the backbone alive.
+#[cfg(unix)]
+pub fn spawn_handler(backbone: Weak<Backbone>, registry_loop: AbortHandle) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut sigusr1 = match signal(SignalKind::user_defined1()) {
+        Ok(signal) => signal,
+        Err(e) => {
+            warn!("Failed to register the SIGUSR1 handler; the state can't be dumped: {e}");
+            return;
+        }
+    };
+
+    tokio::spawn(async move {
+        while sigusr1.recv().await.is_some() {
+            dump(&backbone, &registry_loop).await;
+        }
+    });
+}
+
+/// Signals are not supported on this platform.
+#[cfg(not(unix))]
+pub fn spawn_handler(_backbone: Weak<Backbone>, _registry_loop: AbortHandle) {}
+
+async fn dump(backbone: &Weak<Backbone>, registry_loop: &AbortHandle) {
+    info!(
+        "State dump requested (SIGUSR1): {requests} requests in flight on {connections} connections; backend event loop {registry}; {guards} cleanup guards outstanding",
+        requests = HttpMetrics::in_flight(),
+        connections = ConnectionMetrics::active(),
+        registry = running(!registry_loop.is_finished()),
+        guards = CleanupGuard::outstanding(),
+    );
+
+    let Some(backbone) = backbone.upgrade() else {
+        info!("State dump: the backbone was dropped");
+        return;
+    };
+
+    let snapshot = backbone.snapshot().await;
+    info!(
+        "State dump: {pending} backbone commands pending, command loop {running}",
+        pending = snapshot.pending_commands,
+        running = running(snapshot.command_loop_running),
+    );
+
+    let Some(files) = &snapshot.files else {
+        warn!(
+            "State dump: backbone lock held for more than {timeout:?}; the files can't be listed",
+            timeout = SNAPSHOT_LOCK_TIMEOUT
+        );
+        return;
+    };
+
+    info!(
+        "State dump: the backbone holds {count} file{plural}",
+        count = files.len(),
+        plural = if files.len() == 1 { "" } else { "s" },
+    );
+
+    let id_format = backbone.id_format();
+    for file in files {
+        let size = file.file_size_bytes.map_or_else(
+            || String::from("being written"),
+            |size| format!("{size} bytes"),
+        );
+        let path = file
+            .temp_file_path
+            .as_ref()
+            .map_or_else(String::new, |path| format!(" in {path:?}"));
+        info!(
+            file_id = %file.id,
+            "State dump: file {id}{path}: {size}, {readers}, age {age:?} of a lease of {lease:?}",
+            id = id_format.format(file.id),
+            readers = if file.accepts_readers {
+                "accepting readers"
+            } else {
+                "expired"
+            },
+            age = file.age,
+            lease = file.lease,
+        );
+    }
+}
+
+fn running(running: bool) -> &'static str {
+    if running {
+        "running"
+    } else {
+        "stopped"
+    }
+}
@@ -0,0 +1,838 @@
+//! Contains an in-process server for tests, enabled by the `testing` feature.
+//!
+//! The [`TestHarness`] wires up the [`AppState`] and the routes like `main` does, but
+//! without binding a socket or registering backends. Requests are sent by driving the
+//! router as a [`tower::Service`], e.g. using [`tower::ServiceExt::oneshot`].
+
+// Outside of tests, the harness is only built to be available to them.
+#![cfg_attr(not(test), allow(dead_code))]
+
+use crate::AppState;
+use app_config::AppConfig;
+use axum::body::Body;
+use axum::headers::ContentType;
+use axum::http::{Request, StatusCode};
+use axum::response::Response;
+use axum::Router;
+use backbone::Backbone;
+use backend_traits::{Backend, BackendCommand, BackendCommandSender, DistributionTracker};
+use rendezvous::Rendezvous;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{broadcast, mpsc};
+use tower::ServiceExt;
+
+/// The number of backend commands buffered by [`TestHarness::new`].
+const BACKEND_COMMAND_BUFFER: usize = 64;
+
+/// The time [`TestHarness::shut_down`] waits for the background tasks.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// An in-process server for driving the endpoints in tests.
+pub struct TestHarness {
+    state: AppState,
+    rendezvous: Rendezvous,
+}
+
+/// Builds a [`TestHarness`].
+pub struct TestHarnessBuilder {
+    config: AppConfig,
+    backend_sender: Option<BackendCommandSender>,
+    backends: Vec<Backend>,
+}
+
+impl TestHarness {
+    /// Creates a harness for the configuration without any backends.
+    ///
+    /// Returns the receiver of the commands the backbone issues to the backends,
+    /// e.g. for asserting that uploaded files are distributed.
+    pub fn new(config: AppConfig) -> (Self, mpsc::Receiver<BackendCommand>) {
+        let (sender, receiver) = mpsc::channel(BACKEND_COMMAND_BUFFER);
+        let backend_sender = BackendCommandSender::new(sender, DistributionTracker::default());
+        let harness = Self::builder(config)
+            .with_backend_sender(backend_sender)
+            .build();
+        (harness, receiver)
+    }
+
+    /// Creates a builder for a harness using the configuration.
+    pub fn builder(config: AppConfig) -> TestHarnessBuilder {
+        TestHarnessBuilder {
+            config,
+            backend_sender: None,
+            backends: Vec::new(),
+        }
+    }
+
+    /// Gets the router serving all endpoints, to be driven as a [`tower::Service`].
+    ///
+    /// Like the routes served by `main`, the router expects the [`ClientIp`](crate::services::ClientIp)
+    /// and connection info extensions to be set by the caller where needed; the
+    /// middleware is not included.
+    pub fn router(&self) -> Router {
+        crate::routes(self.state.clone())
+    }
+
+    /// Gets the application state, e.g. for inspecting the backbone.
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    /// Sends a request to the router and returns the response.
+    pub async fn send(&self, request: Request<Body>) -> Response {
+        self.router()
+            .oneshot(request)
+            .await
+            .unwrap_or_else(|e| match e {})
+    }
+
+    /// Uploads the body to `/yeet` and returns the ID of the file in the client format.
+    ///
+    /// ## Panics
+    /// Panics if the upload is not answered with `201 Created`.
+    pub async fn upload(&self, body: impl Into<Body>) -> String {
+        let request = Request::post("/yeet")
+            .body(body.into())
+            .expect("invalid upload request");
+        self.upload_request(request).await
+    }
+
+    /// Sends an upload request and returns the ID of the file in the client format.
+    ///
+    /// Waits until the file was written completely, so that its digests and metadata
+    /// are served when it is downloaded.
+    ///
+    /// ## Panics
+    /// Panics if the upload is not answered with `201 Created`.
+    pub async fn upload_request(&self, request: Request<Body>) -> String {
+        let response = self.send(request).await;
+        assert_eq!(response.status(), StatusCode::CREATED, "the upload failed");
+        let id = response.headers()["yy-id"]
+            .to_str()
+            .expect("invalid ID header")
+            .to_owned();
+
+        let backbone = &self.state.backbone;
+        let file_id = backbone.id_format().parse(&id).expect("invalid ID");
+        while let Ok(None) = backbone.get_summary(file_id).await {
+            tokio::task::yield_now().await;
+        }
+        id
+    }
+
+    /// Downloads a file from `/yoink/:id`.
+    pub async fn fetch(&self, id: &str) -> Response {
+        let request = Request::get(format!("/yoink/{id}"))
+            .body(Body::empty())
+            .expect("invalid download request");
+        self.send(request).await
+    }
+
+    /// Serves the router on a local port until the harness is shut down, e.g. for
+    /// WebSocket connections or requests that must be sent over the wire.
+    pub async fn serve(&self) -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("failed to bind");
+        let address = listener
+            .local_addr()
+            .expect("failed to get the local address");
+        let server = axum::Server::from_tcp(listener)
+            .expect("failed to serve")
+            .serve(self.router().into_make_service());
+
+        let mut shutdown_rx = self.state.shutdown_tx.subscribe();
+        tokio::spawn(server.with_graceful_shutdown(async move {
+            shutdown_rx.recv().await.ok();
+        }));
+        address
+    }
+
+    /// Stops the backbone and waits briefly for its background tasks to complete.
+    ///
+    /// Files whose lease is still running keep these tasks alive until it expires;
+    /// they are not waited for.
+    pub async fn shut_down(self) {
+        let Self { state, rendezvous } = self;
+        state.shutdown_tx.send(()).ok();
+        drop(state);
+
+        tokio::time::timeout(SHUTDOWN_TIMEOUT, rendezvous.rendezvous_async())
+            .await
+            .ok();
+    }
+}
+
+impl TestHarnessBuilder {
+    /// Uses the sender for the commands the backbone issues to the backends.
+    /// By default, the commands are discarded.
+    pub fn with_backend_sender(mut self, backend_sender: BackendCommandSender) -> Self {
+        self.backend_sender = Some(backend_sender);
+        self
+    }
+
+    /// Uses the backends for reading files, e.g. for `/by-sha256/:hex`.
+    pub fn with_backends<I: IntoIterator<Item = Backend>>(mut self, backends: I) -> Self {
+        self.backends.extend(backends);
+        self
+    }
+
+    /// Builds the harness; must be called within a Tokio runtime.
+    ///
+    /// ## Panics
+    /// Panics if the configured default content type is invalid.
+    pub fn build(self) -> TestHarness {
+        let config = Arc::new(self.config);
+        let default_content_type = config
+            .server
+            .default_content_type()
+            .parse::<ContentType>()
+            .expect("invalid default content type");
+
+        let backend_sender = self.backend_sender.unwrap_or_else(|| {
+            let (sender, _) = mpsc::channel(1);
+            BackendCommandSender::new(sender, DistributionTracker::default())
+        });
+        let distribution = backend_sender.distribution_tracker().clone();
+
+        let rendezvous = Rendezvous::new();
+        let backbone = Arc::new(
+            Backbone::try_new(
+                backend_sender.clone(),
+                rendezvous.fork_guard(),
+                &config.backbone,
+            )
+            .expect("invalid backbone configuration"),
+        );
+
+        let (shutdown_tx, _) = broadcast::channel(1);
+        let state = AppState {
+            shutdown_tx,
+            backbone,
+            distribution,
+            backend_sender,
+            backends: self.backends.into(),
+            config,
+            default_content_type,
+            shutting_down: Arc::default(),
+            uploads_paused: Arc::default(),
+        };
+
+        TestHarness { state, rendezvous }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use backend_traits::DistributionState;
+    use tokio_tungstenite::tungstenite::Message;
+
+    #[tokio::test]
+    async fn upload_and_download_round_trip() {
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+
+        let request = Request::post("/yeet")
+            .header("content-type", "text/plain")
+            .body(Body::from("hello"))
+            .unwrap();
+        let id = harness.upload_request(request).await;
+
+        let response = harness.fetch(&id).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn raw_download_only_describes_the_body() {
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+        let content = "hello ".repeat(64);
+        let request = Request::post("/yeet?file_name=hello.txt")
+            .header("content-type", "text/plain")
+            .header("x-meta-owner", "alice")
+            .body(Body::from(content.clone()))
+            .unwrap();
+        let id = harness.upload_request(request).await;
+
+        // The raw body is not compressed, even if the client accepts it.
+        let request = Request::get(format!("/yoink/{id}?raw=true"))
+            .header("accept-encoding", "gzip")
+            .body(Body::empty())
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-type"], "text/plain");
+        assert_eq!(response.headers()["content-length"], "384");
+        let names: Vec<_> = response
+            .headers()
+            .keys()
+            .map(|name| name.as_str())
+            .collect();
+        assert!(names
+            .iter()
+            .all(|name| ["content-type", "content-length"].contains(name)));
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], content.as_bytes());
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn hashes_are_returned_without_the_content() {
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+        let id = harness.upload("hello").await;
+
+        let response = harness.fetch(&format!("{id}/hashes")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let hashes: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(hashes["md5"], "5d41402abc4b2a76b9719d911017c592");
+        assert_eq!(
+            hashes["sha256"],
+            "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+        );
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn digest_is_verified_and_sent_on_request() {
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+
+        // The MD5 digest of "hello" is XUFAKrxLKna5cZ2REBfFkg==.
+        let request = Request::post("/yeet")
+            .header("digest", "md5=AAAAAAAAAAAAAAAAAAAAAA==")
+            .body(Body::from("hello"))
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        let request = Request::post("/yeet")
+            .header("digest", "MD5=XUFAKrxLKna5cZ2REBfFkg==,unixsum=30")
+            .body(Body::from("hello"))
+            .unwrap();
+        let id = harness.upload_request(request).await;
+
+        let request = Request::get(format!("/yoink/{id}"))
+            .header("want-digest", "sha-256;q=0, md5")
+            .body(Body::empty())
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["digest"], "md5=XUFAKrxLKna5cZ2REBfFkg==");
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn metadata_is_returned_on_download() {
+        let mut config = AppConfig::default();
+        config.server.max_metadata_entries = Some(1);
+        let (harness, _commands) = TestHarness::new(config);
+
+        let request = Request::post("/yeet")
+            .header("x-meta-owner", "alice")
+            .header("x-meta-purpose", "test")
+            .body(Body::from("hello"))
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(
+            response.status(),
+            StatusCode::REQUEST_HEADER_FIELDS_TOO_LARGE
+        );
+
+        let request = Request::post("/yeet")
+            .header("x-meta-owner", "alice")
+            .body(Body::from("hello"))
+            .unwrap();
+        let id = harness.upload_request(request).await;
+
+        let response = harness.fetch(&id).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-meta-owner"], "alice");
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn uploads_are_rejected_while_too_many_files_are_open() {
+        let mut config = AppConfig::default();
+        config.backbone.max_open_files = Some(1);
+        let (harness, _commands) = TestHarness::new(config);
+        harness.upload("hello").await;
+
+        let request = Request::post("/yeet").body(Body::from("world")).unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()["retry-after"], "1");
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn files_removed_within_the_distribution_delay_are_not_distributed() {
+        let mut config = AppConfig::default();
+        config.backbone.max_lease_sec = Some(1);
+        config.backbone.distribution_delay_ms = Some(1500);
+        let (harness, mut commands) = TestHarness::new(config);
+        harness.upload("hello").await;
+
+        // The lease expires before the delay elapsed.
+        tokio::time::advance(Duration::from_millis(1100)).await;
+        let command = tokio::time::timeout(Duration::from_secs(1), commands.recv()).await;
+        assert!(command.is_err(), "the removed file was distributed");
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn delayed_files_are_distributed_on_shutdown() {
+        let mut config = AppConfig::default();
+        config.backbone.distribution_delay_ms = Some(60_000);
+        let (harness, mut commands) = TestHarness::new(config);
+        harness.upload("hello").await;
+
+        harness.shut_down().await;
+        let command = tokio::time::timeout(Duration::from_secs(1), commands.recv()).await;
+        assert!(
+            matches!(command, Ok(Some(BackendCommand::DistributeFile(..)))),
+            "the file was not distributed on shutdown"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn quorum_wait_starts_after_the_distribution_delay() {
+        let mut config = AppConfig::default();
+        config.backbone.distribution_delay_ms = Some(1500);
+        config.backends.write_quorum = Some(1);
+        config.backends.write_quorum_wait_sec = Some(1);
+
+        let tracker = DistributionTracker::new(["test"]).with_write_quorum(Some(1));
+        let (sender, mut commands) = mpsc::channel(BACKEND_COMMAND_BUFFER);
+        let harness = TestHarness::builder(config)
+            .with_backend_sender(BackendCommandSender::new(sender, tracker.clone()))
+            .build();
+
+        // Stores each file right away once the delay elapsed.
+        tokio::spawn(async move {
+            while let Some(command) = commands.recv().await {
+                if let BackendCommand::DistributeFile(id, summary) = command {
+                    tracker.begin(id, summary.expires);
+                    tracker.update(id, "test", DistributionState::Stored);
+                }
+            }
+        });
+
+        // The upload only succeeds if the quorum is awaited past the delay.
+        harness.upload("hello").await;
+
+        // On a paused clock, the shutdown would time out before the backbone stopped.
+        tokio::time::resume();
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn concurrent_uploads_to_the_same_id_are_rejected() {
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+        let backbone = harness.state().backbone.clone();
+        let id = backbone.new_id();
+        backbone.issue_ids([id, id]);
+
+        // The first upload holds the ID while its body is still being transmitted.
+        let (mut sender, body) = Body::channel();
+        let first = tokio::spawn(
+            harness
+                .router()
+                .oneshot(Request::post("/yeet").body(body).unwrap()),
+        );
+        sender.send_data("hel".into()).await.unwrap();
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while backbone.get_file(id).await.is_err() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("the first upload didn't start");
+
+        let request = Request::post("/yeet").body(Body::from("world")).unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        sender.send_data("lo".into()).await.unwrap();
+        drop(sender);
+        let response = first.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+
+        // The file stays intact, and its ID can't be reused while it is held.
+        backbone.issue_ids([id]);
+        let request = Request::post("/yeet").body(Body::from("world")).unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+
+        let response = harness.fetch(&backbone.id_format().format(id)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(&body[..], b"hello");
+
+        drop(backbone);
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn randomly_named_temporary_files_are_listed_in_the_snapshot() {
+        let mut config = AppConfig::default();
+        config.backbone.random_file_names = true;
+        let (harness, _commands) = TestHarness::new(config);
+        let id = harness.upload("hello").await;
+
+        let snapshot = harness.state().backbone.snapshot().await;
+        let files = snapshot.files.expect("the backbone is locked");
+        assert_eq!(files.len(), 1);
+        let path = files[0]
+            .temp_file_path
+            .as_ref()
+            .expect("the temporary file is not listed");
+        assert!(path.is_file());
+        assert!(!path.to_string_lossy().contains(&id));
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn redirected_files_are_held_past_their_lease() {
+        let mut config = AppConfig::default();
+        config.backbone.max_lease_sec = Some(1);
+        // Only randomly named temporary files are listed in the snapshot.
+        config.backbone.random_file_names = true;
+        config.server.accel_redirect = Some(app_config::server::AccelRedirectConfig {
+            location: "/yy-files/".to_string(),
+            hold_sec: Some(60),
+        });
+        let (harness, _commands) = TestHarness::new(config);
+        let id = harness.upload("hello").await;
+
+        let snapshot = harness.state().backbone.snapshot().await;
+        let files = snapshot.files.expect("the backbone is locked");
+        let path = files[0]
+            .temp_file_path
+            .clone()
+            .expect("the temporary file is not listed");
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+
+        let response = harness.fetch(&id).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response.headers()["x-accel-redirect"],
+            format!("/yy-files/{file_name}").as_str()
+        );
+        drop(response);
+
+        // The proxy may still be about to open the file after its lease expired.
+        tokio::time::sleep(Duration::from_millis(1100)).await;
+        for _ in 0..100 {
+            if harness.fetch(&id).await.status() != StatusCode::OK {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_ne!(harness.fetch(&id).await.status(), StatusCode::OK);
+        assert!(path.is_file(), "the redirected file was removed");
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn files_are_deleted_from_the_backends_on_request() {
+        let (harness, mut commands) = TestHarness::new(AppConfig::default());
+        harness.upload("hello").await;
+        let Some(BackendCommand::DistributeFile(id, _)) = commands.recv().await else {
+            panic!("the file is not distributed");
+        };
+
+        let request = Request::post(format!("/admin/delete/{id}"))
+            .body(Body::empty())
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert!(matches!(
+            commands.recv().await,
+            Some(BackendCommand::DeleteFile(deleted)) if deleted == id
+        ));
+
+        harness.shut_down().await;
+    }
+
+    /// Receives the next progress message of an upload from the WebSocket.
+    async fn next_progress<S>(socket: &mut S) -> serde_json::Value
+    where
+        S: futures::Stream<Item = tokio_tungstenite::tungstenite::Result<Message>> + Unpin,
+    {
+        use futures::StreamExt;
+
+        let message = tokio::time::timeout(Duration::from_secs(5), socket.next())
+            .await
+            .expect("no progress was reported")
+            .expect("the socket was closed")
+            .expect("failed to receive the progress");
+        match message {
+            Message::Text(text) => serde_json::from_str(&text).unwrap(),
+            message => panic!("unexpected message {message:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn progress_is_streamed_while_uploading_to_a_reserved_id() {
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+        let address = harness.serve().await;
+
+        let request = Request::post("/yeet/reserve").body(Body::empty()).unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let reservation: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let id = reservation["id"].as_str().unwrap().to_owned();
+
+        let (mut socket, _) =
+            tokio_tungstenite::connect_async(format!("ws://{address}/yeet/{id}/progress"))
+                .await
+                .expect("failed to subscribe to the progress");
+        assert_eq!(next_progress(&mut socket).await["buffered_bytes"], 0);
+
+        let (mut sender, body) = Body::channel();
+        let upload = tokio::spawn(
+            harness
+                .router()
+                .oneshot(Request::post(format!("/yeet?id={id}")).body(body).unwrap()),
+        );
+        sender.send_data("hel".into()).await.unwrap();
+        loop {
+            let progress = next_progress(&mut socket).await;
+            assert_eq!(progress["state"], "pending");
+            if progress["buffered_bytes"] == 3 {
+                break;
+            }
+        }
+
+        sender.send_data("lo".into()).await.unwrap();
+        drop(sender);
+        let response = upload.await.unwrap().unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()["yy-id"], id.as_str());
+        loop {
+            let progress = next_progress(&mut socket).await;
+            if progress["state"] == "completed" {
+                assert_eq!(progress["file_size_bytes"], 5);
+                break;
+            }
+            assert_eq!(progress["state"], "pending");
+        }
+
+        // Reservations are used up by the upload.
+        let request = Request::post(format!("/yeet?id={id}"))
+            .body(Body::from("again"))
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn malformed_chunked_bodies_are_rejected() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+        let addr = harness.serve().await;
+
+        // The second chunk size is not hexadecimal.
+        let mut stream = tokio::net::TcpStream::connect(addr).await.unwrap();
+        stream
+            .write_all(
+                b"POST /yeet HTTP/1.1\r\nHost: localhost\r\nTransfer-Encoding: chunked\r\n\r\n\
+                  5\r\nhello\r\nzz\r\nworld\r\n0\r\n\r\n",
+            )
+            .await
+            .unwrap();
+
+        let mut response = Vec::new();
+        tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut response))
+            .await
+            .expect("the connection was not closed")
+            .unwrap();
+        let response = String::from_utf8_lossy(&response);
+        assert!(
+            response.starts_with("HTTP/1.1 400 Bad Request\r\n"),
+            "unexpected response: {response}"
+        );
+        assert!(response.contains("content-type: application/problem+json"));
+        assert!(response.contains(r#""title":"Malformed request body""#));
+        assert!(!response.contains(r#""error":"#));
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn sha256_trailer_matches_the_header() {
+        use axum::http::Version;
+        use hyper::body::HttpBody;
+
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+        let id = harness.upload("hello").await;
+
+        let request = Request::get(format!("/yoink/{id}"))
+            .version(Version::HTTP_2)
+            .header("te", "trailers")
+            .body(Body::empty())
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let sha256 = response.headers()["yy-file-sha256"].clone();
+
+        let mut body = response.into_body();
+        while let Some(chunk) = body.data().await {
+            chunk.unwrap();
+        }
+        let trailers = body
+            .trailers()
+            .await
+            .unwrap()
+            .expect("no trailers were sent");
+        assert_eq!(trailers["yy-file-sha256"], sha256);
+
+        harness.shut_down().await;
+    }
+
+    #[cfg(feature = "memory")]
+    #[tokio::test]
+    async fn ranges_are_served_without_the_digests_of_the_file() {
+        use app_config::memory::MemoryBackendConfig;
+        use axum::http::Version;
+        use backbone::FileAccessorBridge;
+        use backend_memory::MemoryBackend;
+        use file_distribution::FileProvider;
+        use hyper::body::HttpBody;
+
+        // The file is uploaded to one instance and read from a backend by another.
+        let (uploader, mut commands) = TestHarness::new(AppConfig::default());
+        uploader.upload("hello world").await;
+        let Some(BackendCommand::DistributeFile(id, summary)) = commands.recv().await else {
+            panic!("the file is not distributed");
+        };
+
+        let bridge = Arc::new(FileAccessorBridge::default());
+        bridge.set_backbone(&uploader.state().backbone);
+        let backend = Backend::wrap(MemoryBackend::new(&MemoryBackendConfig {
+            tag: String::from("memory"),
+            ..Default::default()
+        }));
+        backend
+            .distribute_file(id, summary, FileProvider::wrap(&bridge))
+            .await
+            .expect("failed to store the file");
+
+        let harness = TestHarness::builder(AppConfig::default())
+            .with_backends([backend])
+            .build();
+        let request = Request::get(
+            "/by-sha256/b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+        )
+        .version(Version::HTTP_2)
+        .header("te", "trailers")
+        .header("range", "bytes=6-10")
+        .body(Body::empty())
+        .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::PARTIAL_CONTENT);
+        assert_eq!(response.headers()["content-range"], "bytes 6-10/11");
+        for name in ["digest", "content-md5", "yy-file-md5", "yy-file-sha256"] {
+            assert!(
+                !response.headers().contains_key(name),
+                "the partial response carries the {name} header of the complete file"
+            );
+        }
+
+        let mut body = response.into_body();
+        let mut data = Vec::new();
+        while let Some(chunk) = body.data().await {
+            data.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(&data[..], b"world");
+        assert!(body.trailers().await.unwrap().is_none());
+
+        harness.shut_down().await;
+        uploader.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn metrics_are_compressed_if_accepted() {
+        let mut config = AppConfig::default();
+        config.server.metrics_gzip_min_bytes = Some(0);
+        let (harness, _commands) = TestHarness::new(config);
+
+        let request = Request::get("/metrics")
+            .header("accept-encoding", "br, gzip;q=0.5")
+            .body(Body::empty())
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["content-encoding"], "gzip");
+
+        let request = Request::get("/metrics")
+            .header("accept-encoding", "*, gzip;q=0")
+            .body(Body::empty())
+            .unwrap();
+        let response = harness.send(request).await;
+        assert!(!response.headers().contains_key("content-encoding"));
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn ids_use_the_configured_format() {
+        let mut config = AppConfig::default();
+        config.backbone.id_format = app_config::backbone::IdFormat::Prefix;
+        config.backbone.id_prefix_length = Some(8);
+        let (harness, _commands) = TestHarness::new(config);
+
+        let id = harness.upload("hello").await;
+        assert_eq!(id.len(), 8);
+        assert_eq!(harness.fetch(&id).await.status(), StatusCode::OK);
+
+        let response = harness.fetch("AAAAAAAAAAAAAAAAAAAAAA").await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn unknown_file_is_not_found() {
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+
+        let response = harness.fetch("AAAAAAAAAAAAAAAAAAAAAA").await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        harness.shut_down().await;
+    }
+
+    #[tokio::test]
+    async fn unknown_file_is_rendered_as_html_for_browsers() {
+        let (harness, _commands) = TestHarness::new(AppConfig::default());
+
+        let request = Request::get("/yoink/AAAAAAAAAAAAAAAAAAAAAA")
+            .header("accept", "text/html,application/xhtml+xml,*/*;q=0.8")
+            .body(Body::empty())
+            .unwrap();
+        let response = harness.send(request).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            response.headers()["content-type"],
+            "text/html; charset=utf-8"
+        );
+        assert_eq!(response.headers()["vary"], "accept");
+
+        harness.shut_down().await;
+    }
+}
@@ -0,0 +1,379 @@
+//! Contains the termination of TLS connections and the verification of client certificates.
+
+use crate::listener::{Connection, TcpConnection};
+use app_config::tls::TlsConfig;
+use futures::future::BoxFuture;
+use futures::stream::FuturesUnordered;
+use futures::StreamExt;
+use hyper::server::accept::Accept;
+use hyper::service::Service;
+use hyper::Request;
+use pin_project::pin_project;
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::future::Future;
+use std::io::BufReader;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::rustls::server::{VerifierBuilderError, WebPkiClientVerifier};
+use tokio_rustls::rustls::{RootCertStore, ServerConfig};
+use tracing::{debug, info};
+use x509_parser::extensions::GeneralName;
+
+/// The time after which connections that didn't complete the TLS handshake are closed.
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Performs the TLS handshakes of accepted connections.
+#[derive(Clone)]
+pub struct TlsAcceptor {
+    acceptor: tokio_rustls::TlsAcceptor,
+}
+
+/// The verified certificate a client presented during the TLS handshake.
+///
+/// Available to the handlers as a request extension on connections with a
+/// verified client certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientCertificate {
+    /// The distinguished name of the subject, e.g. `CN=uploader, O=Example`.
+    pub subject: String,
+    /// The subject alternative names.
+    pub subject_alt_names: Vec<SubjectAltName>,
+}
+
+/// A subject alternative name of a [`ClientCertificate`]. Other kinds of names are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SubjectAltName {
+    Dns(String),
+    Email(String),
+    Uri(String),
+    Ip(IpAddr),
+}
+
+impl TlsAcceptor {
+    /// Loads the certificates and keys of the configuration.
+    pub fn try_new(config: &TlsConfig) -> Result<Self, TlsConfigError> {
+        let provider = Arc::new(tokio_rustls::rustls::crypto::ring::default_provider());
+        let builder = ServerConfig::builder_with_provider(provider.clone())
+            .with_safe_default_protocol_versions()?;
+
+        let builder = match &config.client_auth {
+            None => builder.with_no_client_auth(),
+            Some(client_auth) => {
+                let mut roots = RootCertStore::empty();
+                for cert in load_certs(&client_auth.ca_path)? {
+                    roots.add(cert)?;
+                }
+
+                let verifier =
+                    WebPkiClientVerifier::builder_with_provider(Arc::new(roots), provider);
+                let verifier = if client_auth.required() {
+                    info!(
+                        "Requiring client certificates issued by the CAs in {path:?}",
+                        path = client_auth.ca_path
+                    );
+                    verifier
+                } else {
+                    info!(
+                        "Verifying optional client certificates issued by the CAs in {path:?}",
+                        path = client_auth.ca_path
+                    );
+                    verifier.allow_unauthenticated()
+                };
+                builder.with_client_cert_verifier(verifier.build()?)
+            }
+        };
+
+        let mut server_config = builder
+            .with_single_cert(load_certs(&config.cert_path)?, load_key(&config.key_path)?)?;
+        server_config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+
+        Ok(Self {
+            acceptor: tokio_rustls::TlsAcceptor::from(Arc::new(server_config)),
+        })
+    }
+
+    /// Performs the handshake of a connection. Returns `None` if it failed, e.g. because
+    /// the client presented no or an invalid certificate.
+    fn handshake(&self, stream: TcpConnection) -> BoxFuture<'static, Option<Connection>> {
+        let addr = stream.get_ref().get_ref().remote_addr();
+        let accept = self.acceptor.accept(stream);
+        Box::pin(async move {
+            match tokio::time::timeout(HANDSHAKE_TIMEOUT, accept).await {
+                Ok(Ok(stream)) => {
+                    let client_certificate = stream
+                        .get_ref()
+                        .1
+                        .peer_certificates()
+                        .and_then(|certs| certs.first())
+                        .and_then(|cert| ClientCertificate::from_der(cert))
+                        .map(Arc::new);
+                    Some(Connection::Tls {
+                        stream: Box::new(stream),
+                        client_certificate,
+                    })
+                }
+                Ok(Err(e)) => {
+                    debug!(
+                        "Closing the connection from {addr} after the TLS handshake failed: {e}"
+                    );
+                    None
+                }
+                Err(_) => {
+                    debug!("Closing the connection from {addr} after the TLS handshake timed out");
+                    None
+                }
+            }
+        })
+    }
+}
+
+impl ClientCertificate {
+    /// Parses the subject and subject alternative names of a DER encoded certificate.
+    fn from_der(der: &[u8]) -> Option<Self> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        let subject_alt_names = cert
+            .subject_alternative_name()
+            .ok()
+            .flatten()
+            .map(|names| {
+                names
+                    .value
+                    .general_names
+                    .iter()
+                    .filter_map(SubjectAltName::from_general_name)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        Some(Self {
+            subject: cert.subject().to_string(),
+            subject_alt_names,
+        })
+    }
+}
+
+impl SubjectAltName {
+    fn from_general_name(name: &GeneralName) -> Option<Self> {
+        match name {
+            GeneralName::DNSName(name) => Some(Self::Dns(name.to_string())),
+            GeneralName::RFC822Name(email) => Some(Self::Email(email.to_string())),
+            GeneralName::URI(uri) => Some(Self::Uri(uri.to_string())),
+            GeneralName::IPAddress(bytes) => match bytes.len() {
+                4 => <[u8; 4]>::try_from(*bytes).ok().map(IpAddr::from),
+                16 => <[u8; 16]>::try_from(*bytes).ok().map(IpAddr::from),
+                _ => None,
+            }
+            .map(Self::Ip),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ClientCertificate {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.subject)?;
+        for name in &self.subject_alt_names {
+            write!(f, ", {name}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SubjectAltName {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SubjectAltName::Dns(name) => write!(f, "DNS:{name}"),
+            SubjectAltName::Email(email) => write!(f, "email:{email}"),
+            SubjectAltName::Uri(uri) => write!(f, "URI:{uri}"),
+            SubjectAltName::Ip(ip) => write!(f, "IP:{ip}"),
+        }
+    }
+}
+
+/// Accepts connections, performing their TLS handshakes concurrently if TLS is configured.
+pub struct Incoming<I> {
+    incoming: I,
+    /// The acceptor for TLS connections; `None` if connections are unencrypted.
+    acceptor: Option<TlsAcceptor>,
+    /// The connections whose handshake is in progress.
+    handshakes: FuturesUnordered<BoxFuture<'static, Option<Connection>>>,
+    /// Whether the wrapped listener stopped accepting connections.
+    exhausted: bool,
+}
+
+impl<I> Incoming<I> {
+    pub fn new(incoming: I, acceptor: Option<TlsAcceptor>) -> Self {
+        Self {
+            incoming,
+            acceptor,
+            handshakes: FuturesUnordered::new(),
+            exhausted: false,
+        }
+    }
+}
+
+impl<I> Accept for Incoming<I>
+where
+    I: Accept<Conn = TcpConnection, Error = std::io::Error> + Unpin,
+{
+    type Conn = Connection;
+    type Error = std::io::Error;
+
+    fn poll_accept(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Conn, Self::Error>>> {
+        let this = self.get_mut();
+        let Some(acceptor) = &this.acceptor else {
+            return Pin::new(&mut this.incoming)
+                .poll_accept(cx)
+                .map_ok(Connection::Plain);
+        };
+
+        // Handshakes are started for all pending connections so that a slow client
+        // doesn't hold up the others.
+        while !this.exhausted {
+            match Pin::new(&mut this.incoming).poll_accept(cx) {
+                Poll::Ready(Some(Ok(stream))) => this.handshakes.push(acceptor.handshake(stream)),
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Some(Err(e))),
+                Poll::Ready(None) => this.exhausted = true,
+                Poll::Pending => break,
+            }
+        }
+
+        while let Poll::Ready(Some(connection)) = this.handshakes.poll_next_unpin(cx) {
+            if let Some(connection) = connection {
+                return Poll::Ready(Some(Ok(connection)));
+            }
+        }
+
+        if this.exhausted && this.handshakes.is_empty() {
+            Poll::Ready(None)
+        } else {
+            Poll::Pending
+        }
+    }
+}
+
+/// Provides the [`ClientCertificate`] of each connection to the handlers.
+#[derive(Clone)]
+pub struct ClientCertificateMakeService<M> {
+    inner: M,
+}
+
+/// Adds the [`ClientCertificate`] of the connection to each request.
+#[derive(Clone)]
+pub struct ClientCertificateService<S> {
+    inner: S,
+    client_certificate: Option<Arc<ClientCertificate>>,
+}
+
+impl<M> ClientCertificateMakeService<M> {
+    pub fn new(inner: M) -> Self {
+        Self { inner }
+    }
+}
+
+impl<'a, M> Service<&'a Connection> for ClientCertificateMakeService<M>
+where
+    M: Service<&'a Connection>,
+{
+    type Response = ClientCertificateService<M::Response>;
+    type Error = M::Error;
+    type Future = ClientCertificateFuture<M::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, connection: &'a Connection) -> Self::Future {
+        ClientCertificateFuture {
+            client_certificate: connection.client_certificate().cloned(),
+            inner: self.inner.call(connection),
+        }
+    }
+}
+
+/// Resolves to the [`ClientCertificateService`] of a connection.
+#[pin_project]
+pub struct ClientCertificateFuture<F> {
+    #[pin]
+    inner: F,
+    client_certificate: Option<Arc<ClientCertificate>>,
+}
+
+impl<F, S, E> Future for ClientCertificateFuture<F>
+where
+    F: Future<Output = Result<S, E>>,
+{
+    type Output = Result<ClientCertificateService<S>, E>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.project();
+        let service = ready!(this.inner.poll(cx))?;
+        Poll::Ready(Ok(ClientCertificateService {
+            inner: service,
+            client_certificate: this.client_certificate.take(),
+        }))
+    }
+}
+
+impl<S, B> Service<Request<B>> for ClientCertificateService<S>
+where
+    S: Service<Request<B>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut request: Request<B>) -> Self::Future {
+        if let Some(client_certificate) = &self.client_certificate {
+            request
+                .extensions_mut()
+                .insert(ClientCertificate::clone(client_certificate));
+        }
+        self.inner.call(request)
+    }
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, TlsConfigError> {
+    let file = File::open(path).map_err(|e| TlsConfigError::Io(path.to_path_buf(), e))?;
+    let certs = rustls_pemfile::certs(&mut BufReader::new(file))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| TlsConfigError::Io(path.to_path_buf(), e))?;
+    if certs.is_empty() {
+        return Err(TlsConfigError::NoCertificates(path.to_path_buf()));
+    }
+    Ok(certs)
+}
+
+fn load_key(path: &Path) -> Result<PrivateKeyDer<'static>, TlsConfigError> {
+    let file = File::open(path).map_err(|e| TlsConfigError::Io(path.to_path_buf(), e))?;
+    rustls_pemfile::private_key(&mut BufReader::new(file))
+        .map_err(|e| TlsConfigError::Io(path.to_path_buf(), e))?
+        .ok_or_else(|| TlsConfigError::NoPrivateKey(path.to_path_buf()))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum TlsConfigError {
+    #[error("Failed to read {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("The file {0:?} contains no certificates")]
+    NoCertificates(PathBuf),
+    #[error("The file {0:?} contains no private key")]
+    NoPrivateKey(PathBuf),
+    #[error(transparent)]
+    Tls(#[from] tokio_rustls::rustls::Error),
+    #[error("Invalid client certificate verification: {0}")]
+    Verifier(#[from] VerifierBuilderError),
+}
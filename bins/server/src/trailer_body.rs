@@ -0,0 +1,75 @@
+//! Contains a response body emitting the SHA-256 hash of the transmitted data as a trailer.
+
+use axum::body::{Bytes, HttpBody};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use file_distribution::hash::HashSha256;
+use futures::Stream;
+use pin_project::pin_project;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+/// The name of the trailer carrying the hex encoded SHA-256 hash of the body.
+const SHA256_TRAILER: HeaderName = HeaderName::from_static("yy-file-sha256");
+
+/// Wraps a stream of data and hashes it as it is transmitted. Once the stream
+/// is exhausted, the hash is sent as the [`SHA256_TRAILER`] trailer.
+///
+/// Since the hash is calculated from the transmitted data, it is available even
+/// if the file's hash was not known up front.
+#[pin_project]
+pub struct Sha256TrailerBody<S> {
+    #[pin]
+    stream: S,
+    hasher: Option<HashSha256>,
+}
+
+impl<S> Sha256TrailerBody<S> {
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            hasher: Some(HashSha256::new()),
+        }
+    }
+}
+
+impl<S> HttpBody for Sha256TrailerBody<S>
+where
+    S: Stream<Item = std::io::Result<Bytes>>,
+{
+    type Data = Bytes;
+    type Error = std::io::Error;
+
+    fn poll_data(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Self::Data, Self::Error>>> {
+        let this = self.project();
+        let result = ready!(this.stream.poll_next(cx));
+        match &result {
+            Some(Ok(chunk)) => {
+                if let Some(hasher) = this.hasher.as_mut() {
+                    hasher.update(chunk);
+                }
+            }
+            // An incomplete body must not be vouched for.
+            Some(Err(_)) => *this.hasher = None,
+            None => {}
+        }
+        Poll::Ready(result)
+    }
+
+    fn poll_trailers(
+        self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+    ) -> Poll<Result<Option<HeaderMap>, Self::Error>> {
+        let Some(hasher) = self.project().hasher.take() else {
+            return Poll::Ready(Ok(None));
+        };
+
+        let value = HeaderValue::from_str(&hex::encode(hasher.finalize()))
+            .expect("hex encoded values are valid header values");
+        let mut trailers = HeaderMap::new();
+        trailers.insert(SHA256_TRAILER, value);
+        Poll::Ready(Ok(Some(trailers)))
+    }
+}
@@ -0,0 +1,89 @@
+//! Contains the `validate-config` subcommand.
+
+use crate::{cors, services};
+use app_config::AppConfig;
+use axum::headers::ContentType;
+use std::process::ExitCode;
+
+/// Validates the configuration without starting the server or connecting to any backend,
+/// printing the problems found and a summary of what would be registered.
+///
+/// Exits with [`exitcode::CONFIG`] if the configuration is invalid.
+pub fn run(cfg: &AppConfig) -> ExitCode {
+    let mut problems = cfg.validate();
+
+    let server = &cfg.server;
+    if server
+        .default_content_type()
+        .parse::<ContentType>()
+        .is_err()
+    {
+        problems.push(format!(
+            "Invalid default content type: {content_type}",
+            content_type = server.default_content_type()
+        ));
+    }
+    if let Some(Err(e)) = server.cors.as_ref().map(cors::build_layer) {
+        problems.push(format!("Invalid CORS configuration: {e}"));
+    }
+    if let Err(e) =
+        services::ClientIpLayer::try_new(&server.trusted_proxies, server.forwarded_header)
+    {
+        problems.push(format!("Invalid trusted proxy configuration: {e}"));
+    }
+    if let Err(e) = services::ResponseHeadersLayer::try_new(
+        &server.response_headers,
+        server.server_header.as_deref(),
+    ) {
+        problems.push(format!("Invalid response header configuration: {e}"));
+    }
+    if !server.http_duration_buckets.is_empty()
+        && !metrics::http::valid_buckets(&server.http_duration_buckets)
+    {
+        problems.push(format!(
+            "Invalid HTTP duration buckets: {e}",
+            e = metrics::http::InvalidBuckets
+        ));
+    }
+    if let Some(Err(e)) = server.chaos.as_ref().map(services::ChaosLayer::try_new) {
+        problems.push(format!("Invalid chaos mode configuration: {e}"));
+    }
+    if let Some(Err(e)) = server
+        .upload_rate_limit
+        .as_ref()
+        .map(services::UploadRateLimitLayer::try_new)
+    {
+        problems.push(format!("Invalid upload rate limit configuration: {e}"));
+    }
+
+    let backends = cfg.backends.configured();
+    if backends.is_empty() {
+        println!("No backends would be registered");
+    } else {
+        println!("Backends that would be registered:");
+        for backend in &backends {
+            println!("  {tag} ({kind})", tag = backend.tag, kind = backend.kind);
+        }
+    }
+    if let Some(quorum) = cfg.backends.write_quorum {
+        println!("Write quorum: {quorum}");
+    }
+    if !cfg.backends.routing.is_empty() {
+        println!("Routing rules: {count}", count = cfg.backends.routing.len());
+    }
+
+    if problems.is_empty() {
+        println!("The configuration is valid");
+        return ExitCode::SUCCESS;
+    }
+
+    eprintln!(
+        "The configuration has {count} problem{plural}:",
+        count = problems.len(),
+        plural = if problems.len() == 1 { "" } else { "s" }
+    );
+    for problem in &problems {
+        eprintln!("  - {problem}");
+    }
+    ExitCode::from(exitcode::CONFIG as u8)
+}
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The default time for which the IDs of removed files are remembered.
+pub const DEFAULT_TOMBSTONE_TTL: Duration = Duration::from_secs(3600);
+
+/// The default maximum time for which a file is kept if reads extend its lease.
+pub const DEFAULT_MAX_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// The default number of characters of IDs in the [`IdFormat::Prefix`] format.
+pub const DEFAULT_ID_PREFIX_LENGTH: usize = 11;
+
+/// The default number of bytes of each upload held in memory; `0` writes all uploads to
+/// temporary files.
+pub const DEFAULT_MEMORY_BUFFER_BYTES: usize = 0;
+
+/// The valid numbers of characters of IDs in the [`IdFormat::Prefix`] format, i.e. 48 to 126 bits.
+pub const ID_PREFIX_LENGTHS: std::ops::RangeInclusive<usize> = 8..=21;
+
+/// The maximum lease jitter in percent; a jitter of `100` could shorten leases to zero.
+pub const MAX_LEASE_JITTER_PERCENT: u8 = 99;
+
+/// The configuration of the local file buffering backbone.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct BackboneConfig {
+    /// Whether to deduplicate uploads by their SHA-256 hash. If enabled, uploading a file
+    /// whose content is identical to a file that is currently held returns the ID of the
+    /// existing file instead of storing the duplicate. Defaults to `false`.
+    #[serde(default)]
+    pub deduplicate: bool,
+    /// The maximum deviation of a file's lease from the default lease, in percent.
+    /// Each file's lease is randomly extended or shortened by up to this amount so that
+    /// files uploaded in a burst don't all expire at the same instant.
+    /// At most [`MAX_LEASE_JITTER_PERCENT`]. Defaults to `0`, i.e. no jitter.
+    #[serde(default)]
+    pub lease_jitter_percent: u8,
+    /// The number of seconds for which expired files can still be read until they are
+    /// removed. Reads within this window are marked as stale. Defaults to no grace period,
+    /// i.e. files are removed as soon as their lease expires.
+    #[serde(default)]
+    pub expired_grace_sec: Option<u32>,
+    /// The digests to calculate for uploaded files unless overridden per request.
+    /// Defaults to MD5 and SHA-256.
+    #[serde(default)]
+    pub digests: Option<Vec<DigestAlgorithm>>,
+    /// The minimum number of bytes that must be available in the temporary directory.
+    /// Below it, the readiness and health checks fail with `503 Service Unavailable` so that
+    /// load balancers can drain the node before uploads fail. Disabled by default.
+    #[serde(default)]
+    pub min_free_bytes: Option<u64>,
+    /// The minimum number of inodes that must be available in the temporary directory.
+    /// Below it, the readiness and health checks fail. Disabled by default.
+    #[serde(default)]
+    pub min_free_inodes: Option<u64>,
+    /// The strategy for assigning IDs to uploaded files, i.e. `random`, `uuidv7` or
+    /// `content-hash`. Defaults to [`IdStrategy::Random`].
+    #[serde(default)]
+    pub id_strategy: IdStrategy,
+    /// The format in which IDs are returned to and accepted from clients, i.e. `short-guid`,
+    /// `uuid` or `prefix`. Defaults to [`IdFormat::ShortGuid`].
+    #[serde(default)]
+    pub id_format: IdFormat,
+    /// The number of characters of IDs in the `prefix` format.
+    /// Defaults to [`DEFAULT_ID_PREFIX_LENGTH`].
+    #[serde(default)]
+    pub id_prefix_length: Option<usize>,
+    /// The number of seconds for which the IDs of removed files are remembered. Requests for
+    /// such files are answered with `410 Gone`, while IDs that were never issued result in
+    /// `404 Not Found`. Use `0` to disable. Defaults to [`DEFAULT_TOMBSTONE_TTL`].
+    #[serde(default)]
+    pub tombstone_ttl_sec: Option<u64>,
+    /// The number of bytes of each upload to hold in memory. Uploads are only written to
+    /// a temporary file once they exceed it, or right away if they are announced to.
+    /// Defaults to [`DEFAULT_MEMORY_BUFFER_BYTES`], i.e. all uploads are written to
+    /// temporary files.
+    #[serde(default)]
+    pub memory_buffer_bytes: Option<usize>,
+    /// Whether reading a file via `/yoink` extends its lease, so that frequently read files
+    /// stay in the local buffer instead of being fetched from the backends again.
+    /// Defaults to `false`.
+    #[serde(default)]
+    pub sliding_expiration: bool,
+    /// The maximum number of seconds for which a file is kept after its creation if reads
+    /// extend its lease. Defaults to [`DEFAULT_MAX_LIFETIME`].
+    #[serde(default)]
+    pub max_lifetime_sec: Option<u64>,
+    /// Whether to name temporary files randomly instead of after the file ID, so that
+    /// other processes on the host can't correlate files on disk with the IDs returned
+    /// to clients. Defaults to `false`.
+    #[serde(default)]
+    pub random_file_names: bool,
+    /// Whether to calculate the hashes of uploads on the blocking thread pool instead of
+    /// the task receiving the upload, keeping the async runtime responsive under heavy
+    /// ingest. Each upload then occupies a blocking thread. Defaults to `false`.
+    #[serde(default)]
+    pub blocking_hashing: bool,
+    /// The maximum number of seconds for which a file is held after its creation,
+    /// regardless of how its lease was determined. Longer leases, e.g. due to the jitter
+    /// or extensions by reads, are clamped to it. Disabled by default.
+    #[serde(default)]
+    pub max_lease_sec: Option<u64>,
+    /// The directory of the write-ahead log. If set, uploads are mirrored to it until a
+    /// backend stored them, and files left over after a crash are distributed again on
+    /// startup. Only used if backends are registered. Disabled by default.
+    #[serde(default)]
+    pub wal_dir: Option<PathBuf>,
+    /// The maximum number of files held at once, including files that are being uploaded.
+    /// New uploads are rejected with `503 Service Unavailable` while the limit is reached.
+    /// Defaults to no limit.
+    #[serde(default)]
+    pub max_open_files: Option<usize>,
+    /// The number of milliseconds to wait after a file was uploaded before distributing it
+    /// to the backends. Files removed within this window are never distributed; as files
+    /// can't be deleted locally via the API yet, this only applies to files whose lease
+    /// expired. Files still waiting on shutdown are distributed right away.
+    /// Defaults to `0`, i.e. files are distributed as soon as they are uploaded.
+    #[serde(default)]
+    pub distribution_delay_ms: Option<u64>,
+}
+
+/// The strategy for assigning IDs to uploaded files.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdStrategy {
+    /// Random IDs (UUID v4).
+    #[default]
+    Random,
+    /// Time-sortable IDs (UUID v7).
+    Uuidv7,
+    /// IDs derived from the SHA-256 hash of the content. Since the hash is only known once
+    /// the upload completes, files are held under a provisional random ID until then.
+    /// Uploading identical content yields the ID of the file already held.
+    ContentHash,
+}
+
+/// The format in which IDs are returned to and accepted from clients.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum IdFormat {
+    /// The 22 character URL-safe base64 form, e.g. `KmC6e8laTnK3dioUSMpM0Q`.
+    #[default]
+    ShortGuid,
+    /// The hyphenated UUID form, e.g. `2a60ba7b-c95a-4e72-b776-2a14c8ca4cd1`.
+    Uuid,
+    /// A prefix of the base64 form with `id_prefix_length` characters. IDs are generated
+    /// with the remaining bits set to zero, so shorter prefixes make collisions more likely.
+    /// Can't be combined with [`IdStrategy::Uuidv7`], whose prefixes are mostly timestamp.
+    Prefix,
+}
+
+/// A digest algorithm calculated for uploaded files.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DigestAlgorithm {
+    /// The MD5 digest.
+    Md5,
+    /// The SHA-256 hash.
+    Sha256,
+    /// The CRC32C checksum.
+    Crc32c,
+}
+
+impl BackboneConfig {
+    /// Gets the time for which expired files can still be read.
+    pub fn expired_grace(&self) -> Duration {
+        self.expired_grace_sec
+            .map_or(Duration::ZERO, |secs| Duration::from_secs(secs as _))
+    }
+
+    /// Gets the maximum time for which a file is kept if reads extend its lease,
+    /// or `None` if sliding expiration is disabled.
+    pub fn max_lifetime(&self) -> Option<Duration> {
+        self.sliding_expiration.then(|| {
+            self.max_lifetime_sec
+                .map_or(DEFAULT_MAX_LIFETIME, Duration::from_secs)
+        })
+    }
+
+    /// Gets the maximum lease of a file, if limited.
+    pub fn max_lease(&self) -> Option<Duration> {
+        self.max_lease_sec.map(Duration::from_secs)
+    }
+
+    /// Gets the time to wait before distributing a file, if files are not distributed
+    /// right away.
+    pub fn distribution_delay(&self) -> Option<Duration> {
+        self.distribution_delay_ms
+            .filter(|&millis| millis > 0)
+            .map(Duration::from_millis)
+    }
+
+    /// Gets the number of characters of IDs in the [`IdFormat::Prefix`] format.
+    pub fn id_prefix_length(&self) -> usize {
+        self.id_prefix_length.unwrap_or(DEFAULT_ID_PREFIX_LENGTH)
+    }
+
+    /// Gets the number of bytes of each upload to hold in memory.
+    pub fn memory_buffer_bytes(&self) -> usize {
+        self.memory_buffer_bytes
+            .unwrap_or(DEFAULT_MEMORY_BUFFER_BYTES)
+    }
+
+    /// Gets the time for which the IDs of removed files are remembered.
+    pub fn tombstone_ttl(&self) -> Duration {
+        self.tombstone_ttl_sec
+            .map_or(DEFAULT_TOMBSTONE_TTL, Duration::from_secs)
+    }
+}
@@ -0,0 +1,93 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default status codes returned for injected errors.
+pub const DEFAULT_ERROR_STATUS_CODES: &[u16] = &[503];
+
+/// The chaos mode configuration, injecting latency and errors into `/yeet` and `/yoink`
+/// requests for testing the retry logic of clients. Must not be used in production.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ChaosConfig {
+    /// The fraction of requests to delay, between `0.0` and `1.0`. Defaults to `0.0`.
+    pub delay_rate: Option<f64>,
+    /// The minimum delay in milliseconds. Defaults to `0`.
+    pub delay_min_ms: Option<u64>,
+    /// The maximum delay in milliseconds. Delays are uniformly distributed between
+    /// the minimum and the maximum. Defaults to the minimum delay.
+    pub delay_max_ms: Option<u64>,
+    /// The fraction of requests to fail, between `0.0` and `1.0`. Defaults to `0.0`.
+    pub error_rate: Option<f64>,
+    /// The status codes to return for failed requests, chosen at random.
+    /// Defaults to [`DEFAULT_ERROR_STATUS_CODES`].
+    pub error_status_codes: Option<Vec<u16>>,
+}
+
+impl ChaosConfig {
+    /// Gets the fraction of requests to delay.
+    pub fn delay_rate(&self) -> f64 {
+        clamp_rate(self.delay_rate)
+    }
+
+    /// Gets the minimum delay.
+    pub fn delay_min(&self) -> Duration {
+        Duration::from_millis(self.delay_min_ms.unwrap_or(0))
+    }
+
+    /// Gets the maximum delay, which is never less than the minimum delay.
+    pub fn delay_max(&self) -> Duration {
+        self.delay_max_ms
+            .map_or(Duration::ZERO, Duration::from_millis)
+            .max(self.delay_min())
+    }
+
+    /// Gets the fraction of requests to fail.
+    pub fn error_rate(&self) -> f64 {
+        clamp_rate(self.error_rate)
+    }
+
+    /// Gets the status codes to return for failed requests.
+    pub fn error_status_codes(&self) -> &[u16] {
+        self.error_status_codes
+            .as_deref()
+            .unwrap_or(DEFAULT_ERROR_STATUS_CODES)
+    }
+}
+
+/// Clamps a rate to between `0.0` and `1.0`, treating an unset or NaN rate as `0.0`.
+fn clamp_rate(rate: Option<f64>) -> f64 {
+    rate.filter(|rate| !rate.is_nan())
+        .unwrap_or(0.0)
+        .clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_chaos_config_works() {
+        let yaml = r#"
+            delay_rate: 0.5
+            delay_min_ms: 100
+            error_rate: 2.0
+        "#;
+
+        let config: ChaosConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize chaos config");
+        assert_eq!(config.delay_rate(), 0.5);
+        assert_eq!(config.delay_min(), Duration::from_millis(100));
+        assert_eq!(config.delay_max(), Duration::from_millis(100));
+        assert_eq!(config.error_rate(), 1.0);
+        assert_eq!(config.error_status_codes(), DEFAULT_ERROR_STATUS_CODES);
+    }
+    #[test]
+    fn invalid_rates_are_never_used() {
+        let config = ChaosConfig {
+            delay_rate: Some(f64::NAN),
+            error_rate: Some(f64::NEG_INFINITY),
+            ..Default::default()
+        };
+        assert_eq!(config.delay_rate(), 0.0);
+        assert_eq!(config.error_rate(), 0.0);
+    }
+}
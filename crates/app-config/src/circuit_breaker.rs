@@ -0,0 +1,69 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default number of consecutive failures after which a backend is skipped.
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// The default time window in which the consecutive failures need to occur.
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(60);
+
+/// The default time for which a failing backend is skipped.
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(30);
+
+/// The configuration of the per-backend circuit breakers.
+///
+/// After [`failure_threshold`](Self::failure_threshold) consecutive failed distributions
+/// within the window, the circuit of the backend opens and files are not distributed to
+/// it for the cool-down period. Afterwards, a single distribution is attempted to test
+/// whether the backend recovered.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct CircuitBreakerConfig {
+    /// The number of consecutive failures opening the circuit.
+    /// Defaults to [`DEFAULT_FAILURE_THRESHOLD`].
+    pub failure_threshold: Option<u32>,
+    /// The number of seconds within which the failures need to occur.
+    /// Defaults to [`DEFAULT_WINDOW`].
+    pub window_sec: Option<u64>,
+    /// The number of seconds for which the backend is skipped once the circuit opened.
+    /// Defaults to [`DEFAULT_COOLDOWN`].
+    pub cooldown_sec: Option<u64>,
+}
+
+impl CircuitBreakerConfig {
+    /// Gets the number of consecutive failures opening the circuit.
+    pub fn failure_threshold(&self) -> u32 {
+        self.failure_threshold
+            .unwrap_or(DEFAULT_FAILURE_THRESHOLD)
+            .max(1)
+    }
+
+    /// Gets the time window within which the failures need to occur.
+    pub fn window(&self) -> Duration {
+        self.window_sec.map_or(DEFAULT_WINDOW, Duration::from_secs)
+    }
+
+    /// Gets the time for which the backend is skipped once the circuit opened.
+    pub fn cooldown(&self) -> Duration {
+        self.cooldown_sec
+            .map_or(DEFAULT_COOLDOWN, Duration::from_secs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_circuit_breaker_config_works() {
+        let yaml = r#"
+            failure_threshold: 3
+            cooldown_sec: 10
+        "#;
+
+        let config: CircuitBreakerConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize circuit breaker config");
+        assert_eq!(config.failure_threshold(), 3);
+        assert_eq!(config.window(), DEFAULT_WINDOW);
+        assert_eq!(config.cooldown(), Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,92 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default methods allowed for cross-origin requests.
+pub const DEFAULT_ALLOWED_METHODS: &[&str] = &["GET", "HEAD", "POST"];
+
+/// The default request headers allowed for cross-origin requests.
+pub const DEFAULT_ALLOWED_HEADERS: &[&str] = &["content-type", "content-md5", "yy-digests"];
+
+/// The default response headers exposed to cross-origin clients.
+pub const DEFAULT_EXPOSED_HEADERS: &[&str] = &[
+    "yy-id",
+    "yy-file-md5",
+    "yy-file-sha256",
+    "etag",
+    "content-md5",
+    "content-disposition",
+    "expires",
+];
+
+/// The Cross-Origin Resource Sharing (CORS) configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct CorsConfig {
+    /// The origins allowed to access the API, e.g. `https://example.com`,
+    /// or `*` to allow any origin.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// The methods allowed for cross-origin requests. Defaults to [`DEFAULT_ALLOWED_METHODS`].
+    pub allowed_methods: Option<Vec<String>>,
+    /// The request headers allowed for cross-origin requests, or `*` to allow any header.
+    /// Defaults to [`DEFAULT_ALLOWED_HEADERS`].
+    pub allowed_headers: Option<Vec<String>>,
+    /// The response headers exposed to cross-origin clients.
+    /// Defaults to [`DEFAULT_EXPOSED_HEADERS`].
+    pub exposed_headers: Option<Vec<String>>,
+    /// The number of seconds for which clients may cache preflight responses.
+    /// Defaults to not specifying a duration.
+    pub max_age_sec: Option<u32>,
+}
+
+impl CorsConfig {
+    /// Gets the methods allowed for cross-origin requests.
+    pub fn allowed_methods(&self) -> Vec<&str> {
+        Self::or_defaults(&self.allowed_methods, DEFAULT_ALLOWED_METHODS)
+    }
+
+    /// Gets the request headers allowed for cross-origin requests.
+    pub fn allowed_headers(&self) -> Vec<&str> {
+        Self::or_defaults(&self.allowed_headers, DEFAULT_ALLOWED_HEADERS)
+    }
+
+    /// Gets the response headers exposed to cross-origin clients.
+    pub fn exposed_headers(&self) -> Vec<&str> {
+        Self::or_defaults(&self.exposed_headers, DEFAULT_EXPOSED_HEADERS)
+    }
+
+    /// Gets the time for which clients may cache preflight responses.
+    pub fn max_age(&self) -> Option<Duration> {
+        self.max_age_sec.map(|secs| Duration::from_secs(secs as _))
+    }
+
+    fn or_defaults<'a>(values: &'a Option<Vec<String>>, defaults: &[&'static str]) -> Vec<&'a str> {
+        match values {
+            Some(values) => values.iter().map(String::as_str).collect(),
+            None => defaults.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_cors_config_works() {
+        let yaml = r#"
+            allowed_origins:
+              - https://example.com
+            allowed_methods:
+              - GET
+            max_age_sec: 600
+        "#;
+
+        let config: CorsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize CORS config");
+        assert_eq!(config.allowed_origins, vec!["https://example.com"]);
+        assert_eq!(config.allowed_methods(), vec!["GET"]);
+        assert_eq!(config.allowed_headers(), DEFAULT_ALLOWED_HEADERS);
+        assert_eq!(config.exposed_headers(), DEFAULT_EXPOSED_HEADERS);
+        assert_eq!(config.max_age(), Some(Duration::from_secs(600)));
+    }
+}
@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The default number of events buffered for the sink.
+pub const DEFAULT_BUFFER_SIZE: usize = 1024;
+
+/// The configuration of the sink receiving a structured event for every `/yeet`
+/// and `/yoink` request, e.g. for auditing or analytics.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventsConfig {
+    /// The destination of the events.
+    pub sink: EventSinkConfig,
+    /// The number of events buffered for the sink. Events are dropped while the buffer is
+    /// full so that requests are never blocked. Defaults to [`DEFAULT_BUFFER_SIZE`].
+    pub buffer_size: Option<usize>,
+}
+
+/// The destination of the events, each encoded as a single line of JSON.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum EventSinkConfig {
+    /// Writes the events to standard output.
+    Stdout,
+    /// Appends the events to the file at the specified path.
+    File { path: PathBuf },
+    /// Sends each event to the specified URL in a `POST` request.
+    Webhook { url: String },
+}
+
+impl EventsConfig {
+    /// Gets the number of events buffered for the sink.
+    pub fn buffer_size(&self) -> usize {
+        self.buffer_size.unwrap_or(DEFAULT_BUFFER_SIZE).max(1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_events_config_works() {
+        let yaml = r#"
+            sink:
+              type: file
+              path: /var/log/yeet-yoink/events.jsonl
+        "#;
+
+        let config: EventsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize events config");
+        assert_eq!(
+            config.sink,
+            EventSinkConfig::File {
+                path: PathBuf::from("/var/log/yeet-yoink/events.jsonl")
+            }
+        );
+        assert_eq!(config.buffer_size(), DEFAULT_BUFFER_SIZE);
+    }
+}
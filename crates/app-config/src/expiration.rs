@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The expiration of stored files relative to their lease, shared by the backends.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct LeaseExpirationConfig {
+    /// The number of seconds to keep a stored file after the lease of the file ended.
+    /// If set, the file expires this long after the end of its lease on this instance,
+    /// rather than after the `expiration_sec` of the backend.
+    pub lease_offset_sec: Option<u32>,
+}
+
+impl LeaseExpirationConfig {
+    /// Gets the time to keep a stored file after its lease ended, if files expire
+    /// relative to their lease.
+    pub fn lease_offset(&self) -> Option<Duration> {
+        self.lease_offset_sec
+            .map(|secs| Duration::from_secs(secs as _))
+    }
+}
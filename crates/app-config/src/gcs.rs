@@ -0,0 +1,47 @@
+use crate::expiration::LeaseExpirationConfig;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The default expiration time for objects stored in Google Cloud Storage.
+pub const DEFAULT_EXPIRATION: Duration = Duration::from_secs(3600);
+
+/// The Google Cloud Storage specific configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct GcsBackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The name of the bucket to store the files in.
+    pub bucket: String,
+    /// The path to a service account key file. If not set, the application default
+    /// credentials are used.
+    pub credentials_file: Option<PathBuf>,
+    /// The number of seconds after which the object is considered expired.
+    /// Defaults to [`DEFAULT_EXPIRATION`].
+    ///
+    /// The expiration date is stored as the object's `Custom-Time`. To have expired objects
+    /// removed, configure a lifecycle rule on the bucket that deletes objects with a
+    /// `daysSinceCustomTime` of `0`.
+    pub expiration_sec: Option<u32>,
+    /// See [`LeaseExpirationConfig`].
+    #[serde(flatten)]
+    pub lease_expiration: LeaseExpirationConfig,
+    /// The number of seconds for which presigned download URLs are valid. If set, clients
+    /// can obtain presigned URLs to download files directly from the bucket, which requires
+    /// credentials that are able to sign. Defaults to not issuing presigned URLs.
+    pub presign_expiration_sec: Option<u32>,
+}
+
+impl GcsBackendConfig {
+    /// Gets the time after which an object is considered expired.
+    pub fn expiration(&self) -> Duration {
+        self.expiration_sec
+            .map_or(DEFAULT_EXPIRATION, |secs| Duration::from_secs(secs as _))
+    }
+
+    /// Gets the time for which presigned download URLs are valid, if they are enabled.
+    pub fn presign_expiration(&self) -> Option<Duration> {
+        self.presign_expiration_sec
+            .map(|secs| Duration::from_secs(secs as _))
+    }
+}
@@ -2,21 +2,55 @@
 // the `docsrs` configuration attribute is defined
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+pub mod backbone;
+pub mod chaos;
+pub mod circuit_breaker;
+pub mod cors;
+pub mod events;
+pub mod expiration;
+#[cfg(feature = "gcs")]
+pub mod gcs;
+pub mod load_shedding;
 #[cfg(feature = "memcache")]
 pub mod memcache;
+#[cfg(feature = "memory")]
+pub mod memory;
+pub mod mirror;
+pub mod rate_limit;
+pub mod routing;
+pub mod server;
+#[cfg(feature = "sftp")]
+pub mod sftp;
+pub mod tls;
+mod validation;
 
 use clap::ArgMatches;
 use config::builder::DefaultState;
 use config::{ConfigBuilder, File, FileFormat};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 use tracing::{error, info};
 
+pub use validation::ConfiguredBackend;
+
+/// The default time a backend may take to store a file.
+pub const DEFAULT_DISTRIBUTION_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// The default interval at which the storage statistics of the backends are collected.
+pub const DEFAULT_STATS_INTERVAL: Duration = Duration::from_secs(60);
+
 /// The application configuration.
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct AppConfig {
     /// The version of the configuration.
     version: u8,
+    /// The HTTP server configuration.
+    #[serde(default)]
+    pub server: server::ServerConfig,
+    /// The local file buffering configuration.
+    #[serde(default)]
+    pub backbone: backbone::BackboneConfig,
     /// The backend-specific configuration.
     pub backends: BackendsConfig,
 }
@@ -24,10 +58,121 @@ pub struct AppConfig {
 /// Provides backend-specific configuration.
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct BackendsConfig {
+    /// Provides Google Cloud Storage specific configuration.
+    #[cfg_attr(docsrs, doc(cfg(feature = "gcs")))]
+    #[cfg(feature = "gcs")]
+    #[serde(default)]
+    pub gcs: Vec<gcs::GcsBackendConfig>,
     /// Provides Memcached specific configuration.
     #[cfg_attr(docsrs, doc(cfg(feature = "memcache")))]
     #[cfg(feature = "memcache")]
     pub memcache: Vec<memcache::MemcacheBackendConfig>,
+    /// Provides configuration for striping files across several Memcached servers.
+    #[cfg_attr(docsrs, doc(cfg(feature = "memcache")))]
+    #[cfg(feature = "memcache")]
+    #[serde(default)]
+    pub memcache_striped: Vec<memcache::StripedMemcacheBackendConfig>,
+    /// Provides in-memory backend specific configuration.
+    #[cfg_attr(docsrs, doc(cfg(feature = "memory")))]
+    #[cfg(feature = "memory")]
+    #[serde(default)]
+    pub memory: Vec<memory::MemoryBackendConfig>,
+    /// Provides SFTP specific configuration.
+    #[cfg_attr(docsrs, doc(cfg(feature = "sftp")))]
+    #[cfg(feature = "sftp")]
+    #[serde(default)]
+    pub sftp: Vec<sftp::SftpBackendConfig>,
+    /// Provides configuration for writing files to several of the above backends at once.
+    #[serde(default)]
+    pub mirror: Vec<mirror::MirrorBackendConfig>,
+    /// Whether to test each backend on startup by distributing a small file and reading
+    /// it back. Failures are logged (`warn`) or abort the startup (`abort`).
+    /// Disabled by default.
+    #[serde(default)]
+    pub self_test: Option<SelfTestMode>,
+    /// The number of seconds each backend may take to store a file. Distributions taking
+    /// longer are aborted and treated as failed. Defaults to [`DEFAULT_DISTRIBUTION_TIMEOUT`].
+    pub distribution_timeout_sec: Option<u64>,
+    /// The number of backends that need to store a file for it to be considered durable.
+    /// Whether the quorum was met is reported by `/yeet` and `/meta/:id`.
+    /// Disabled by default.
+    #[serde(default)]
+    pub write_quorum: Option<usize>,
+    /// The number of seconds `/yeet` waits for the write quorum to be met before responding,
+    /// counted from the end of the `distribution_delay_ms` of the backbone.
+    /// Uploads not meeting the quorum in time are answered with an error.
+    /// By default, `/yeet` responds without waiting.
+    #[serde(default)]
+    pub write_quorum_wait_sec: Option<u64>,
+    /// Rules restricting which files are distributed to which backends, e.g. by content
+    /// type or size. Backends without rules receive all files. By default, all files are
+    /// distributed to all backends.
+    #[serde(default)]
+    pub routing: Vec<routing::RoutingRule>,
+    /// Skips backends for a while after repeated failed distributions.
+    /// Disabled by default.
+    #[serde(default)]
+    pub circuit_breaker: Option<circuit_breaker::CircuitBreakerConfig>,
+    /// The maximum number of files stored on backends at the same time, across all files
+    /// and backends. Further distributions wait until one completes. By default, each file
+    /// is stored on all of its backends concurrently.
+    pub max_concurrent_distributions: Option<usize>,
+    /// The number of seconds between collecting the storage statistics of the backends for
+    /// the metrics. Use `0` to only collect them when requested from `/admin/backends/stats`.
+    /// Defaults to [`DEFAULT_STATS_INTERVAL`].
+    pub stats_interval_sec: Option<u64>,
+    /// What to do if no backend stored a file because a backend rejected it, e.g. because
+    /// it is too large. Defaults to [`RejectionPolicy::Ignore`].
+    #[serde(default)]
+    pub on_rejection: RejectionPolicy,
+}
+
+impl BackendsConfig {
+    /// Gets the time each backend may take to store a file.
+    pub fn distribution_timeout(&self) -> Duration {
+        self.distribution_timeout_sec
+            .map_or(DEFAULT_DISTRIBUTION_TIMEOUT, Duration::from_secs)
+    }
+
+    /// Gets the interval at which the storage statistics are collected, if they are at all.
+    pub fn stats_interval(&self) -> Option<Duration> {
+        match self.stats_interval_sec {
+            Some(0) => None,
+            Some(secs) => Some(Duration::from_secs(secs)),
+            None => Some(DEFAULT_STATS_INTERVAL),
+        }
+    }
+
+    /// Gets the time `/yeet` waits for the write quorum, if it waits at all.
+    pub fn write_quorum_wait(&self) -> Option<Duration> {
+        self.write_quorum
+            .and(self.write_quorum_wait_sec)
+            .map(Duration::from_secs)
+    }
+}
+
+/// The reaction to a file that no backend stored because a backend rejected it.
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RejectionPolicy {
+    /// Log the rejection and keep the file locally until it expires.
+    #[default]
+    Ignore,
+    /// Additionally log an error and count the file in the `distribution_unstored_rejections` metric.
+    WarnLoudly,
+    /// Additionally answer the upload with `507 Insufficient Storage` once the distribution
+    /// completed, like a failed write quorum.
+    FailUpload,
+}
+
+/// The reaction to a failing backend self-test.
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SelfTestMode {
+    /// Log the failure and continue.
+    Warn,
+    /// Abort the startup.
+    Abort,
 }
 
 impl AppConfig {
@@ -0,0 +1,68 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default time after which clients of shed requests are asked to retry.
+pub const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+/// The routes that are never shed by default, so that health checks and metrics
+/// remain available under overload.
+pub const DEFAULT_EXEMPT_ROUTES: &[&str] = &[
+    "/startupz",
+    "/readyz",
+    "/livez",
+    "/health",
+    "/healthz",
+    "/metrics",
+];
+
+/// The load shedding configuration.
+///
+/// While [`max_in_flight`](Self::max_in_flight) requests are in flight, new requests are
+/// rejected with `503 Service Unavailable` unless their route is exempt. Requests are in
+/// flight until their response was transmitted.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct LoadSheddingConfig {
+    /// The number of requests in flight above which new requests are shed.
+    pub max_in_flight: usize,
+    /// The number of seconds after which clients are asked to retry, sent in the
+    /// `Retry-After` header. Defaults to [`DEFAULT_RETRY_AFTER`].
+    pub retry_after_sec: Option<u64>,
+    /// The routes that are never shed, e.g. `/yoink/:id`.
+    /// Defaults to [`DEFAULT_EXEMPT_ROUTES`].
+    pub exempt_routes: Option<Vec<String>>,
+}
+
+impl LoadSheddingConfig {
+    /// Gets the time after which clients are asked to retry, which is at least one second.
+    pub fn retry_after(&self) -> Duration {
+        self.retry_after_sec
+            .map_or(DEFAULT_RETRY_AFTER, Duration::from_secs)
+            .max(Duration::from_secs(1))
+    }
+
+    /// Gets the routes that are never shed.
+    pub fn exempt_routes(&self) -> Vec<&str> {
+        match &self.exempt_routes {
+            Some(routes) => routes.iter().map(String::as_str).collect(),
+            None => DEFAULT_EXEMPT_ROUTES.to_vec(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_load_shedding_config_works() {
+        let yaml = r#"
+            max_in_flight: 500
+        "#;
+
+        let config: LoadSheddingConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize load shedding config");
+        assert_eq!(config.max_in_flight, 500);
+        assert_eq!(config.retry_after(), DEFAULT_RETRY_AFTER);
+        assert_eq!(config.exempt_routes(), DEFAULT_EXEMPT_ROUTES);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::expiration::LeaseExpirationConfig;
 use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{Display, Formatter};
 use std::str::FromStr;
@@ -7,6 +8,12 @@ use url::Url;
 /// The default expiration time for Memcached entries.
 pub const DEFAULT_EXPIRATION: Duration = Duration::from_secs(3600);
 
+/// The default number of idle connections kept open to a Memcached server.
+pub const DEFAULT_MIN_IDLE: u32 = 1;
+
+/// The default maximum number of connections to a Memcached server.
+pub const DEFAULT_MAX_POOL_SIZE: u32 = 10;
+
 /// The Memcached-specific configuration.
 #[derive(Default, Debug, Serialize, Deserialize)]
 pub struct MemcacheBackendConfig {
@@ -30,6 +37,65 @@ pub struct MemcacheBackendConfig {
     /// 300
     /// ```
     pub expiration_sec: Option<u32>,
+    /// See [`LeaseExpirationConfig`].
+    #[serde(flatten)]
+    pub lease_expiration: LeaseExpirationConfig,
+    /// The number of idle connections the pool keeps open. Defaults to [`DEFAULT_MIN_IDLE`].
+    pub min_idle: Option<u32>,
+    /// The maximum number of connections in the pool. Defaults to [`DEFAULT_MAX_POOL_SIZE`].
+    pub max_size: Option<u32>,
+    /// Whether to open [`min_idle`](Self::min_idle) connections at startup, failing to
+    /// register the backend if that doesn't succeed in time. Otherwise, the connections are
+    /// opened in the background and on first use. Defaults to `true`.
+    pub warm_up: Option<bool>,
+}
+
+impl MemcacheBackendConfig {
+    /// Gets the maximum number of connections in the pool.
+    pub fn max_size(&self) -> u32 {
+        self.max_size.unwrap_or(DEFAULT_MAX_POOL_SIZE).max(1)
+    }
+
+    /// Gets the number of idle connections the pool keeps open, at most [`max_size`](Self::max_size).
+    pub fn min_idle(&self) -> u32 {
+        self.min_idle
+            .unwrap_or(DEFAULT_MIN_IDLE)
+            .min(self.max_size())
+    }
+
+    /// Gets whether to open the idle connections at startup.
+    pub fn warm_up(&self) -> bool {
+        self.warm_up.unwrap_or(true)
+    }
+}
+
+/// The default size of the chunks of files striped across several Memcached servers.
+pub const DEFAULT_CHUNK_SIZE: usize = 512 * 1024;
+
+/// The configuration of a backend striping files across several Memcached servers.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct StripedMemcacheBackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The connection strings of the servers to distribute the chunks across.
+    /// Files are only readable as long as the servers they were stored on are configured.
+    pub connection_strings: Vec<MemcacheConnectionString>,
+    /// The size of each chunk in bytes. Must not exceed the item size limit of the servers.
+    /// Defaults to [`DEFAULT_CHUNK_SIZE`].
+    pub chunk_size_bytes: Option<usize>,
+    /// The number of seconds after which the chunks are considered expired. Use `0`
+    /// to keep the entries indefinitely. Defaults to [`DEFAULT_EXPIRATION`].
+    pub expiration_sec: Option<u32>,
+    /// See [`LeaseExpirationConfig`].
+    #[serde(flatten)]
+    pub lease_expiration: LeaseExpirationConfig,
+}
+
+impl StripedMemcacheBackendConfig {
+    /// Gets the size of each chunk in bytes.
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size_bytes.unwrap_or(DEFAULT_CHUNK_SIZE).max(1)
+    }
 }
 
 /// A Memcached connection string.
@@ -120,6 +186,10 @@ mod tests {
             tag: memcache-1
             connection_string: "memcache://127.0.0.1:12345?timeout=10&tcp_nodelay=true"
             expiration_sec: 500
+            lease_offset_sec: 120
+            min_idle: 4
+            max_size: 16
+            warm_up: false
         "#;
 
         let config: MemcacheBackendConfig =
@@ -130,6 +200,43 @@ mod tests {
             "memcache://127.0.0.1:12345?timeout=10&tcp_nodelay=true"
         );
         assert_eq!(config.expiration_sec, Some(500));
+        assert_eq!(
+            config.lease_expiration.lease_offset(),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(config.min_idle(), 4);
+        assert_eq!(config.max_size(), 16);
+        assert!(!config.warm_up());
+    }
+
+    #[test]
+    fn memcache_pool_defaults_work() {
+        let config = MemcacheBackendConfig {
+            min_idle: Some(20),
+            ..Default::default()
+        };
+        assert_eq!(config.max_size(), DEFAULT_MAX_POOL_SIZE);
+        assert_eq!(config.min_idle(), DEFAULT_MAX_POOL_SIZE);
+        assert!(config.warm_up());
+    }
+
+    #[test]
+    fn deserialize_striped_memcache_config_works() {
+        let yaml = r#"
+            tag: memcache-striped
+            connection_strings:
+              - "memcache://127.0.0.1:11211"
+              - "memcache://127.0.0.1:11212"
+            chunk_size_bytes: 1024
+        "#;
+
+        let config: StripedMemcacheBackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize Memcache config");
+        assert_eq!(config.tag, "memcache-striped");
+        assert_eq!(config.connection_strings.len(), 2);
+        assert_eq!(config.chunk_size(), 1024);
+        assert_eq!(config.expiration_sec, None);
+        assert_eq!(config.lease_expiration.lease_offset(), None);
     }
 
     #[test]
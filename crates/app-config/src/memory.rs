@@ -0,0 +1,71 @@
+use crate::expiration::LeaseExpirationConfig;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default expiration time for in-memory entries.
+pub const DEFAULT_EXPIRATION: Duration = Duration::from_secs(3600);
+
+/// The default maximum number of bytes held by an in-memory backend.
+pub const DEFAULT_MAX_TOTAL_BYTES: u64 = 64 * 1024 * 1024;
+
+/// The default maximum number of entries held by an in-memory backend.
+pub const DEFAULT_MAX_ENTRIES: usize = 1024;
+
+/// The in-memory backend configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct MemoryBackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The maximum number of bytes of file contents to keep. When exceeded, the least
+    /// recently used entries are evicted. Defaults to [`DEFAULT_MAX_TOTAL_BYTES`].
+    pub max_total_bytes: Option<u64>,
+    /// The maximum number of files to keep. When exceeded, the least recently used
+    /// entries are evicted. Defaults to [`DEFAULT_MAX_ENTRIES`].
+    pub max_entries: Option<usize>,
+    /// The number of seconds after which the item is considered expired.
+    /// Defaults to [`DEFAULT_EXPIRATION`].
+    pub expiration_sec: Option<u32>,
+    /// See [`LeaseExpirationConfig`].
+    #[serde(flatten)]
+    pub lease_expiration: LeaseExpirationConfig,
+}
+
+impl MemoryBackendConfig {
+    /// Gets the maximum number of bytes of file contents to keep.
+    pub fn max_total_bytes(&self) -> u64 {
+        self.max_total_bytes.unwrap_or(DEFAULT_MAX_TOTAL_BYTES)
+    }
+
+    /// Gets the maximum number of files to keep.
+    pub fn max_entries(&self) -> usize {
+        self.max_entries.unwrap_or(DEFAULT_MAX_ENTRIES)
+    }
+
+    /// Gets the time after which an item is considered expired.
+    pub fn expiration(&self) -> Duration {
+        self.expiration_sec
+            .map_or(DEFAULT_EXPIRATION, |secs| Duration::from_secs(secs as _))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_memory_backend_config_works() {
+        let yaml = r#"
+            tag: memory-1
+            max_total_bytes: 1048576
+            expiration_sec: 60
+        "#;
+
+        let config: MemoryBackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize memory backend config");
+        assert_eq!(config.tag, "memory-1");
+        assert_eq!(config.max_total_bytes(), 1048576);
+        assert_eq!(config.max_entries(), DEFAULT_MAX_ENTRIES);
+        assert_eq!(config.expiration(), Duration::from_secs(60));
+        assert_eq!(config.lease_expiration.lease_offset(), None);
+    }
+}
@@ -0,0 +1,51 @@
+use serde::{Deserialize, Serialize};
+
+/// The configuration of a backend writing files to several other backends at once.
+///
+/// The mirrored backends are referenced by their tags. They only receive files through
+/// the mirror, so routing rules need to refer to the mirror instead.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct MirrorBackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The tags of the backends that must store each file for the distribution to succeed.
+    pub primary: Vec<String>,
+    /// The tags of the backends storing files on a best-effort basis; their failures are
+    /// only logged.
+    #[serde(default)]
+    pub secondary: Vec<String>,
+}
+
+impl MirrorBackendConfig {
+    /// Gets the tags of all mirrored backends, primary ones first.
+    pub fn mirrored(&self) -> impl Iterator<Item = &str> {
+        self.primary
+            .iter()
+            .chain(self.secondary.iter())
+            .map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_mirror_backend_config_works() {
+        let yaml = r#"
+            tag: mirrored
+            primary:
+              - memcache-1
+            secondary:
+              - gcs-1
+        "#;
+
+        let config: MirrorBackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize mirror backend config");
+        assert_eq!(config.tag, "mirrored");
+        assert_eq!(
+            config.mirrored().collect::<Vec<_>>(),
+            ["memcache-1", "gcs-1"]
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// The default interval the upload limits apply to.
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The default header carrying the API key of a client.
+pub const DEFAULT_API_KEY_HEADER: &str = "x-api-key";
+
+/// The per-client upload rate limit configuration.
+///
+/// Each client may upload [`max_uploads`](Self::max_uploads) files and
+/// [`max_bytes`](Self::max_bytes) bytes per interval, with short bursts of up to these
+/// amounts. Uploads exceeding a limit are rejected with `429 Too Many Requests`.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct UploadRateLimitConfig {
+    /// What identifies a client. Defaults to [`RateLimitKey::Ip`].
+    #[serde(default)]
+    pub key: RateLimitKey,
+    /// The header carrying the API key if clients are identified by [`RateLimitKey::ApiKey`].
+    /// Defaults to [`DEFAULT_API_KEY_HEADER`].
+    pub api_key_header: Option<String>,
+    /// The API keys limited on their own if clients are identified by [`RateLimitKey::ApiKey`].
+    /// Requests with other keys are limited by client IP, so that clients can't evade the
+    /// limit by sending a new key with each upload.
+    #[serde(default)]
+    pub api_keys: Vec<String>,
+    /// The number of seconds the limits apply to. Defaults to [`DEFAULT_INTERVAL`].
+    pub interval_sec: Option<u64>,
+    /// The maximum number of uploads per client and interval. Defaults to no limit.
+    pub max_uploads: Option<u32>,
+    /// The maximum number of bytes uploaded per client and interval. Defaults to no limit.
+    ///
+    /// Uploads are admitted if the client has the announced `Content-Length` left, or any
+    /// bytes for uploads of unknown size; the stored size is deducted once the upload completed.
+    pub max_bytes: Option<u64>,
+}
+
+/// What identifies a client for rate limiting.
+#[derive(Default, Debug, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RateLimitKey {
+    /// The client IP, as resolved from the trusted proxies.
+    #[default]
+    Ip,
+    /// The API key sent in the configured header, if it is one of the configured keys.
+    /// Requests without a configured key are limited by client IP.
+    ApiKey,
+}
+
+impl UploadRateLimitConfig {
+    /// Gets the header carrying the API key.
+    pub fn api_key_header(&self) -> &str {
+        self.api_key_header
+            .as_deref()
+            .unwrap_or(DEFAULT_API_KEY_HEADER)
+    }
+
+    /// Gets the interval the limits apply to.
+    pub fn interval(&self) -> Duration {
+        self.interval_sec
+            .map_or(DEFAULT_INTERVAL, Duration::from_secs)
+            .max(Duration::from_secs(1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_upload_rate_limit_config_works() {
+        let yaml = r#"
+            key: api_key
+            api_keys: [secret]
+            max_uploads: 10
+            max_bytes: 1048576
+        "#;
+
+        let config: UploadRateLimitConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize upload rate limit config");
+        assert_eq!(config.key, RateLimitKey::ApiKey);
+        assert_eq!(config.api_key_header(), DEFAULT_API_KEY_HEADER);
+        assert_eq!(config.api_keys, ["secret"]);
+        assert_eq!(config.interval(), DEFAULT_INTERVAL);
+        assert_eq!(config.max_uploads, Some(10));
+        assert_eq!(config.max_bytes, Some(1048576));
+    }
+}
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+
+/// A rule restricting which files are distributed to a backend.
+///
+/// Backends without any rule receive all files. Backends with rules only receive files
+/// matching at least one of their rules; all conditions of a rule must match.
+#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingRule {
+    /// The tag of the backend the rule applies to.
+    pub backend: String,
+    /// The content types to match, e.g. `image/png` or `image/*`. Matches any content type
+    /// if empty. Files without a content type only match if the list is empty.
+    #[serde(default)]
+    pub content_types: Vec<String>,
+    /// The minimum file size in bytes. Disabled by default.
+    #[serde(default)]
+    pub min_size_bytes: Option<u64>,
+    /// The maximum file size in bytes. Disabled by default.
+    #[serde(default)]
+    pub max_size_bytes: Option<u64>,
+    /// The file name patterns to match, where `*` matches any sequence of characters and
+    /// `?` matches a single character, e.g. `*.jpg`. Matches any file name if empty.
+    /// Files without a name only match if the list is empty.
+    #[serde(default)]
+    pub file_names: Vec<String>,
+}
+
+/// The properties of a file that routing rules are evaluated against.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RoutedFile<'a> {
+    /// The content type of the file, if known.
+    pub content_type: Option<&'a str>,
+    /// The file size in bytes.
+    pub size: u64,
+    /// The name of the file, if known.
+    pub file_name: Option<&'a str>,
+}
+
+impl RoutingRule {
+    /// Indicates whether the rule matches the specified file.
+    pub fn matches(&self, file: &RoutedFile) -> bool {
+        if self.min_size_bytes.is_some_and(|min| file.size < min)
+            || self.max_size_bytes.is_some_and(|max| file.size > max)
+        {
+            return false;
+        }
+
+        let content_type_matches = self.content_types.is_empty()
+            || file.content_type.is_some_and(|content_type| {
+                // Ignore parameters such as the charset.
+                let essence = content_type.split(';').next().unwrap_or_default().trim();
+                self.content_types.iter().any(|pattern| {
+                    wildcard_match(&pattern.to_ascii_lowercase(), &essence.to_ascii_lowercase())
+                })
+            });
+
+        let file_name_matches = self.file_names.is_empty()
+            || file.file_name.is_some_and(|name| {
+                self.file_names
+                    .iter()
+                    .any(|pattern| wildcard_match(pattern, name))
+            });
+
+        content_type_matches && file_name_matches
+    }
+
+    /// Indicates whether the rule needs the content type of files to be evaluated.
+    pub fn needs_content_type(&self) -> bool {
+        !self.content_types.is_empty()
+    }
+}
+
+/// Matches `text` against a pattern in which `*` matches any sequence of characters
+/// and `?` matches a single character.
+fn wildcard_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    let (mut p, mut t) = (0, 0);
+    // The position of the last `*` in the pattern and the text position it was tried at.
+    let mut backtrack = None;
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match backtrack {
+                // Let the last `*` match one more character.
+                Some((star, start)) => {
+                    backtrack = Some((star, start + 1));
+                    p = star + 1;
+                    t = start + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_routing_rule_works() {
+        let yaml = r#"
+            backend: memcache-1
+            content_types: ["image/*"]
+            max_size_bytes: 1048576
+        "#;
+
+        let rule: RoutingRule =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize routing rule");
+        assert_eq!(rule.backend, "memcache-1");
+        assert!(rule.needs_content_type());
+        assert!(rule.matches(&RoutedFile {
+            content_type: Some("Image/PNG"),
+            size: 1024,
+            file_name: None,
+        }));
+        assert!(!rule.matches(&RoutedFile {
+            content_type: Some("image/png"),
+            size: 2 * 1048576,
+            file_name: None,
+        }));
+        assert!(!rule.matches(&RoutedFile {
+            content_type: Some("text/plain; charset=utf-8"),
+            size: 1024,
+            file_name: None,
+        }));
+        assert!(!rule.matches(&RoutedFile {
+            content_type: None,
+            size: 1024,
+            file_name: None,
+        }));
+    }
+
+    #[test]
+    fn wildcard_match_works() {
+        assert!(wildcard_match("*.jpg", "cat.jpg"));
+        assert!(wildcard_match("*.jpg", ".jpg"));
+        assert!(wildcard_match("report-????.pdf", "report-2023.pdf"));
+        assert!(wildcard_match("a*b*c", "axxbyybzc"));
+        assert!(wildcard_match("*", ""));
+        assert!(!wildcard_match("*.jpg", "cat.jpeg"));
+        assert!(!wildcard_match("report-????.pdf", "report-23.pdf"));
+        assert!(!wildcard_match("a*b*c", "axxbyyb"));
+    }
+}
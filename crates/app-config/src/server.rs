@@ -0,0 +1,499 @@
+use crate::chaos::ChaosConfig;
+use crate::cors::CorsConfig;
+use crate::events::EventsConfig;
+use crate::load_shedding::LoadSheddingConfig;
+use crate::rate_limit::UploadRateLimitConfig;
+use crate::tls::TlsConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// The default time to wait for in-flight requests to complete during shutdown.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The default time for which a file is held after redirecting the reverse proxy to it.
+pub const DEFAULT_ACCEL_REDIRECT_HOLD: Duration = Duration::from_secs(60);
+
+/// The default content type of files uploaded without a declared type.
+pub const DEFAULT_CONTENT_TYPE: &str = "application/octet-stream";
+
+/// The default maximum number of pending connections per listening socket.
+pub const DEFAULT_LISTEN_BACKLOG: u32 = 1024;
+
+/// The default maximum total size of the request headers in bytes.
+pub const DEFAULT_MAX_HEADER_BYTES: u32 = 64 * 1024;
+
+/// The default maximum number of request headers.
+pub const DEFAULT_MAX_HEADER_COUNT: u32 = 100;
+
+/// The default maximum number of files requested at once from `/yoink-bundle`.
+pub const DEFAULT_MAX_BUNDLE_FILES: usize = 256;
+
+/// The default maximum number of distinct `path` label values of the HTTP metrics.
+pub const DEFAULT_MAX_METRIC_PATHS: usize = 256;
+
+/// The default size in bytes from which `/metrics` responses are gzip compressed.
+pub const DEFAULT_METRICS_GZIP_MIN_BYTES: usize = 1024;
+
+/// The default maximum number of `x-meta-*` headers of an upload.
+pub const DEFAULT_MAX_METADATA_ENTRIES: usize = 16;
+
+/// The default maximum total size of the `x-meta-*` headers of an upload in bytes.
+pub const DEFAULT_MAX_METADATA_BYTES: usize = 4096;
+
+/// The default number of bytes read from a file at once when streaming it to a client.
+pub const DEFAULT_DOWNLOAD_BUFFER_BYTES: usize = 64 * 1024;
+
+/// The HTTP server configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct ServerConfig {
+    /// The number of seconds to wait for in-flight requests to complete after a shutdown
+    /// was initiated. Connections still open after this time are forcibly closed.
+    /// Defaults to [`DEFAULT_DRAIN_TIMEOUT`].
+    pub drain_timeout_sec: Option<u32>,
+    /// Whether to reject uploads with `503 Service Unavailable` if no backend is
+    /// registered to durably store them. Defaults to `false`.
+    #[serde(default)]
+    pub require_backend: bool,
+    /// The content type to assume for files uploaded without a `Content-Type` header.
+    /// Defaults to [`DEFAULT_CONTENT_TYPE`].
+    pub default_content_type: Option<String>,
+    /// The maximum size of an upload in bytes. Uploads exceeding this size are rejected
+    /// with `413 Payload Too Large`, regardless of whether their size was declared up front.
+    /// Defaults to no limit.
+    pub max_upload_bytes: Option<u64>,
+    /// The maximum number of `x-meta-*` headers of an upload. Uploads exceeding it are
+    /// rejected with `431 Request Header Fields Too Large`.
+    /// Defaults to [`DEFAULT_MAX_METADATA_ENTRIES`].
+    pub max_metadata_entries: Option<usize>,
+    /// The maximum total size of the names (without the `x-meta-` prefix) and values of the
+    /// `x-meta-*` headers of an upload in bytes. Uploads exceeding it are rejected with
+    /// `431 Request Header Fields Too Large`. Defaults to [`DEFAULT_MAX_METADATA_BYTES`].
+    pub max_metadata_bytes: Option<usize>,
+    /// The number of bytes of an upload to collect in memory before writing them to the
+    /// temporary file and syncing it to disk. Larger values reduce the number of syscalls for
+    /// uploads arriving in small chunks, at the cost of memory per upload and of readers of
+    /// files still being uploaded seeing the data later. Defaults to `0`, i.e. every received
+    /// chunk is written and synced immediately.
+    #[serde(default)]
+    pub upload_buffer_bytes: usize,
+    /// The number of seconds after which connections without any reads or writes are closed.
+    /// The timeout is paused while a request on the connection is in progress, e.g. an upload
+    /// waiting for its distribution. Defaults to no timeout.
+    pub idle_timeout_sec: Option<u32>,
+    /// The number of seconds within which a client must transmit the complete request
+    /// headers once it started sending them. Defaults to no timeout.
+    pub header_read_timeout_sec: Option<u32>,
+    /// The interval in seconds for keep-alive probes on idle connections, i.e. TCP
+    /// keep-alive probes and HTTP/2 pings. Defaults to no keep-alive probes.
+    pub keep_alive_interval_sec: Option<u32>,
+    /// The number of seconds after which uploads that don't transmit any data are aborted
+    /// with `408 Request Timeout`. Defaults to no timeout.
+    pub body_read_timeout_sec: Option<u32>,
+    /// The Cross-Origin Resource Sharing (CORS) configuration.
+    /// Defaults to not handling cross-origin requests.
+    pub cors: Option<CorsConfig>,
+    /// The maximum number of pending connections per listening socket.
+    /// Defaults to [`DEFAULT_LISTEN_BACKLOG`].
+    pub listen_backlog: Option<u32>,
+    /// Whether to set `SO_REUSEADDR` on the listening sockets, allowing to rebind
+    /// immediately after a restart. Defaults to `true`.
+    pub reuse_address: Option<bool>,
+    /// Whether to set `SO_REUSEPORT` on the listening sockets, allowing multiple instances
+    /// to listen on the same port, e.g. for zero-downtime restarts. Only supported on Unix.
+    /// Defaults to `false`.
+    #[serde(default)]
+    pub reuse_port: bool,
+    /// The sampling of request logs.
+    #[serde(default)]
+    pub access_log: AccessLogConfig,
+    /// The upper bounds in seconds of the buckets of the `http_request_duration_seconds`
+    /// histogram, e.g. `[0.01, 0.1, 1, 10]`. Defaults to 14 exponential buckets from 5 ms
+    /// to about 41 s.
+    #[serde(default)]
+    pub http_duration_buckets: Vec<f64>,
+    /// The maximum number of distinct `path` label values of the HTTP metrics. Requests to
+    /// further routes are tracked with the path `other`, protecting the metrics from a
+    /// cardinality blow-up. Defaults to [`DEFAULT_MAX_METRIC_PATHS`].
+    pub max_metric_paths: Option<usize>,
+    /// The maximum total size of the request header names and values in bytes.
+    /// Requests exceeding it are rejected with `431 Request Header Fields Too Large`;
+    /// HTTP/1 requests are read into a buffer sized for it, so that much larger headers
+    /// are refused before they are parsed.
+    /// Defaults to [`DEFAULT_MAX_HEADER_BYTES`].
+    pub max_header_bytes: Option<u32>,
+    /// The maximum number of request headers. Requests exceeding it are rejected with
+    /// `431 Request Header Fields Too Large`. Note that HTTP/1 requests with more than
+    /// 100 headers are always rejected. Defaults to [`DEFAULT_MAX_HEADER_COUNT`].
+    pub max_header_count: Option<u32>,
+    /// The chaos mode injecting latency and errors into `/yeet` and `/yoink` requests
+    /// for testing clients. Defaults to disabled.
+    pub chaos: Option<ChaosConfig>,
+    /// Limits the number and total size of uploads per client.
+    /// Defaults to no limit.
+    pub upload_rate_limit: Option<UploadRateLimitConfig>,
+    /// Rejects new requests with `503 Service Unavailable` while too many requests are
+    /// in flight, except for health checks and metrics. Defaults to no limit.
+    pub load_shedding: Option<LoadSheddingConfig>,
+    /// Terminates TLS on the HTTP listeners, optionally verifying client certificates.
+    /// Defaults to plain HTTP.
+    pub tls: Option<TlsConfig>,
+    /// The sink receiving a structured event for every `/yeet` and `/yoink` request.
+    /// Defaults to disabled.
+    pub events: Option<EventsConfig>,
+    /// Serves completely buffered files by instructing a reverse proxy to send the
+    /// temporary file using an `X-Accel-Redirect` header, rather than streaming the file.
+    /// Requires the service to be exclusively accessed through a trusted proxy.
+    /// Defaults to streaming the files.
+    pub accel_redirect: Option<AccelRedirectConfig>,
+    /// The content types accepted for uploads, e.g. `image/png` or `image/*`.
+    /// Uploads of other types are rejected with `415 Unsupported Media Type`.
+    /// Defaults to accepting all types.
+    #[serde(default)]
+    pub allowed_content_types: Vec<String>,
+    /// The hash and format of the `ETag` of downloads, i.e. `sha256`, `md5` or `weak`.
+    /// Defaults to [`ETagFormat::Sha256`].
+    #[serde(default)]
+    pub etag: ETagFormat,
+    /// The content types rejected for uploads with `415 Unsupported Media Type`,
+    /// e.g. `text/html` or `application/*`. Takes precedence over the allowed types.
+    #[serde(default)]
+    pub denied_content_types: Vec<String>,
+    /// The addresses or CIDR ranges of trusted reverse proxies, e.g. `10.0.0.0/8`.
+    /// For requests from these peers, the client IP is taken from the
+    /// [`forwarded_header`](Self::forwarded_header); otherwise, the address of the peer is used.
+    /// Defaults to trusting no proxies.
+    #[serde(default)]
+    pub trusted_proxies: Vec<String>,
+    /// The header the trusted proxies report the client IP in, i.e. `x-forwarded-for` or
+    /// `forwarded`. Only this header is read, so clients can't pass another one through
+    /// the proxies. Defaults to [`ForwardedHeader::XForwardedFor`].
+    #[serde(default)]
+    pub forwarded_header: ForwardedHeader,
+    /// Headers added to all responses unless already set, e.g. `X-Served-By`. The security
+    /// headers `X-Content-Type-Options: nosniff`, `X-Frame-Options: DENY` and
+    /// `Referrer-Policy: no-referrer` are added by default; configure them with a different
+    /// value to override them, or with an empty value to omit them.
+    #[serde(default)]
+    pub response_headers: BTreeMap<String, String>,
+    /// The value of the `Server` header of all responses, e.g. `yeet-yoink`, overriding any
+    /// value set elsewhere. An empty value removes the header from all responses.
+    /// Defaults to not touching the header.
+    pub server_header: Option<String>,
+    /// The maximum number of connections accepted per second across all listeners,
+    /// allowing bursts of up to one second. Excess connections wait in the backlog of
+    /// the listening sockets. Defaults to no limit.
+    pub max_accept_rate: Option<u32>,
+    /// The maximum number of concurrently open connections across all listeners.
+    /// While reached, new connections wait in the backlog of the listening sockets.
+    /// Defaults to no limit.
+    pub max_connections: Option<u32>,
+    /// How `/yoink-bundle` reports requested files that can't be included in the archive,
+    /// i.e. `manifest` or `skip`. Defaults to [`BundleMissingFiles::Manifest`].
+    #[serde(default)]
+    pub bundle_missing_files: BundleMissingFiles,
+    /// The maximum number of files requested at once from `/yoink-bundle`.
+    /// Defaults to [`DEFAULT_MAX_BUNDLE_FILES`].
+    pub max_bundle_files: Option<usize>,
+    /// The size in bytes from which `/metrics` responses are gzip compressed if the scraper
+    /// accepts it. Defaults to [`DEFAULT_METRICS_GZIP_MIN_BYTES`].
+    pub metrics_gzip_min_bytes: Option<usize>,
+    /// The number of bytes read from a file at once when streaming it from `/yoink`.
+    /// Larger values improve the throughput of large sequential downloads, e.g. from
+    /// spinning disks, at the cost of memory per download.
+    /// Defaults to [`DEFAULT_DOWNLOAD_BUFFER_BYTES`].
+    pub download_buffer_bytes: Option<usize>,
+}
+
+/// The format of the `ETag` header of downloads.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ETagFormat {
+    /// A strong validator using the base64 encoded SHA-256 hash.
+    #[default]
+    Sha256,
+    /// A strong validator using the base64 encoded MD5 digest.
+    Md5,
+    /// A weak validator using the base64 encoded SHA-256 hash.
+    Weak,
+}
+
+/// The header trusted proxies report the client IP in.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ForwardedHeader {
+    /// The de facto standard `X-Forwarded-For` header.
+    #[default]
+    XForwardedFor,
+    /// The `Forwarded` header of RFC 7239.
+    Forwarded,
+}
+
+/// How `/yoink-bundle` reports requested files that can't be included in the archive.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BundleMissingFiles {
+    /// Adds a `manifest.json` entry to the archive listing the included and missing files.
+    #[default]
+    Manifest,
+    /// Leaves missing files out of the archive without reporting them.
+    Skip,
+}
+
+/// The configuration of `X-Accel-Redirect` responses.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct AccelRedirectConfig {
+    /// The internal location of the reverse proxy serving the temporary file directory,
+    /// e.g. `/yy-files`. The file name is appended to it.
+    pub location: String,
+    /// The number of seconds for which the file is held after the redirect, so that it
+    /// isn't removed before the reverse proxy sent it, even if its lease expires.
+    /// Defaults to [`DEFAULT_ACCEL_REDIRECT_HOLD`].
+    pub hold_sec: Option<u64>,
+}
+
+impl AccelRedirectConfig {
+    /// Gets the time for which the file is held after the redirect.
+    pub fn hold(&self) -> Duration {
+        self.hold_sec
+            .map_or(DEFAULT_ACCEL_REDIRECT_HOLD, Duration::from_secs)
+    }
+}
+
+/// The sampling of request logs. Failed requests are always logged; successful requests
+/// are logged if they are sampled or exceed any of the configured thresholds.
+/// Metrics are recorded for every request regardless of these settings.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct AccessLogConfig {
+    /// The fraction of successful requests to log, between `0.0` and `1.0`.
+    /// Use `0.0` to only log requests exceeding a threshold. Defaults to `1.0`.
+    pub sample_rate: Option<f64>,
+    /// The duration in milliseconds above which successful requests are always logged.
+    pub min_duration_ms: Option<u64>,
+    /// The request or response body size in bytes above which successful requests
+    /// are always logged.
+    pub min_size_bytes: Option<u64>,
+}
+
+impl AccessLogConfig {
+    /// Gets the fraction of successful requests to log.
+    pub fn sample_rate(&self) -> f64 {
+        self.sample_rate.unwrap_or(1.0).clamp(0.0, 1.0)
+    }
+
+    /// Gets the duration above which successful requests are always logged.
+    pub fn min_duration(&self) -> Option<Duration> {
+        self.min_duration_ms.map(Duration::from_millis)
+    }
+}
+
+impl ServerConfig {
+    /// Gets the time to wait for in-flight requests to complete during shutdown.
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout_sec
+            .map_or(DEFAULT_DRAIN_TIMEOUT, |secs| Duration::from_secs(secs as _))
+    }
+
+    /// Gets the maximum number of pending connections per listening socket.
+    pub fn listen_backlog(&self) -> u32 {
+        self.listen_backlog.unwrap_or(DEFAULT_LISTEN_BACKLOG)
+    }
+
+    /// Gets the maximum total size of the request headers in bytes.
+    pub fn max_header_bytes(&self) -> u32 {
+        self.max_header_bytes.unwrap_or(DEFAULT_MAX_HEADER_BYTES)
+    }
+
+    /// Gets the maximum number of request headers.
+    pub fn max_header_count(&self) -> u32 {
+        self.max_header_count.unwrap_or(DEFAULT_MAX_HEADER_COUNT)
+    }
+
+    /// Gets whether to set `SO_REUSEADDR` on the listening sockets.
+    pub fn reuse_address(&self) -> bool {
+        self.reuse_address.unwrap_or(true)
+    }
+
+    /// Gets the time after which connections without any activity are closed.
+    pub fn idle_timeout(&self) -> Option<Duration> {
+        self.idle_timeout_sec
+            .map(|secs| Duration::from_secs(secs as _))
+    }
+
+    /// Gets the time within which a client must transmit the complete request headers.
+    pub fn header_read_timeout(&self) -> Option<Duration> {
+        self.header_read_timeout_sec
+            .map(|secs| Duration::from_secs(secs as _))
+    }
+
+    /// Gets the interval for keep-alive probes on idle connections.
+    pub fn keep_alive_interval(&self) -> Option<Duration> {
+        self.keep_alive_interval_sec
+            .map(|secs| Duration::from_secs(secs as _))
+    }
+
+    /// Gets the time after which uploads without any transmitted data are aborted.
+    pub fn body_read_timeout(&self) -> Option<Duration> {
+        self.body_read_timeout_sec
+            .map(|secs| Duration::from_secs(secs as _))
+    }
+
+    /// Determines whether uploads of the specified content type are accepted.
+    /// Parameters such as `charset` are ignored.
+    pub fn is_content_type_allowed(&self, content_type: &str) -> bool {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let matches = |pattern: &String| content_type_matches(pattern, &essence);
+
+        if self.denied_content_types.iter().any(matches) {
+            return false;
+        }
+
+        self.allowed_content_types.is_empty() || self.allowed_content_types.iter().any(matches)
+    }
+
+    /// Gets the maximum number of files requested at once from `/yoink-bundle`.
+    pub fn max_bundle_files(&self) -> usize {
+        self.max_bundle_files.unwrap_or(DEFAULT_MAX_BUNDLE_FILES)
+    }
+
+    /// Gets the maximum number of distinct `path` label values of the HTTP metrics.
+    pub fn max_metric_paths(&self) -> usize {
+        self.max_metric_paths.unwrap_or(DEFAULT_MAX_METRIC_PATHS)
+    }
+
+    /// Gets the size from which `/metrics` responses are gzip compressed.
+    pub fn metrics_gzip_min_bytes(&self) -> usize {
+        self.metrics_gzip_min_bytes
+            .unwrap_or(DEFAULT_METRICS_GZIP_MIN_BYTES)
+    }
+
+    /// Gets the number of bytes read from a file at once when streaming it to a client.
+    pub fn download_buffer_bytes(&self) -> usize {
+        self.download_buffer_bytes
+            .unwrap_or(DEFAULT_DOWNLOAD_BUFFER_BYTES)
+    }
+
+    /// Gets the maximum number of `x-meta-*` headers of an upload.
+    pub fn max_metadata_entries(&self) -> usize {
+        self.max_metadata_entries
+            .unwrap_or(DEFAULT_MAX_METADATA_ENTRIES)
+    }
+
+    /// Gets the maximum total size of the `x-meta-*` headers of an upload in bytes.
+    pub fn max_metadata_bytes(&self) -> usize {
+        self.max_metadata_bytes
+            .unwrap_or(DEFAULT_MAX_METADATA_BYTES)
+    }
+
+    /// Gets the content type to assume for files uploaded without a declared type.
+    pub fn default_content_type(&self) -> &str {
+        self.default_content_type
+            .as_deref()
+            .unwrap_or(DEFAULT_CONTENT_TYPE)
+    }
+}
+
+/// Matches a content type against a pattern such as `image/png`, `image/*` or `*/*`.
+fn content_type_matches(pattern: &str, essence: &str) -> bool {
+    let pattern = pattern.trim().to_ascii_lowercase();
+    if pattern == "*/*" {
+        return true;
+    }
+
+    match pattern.strip_suffix("/*") {
+        Some(main_type) => {
+            matches!(essence.split_once('/'), Some((essence_type, _)) if essence_type == main_type)
+        }
+        None => pattern == essence,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_server_config_works() {
+        let yaml = r#"
+            drain_timeout_sec: 10
+            require_backend: true
+            default_content_type: text/plain
+            idle_timeout_sec: 60
+            access_log:
+              sample_rate: 0.1
+              min_duration_ms: 500
+            etag: md5
+            bundle_missing_files: skip
+            upload_buffer_bytes: 65536
+            server_header: ""
+            http_duration_buckets: [0.1, 1, 10]
+            max_metadata_entries: 4
+            forwarded_header: forwarded
+        "#;
+
+        let config: ServerConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize server config");
+        assert_eq!(config.drain_timeout(), Duration::from_secs(10));
+        assert!(config.require_backend);
+        assert_eq!(config.default_content_type(), "text/plain");
+        assert_eq!(config.idle_timeout(), Some(Duration::from_secs(60)));
+        assert_eq!(config.header_read_timeout(), None);
+        assert_eq!(config.etag, ETagFormat::Md5);
+        assert_eq!(config.bundle_missing_files, BundleMissingFiles::Skip);
+        assert_eq!(config.max_bundle_files(), DEFAULT_MAX_BUNDLE_FILES);
+        assert_eq!(config.upload_buffer_bytes, 65536);
+        assert_eq!(config.server_header.as_deref(), Some(""));
+        assert_eq!(config.http_duration_buckets, [0.1, 1.0, 10.0]);
+        assert_eq!(config.max_metadata_entries(), 4);
+        assert_eq!(config.forwarded_header, ForwardedHeader::Forwarded);
+        assert_eq!(config.max_metadata_bytes(), DEFAULT_MAX_METADATA_BYTES);
+        assert_eq!(config.access_log.sample_rate(), 0.1);
+        assert_eq!(
+            config.access_log.min_duration(),
+            Some(Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn content_type_filter_works() {
+        let config = ServerConfig {
+            allowed_content_types: vec!["image/*".into(), "application/pdf".into()],
+            denied_content_types: vec!["image/svg+xml".into()],
+            ..Default::default()
+        };
+
+        assert!(config.is_content_type_allowed("image/png"));
+        assert!(config.is_content_type_allowed("Application/PDF; charset=binary"));
+        assert!(!config.is_content_type_allowed("image/svg+xml"));
+        assert!(!config.is_content_type_allowed("text/plain"));
+        assert!(ServerConfig::default().is_content_type_allowed("text/plain"));
+    }
+
+    #[test]
+    fn default_server_config_works() {
+        let config = ServerConfig::default();
+        assert_eq!(config.drain_timeout(), DEFAULT_DRAIN_TIMEOUT);
+        assert!(!config.require_backend);
+        assert_eq!(config.default_content_type(), DEFAULT_CONTENT_TYPE);
+        assert_eq!(config.listen_backlog(), DEFAULT_LISTEN_BACKLOG);
+        assert!(config.reuse_address());
+        assert!(!config.reuse_port);
+        assert_eq!(config.access_log.sample_rate(), 1.0);
+        assert_eq!(config.max_header_bytes(), DEFAULT_MAX_HEADER_BYTES);
+        assert_eq!(config.max_header_count(), DEFAULT_MAX_HEADER_COUNT);
+        assert_eq!(
+            config.metrics_gzip_min_bytes(),
+            DEFAULT_METRICS_GZIP_MIN_BYTES
+        );
+        assert_eq!(config.max_metric_paths(), DEFAULT_MAX_METRIC_PATHS);
+        assert_eq!(
+            config.download_buffer_bytes(),
+            DEFAULT_DOWNLOAD_BUFFER_BYTES
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use crate::expiration::LeaseExpirationConfig;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The default SSH port.
+pub const DEFAULT_PORT: u16 = 22;
+
+/// The default expiration time for files stored on SFTP servers.
+pub const DEFAULT_EXPIRATION: Duration = Duration::from_secs(3600);
+
+/// The SFTP backend configuration.
+#[derive(Default, Debug, Serialize, Deserialize)]
+pub struct SftpBackendConfig {
+    /// A tag to identify the backend.
+    pub tag: String,
+    /// The host name or address of the SSH server.
+    pub host: String,
+    /// The port of the SSH server. Defaults to [`DEFAULT_PORT`].
+    pub port: Option<u16>,
+    /// The user to authenticate as.
+    pub user: String,
+    /// The path to the private key used for authentication.
+    pub key_path: PathBuf,
+    /// The passphrase of the private key, if it is encrypted.
+    pub key_passphrase: Option<String>,
+    /// The path to a `known_hosts` file to verify the server's host key against.
+    /// Defaults to `~/.ssh/known_hosts`. Connections to servers whose host key is missing
+    /// from the file or doesn't match it are refused.
+    pub known_hosts_file: Option<PathBuf>,
+    /// Whether to accept any host key instead of verifying it against the `known_hosts`
+    /// file. This allows anyone able to intercept the connection to receive all
+    /// distributed files; only use it for testing. Defaults to `false`.
+    #[serde(default)]
+    pub insecure_accept_any_host_key: bool,
+    /// The remote directory to store the files in. Each file is stored under its ID,
+    /// along with a sidecar file holding its metadata.
+    pub remote_path: String,
+    /// The number of seconds after which the file is considered expired.
+    /// Defaults to [`DEFAULT_EXPIRATION`].
+    ///
+    /// Expired files are no longer served, but are not removed from the server;
+    /// remove them with a scheduled job on the server if needed.
+    pub expiration_sec: Option<u32>,
+    /// See [`LeaseExpirationConfig`].
+    #[serde(flatten)]
+    pub lease_expiration: LeaseExpirationConfig,
+}
+
+impl SftpBackendConfig {
+    /// Gets the port of the SSH server.
+    pub fn port(&self) -> u16 {
+        self.port.unwrap_or(DEFAULT_PORT)
+    }
+
+    /// Gets the path of the `known_hosts` file, or `None` if it is not configured and
+    /// the home directory is unknown.
+    pub fn known_hosts_file(&self) -> Option<PathBuf> {
+        self.known_hosts_file.clone().or_else(|| {
+            std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".ssh/known_hosts"))
+        })
+    }
+
+    /// Gets the time after which a file is considered expired.
+    pub fn expiration(&self) -> Duration {
+        self.expiration_sec
+            .map_or(DEFAULT_EXPIRATION, |secs| Duration::from_secs(secs as _))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_sftp_backend_config_works() {
+        let yaml = r#"
+            tag: sftp-1
+            host: archive.example.com
+            user: yeet
+            key_path: /etc/yeet-yoink/id_ed25519
+            remote_path: /srv/archive
+        "#;
+
+        let config: SftpBackendConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize SFTP backend config");
+        assert_eq!(config.tag, "sftp-1");
+        assert_eq!(config.port(), DEFAULT_PORT);
+        assert_eq!(config.key_path, PathBuf::from("/etc/yeet-yoink/id_ed25519"));
+        assert!(config.known_hosts_file.is_none());
+        assert!(!config.insecure_accept_any_host_key);
+        assert_eq!(config.expiration(), DEFAULT_EXPIRATION);
+    }
+}
@@ -0,0 +1,64 @@
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// The TLS configuration of the HTTP listeners.
+///
+/// If configured, all listeners only accept TLS connections.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TlsConfig {
+    /// The PEM file holding the certificate chain of the server, leaf certificate first.
+    pub cert_path: PathBuf,
+    /// The PEM file holding the private key of the server.
+    pub key_path: PathBuf,
+    /// Verifies the certificates of clients. Defaults to disabled.
+    pub client_auth: Option<ClientAuthConfig>,
+}
+
+/// The verification of client certificates, i.e. mutual TLS.
+///
+/// The subject and subject alternative names of verified certificates are made
+/// available to the handlers and included in the request logs.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ClientAuthConfig {
+    /// The PEM file holding the CA certificates that client certificates are verified against.
+    pub ca_path: PathBuf,
+    /// Whether connections without a client certificate are rejected during the handshake.
+    /// If `false`, clients may connect without a certificate, but certificates that are
+    /// presented must still be valid. Defaults to `true`.
+    pub required: Option<bool>,
+}
+
+impl ClientAuthConfig {
+    /// Gets whether connections without a client certificate are rejected.
+    pub fn required(&self) -> bool {
+        self.required.unwrap_or(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn deserialize_tls_config_works() {
+        let yaml = r#"
+            cert_path: /etc/yeet-yoink/server.pem
+            key_path: /etc/yeet-yoink/server.key
+            client_auth:
+              ca_path: /etc/yeet-yoink/clients-ca.pem
+        "#;
+
+        let config: TlsConfig =
+            serde_yaml::from_str(yaml).expect("Failed to deserialize TLS config");
+        assert_eq!(
+            config.cert_path,
+            PathBuf::from("/etc/yeet-yoink/server.pem")
+        );
+        let client_auth = config.client_auth.expect("client auth is missing");
+        assert_eq!(
+            client_auth.ca_path,
+            PathBuf::from("/etc/yeet-yoink/clients-ca.pem")
+        );
+        assert!(client_auth.required());
+    }
+}
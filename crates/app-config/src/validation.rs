@@ -0,0 +1,401 @@
+use crate::backbone::{IdFormat, IdStrategy, ID_PREFIX_LENGTHS, MAX_LEASE_JITTER_PERCENT};
+use crate::rate_limit::RateLimitKey;
+use crate::{AppConfig, BackendsConfig};
+use std::collections::HashSet;
+
+/// A backend that is registered on startup.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ConfiguredBackend {
+    /// The kind of backend, e.g. `memcache`.
+    pub kind: &'static str,
+    /// The tag of the backend.
+    pub tag: String,
+}
+
+impl BackendsConfig {
+    /// Lists the backends enabled by the configuration in registration order.
+    pub fn configured(&self) -> Vec<ConfiguredBackend> {
+        #[allow(unused_mut)]
+        let mut backends = Vec::new();
+        #[cfg(feature = "memcache")]
+        backends.extend(self.memcache.iter().map(|c| backend("memcache", &c.tag)));
+        #[cfg(feature = "memcache")]
+        backends.extend(
+            self.memcache_striped
+                .iter()
+                .map(|c| backend("memcache_striped", &c.tag)),
+        );
+        #[cfg(feature = "gcs")]
+        backends.extend(self.gcs.iter().map(|c| backend("gcs", &c.tag)));
+        #[cfg(feature = "memory")]
+        backends.extend(self.memory.iter().map(|c| backend("memory", &c.tag)));
+        #[cfg(feature = "sftp")]
+        backends.extend(self.sftp.iter().map(|c| backend("sftp", &c.tag)));
+        backends.extend(self.mirror.iter().map(|c| backend("mirror", &c.tag)));
+        backends
+    }
+}
+
+fn backend(kind: &'static str, tag: &str) -> ConfiguredBackend {
+    ConfiguredBackend {
+        kind,
+        tag: tag.to_string(),
+    }
+}
+
+impl AppConfig {
+    /// Checks the configuration for mistakes that deserializing it doesn't catch, such as
+    /// missing required values, duplicate backend tags or references to unknown backends.
+    ///
+    /// Returns a description of each problem found; the list is empty if there are none.
+    /// Nothing is connected to, so unreachable servers are not detected.
+    pub fn validate(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let backends = self.backends.configured();
+
+        let mut tags = HashSet::new();
+        for backend in &backends {
+            if backend.tag.trim().is_empty() {
+                problems.push(format!("A {kind} backend has no tag", kind = backend.kind));
+            } else if !tags.insert(backend.tag.as_str()) {
+                problems.push(format!(
+                    "The backend tag {tag} is used more than once",
+                    tag = backend.tag
+                ));
+            }
+        }
+
+        #[cfg(feature = "memcache")]
+        for config in &self.backends.memcache {
+            if config.min_idle.unwrap_or_default() > config.max_size() {
+                problems.push(format!(
+                    "The Memcached backend {tag} keeps more idle connections than its pool size of {max_size}",
+                    tag = config.tag,
+                    max_size = config.max_size()
+                ));
+            }
+        }
+
+        #[cfg(feature = "memcache")]
+        for config in &self.backends.memcache_striped {
+            if config.connection_strings.is_empty() {
+                problems.push(format!(
+                    "The striped Memcached backend {tag} has no connection strings",
+                    tag = config.tag
+                ));
+            }
+        }
+
+        #[cfg(feature = "gcs")]
+        for config in &self.backends.gcs {
+            if config.bucket.trim().is_empty() {
+                problems.push(format!(
+                    "The GCS backend {tag} has no bucket",
+                    tag = config.tag
+                ));
+            }
+            if let Some(path) = &config.credentials_file {
+                if !path.is_file() {
+                    problems.push(format!(
+                        "The credentials file {path:?} of the GCS backend {tag} does not exist",
+                        tag = config.tag
+                    ));
+                }
+            }
+        }
+
+        #[cfg(feature = "sftp")]
+        for config in &self.backends.sftp {
+            for (name, value) in [
+                ("host", &config.host),
+                ("user", &config.user),
+                ("remote_path", &config.remote_path),
+            ] {
+                if value.trim().is_empty() {
+                    problems.push(format!(
+                        "The SFTP backend {tag} has no {name}",
+                        tag = config.tag
+                    ));
+                }
+            }
+            if !config.key_path.is_file() {
+                problems.push(format!(
+                    "The private key {path:?} of the SFTP backend {tag} does not exist",
+                    path = config.key_path,
+                    tag = config.tag
+                ));
+            }
+            if !config.insecure_accept_any_host_key
+                && !config.known_hosts_file().is_some_and(|path| path.is_file())
+            {
+                problems.push(format!(
+                    "The known hosts file of the SFTP backend {tag} does not exist",
+                    tag = config.tag
+                ));
+            }
+        }
+
+        let mut mirrored = HashSet::new();
+        for mirror in &self.backends.mirror {
+            if mirror.primary.is_empty() {
+                problems.push(format!(
+                    "The mirror backend {tag} has no primary backend",
+                    tag = mirror.tag
+                ));
+            }
+            for tag in mirror.mirrored() {
+                if !backends
+                    .iter()
+                    .any(|backend| backend.kind != "mirror" && backend.tag == tag)
+                {
+                    problems.push(format!(
+                        "The mirror backend {mirror} refers to the backend {tag}, which is not configured",
+                        mirror = mirror.tag
+                    ));
+                } else if !mirrored.insert(tag) {
+                    problems.push(format!("The backend {tag} is mirrored more than once"));
+                }
+            }
+        }
+
+        for rule in &self.backends.routing {
+            if !tags.contains(rule.backend.as_str()) {
+                problems.push(format!(
+                    "A routing rule refers to the backend {tag}, which is not configured",
+                    tag = rule.backend
+                ));
+            } else if mirrored.contains(rule.backend.as_str()) {
+                problems.push(format!(
+                    "A routing rule refers to the backend {tag}, which only receives files through its mirror",
+                    tag = rule.backend
+                ));
+            }
+        }
+
+        if self.backbone.id_format == IdFormat::Prefix
+            && !ID_PREFIX_LENGTHS.contains(&self.backbone.id_prefix_length())
+        {
+            problems.push(format!(
+                "The ID prefix length must be between {min} and {max} characters",
+                min = ID_PREFIX_LENGTHS.start(),
+                max = ID_PREFIX_LENGTHS.end()
+            ));
+        }
+
+        // Prefixes of time-sortable IDs hold little more than the timestamp.
+        if self.backbone.id_format == IdFormat::Prefix
+            && self.backbone.id_strategy == IdStrategy::Uuidv7
+        {
+            problems.push(String::from(
+                "The prefix ID format can't be used with the uuidv7 ID strategy",
+            ));
+        }
+
+        if self.backbone.lease_jitter_percent > MAX_LEASE_JITTER_PERCENT {
+            problems.push(format!(
+                "The lease jitter must be at most {MAX_LEASE_JITTER_PERCENT} percent"
+            ));
+        }
+
+        if self.backbone.max_open_files == Some(0) {
+            problems.push(String::from(
+                "The maximum number of open files must be at least 1",
+            ));
+        }
+
+        if self.server.download_buffer_bytes == Some(0) {
+            problems.push(String::from(
+                "The download buffer must hold at least 1 byte",
+            ));
+        }
+
+        if matches!(&self.server.upload_rate_limit, Some(config) if config.key == RateLimitKey::ApiKey && config.api_keys.is_empty())
+        {
+            problems.push(String::from(
+                "The upload rate limit identifies clients by API key, but no API keys are configured",
+            ));
+        }
+
+        if let Some(chaos) = &self.server.chaos {
+            for (kind, rate) in [("delay", chaos.delay_rate), ("error", chaos.error_rate)] {
+                if matches!(rate, Some(rate) if !rate.is_finite()) {
+                    problems.push(format!("The chaos {kind} rate must be a finite number"));
+                }
+            }
+        }
+
+        if matches!(&self.server.load_shedding, Some(config) if config.max_in_flight == 0) {
+            problems.push(String::from(
+                "The maximum number of requests in flight before shedding load must be at least 1",
+            ));
+        }
+
+        if self.backends.max_concurrent_distributions == Some(0) {
+            problems.push(String::from(
+                "The maximum number of concurrent distributions must be at least 1",
+            ));
+        }
+
+        if let Some(quorum) = self.backends.write_quorum {
+            // Mirrored backends only count once, as their mirror.
+            let count = backends.len() - mirrored.len();
+            if quorum > count {
+                problems.push(format!(
+                    "The write quorum of {quorum} backends can never be met with {count} backends configured"
+                ));
+            }
+        }
+
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chaos::ChaosConfig;
+    use crate::mirror::MirrorBackendConfig;
+    use crate::rate_limit::UploadRateLimitConfig;
+    use crate::routing::RoutingRule;
+
+    #[test]
+    fn validate_reports_unknown_routing_backends() {
+        let mut config = AppConfig::default();
+        config.backends.routing.push(RoutingRule {
+            backend: String::from("missing"),
+            ..Default::default()
+        });
+        config.backends.write_quorum = Some(1);
+
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("missing"));
+    }
+
+    #[test]
+    fn validate_reports_invalid_mirrors() {
+        let mut config = AppConfig::default();
+        config.backends.mirror.push(MirrorBackendConfig {
+            tag: String::from("mirrored"),
+            primary: Vec::default(),
+            secondary: vec![String::from("missing")],
+        });
+        config.backends.write_quorum = Some(1);
+
+        let problems = config.validate();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].contains("no primary backend"));
+        assert!(problems[1].contains("missing"));
+    }
+
+    #[cfg(feature = "memory")]
+    #[test]
+    fn validate_reports_routing_to_mirrored_backends() {
+        use crate::memory::MemoryBackendConfig;
+
+        let mut config = AppConfig::default();
+        for tag in ["memory-1", "memory-2"] {
+            config.backends.memory.push(MemoryBackendConfig {
+                tag: String::from(tag),
+                ..Default::default()
+            });
+        }
+        config.backends.mirror.push(MirrorBackendConfig {
+            tag: String::from("mirrored"),
+            primary: vec![String::from("memory-1"), String::from("memory-2")],
+            secondary: vec![String::from("memory-1")],
+        });
+        config.backends.routing.push(RoutingRule {
+            backend: String::from("memory-2"),
+            ..Default::default()
+        });
+        config.backends.write_quorum = Some(2);
+
+        let problems = config.validate();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].contains("mirrored more than once"));
+        assert!(problems[1].contains("through its mirror"));
+        assert!(problems[2].contains("quorum"));
+    }
+
+    #[test]
+    fn validate_reports_invalid_id_prefix_length() {
+        let mut config = AppConfig::default();
+        config.backbone.id_prefix_length = Some(4);
+        assert!(config.validate().is_empty());
+
+        config.backbone.id_format = IdFormat::Prefix;
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("prefix length"));
+    }
+
+    #[test]
+    fn validate_reports_prefix_ids_with_uuidv7() {
+        let mut config = AppConfig::default();
+        config.backbone.id_strategy = IdStrategy::Uuidv7;
+        assert!(config.validate().is_empty());
+
+        config.backbone.id_format = IdFormat::Prefix;
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("uuidv7"));
+    }
+
+    #[test]
+    fn validate_reports_excessive_lease_jitter() {
+        let mut config = AppConfig::default();
+        config.backbone.lease_jitter_percent = MAX_LEASE_JITTER_PERCENT;
+        assert!(config.validate().is_empty());
+
+        config.backbone.lease_jitter_percent = 100;
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("lease jitter"));
+    }
+
+    #[test]
+    fn validate_reports_non_finite_chaos_rates() {
+        let mut config = AppConfig::default();
+        config.server.chaos = Some(ChaosConfig {
+            delay_rate: Some(f64::INFINITY),
+            error_rate: Some(2.0),
+            ..Default::default()
+        });
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("delay rate"));
+
+        config.server.chaos = Some(ChaosConfig {
+            error_rate: Some(f64::NAN),
+            ..Default::default()
+        });
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("error rate"));
+    }
+
+    #[test]
+    fn validate_reports_api_key_limits_without_keys() {
+        let mut config = AppConfig::default();
+        config.server.upload_rate_limit = Some(UploadRateLimitConfig {
+            key: RateLimitKey::ApiKey,
+            ..Default::default()
+        });
+        let problems = config.validate();
+        assert_eq!(problems.len(), 1);
+        assert!(problems[0].contains("API keys"));
+
+        config.server.upload_rate_limit = Some(UploadRateLimitConfig {
+            key: RateLimitKey::ApiKey,
+            api_keys: vec![String::from("secret")],
+            ..Default::default()
+        });
+        assert!(config.validate().is_empty());
+    }
+
+    #[test]
+    fn validate_accepts_default_config() {
+        assert!(AppConfig::default().validate().is_empty());
+    }
+}
@@ -1,27 +1,52 @@
+use crate::cleanup_guard::CleanupGuard;
+use crate::content_index::ContentIndex;
+use crate::file_buffer::{FileBuffer, FileBufferWriter};
+use crate::file_id::{FileIdFormat, InvalidIdFormat};
 use crate::file_reader::FileReader;
 use crate::file_record::FileRecord;
 use crate::file_writer::FileWriter;
-use crate::file_writer_guard::FileWriterGuard;
-use async_tempfile::TempFile;
+use crate::file_writer_guard::{ExpectedContent, FileWriterGuard};
+use crate::reservations::Reservations;
+use crate::snapshot::{BackboneSnapshot, FileSnapshot, SNAPSHOT_LOCK_TIMEOUT};
+use crate::temp_file_names::TempFileNames;
+use crate::tombstones::Tombstones;
+use crate::upload_progress::{upload_progress_channel, UploadProgress, UploadProgressReceiver};
+use crate::write_ahead_log::{PendingEntry, WriteAheadLog};
+use crate::write_locks::WriteLocks;
+use crate::{CompletionMode, FinalizationError};
+use app_config::backbone::{BackboneConfig, DigestAlgorithm, IdStrategy, MAX_LEASE_JITTER_PERCENT};
 use axum::headers::ContentType;
 use backend_traits::{BackendCommand, BackendCommandSender};
+use file_distribution::hash::{DigestSet, Sha256Digest};
 use file_distribution::{BoxedFileReader, GetFileReaderError, WriteSummary};
+use metrics::backbone::BackboneMetrics;
+use metrics::distribution::DistributionMetrics;
+use metrics::files::{FileMetrics, FileOperation};
+use rand::Rng;
 use rendezvous::RendezvousGuard;
-use shared_files::{SharedFileWriter, SharedTemporaryFile};
 use shortguid::ShortGuid;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
+use tokio::io::AsyncReadExt;
 use tokio::sync::mpsc::Sender;
-use tokio::sync::{mpsc, oneshot, RwLock};
+use tokio::sync::{mpsc, oneshot, watch, RwLock};
 use tokio::task::JoinHandle;
 use tokio::time::Instant;
-use tracing::info;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
 
 /// The duration for which to keep each file alive.
 pub const TEMPORAL_LEASE: Duration = Duration::from_secs(5 * 60);
 
+/// The number of bytes read at a time when recovering files from the write-ahead log.
+const RECOVERY_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The number of commands that can be queued for the backbone.
+const COMMAND_CHANNEL_CAPACITY: usize = 1024;
+
 /// A local file distribution manager.
 ///
 /// This instance keeps track of currently processed files.
@@ -29,30 +54,259 @@ pub struct Backbone {
     inner: Arc<RwLock<Inner>>,
     sender: Sender<BackboneCommand>,
     loop_handle: JoinHandle<()>,
+    /// Dropped along with the backbone, which starts delayed distributions right away.
+    _shutdown: watch::Sender<()>,
+    /// The index of files by their content, also used for deduplicating files.
+    content_index: ContentIndex,
+    /// Whether any backend is registered to durably store the files.
+    has_backends: bool,
+    /// The maximum relative deviation of a file's lease from [`TEMPORAL_LEASE`].
+    lease_jitter: f64,
+    /// The time for which expired files can still be read until they are removed.
+    expired_grace: Duration,
+    /// The digests to calculate unless overridden per file.
+    digests: DigestSet,
+    /// The strategy for assigning IDs to new files.
+    id_strategy: IdStrategy,
+    /// The format of the IDs returned to clients.
+    id_format: FileIdFormat,
+    /// The number of bytes of each file to hold in memory before spilling it to disk.
+    memory_buffer_bytes: usize,
+    /// The maximum lifetime of files whose lease is extended by reads;
+    /// `None` if sliding expiration is disabled.
+    max_lifetime: Option<Duration>,
+    /// The names of the temporary files.
+    temp_file_names: TempFileNames,
+    /// Whether to calculate the hashes of uploads on the blocking thread pool.
+    blocking_hashing: bool,
+    /// The maximum lease of files; `None` if unlimited.
+    max_lease: Option<Duration>,
+    /// The write-ahead log mirroring uploads until a backend stored them; `None` if disabled.
+    wal: Option<WriteAheadLog>,
+    /// The maximum number of files held at once; `None` if unlimited.
+    max_open_files: Option<usize>,
+    /// The IDs of the files being written, so that each file has a single writer.
+    write_locks: WriteLocks,
+    /// The IDs reserved for uploads that haven't started yet.
+    reservations: Reservations,
+    /// The IDs to issue to the next new files instead of generating them.
+    #[cfg(feature = "testing")]
+    issued_ids: std::sync::Mutex<std::collections::VecDeque<ShortGuid>>,
 }
 
-struct Inner {
+pub(crate) struct Inner {
     open: HashMap<ShortGuid, FileRecord>,
+    /// The IDs of recently removed files.
+    tombstones: Tombstones,
+}
+
+/// The outcome of reassigning a file to the ID derived from its content.
+pub(crate) enum ContentIdAssignment {
+    /// The file is now registered under the content-derived ID.
+    Assigned,
+    /// A file with identical content is already held under the content-derived ID.
+    Existing(Arc<WriteSummary>),
+    /// The content-derived ID is taken by a file that is being removed;
+    /// the file keeps its provisional ID.
+    Unavailable,
+}
+
+impl Inner {
+    /// Registers the file held under the provisional `id` under the `content_id` instead.
+    pub(crate) async fn assign_content_id(
+        &mut self,
+        id: ShortGuid,
+        content_id: ShortGuid,
+    ) -> ContentIdAssignment {
+        if let Some(existing) = self.open.get(&content_id) {
+            return match existing.get_summary().await {
+                Some(summary) if existing.is_open().await => ContentIdAssignment::Existing(summary),
+                _ => ContentIdAssignment::Unavailable,
+            };
+        }
+
+        let Some(mut record) = self.open.remove(&id) else {
+            return ContentIdAssignment::Unavailable;
+        };
+        record.id = content_id;
+        self.open.insert(content_id, record);
+        ContentIdAssignment::Assigned
+    }
 }
 
 impl Backbone {
-    pub fn new(backend_sender: BackendCommandSender, cleanup_rendezvous: RendezvousGuard) -> Self {
-        let (sender, receiver) = mpsc::channel(1024);
+    /// Creates the backbone and starts its command loop.
+    ///
+    /// Fails if the configured ID format would issue colliding IDs.
+    pub fn try_new(
+        backend_sender: BackendCommandSender,
+        cleanup_rendezvous: RendezvousGuard,
+        config: &BackboneConfig,
+    ) -> Result<Self, InvalidIdFormat> {
+        let id_format = FileIdFormat::try_from_config(config)?;
+        let (sender, receiver) = mpsc::channel(COMMAND_CHANNEL_CAPACITY);
         let inner = Arc::new(RwLock::new(Inner {
             open: HashMap::default(),
+            tombstones: Tombstones::new(config.tombstone_ttl()),
         }));
 
+        let content_index = ContentIndex::new(config.deduplicate);
+        if content_index.deduplicates() {
+            info!("Deduplication of uploaded files is enabled");
+        }
+
+        if config.random_file_names {
+            info!("Temporary files are named randomly instead of after their file ID");
+        }
+
+        let has_backends = backend_sender.has_backends();
+        let wal = Self::wal_from_config(config, has_backends);
+        let (shutdown, shutdown_receiver) = watch::channel(());
         let loop_handle = tokio::spawn(Self::command_loop(
             inner.clone(),
             receiver,
             backend_sender,
-            cleanup_rendezvous,
+            CleanupGuard::new(cleanup_rendezvous),
+            content_index.clone(),
+            wal.clone(),
+            config.distribution_delay(),
+            shutdown_receiver,
         ));
-        Self {
+        Ok(Self {
             inner,
             sender,
             loop_handle,
+            _shutdown: shutdown,
+            content_index,
+            has_backends,
+            lease_jitter: f64::from(config.lease_jitter_percent.min(MAX_LEASE_JITTER_PERCENT))
+                / 100.0,
+            expired_grace: config.expired_grace(),
+            digests: Self::digests_from_config(config),
+            id_strategy: config.id_strategy,
+            id_format,
+            memory_buffer_bytes: config.memory_buffer_bytes(),
+            max_lifetime: Self::max_lifetime_from_config(config),
+            temp_file_names: TempFileNames::new(config.random_file_names),
+            blocking_hashing: config.blocking_hashing,
+            max_lease: config.max_lease(),
+            wal,
+            max_open_files: config.max_open_files,
+            write_locks: WriteLocks::default(),
+            reservations: Reservations::default(),
+            #[cfg(feature = "testing")]
+            issued_ids: Default::default(),
+        })
+    }
+
+    /// Indicates whether any backend is registered to durably store the files.
+    /// If not, files only live in the local buffer until their lease expires.
+    pub fn has_backends(&self) -> bool {
+        self.has_backends
+    }
+
+    /// Creates the ID for a new file according to the configured strategy.
+    ///
+    /// If IDs are derived from the file contents, this is the provisional ID
+    /// under which the file is held until the upload completes.
+    pub fn new_id(&self) -> ShortGuid {
+        #[cfg(feature = "testing")]
+        if let Some(id) = self
+            .issued_ids
+            .lock()
+            .expect("failed to lock the issued IDs")
+            .pop_front()
+        {
+            return id;
         }
+
+        let id = match self.id_strategy {
+            IdStrategy::Random | IdStrategy::ContentHash => ShortGuid::new_random(),
+            IdStrategy::Uuidv7 => ShortGuid::from(Uuid::now_v7()),
+        };
+        self.id_format.truncate(id)
+    }
+
+    /// Reserves a new ID for an upload that is started later, so that clients can subscribe
+    /// to its progress before streaming it. Returns the ID and the time until which the
+    /// upload must be started, or `None` if too many IDs are reserved.
+    pub fn reserve_id(&self) -> Option<(ShortGuid, Instant)> {
+        let id = self.new_id();
+        let expires = self.reservations.reserve(id)?;
+        Some((id, expires))
+    }
+
+    /// Indicates whether the ID was reserved using [`Backbone::reserve_id`] and its
+    /// upload can still be started.
+    pub fn is_reserved(&self, id: ShortGuid) -> bool {
+        self.reservations.contains(id)
+    }
+
+    /// Issues the IDs, in order, to the next new files instead of generating them,
+    /// e.g. to have concurrent uploads use the same ID in tests.
+    #[cfg(feature = "testing")]
+    pub fn issue_ids<I: IntoIterator<Item = ShortGuid>>(&self, ids: I) {
+        self.issued_ids
+            .lock()
+            .expect("failed to lock the issued IDs")
+            .extend(ids);
+    }
+
+    /// Gets the format of the IDs returned to clients.
+    pub fn id_format(&self) -> &FileIdFormat {
+        &self.id_format
+    }
+
+    /// Gets the maximum lifetime of files whose lease is extended by reads,
+    /// which never exceeds the maximum lease.
+    fn max_lifetime_from_config(config: &BackboneConfig) -> Option<Duration> {
+        let max_lifetime = config.max_lifetime()?;
+        match config.max_lease() {
+            Some(max_lease) if max_lifetime > max_lease => {
+                warn!("The maximum lifetime of {max_lifetime:?} exceeds the maximum lease and is clamped to {max_lease:?}");
+                Some(max_lease)
+            }
+            _ => Some(max_lifetime),
+        }
+    }
+
+    /// Opens the write-ahead log if configured. Without backends, files are never meant
+    /// to outlive their lease, so there is nothing to recover.
+    fn wal_from_config(config: &BackboneConfig, has_backends: bool) -> Option<WriteAheadLog> {
+        let dir = config.wal_dir.as_ref()?;
+        if !has_backends {
+            warn!("The write-ahead log is disabled since no backends are registered");
+            return None;
+        }
+
+        match WriteAheadLog::open(dir) {
+            Ok(wal) => {
+                info!("Mirroring uploads to the write-ahead log in {dir:?}");
+                Some(wal)
+            }
+            Err(e) => {
+                warn!("Failed to open the write-ahead log in {dir:?}; uploads won't be recovered after a crash: {e}");
+                None
+            }
+        }
+    }
+
+    fn digests_from_config(config: &BackboneConfig) -> DigestSet {
+        let Some(digests) = &config.digests else {
+            return DigestSet::DEFAULT;
+        };
+
+        let mut set = DigestSet::NONE;
+        for digest in digests {
+            match digest {
+                DigestAlgorithm::Md5 => set.md5 = true,
+                DigestAlgorithm::Sha256 => set.sha256 = true,
+                DigestAlgorithm::Crc32c => set.crc32c = true,
+            }
+        }
+
+        info!("Calculating the digests {set} for uploaded files");
+        set
     }
 
     pub async fn join(self) {
@@ -60,32 +314,84 @@ impl Backbone {
     }
 
     /// Creates a new file buffer, registers it and returns a writer to it.
+    ///
+    /// Each file has a single writer. Creating a file with the ID of a file that is being
+    /// written or held fails with [`NewFileError::AlreadyExists`] without affecting it.
+    ///
+    /// The specified `digests` override the configured digests to calculate for the file.
+    /// Regardless of the selection, the digests of the `expected` content are calculated,
+    /// and the SHA-256 hash is calculated if deduplication is enabled.
     pub async fn new_file(
         &self,
         id: ShortGuid,
-        expected_size: Option<u64>,
+        expected: ExpectedContent,
         content_type: Option<ContentType>,
-        content_md5: Option<[u8; 16]>,
         file_name: Option<String>,
+        metadata: BTreeMap<String, String>,
+        digests: Option<DigestSet>,
     ) -> Result<FileWriterGuard, NewFileError> {
-        // We reuse the ID such that it is easier to find and debug the
-        // created file if necessary.
-        let file = Self::create_new_temporary_file(id).await?;
-        let writer = Self::create_writer_for_file(id, &file).await?;
+        // Each file issues several commands over its lifetime; if the backbone can't
+        // keep up with them, new files are rejected rather than queued.
+        let queue_depth = COMMAND_CHANNEL_CAPACITY - self.sender.capacity();
+        BackboneMetrics::set_command_queue_depth(queue_depth);
+        if self.sender.capacity() == 0 {
+            warn!(file_id = %id, "The backbone is saturated with {queue_depth} pending commands; rejecting new file {id}");
+            return Err(NewFileError::Overloaded(id));
+        }
+
+        // The buffer of a file named after its ID would replace the file of another writer.
+        let Some(write_lock) = self.write_locks.try_lock(id) else {
+            warn!(file_id = %id, "The file {id} is already being written; rejecting the concurrent writer");
+            return Err(NewFileError::AlreadyExists(id));
+        };
+        {
+            let inner = self.inner.read().await;
+            if inner.open.contains_key(&id) {
+                warn!(file_id = %id, "The file {id} already exists; rejecting the new writer");
+                return Err(NewFileError::AlreadyExists(id));
+            }
+
+            // Checked before the buffer is created so that rejected files cost no disk I/O.
+            if self
+                .max_open_files
+                .is_some_and(|max| inner.open.len() >= max)
+            {
+                warn!(file_id = %id, "{count} files are open; rejecting new file {id}", count = inner.open.len());
+                return Err(NewFileError::TooManyOpenFiles(id));
+            }
+        }
+
+        let (file, writer) = self
+            .create_file_buffer(id, expected.size)
+            .await
+            .map_err(track_creation_failure)?;
 
         let mut inner = self.inner.write().await;
         let (sender, receiver) = oneshot::channel();
+        let (progress, progress_receiver) = match self.reservations.take(id) {
+            Some(progress) => {
+                progress.send_replace(UploadProgress::Pending {
+                    buffered: 0,
+                    expected: expected.size,
+                });
+                let receiver = progress.subscribe();
+                (progress, receiver)
+            }
+            None => upload_progress_channel(expected.size),
+        };
 
-        let temporal_lease = TEMPORAL_LEASE;
+        // The same lease is used for the file's lifetime and its reported expiration date.
+        let temporal_lease = self.clamp_lease(id, self.jittered_lease());
 
         // This needs to happen synchronously so that the moment we return the writer,
         // we know the entry exists.
+        let wal_content_type = content_type.as_ref().map(ToString::to_string);
         match inner.open.entry(id) {
             Entry::Occupied(_) => {
-                // TODO: Actively mark the file as failed? This could invalidate all readers and writers.
+                // Files may be registered under content-derived IDs without a write lock.
                 drop(writer);
                 drop(file);
-                return Err(NewFileError::InternalErrorMayRetry(id));
+                return Err(NewFileError::AlreadyExists(id));
             }
             Entry::Vacant(v) => v.insert(FileRecord::new(
                 id,
@@ -93,25 +399,89 @@ impl Backbone {
                 self.sender.clone(),
                 receiver,
                 temporal_lease,
+                self.expired_grace,
                 content_type,
+                expected.size,
                 Instant::now(),
+                progress_receiver,
             )),
         };
+        BackboneMetrics::set_open_files(inner.open.len());
+        drop(inner);
+
+        let wal = match &self.wal {
+            Some(wal) => match wal.create(id, wal_content_type).await {
+                Ok(writer) => Some(writer),
+                Err(e) => {
+                    warn!(file_id = %id, "Failed to write file {id} to the write-ahead log; the file won't be recovered after a crash: {e}");
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let content_addressed = self.id_strategy == IdStrategy::ContentHash;
+        let mut digests = digests.unwrap_or(self.digests);
+        digests.md5 |= expected.md5.is_some();
+        digests.sha256 |=
+            expected.sha256.is_some() || self.content_index.deduplicates() || content_addressed;
 
-        let writer = FileWriter::new(&id, writer, file_name);
+        let writer = FileWriter::new(
+            &id,
+            writer,
+            file_name,
+            metadata,
+            digests,
+            self.blocking_hashing,
+        );
         Ok(FileWriterGuard::new(
+            id,
             writer,
             sender,
             temporal_lease,
-            expected_size,
-            content_md5,
+            expected,
+            self.content_index.clone(),
+            content_addressed.then(|| self.inner.clone()),
+            self.id_format,
+            progress,
+            wal,
+            write_lock,
         ))
     }
 
-    /// Creates a new file buffer, registers it and returns a writer to it.
+    /// Gets the lease for a new file, i.e. the [`TEMPORAL_LEASE`] randomly
+    /// extended or shortened by up to the configured jitter.
+    fn jittered_lease(&self) -> Duration {
+        if self.lease_jitter <= 0.0 {
+            return TEMPORAL_LEASE;
+        }
+
+        let factor = rand::thread_rng().gen_range(-self.lease_jitter..=self.lease_jitter);
+        TEMPORAL_LEASE.mul_f64(1.0 + factor)
+    }
+
+    /// Clamps the lease of a file to the configured maximum lease.
+    pub fn clamp_lease(&self, id: ShortGuid, lease: Duration) -> Duration {
+        match self.max_lease {
+            Some(max_lease) if lease > max_lease => {
+                debug!(file_id = %id, "Clamping the lease of file {id} from {lease:?} to the maximum lease of {max_lease:?}");
+                max_lease
+            }
+            _ => lease,
+        }
+    }
+
+    /// Gets a reader for a buffered file.
+    ///
+    /// Files that were removed recently are reported as expired rather than unknown.
+    /// Files that are still being written can be read while they are written; reads wait
+    /// for more data until the write completes, and fail if the write fails.
+    /// Files whose lease has expired can still be read during the configured grace period;
+    /// their readers report an expiration date in the past.
     pub async fn get_file(&self, id: ShortGuid) -> Result<BoxedFileReader, GetFileReaderError> {
         let inner = self.inner.read().await;
         match inner.open.get(&id) {
+            None if inner.tombstones.contains(&id) => Err(GetFileReaderError::FileExpired(id)),
             None => Err(GetFileReaderError::UnknownFile(id)),
             Some(file) => {
                 let reader = file.get_reader().await?;
@@ -119,7 +489,9 @@ impl Backbone {
                     reader,
                     file.content_type.clone(),
                     file.created,
-                    file.expiration_duration,
+                    file.lease_duration(),
+                    file.expected_size,
+                    file.get_path().await,
                     file.get_summary().await,
                 );
                 Ok(BoxedFileReader::new(reader))
@@ -127,40 +499,320 @@ impl Backbone {
         }
     }
 
-    async fn create_new_temporary_file(id: ShortGuid) -> Result<SharedTemporaryFile, NewFileError> {
-        SharedTemporaryFile::new_with_uuid(id.into())
+    /// Gets the write summary of a file without opening a reader, or `None` if the file
+    /// is still being written.
+    pub async fn get_summary(
+        &self,
+        id: ShortGuid,
+    ) -> Result<Option<Arc<WriteSummary>>, GetFileReaderError> {
+        let inner = self.inner.read().await;
+        match inner.open.get(&id) {
+            None if inner.tombstones.contains(&id) => Err(GetFileReaderError::FileExpired(id)),
+            None => Err(GetFileReaderError::UnknownFile(id)),
+            Some(file) if !file.is_open().await => Err(GetFileReaderError::FileExpired(id)),
+            Some(file) => Ok(file.get_summary().await),
+        }
+    }
+
+    /// Extends the lease of a file that is read by a client if sliding expiration is enabled.
+    /// The lease is extended to one lease duration from now, up to the configured maximum
+    /// lifetime of the file.
+    pub async fn extend_lease(&self, id: ShortGuid) {
+        let Some(max_lifetime) = self.max_lifetime else {
+            return;
+        };
+
+        let inner = self.inner.read().await;
+        if let Some(file) = inner.open.get(&id) {
+            file.extend_lease(max_lifetime);
+        }
+    }
+
+    /// Gets the ID of a completely buffered file by its SHA-256 hash, or `None` if no
+    /// such file is held. If several identical files are held, the latest one is returned.
+    pub fn find_by_sha256(&self, sha256: &Sha256Digest) -> Option<ShortGuid> {
+        self.content_index.get(sha256)
+    }
+
+    /// Subscribes to the progress of the upload of a file.
+    ///
+    /// The receiver reports the latest progress and is notified whenever data is buffered
+    /// or the upload completes. Uploads to reserved IDs can be subscribed to before they
+    /// start. Returns `None` if the file is unknown.
+    pub async fn subscribe_progress(&self, id: ShortGuid) -> Option<UploadProgressReceiver> {
+        let inner = self.inner.read().await;
+        inner
+            .open
+            .get(&id)
+            .map(FileRecord::subscribe_progress)
+            .or_else(|| self.reservations.subscribe(id))
+    }
+
+    /// Schedules a completely buffered file for distribution to the backends again,
+    /// e.g. after a backend was unavailable.
+    pub async fn redistribute(&self, id: ShortGuid) -> Result<(), RedistributeError> {
+        let summary = {
+            let inner = self.inner.read().await;
+            let record = inner
+                .open
+                .get(&id)
+                .ok_or(RedistributeError::UnknownFile(id))?;
+            if !record.is_open().await {
+                return Err(RedistributeError::UnknownFile(id));
+            }
+
+            record
+                .get_summary()
+                .await
+                .ok_or(RedistributeError::NotReady(id))?
+        };
+
+        info!(file_id = %id, "Scheduling redistribution of file {id}");
+        self.sender
+            .send(BackboneCommand::ReadyForDistribution(id, summary))
             .await
-            .map_err(|e| NewFileError::FailedCreatingFile(id, e))
+            .map_err(|_| RedistributeError::BackboneCommunicationFailed(id))
     }
 
-    async fn create_writer_for_file(
+    /// Buffers the files left over in the write-ahead log, e.g. after a crash, and
+    /// schedules them for distribution again. Returns the number of recovered files.
+    ///
+    /// Files keep their ID unless IDs are derived from the content, in which case
+    /// the same ID is derived again.
+    pub async fn recover_from_wal(&self) -> usize {
+        let Some(wal) = &self.wal else {
+            return 0;
+        };
+
+        let pending = match wal.take_pending().await {
+            Ok(pending) => pending,
+            Err(e) => {
+                warn!(
+                    "Failed to read the write-ahead log in {dir:?}: {e}",
+                    dir = wal.dir()
+                );
+                return 0;
+            }
+        };
+
+        let mut recovered = 0;
+        for entry in pending {
+            match self.recover_file(&entry).await {
+                Ok(id) => {
+                    info!(file_id = %id, "Recovered file {id} from the write-ahead log");
+                    wal.remove_recovered(&entry).await;
+                    recovered += 1;
+                }
+                // The entry is kept so that recovering it can be attempted again on the next start.
+                Err(e) => {
+                    warn!(file_id = %entry.id, "Failed to recover file {id} from the write-ahead log: {e}", id = entry.id)
+                }
+            }
+        }
+
+        if recovered > 0 {
+            info!("Recovered {recovered} files from the write-ahead log");
+        }
+        recovered
+    }
+
+    async fn recover_file(&self, entry: &PendingEntry) -> Result<ShortGuid, RecoverFileError> {
+        let item = entry
+            .record
+            .item
+            .as_ref()
+            .ok_or(RecoverFileError::InvalidRecord)?;
+        let hashes = item
+            .hashes
+            .as_ref()
+            .and_then(|hashes| hashes.to_file_hashes())
+            .ok_or(RecoverFileError::InvalidRecord)?;
+
+        // The content-derived ID is only assigned once the content is known.
+        let id = match self.id_strategy {
+            IdStrategy::ContentHash => self.new_id(),
+            IdStrategy::Random | IdStrategy::Uuidv7 => entry.id,
+        };
+
+        let expected = ExpectedContent {
+            size: Some(entry.record.size),
+            md5: hashes.md5.map(|md5| md5.0),
+            sha256: hashes.sha256.map(Into::into),
+        };
+        let content_type = entry
+            .record
+            .content_type
+            .as_deref()
+            .and_then(|content_type| content_type.parse().ok());
+        let mut writer = self
+            .new_file(
+                id,
+                expected,
+                content_type,
+                item.file_name.clone(),
+                item.metadata.clone(),
+                None,
+            )
+            .await?;
+
+        let mut file = tokio::fs::File::open(&entry.path).await?;
+        let mut buf = vec![0; RECOVERY_CHUNK_SIZE];
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            writer.write(&buf[..read]).await?;
+        }
+
+        let finalized = writer.finalize(CompletionMode::Sync).await?;
+        Ok(finalized.id)
+    }
+
+    /// Takes a snapshot of the files held and the state of the command loop,
+    /// e.g. for diagnosing a stuck instance. The files are ordered by their age.
+    ///
+    /// The files are only listed if their bookkeeping can be locked within
+    /// [`SNAPSHOT_LOCK_TIMEOUT`], since a stuck instance may be holding the lock.
+    pub async fn snapshot(&self) -> BackboneSnapshot {
+        let files = match tokio::time::timeout(SNAPSHOT_LOCK_TIMEOUT, self.inner.read()).await {
+            Ok(inner) => {
+                let mut files = Vec::with_capacity(inner.open.len());
+                for (id, file) in &inner.open {
+                    files.push(FileSnapshot {
+                        id: *id,
+                        file_size_bytes: file
+                            .get_summary()
+                            .await
+                            .map(|summary| summary.file_size_bytes),
+                        accepts_readers: file.is_open().await,
+                        age: file.created.elapsed(),
+                        lease: file.lease_duration(),
+                        temp_file_path: self.temp_file_path(id),
+                    });
+                }
+                drop(inner);
+
+                files.sort_by_key(|file| std::cmp::Reverse(file.age));
+                Some(files)
+            }
+            Err(_) => None,
+        };
+
+        BackboneSnapshot {
+            files,
+            pending_commands: COMMAND_CHANNEL_CAPACITY - self.sender.capacity(),
+            command_loop_running: !self.loop_handle.is_finished(),
+        }
+    }
+
+    /// Gets the path of the randomly named temporary file of the file with the
+    /// specified ID, for debugging. Returns `None` if temporary files are named after
+    /// the file ID, or if the file is not (or no longer) held in a temporary file.
+    fn temp_file_path(&self, id: &ShortGuid) -> Option<PathBuf> {
+        self.temp_file_names.get(id)
+    }
+
+    /// Creates the buffer for a new file. Files are held in memory unless they are
+    /// announced to exceed the configured memory buffer, and spilled to disk once they do.
+    async fn create_file_buffer(
+        &self,
         id: ShortGuid,
-        file: &SharedTemporaryFile,
-    ) -> Result<SharedFileWriter<TempFile>, NewFileError> {
-        file.writer()
+        expected_size: Option<u64>,
+    ) -> Result<(FileBuffer, FileBufferWriter), NewFileError> {
+        let exceeds_memory_buffer =
+            expected_size.is_some_and(|size| size > self.memory_buffer_bytes as u64);
+        if self.memory_buffer_bytes > 0 && !exceeds_memory_buffer {
+            return Ok(FileBuffer::in_memory(
+                id,
+                self.memory_buffer_bytes,
+                self.temp_file_names.clone(),
+            ));
+        }
+
+        let file = self
+            .temp_file_names
+            .create(id)
+            .await
+            .map_err(|e| NewFileError::FailedCreatingFile(id, e))?;
+        FileBuffer::on_disk(id, file, self.temp_file_names.clone())
             .await
             .map_err(|e| NewFileError::FailedCreatingWriter(id, e))
     }
 
+    #[allow(clippy::too_many_arguments)]
     async fn command_loop(
         inner: Arc<RwLock<Inner>>,
         mut channel: mpsc::Receiver<BackboneCommand>,
         backend_sender: BackendCommandSender,
-        cleanup_rendezvous: RendezvousGuard,
+        cleanup_rendezvous: CleanupGuard,
+        content_index: ContentIndex,
+        wal: Option<WriteAheadLog>,
+        distribution_delay: Option<Duration>,
+        shutdown: watch::Receiver<()>,
     ) {
         while let Some(command) = channel.recv().await {
+            BackboneMetrics::set_command_queue_depth(channel.len());
             match command {
                 BackboneCommand::RemoveWriter(id) => {
                     info!(file_id = %id, "Removing file {id} from bookkeeping");
+                    if let Some(wal) = &wal {
+                        wal.remove(id).await;
+                    }
+
                     let mut inner = inner.write().await;
-                    inner.open.remove(&id);
+                    let removed = inner.open.remove(&id);
+                    BackboneMetrics::set_open_files(inner.open.len());
+                    if let Some(record) = removed {
+                        // Only files that were stored successfully were ever handed out.
+                        let Some(summary) = record.get_summary().await else {
+                            continue;
+                        };
+
+                        inner.tombstones.insert(id);
+                        if let Some(sha256) = &summary.hashes.sha256 {
+                            content_index.remove(id, sha256);
+                        }
+                    }
+                }
+                BackboneCommand::LeaseExpired(id) => {
+                    // Without backends, files are never meant to outlive their lease.
+                    if backend_sender.has_backends()
+                        && !backend_sender.distribution_tracker().is_stored(&id)
+                    {
+                        warn!(file_id = %id, "The lease of file {id} expired before any backend stored it; the file will be lost");
+                        FileMetrics::track_expired_undistributed();
+                    }
                 }
                 BackboneCommand::ReadyForDistribution(id, summary) => {
                     info!(file_id = %id, "The file {id} was buffered completely and can now be distributed");
-                    backend_sender
-                        .send(BackendCommand::DistributeFile(id, summary))
-                        .await
-                        .ok();
+                    let Some(delay) = distribution_delay else {
+                        Self::distribute(id, summary, &backend_sender, wal.clone()).await;
+                        continue;
+                    };
+
+                    // Files removed shortly after their creation are never distributed. Once
+                    // the backbone is dropped, pending files are distributed right away, and
+                    // the shutdown waits for them.
+                    let inner = inner.clone();
+                    let backend_sender = backend_sender.clone();
+                    let wal = wal.clone();
+                    let mut shutdown = shutdown.clone();
+                    let guard = cleanup_rendezvous.fork();
+                    tokio::spawn(async move {
+                        tokio::time::timeout(delay, shutdown.changed()).await.ok();
+                        let removed = match inner.read().await.open.get(&id) {
+                            Some(record) => !record.is_open().await,
+                            None => true,
+                        };
+                        if removed {
+                            debug!(file_id = %id, "Skipping the distribution of file {id} since it was removed within {delay:?}");
+                            DistributionMetrics::track_cancelled();
+                        } else {
+                            Self::distribute(id, summary, &backend_sender, wal).await;
+                        }
+                        guard.completed();
+                    });
                 }
             }
         }
@@ -168,6 +820,30 @@ impl Backbone {
         info!("The backbone command loop stopped");
         cleanup_rendezvous.completed();
     }
+
+    /// Hands a file to the backends for distribution.
+    async fn distribute(
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        backend_sender: &BackendCommandSender,
+        wal: Option<WriteAheadLog>,
+    ) {
+        backend_sender
+            .send(BackendCommand::DistributeFile(id, summary))
+            .await
+            .ok();
+
+        // Files are kept in the write-ahead log until a backend stored them.
+        if let Some(wal) = wal {
+            let tracker = backend_sender.distribution_tracker().clone();
+            tokio::spawn(async move {
+                tracker.wait_for_completion(&id).await;
+                if tracker.is_stored(&id) {
+                    wal.remove(id).await;
+                }
+            });
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -178,16 +854,63 @@ pub enum BackboneCommand {
     /// Currently open writers or readers will continue to work.
     /// When the last reference is closed, the file will be removed.
     RemoveWriter(ShortGuid),
+    /// Indicates that the lease of a file has expired.
+    LeaseExpired(ShortGuid),
     /// Marks the file ready for distribution to other backends.
     ReadyForDistribution(ShortGuid, Arc<WriteSummary>),
 }
 
+/// Tracks a failed temporary file operation, labeled by the kind of failure.
+fn track_creation_failure(error: NewFileError) -> NewFileError {
+    let (operation, e) = match &error {
+        NewFileError::FailedCreatingFile(_, e) => (FileOperation::CreateFile, e),
+        NewFileError::FailedCreatingWriter(_, e) => (FileOperation::CreateWriter, e),
+        NewFileError::AlreadyExists(_)
+        | NewFileError::Overloaded(_)
+        | NewFileError::TooManyOpenFiles(_) => return error,
+    };
+
+    let kind = match e {
+        async_tempfile::Error::InvalidDirectory => String::from("InvalidDirectory"),
+        async_tempfile::Error::InvalidFile => String::from("InvalidFile"),
+        async_tempfile::Error::Io(e) => format!("{:?}", e.kind()),
+    };
+    FileMetrics::track_creation_failure(operation, kind);
+    error
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum NewFileError {
     #[error("Failed to create the file: {1}")]
     FailedCreatingFile(ShortGuid, async_tempfile::Error),
     #[error("Failed to create a writer to the file: {1}")]
     FailedCreatingWriter(ShortGuid, async_tempfile::Error),
-    #[error("An internal error occurred; the operation may be retried")]
-    InternalErrorMayRetry(ShortGuid),
+    #[error("A file with the ID already exists or is being written")]
+    AlreadyExists(ShortGuid),
+    #[error("The backbone is overloaded; the operation may be retried later")]
+    Overloaded(ShortGuid),
+    #[error("Too many files are open; the operation may be retried later")]
+    TooManyOpenFiles(ShortGuid),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum RedistributeError {
+    #[error("The file is unknown or has been removed")]
+    UnknownFile(ShortGuid),
+    #[error("The file is still being written")]
+    NotReady(ShortGuid),
+    #[error("Failed to communicate to the backbone")]
+    BackboneCommunicationFailed(ShortGuid),
+}
+
+#[derive(Debug, thiserror::Error)]
+enum RecoverFileError {
+    #[error("The record of the file is invalid")]
+    InvalidRecord,
+    #[error(transparent)]
+    NewFile(#[from] NewFileError),
+    #[error("Failed to copy the file: {0}")]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Finalization(#[from] FinalizationError),
 }
@@ -0,0 +1,41 @@
+use rendezvous::RendezvousGuard;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// The number of cleanup guards that are currently held.
+static OUTSTANDING: AtomicUsize = AtomicUsize::new(0);
+
+/// A [`RendezvousGuard`] that keeps count of the guards held, so that a shutdown
+/// waiting for the rendezvous can be attributed to the tasks that are still running.
+pub struct CleanupGuard(RendezvousGuard);
+
+impl CleanupGuard {
+    pub fn new(guard: RendezvousGuard) -> Self {
+        OUTSTANDING.fetch_add(1, Ordering::Relaxed);
+        Self(guard)
+    }
+
+    /// Forks a guard that holds off the rendezvous until it is dropped as well.
+    pub fn fork(&self) -> Self {
+        Self::new(self.0.fork())
+    }
+
+    /// Marks the rendezvous point by consuming the guard.
+    pub fn completed(self) {}
+
+    /// Gets the number of guards that are currently held across the process.
+    pub fn outstanding() -> usize {
+        OUTSTANDING.load(Ordering::Relaxed)
+    }
+}
+
+impl From<RendezvousGuard> for CleanupGuard {
+    fn from(guard: RendezvousGuard) -> Self {
+        Self::new(guard)
+    }
+}
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        OUTSTANDING.fetch_sub(1, Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,71 @@
+use file_distribution::hash::Sha256Digest;
+use file_distribution::WriteSummary;
+use shortguid::ShortGuid;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+/// The ID and write summary of an indexed file.
+type IndexedFile = (ShortGuid, Arc<WriteSummary>);
+
+/// An index of currently held files by their SHA-256 hash. Can be cheaply cloned.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ContentIndex {
+    inner: Arc<Mutex<HashMap<Sha256Digest, IndexedFile>>>,
+    /// Whether files identical to an indexed file are replaced by it.
+    deduplicate: bool,
+}
+
+impl ContentIndex {
+    /// Creates an index that optionally deduplicates files; see [`register`](Self::register).
+    pub fn new(deduplicate: bool) -> Self {
+        Self {
+            inner: Arc::default(),
+            deduplicate,
+        }
+    }
+
+    /// Indicates whether files identical to an indexed file are replaced by it.
+    pub fn deduplicates(&self) -> bool {
+        self.deduplicate
+    }
+
+    /// Registers the file under its SHA-256 hash.
+    ///
+    /// If deduplication is enabled and a different file with the same hash is already
+    /// registered, the ID and summary of the existing file are returned instead. Otherwise,
+    /// the file supersedes any file registered under the same hash. Files without a SHA-256
+    /// hash are not indexed.
+    pub fn register(&self, id: ShortGuid, summary: &Arc<WriteSummary>) -> Option<IndexedFile> {
+        let sha256 = summary.hashes.sha256?;
+        let mut inner = self.inner.lock().expect("failed to lock content index");
+        match inner.entry(sha256) {
+            Entry::Occupied(entry) if self.deduplicate && entry.get().0 != id => {
+                let (existing_id, existing_summary) = entry.get();
+                Some((*existing_id, existing_summary.clone()))
+            }
+            Entry::Occupied(mut entry) => {
+                entry.insert((id, summary.clone()));
+                None
+            }
+            Entry::Vacant(entry) => {
+                entry.insert((id, summary.clone()));
+                None
+            }
+        }
+    }
+
+    /// Gets the ID of the file registered under the specified hash.
+    pub fn get(&self, sha256: &Sha256Digest) -> Option<ShortGuid> {
+        let inner = self.inner.lock().expect("failed to lock content index");
+        inner.get(sha256).map(|(id, _)| *id)
+    }
+
+    /// Removes the file from the index if it is registered under the specified hash.
+    pub fn remove(&self, id: ShortGuid, sha256: &Sha256Digest) {
+        let mut inner = self.inner.lock().expect("failed to lock content index");
+        if matches!(inner.get(sha256), Some((existing_id, _)) if *existing_id == id) {
+            inner.remove(sha256);
+        }
+    }
+}
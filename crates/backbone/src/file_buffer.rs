@@ -0,0 +1,491 @@
+use crate::temp_file_names::TempFileNames;
+use shared_files::prelude::{CompleteWritingError, ReadError};
+use shared_files::SharedTemporaryFileWriter;
+use shared_files::{FileSize, SharedTemporaryFile, SharedTemporaryFileReader};
+use shortguid::ShortGuid;
+use std::fmt::{Debug, Formatter};
+use std::future::Future;
+use std::io::{Error, ErrorKind};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::task::{ready, Context, Poll, Waker};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt, ReadBuf};
+use tracing::debug;
+
+/// The buffer of a file, holding it in memory until it exceeds a threshold
+/// and spilling it to a temporary file afterwards.
+///
+/// Readers can be created at any time and follow the data as it is written,
+/// regardless of whether it is held in memory or on disk.
+#[derive(Debug)]
+pub(crate) struct FileBuffer {
+    shared: Arc<Shared>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    id: ShortGuid,
+    /// The names of temporary files, for spilling the data to disk.
+    names: TempFileNames,
+    state: Mutex<State>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The buffered data; empty once the file was spilled to disk.
+    data: Vec<u8>,
+    /// The temporary file, if the data was spilled to disk.
+    spilled: Option<Spilled>,
+    /// The state of the in-memory write.
+    write_state: WriteState,
+    /// The readers waiting for more data.
+    wakers: Vec<Waker>,
+}
+
+struct Spilled {
+    file: Arc<SharedTemporaryFile>,
+    /// A reader only used to query the size of the file for readers that started
+    /// reading from memory; `None` if the file was written to disk right away.
+    probe: Option<SharedTemporaryFileReader>,
+}
+
+impl Debug for Spilled {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Spilled")
+            .field("file", &self.file.file_path())
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+enum WriteState {
+    #[default]
+    Pending,
+    Completed,
+    Failed,
+}
+
+impl FileBuffer {
+    /// Creates a buffer holding up to `threshold` bytes in memory before spilling to disk.
+    pub fn in_memory(
+        id: ShortGuid,
+        threshold: usize,
+        names: TempFileNames,
+    ) -> (Self, FileBufferWriter) {
+        let shared = Arc::new(Shared {
+            id,
+            names,
+            state: Mutex::new(State::default()),
+        });
+        let writer = FileBufferWriter {
+            shared: shared.clone(),
+            threshold,
+            disk: None,
+            completed: false,
+        };
+        (Self { shared }, writer)
+    }
+
+    /// Creates a buffer writing to the specified temporary file right away.
+    pub async fn on_disk(
+        id: ShortGuid,
+        file: SharedTemporaryFile,
+        names: TempFileNames,
+    ) -> Result<(Self, FileBufferWriter), async_tempfile::Error> {
+        let disk = file.writer().await?;
+        let state = State {
+            spilled: Some(Spilled {
+                file: Arc::new(file),
+                probe: None,
+            }),
+            ..Default::default()
+        };
+        let shared = Arc::new(Shared {
+            id,
+            names,
+            state: Mutex::new(state),
+        });
+        let writer = FileBufferWriter {
+            shared: shared.clone(),
+            threshold: 0,
+            disk: Some(disk),
+            completed: false,
+        };
+        Ok((Self { shared }, writer))
+    }
+
+    /// Gets an additional reader for the file.
+    pub async fn reader(&self) -> Result<FileBufferReader, async_tempfile::Error> {
+        let file = self.shared.lock().spilled.as_ref().map(|s| s.file.clone());
+        let disk = match file {
+            None => DiskReader::None,
+            Some(file) => DiskReader::Open(file.reader().await?),
+        };
+        Ok(FileBufferReader {
+            shared: self.shared.clone(),
+            position: 0,
+            disk,
+        })
+    }
+
+    /// Gets the path of the temporary file, or `None` if the file is held in memory.
+    pub fn file_path(&self) -> Option<PathBuf> {
+        self.shared
+            .lock()
+            .spilled
+            .as_ref()
+            .map(|spilled| spilled.file.file_path().clone())
+    }
+}
+
+impl Shared {
+    fn lock(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn file_size(&self) -> FileSize {
+        let state = self.lock();
+        if let Some(spilled) = &state.spilled {
+            return spilled
+                .probe
+                .as_ref()
+                .map_or(FileSize::AtLeast(0), |probe| probe.file_size());
+        }
+
+        match state.write_state {
+            WriteState::Pending => FileSize::AtLeast(state.data.len()),
+            WriteState::Completed => FileSize::Exactly(state.data.len()),
+            WriteState::Failed => FileSize::Error,
+        }
+    }
+
+    fn set_write_state(&self, write_state: WriteState) {
+        let mut state = self.lock();
+        state.write_state = write_state;
+        state.wake_readers();
+    }
+}
+
+/// The temporary file is removed along with the buffer, so its name is no longer needed.
+impl Drop for Shared {
+    fn drop(&mut self) {
+        self.names.remove(&self.id);
+    }
+}
+
+impl State {
+    fn wake_readers(&mut self) {
+        self.wakers.drain(..).for_each(Waker::wake);
+    }
+
+    fn register_waker(&mut self, waker: &Waker) {
+        if !self.wakers.iter().any(|w| w.will_wake(waker)) {
+            self.wakers.push(waker.clone());
+        }
+    }
+}
+
+/// The single writer of a [`FileBuffer`].
+///
+/// Dropping the writer without completing it fails the in-memory file.
+pub(crate) struct FileBufferWriter {
+    shared: Arc<Shared>,
+    /// The number of bytes to hold in memory.
+    threshold: usize,
+    /// The writer of the temporary file, if the data was spilled to disk.
+    disk: Option<SharedTemporaryFileWriter>,
+    completed: bool,
+}
+
+impl FileBufferWriter {
+    /// Gets the path of the temporary file, or `None` if the file is held in memory.
+    pub fn file_path(&self) -> Option<&PathBuf> {
+        self.disk.as_ref().map(|disk| disk.file_path())
+    }
+
+    pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<usize> {
+        if self.disk.is_none() {
+            if self.write_to_memory(chunk) {
+                return Ok(chunk.len());
+            }
+
+            self.spill().await?;
+        }
+
+        match &mut self.disk {
+            Some(disk) => disk.write(chunk).await,
+            None => unreachable!("the file was spilled to disk"),
+        }
+    }
+
+    /// Appends the chunk to the data held in memory unless it would exceed the threshold.
+    fn write_to_memory(&self, chunk: &[u8]) -> bool {
+        let mut state = self.shared.lock();
+        if state.data.len() + chunk.len() > self.threshold {
+            return false;
+        }
+
+        state.data.extend_from_slice(chunk);
+        state.wake_readers();
+        true
+    }
+
+    /// Moves the data held in memory to a temporary file.
+    async fn spill(&mut self) -> std::io::Result<()> {
+        let id = self.shared.id;
+        let file = self.shared.names.create(id).await.map_err(into_io_error)?;
+        let mut disk = file.writer().await.map_err(into_io_error)?;
+        let probe = file.reader().await.map_err(into_io_error)?;
+        debug!(
+            file_id = %id,
+            "Spilling payload for request {id} to {file:?}",
+            file = file.file_path()
+        );
+
+        // Only this writer appends to the data, so it can be copied without holding the lock.
+        let data = self.shared.lock().data.clone();
+        disk.write_all(&data).await?;
+        disk.flush().await?;
+
+        {
+            let mut state = self.shared.lock();
+            state.spilled = Some(Spilled {
+                file: Arc::new(file),
+                probe: Some(probe),
+            });
+            state.data = Vec::new();
+            state.wake_readers();
+        }
+
+        self.disk = Some(disk);
+        Ok(())
+    }
+
+    pub async fn sync_data(&self) -> Result<(), CompleteWritingError> {
+        match &self.disk {
+            Some(disk) => disk.sync_data().await,
+            None => Ok(()),
+        }
+    }
+
+    pub async fn complete(mut self) -> Result<(), CompleteWritingError> {
+        self.completed = true;
+        match self.disk.take() {
+            Some(disk) => disk.complete().await,
+            None => {
+                self.shared.set_write_state(WriteState::Completed);
+                Ok(())
+            }
+        }
+    }
+
+    pub fn complete_no_sync(mut self) -> Result<(), CompleteWritingError> {
+        self.completed = true;
+        match self.disk.take() {
+            Some(disk) => disk.complete_no_sync(),
+            None => {
+                self.shared.set_write_state(WriteState::Completed);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for FileBufferWriter {
+    fn drop(&mut self) {
+        if !self.completed && self.disk.is_none() {
+            self.shared.set_write_state(WriteState::Failed);
+        }
+    }
+}
+
+/// A reader of a [`FileBuffer`].
+pub(crate) struct FileBufferReader {
+    shared: Arc<Shared>,
+    /// The number of bytes read from memory.
+    position: usize,
+    disk: DiskReader,
+}
+
+enum DiskReader {
+    /// The data is read from memory.
+    None,
+    /// The data was spilled to disk while it was read from memory.
+    Opening(Pin<Box<dyn Future<Output = std::io::Result<SharedTemporaryFileReader>> + Send>>),
+    /// The data is read from disk.
+    Open(SharedTemporaryFileReader),
+}
+
+impl FileBufferReader {
+    pub fn file_size(&self) -> FileSize {
+        match &self.disk {
+            DiskReader::Open(reader) => reader.file_size(),
+            _ => self.shared.file_size(),
+        }
+    }
+
+    /// Opens a reader for the spilled file, skipping the bytes already read from memory.
+    async fn open_at(
+        file: Arc<SharedTemporaryFile>,
+        position: usize,
+    ) -> std::io::Result<SharedTemporaryFileReader> {
+        let mut reader = file.reader().await.map_err(into_io_error)?;
+        let mut skipped = (&mut reader).take(position as u64);
+        tokio::io::copy(&mut skipped, &mut tokio::io::sink()).await?;
+        Ok(reader)
+    }
+}
+
+impl AsyncRead for FileBufferReader {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.get_mut();
+        match &mut this.disk {
+            DiskReader::Open(reader) => return Pin::new(reader).poll_read(cx, buf),
+            DiskReader::Opening(future) => {
+                let reader = ready!(future.as_mut().poll(cx))?;
+                this.disk = DiskReader::Open(reader);
+                return Pin::new(this).poll_read(cx, buf);
+            }
+            DiskReader::None => {}
+        }
+
+        let mut state = this.shared.lock();
+        if let Some(spilled) = &state.spilled {
+            let future = Self::open_at(spilled.file.clone(), this.position);
+            drop(state);
+            this.disk = DiskReader::Opening(Box::pin(future));
+            return Pin::new(this).poll_read(cx, buf);
+        }
+
+        if this.position < state.data.len() {
+            let available = &state.data[this.position..];
+            let count = available.len().min(buf.remaining());
+            buf.put_slice(&available[..count]);
+            this.position += count;
+            return Poll::Ready(Ok(()));
+        }
+
+        match state.write_state {
+            WriteState::Completed => Poll::Ready(Ok(())),
+            WriteState::Failed => Poll::Ready(Err(Error::new(
+                ErrorKind::BrokenPipe,
+                ReadError::FileClosed,
+            ))),
+            WriteState::Pending => {
+                state.register_waker(cx.waker());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+fn into_io_error(error: async_tempfile::Error) -> Error {
+    match error {
+        async_tempfile::Error::Io(e) => e,
+        e => Error::other(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn buffer(threshold: usize) -> (FileBuffer, FileBufferWriter) {
+        FileBuffer::in_memory(ShortGuid::new_random(), threshold, TempFileNames::default())
+    }
+
+    async fn read_exactly(reader: &mut FileBufferReader, count: usize) -> Vec<u8> {
+        let mut data = vec![0; count];
+        reader.read_exact(&mut data).await.expect("failed to read");
+        data
+    }
+
+    #[tokio::test]
+    async fn readers_follow_in_memory_writes() {
+        let (buffer, mut writer) = buffer(1024);
+        let mut reader = buffer.reader().await.unwrap();
+
+        writer.write(b"hel").await.unwrap();
+        assert_eq!(read_exactly(&mut reader, 3).await, b"hel");
+
+        // The reader waits for more data instead of ending the file.
+        let mut data = [0; 1];
+        let read = tokio::time::timeout(Duration::from_millis(50), reader.read(&mut data)).await;
+        assert!(read.is_err(), "the reader didn't wait for the writer");
+
+        writer.write(b"lo").await.unwrap();
+        writer.complete().await.unwrap();
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"lo");
+        assert!(buffer.file_path().is_none());
+    }
+
+    #[tokio::test]
+    async fn readers_resume_at_their_offset_after_a_spill() {
+        let (buffer, mut writer) = buffer(8);
+        let mut reader = buffer.reader().await.unwrap();
+
+        writer.write(b"hello").await.unwrap();
+        assert_eq!(read_exactly(&mut reader, 3).await, b"hel");
+
+        // Exceeding the threshold moves the data to disk while the reader is mid-buffer.
+        writer.write(b" world").await.unwrap();
+        assert!(buffer.file_path().is_some());
+        writer.complete().await.unwrap();
+
+        let mut rest = Vec::new();
+        reader.read_to_end(&mut rest).await.unwrap();
+        assert_eq!(rest, b"lo world");
+
+        let mut data = Vec::new();
+        buffer
+            .reader()
+            .await
+            .unwrap()
+            .read_to_end(&mut data)
+            .await
+            .unwrap();
+        assert_eq!(data, b"hello world");
+    }
+
+    #[tokio::test]
+    async fn dropping_the_writer_closes_the_file() {
+        let (buffer, mut writer) = buffer(1024);
+        let mut reader = buffer.reader().await.unwrap();
+        writer.write(b"hel").await.unwrap();
+        assert_eq!(read_exactly(&mut reader, 3).await, b"hel");
+
+        drop(writer);
+        let error = reader.read(&mut [0; 1]).await.unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::BrokenPipe);
+        assert!(matches!(
+            error.get_ref().and_then(|e| e.downcast_ref::<ReadError>()),
+            Some(ReadError::FileClosed)
+        ));
+        assert!(matches!(reader.file_size(), FileSize::Error));
+    }
+
+    #[tokio::test]
+    async fn file_size_is_exact_once_completed() {
+        let (in_memory, mut writer) = buffer(8);
+        let reader = in_memory.reader().await.unwrap();
+        writer.write(b"hello").await.unwrap();
+        assert!(matches!(reader.file_size(), FileSize::AtLeast(5)));
+        writer.complete().await.unwrap();
+        assert!(matches!(reader.file_size(), FileSize::Exactly(5)));
+
+        let (spilled, mut writer) = buffer(8);
+        let reader = spilled.reader().await.unwrap();
+        writer.write(b"hello world").await.unwrap();
+        assert!(matches!(reader.file_size(), FileSize::AtLeast(_)));
+        writer.complete().await.unwrap();
+        assert!(matches!(reader.file_size(), FileSize::Exactly(11)));
+    }
+}
@@ -0,0 +1,194 @@
+use app_config::backbone::{BackboneConfig, IdFormat, IdStrategy, ID_PREFIX_LENGTHS};
+use shortguid::ShortGuid;
+use uuid::fmt::Hyphenated;
+use uuid::Uuid;
+
+/// The number of characters of an ID in the [`IdFormat::ShortGuid`] format.
+const SHORT_GUID_LENGTH: usize = 22;
+
+/// The number of bits encoded by each character of the [`IdFormat::ShortGuid`] format.
+const BITS_PER_CHAR: usize = 6;
+
+/// Formats and parses file IDs in the format returned to clients.
+#[derive(Debug, Copy, Clone)]
+pub struct FileIdFormat {
+    format: IdFormat,
+    /// The number of characters of IDs in the [`IdFormat::Prefix`] format.
+    prefix_length: usize,
+}
+
+impl FileIdFormat {
+    /// Creates the format from the configuration. Prefixes that would make IDs collide,
+    /// i.e. prefixes that are too short or mostly hold the timestamp of time-sortable IDs,
+    /// are rejected.
+    pub fn try_from_config(config: &BackboneConfig) -> Result<Self, InvalidIdFormat> {
+        let prefix_length = config.id_prefix_length();
+        if config.id_format == IdFormat::Prefix {
+            if !ID_PREFIX_LENGTHS.contains(&prefix_length) {
+                return Err(InvalidIdFormat::PrefixLength(prefix_length));
+            }
+            if config.id_strategy == IdStrategy::Uuidv7 {
+                return Err(InvalidIdFormat::TimeSortablePrefix);
+            }
+        }
+
+        Ok(Self {
+            format: config.id_format,
+            prefix_length,
+        })
+    }
+
+    /// Formats the ID for clients, e.g. for the `yy-id` header.
+    pub fn format(&self, id: ShortGuid) -> String {
+        match self.format {
+            IdFormat::ShortGuid => id.to_string(),
+            IdFormat::Uuid => Uuid::from(id).hyphenated().to_string(),
+            IdFormat::Prefix => {
+                let mut value = id.to_string();
+                value.truncate(self.prefix_length);
+                value
+            }
+        }
+    }
+
+    /// Parses an ID in the configured format, e.g. from a request path.
+    /// Returns `None` if the value is not in that format.
+    pub fn parse(&self, value: &str) -> Option<ShortGuid> {
+        match self.format {
+            IdFormat::ShortGuid if value.len() == SHORT_GUID_LENGTH => {
+                ShortGuid::try_parse(value).ok()
+            }
+            IdFormat::Uuid if value.len() == Hyphenated::LENGTH => {
+                Uuid::try_parse(value).ok().map(ShortGuid::from)
+            }
+            IdFormat::Prefix if value.len() == self.prefix_length => {
+                // The omitted characters encode zero bits, as in all IDs that were issued.
+                let padded = format!("{value:A<width$}", width = SHORT_GUID_LENGTH);
+                ShortGuid::try_parse(padded).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Clears the bits of the ID that are not represented in the configured format,
+    /// so that formatting the ID loses no information.
+    pub(crate) fn truncate(&self, id: ShortGuid) -> ShortGuid {
+        if self.format != IdFormat::Prefix {
+            return id;
+        }
+
+        let bits = self.prefix_length * BITS_PER_CHAR;
+        let mut bytes = *id.as_bytes();
+        for (index, byte) in bytes.iter_mut().enumerate() {
+            let start = index * 8;
+            if start >= bits {
+                *byte = 0;
+            } else if start + 8 > bits {
+                *byte &= 0xFF << (start + 8 - bits);
+            }
+        }
+        ShortGuid::from_bytes(bytes)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum InvalidIdFormat {
+    #[error(
+        "The ID prefix length of {0} characters is not between {min} and {max} characters",
+        min = ID_PREFIX_LENGTHS.start(),
+        max = ID_PREFIX_LENGTHS.end()
+    )]
+    PrefixLength(usize),
+    #[error("The prefix ID format can't be used with the uuidv7 ID strategy")]
+    TimeSortablePrefix,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id_format(format: IdFormat, prefix_length: usize) -> FileIdFormat {
+        FileIdFormat::try_from_config(&BackboneConfig {
+            id_format: format,
+            id_prefix_length: Some(prefix_length),
+            ..Default::default()
+        })
+        .expect("invalid ID format")
+    }
+
+    #[test]
+    fn full_ids_round_trip() {
+        let id = ShortGuid::new_random();
+        for format in [IdFormat::ShortGuid, IdFormat::Uuid] {
+            let id_format = id_format(format, 11);
+            assert_eq!(id_format.truncate(id), id);
+            assert_eq!(id_format.parse(&id_format.format(id)), Some(id));
+        }
+    }
+
+    #[test]
+    fn truncated_ids_round_trip() {
+        for prefix_length in [8, 11, 16, 21] {
+            let id_format = id_format(IdFormat::Prefix, prefix_length);
+            let id = id_format.truncate(ShortGuid::new_random());
+            let value = id_format.format(id);
+            assert_eq!(value.len(), prefix_length);
+            assert_eq!(id_format.parse(&value), Some(id));
+        }
+    }
+
+    #[test]
+    fn truncate_clears_the_bits_after_the_prefix() {
+        let id_format = id_format(IdFormat::Prefix, 11);
+        let id = ShortGuid::from_bytes([0xFF; 16]);
+        let truncated = id_format.truncate(id);
+
+        // 11 characters encode 66 bits, i.e. 8 bytes and the two high bits of the ninth.
+        let mut expected = [0u8; 16];
+        expected[..8].fill(0xFF);
+        expected[8] = 0xC0;
+        assert_eq!(truncated.as_bytes(), &expected);
+        assert_eq!(
+            truncated.to_string(),
+            format!("{}{}", &id.to_string()[..11], "A".repeat(11))
+        );
+        assert_eq!(id_format.truncate(truncated), truncated);
+    }
+
+    #[test]
+    fn parse_rejects_other_formats() {
+        let id = ShortGuid::new_random();
+        let short_guid = id_format(IdFormat::ShortGuid, 11);
+        let uuid = id_format(IdFormat::Uuid, 11);
+        let prefix = id_format(IdFormat::Prefix, 11);
+
+        assert_eq!(short_guid.parse(&uuid.format(id)), None);
+        assert_eq!(uuid.parse(&short_guid.format(id)), None);
+        assert_eq!(prefix.parse(&short_guid.format(id)), None);
+        assert_eq!(short_guid.parse(&prefix.format(id)), None);
+    }
+    #[test]
+    fn colliding_prefixes_are_rejected() {
+        for prefix_length in [0, 7, 22] {
+            let config = BackboneConfig {
+                id_format: IdFormat::Prefix,
+                id_prefix_length: Some(prefix_length),
+                ..Default::default()
+            };
+            assert!(matches!(
+                FileIdFormat::try_from_config(&config),
+                Err(InvalidIdFormat::PrefixLength(length)) if length == prefix_length
+            ));
+        }
+
+        let config = BackboneConfig {
+            id_format: IdFormat::Prefix,
+            id_strategy: IdStrategy::Uuidv7,
+            ..Default::default()
+        };
+        assert!(matches!(
+            FileIdFormat::try_from_config(&config),
+            Err(InvalidIdFormat::TimeSortablePrefix)
+        ));
+    }
+}
@@ -1,8 +1,10 @@
+use crate::file_buffer::FileBufferReader;
 use axum::headers::ContentType;
 use file_distribution::{FileReaderTrait, WriteSummary};
 use metrics::transfer::{TransferMethod, TransferMetrics};
-use shared_files::{FileSize, SharedTemporaryFileReader};
+use shared_files::FileSize;
 use std::borrow::Cow;
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -10,22 +12,28 @@ use std::time::Duration;
 use tokio::io::{AsyncRead, ReadBuf};
 use tokio::time::Instant;
 
-/// A read accessor for a temporary file.
+/// A read accessor for a file buffer.
 pub struct FileReader {
     /// The file reader.
-    inner: SharedTemporaryFileReader,
+    inner: FileBufferReader,
     content_type: Option<String>,
     created: Instant,
     expiration_duration: Duration,
+    /// The size declared when the file was created, if any.
+    expected_size: Option<u64>,
+    /// The path of the temporary file, if the file is not held in memory.
+    path: Option<PathBuf>,
     summary: Option<Arc<WriteSummary>>,
 }
 
 impl FileReader {
-    pub fn new(
-        reader: SharedTemporaryFileReader,
+    pub(crate) fn new(
+        reader: FileBufferReader,
         content_type: Option<ContentType>,
         created: Instant,
         expiration_duration: Duration,
+        expected_size: Option<u64>,
+        path: Option<PathBuf>,
         summary: Option<Arc<WriteSummary>>,
     ) -> Self {
         Self {
@@ -33,6 +41,8 @@ impl FileReader {
             content_type: content_type.map(|c| c.to_string()),
             created,
             expiration_duration,
+            expected_size,
+            path,
             summary,
         }
     }
@@ -49,11 +59,23 @@ impl FileReader {
         self.inner.file_size()
     }
 
+    /// Gets the size of the file once it is completely written, if known.
+    ///
+    /// While the file is being written, this is the size declared on creation; since
+    /// writes exceeding it fail, a successfully written file always matches it.
+    pub fn final_file_size(&self) -> Option<usize> {
+        match self.inner.file_size() {
+            FileSize::Exactly(size) => Some(size),
+            FileSize::AtLeast(_) => self.expected_size.map(|size| size as usize),
+            FileSize::Error => None,
+        }
+    }
+
     pub fn file_age(&self) -> Duration {
         Instant::now() - self.created
     }
 
-    pub fn content_type(&self) -> Option<Cow<str>> {
+    pub fn content_type(&self) -> Option<Cow<'_, str>> {
         self.content_type
             .as_ref()
             .map(|content_type| Cow::from(content_type.as_str()))
@@ -77,9 +99,17 @@ impl FileReaderTrait for FileReader {
         self.file_age()
     }
 
-    fn content_type(&self) -> Option<Cow<str>> {
+    fn content_type(&self) -> Option<Cow<'_, str>> {
         self.content_type()
     }
+
+    fn final_file_size(&self) -> Option<usize> {
+        self.final_file_size()
+    }
+
+    fn local_path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
 }
 
 impl AsyncRead for FileReader {
@@ -1,16 +1,18 @@
 use crate::backbone::BackboneCommand;
+use crate::file_buffer::{FileBuffer, FileBufferReader};
 use crate::file_writer_guard::WriteResult;
+use crate::upload_progress::UploadProgressReceiver;
 use axum::headers::ContentType;
 use file_distribution::{GetFileReaderError, WriteSummary};
-use shared_files::{SharedTemporaryFile, SharedTemporaryFileReader};
 use shortguid::ShortGuid;
-use std::sync::Arc;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 use tokio::sync::mpsc::Sender;
 use tokio::sync::oneshot::Receiver;
 use tokio::sync::RwLock;
 use tokio::time::Instant;
-use tracing::{info, warn};
+use tracing::{debug, info, warn};
 
 #[derive(Debug)]
 pub(crate) struct FileRecord {
@@ -22,35 +24,48 @@ pub(crate) struct FileRecord {
     pub created: Instant,
     /// The time after which the file will be inaccessible.
     pub expiration_duration: Duration,
+    /// The size declared when the file was created, if any.
+    pub expected_size: Option<u64>,
+    /// The time until which reads extended the lease, if any.
+    lease_extension: Arc<Mutex<Option<Instant>>>,
+    /// The progress of the upload.
+    progress: UploadProgressReceiver,
     inner: Arc<RwLock<Inner>>,
 }
 
 #[derive(Debug)]
 struct Inner {
-    file: Option<SharedTemporaryFile>,
+    file: Option<FileBuffer>,
     summary: Option<Arc<WriteSummary>>,
 }
 
 impl FileRecord {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         id: ShortGuid,
-        file: SharedTemporaryFile,
+        file: FileBuffer,
         backbone_command: Sender<BackboneCommand>,
         writer_command: Receiver<WriteResult>,
         duration: Duration,
+        grace: Duration,
         content_type: Option<ContentType>,
+        expected_size: Option<u64>,
         created: Instant,
+        progress: UploadProgressReceiver,
     ) -> Self {
         let inner = Arc::new(RwLock::new(Inner {
             file: Some(file),
             summary: None,
         }));
-        let _ = tokio::spawn(Self::lifetime_handler(
+        let lease_extension = Arc::new(Mutex::new(None));
+        tokio::spawn(Self::lifetime_handler(
             id,
             inner.clone(),
+            lease_extension.clone(),
             backbone_command,
             writer_command,
             duration,
+            grace,
         ));
         Self {
             id,
@@ -58,11 +73,43 @@ impl FileRecord {
             content_type,
             created,
             expiration_duration: duration,
+            expected_size,
+            lease_extension,
+            progress,
         }
     }
 
+    /// Extends the lease of the file such that it expires one lease duration from now,
+    /// but no later than `max_lifetime` after its creation.
+    pub fn extend_lease(&self, max_lifetime: Duration) {
+        let until = (Instant::now() + self.expiration_duration).min(self.created + max_lifetime);
+        let mut extension = self
+            .lease_extension
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        if *extension < Some(until) {
+            *extension = Some(until);
+        }
+    }
+
+    /// Gets the duration of the lease, including any extensions by reads.
+    pub fn lease_duration(&self) -> Duration {
+        let extension = *self
+            .lease_extension
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        extension.map_or(self.expiration_duration, |until| {
+            self.expiration_duration.max(until - self.created)
+        })
+    }
+
+    /// Subscribes to the progress of the upload.
+    pub fn subscribe_progress(&self) -> UploadProgressReceiver {
+        self.progress.clone()
+    }
+
     /// Gets an additional reader for the file.
-    pub async fn get_reader(&self) -> Result<SharedTemporaryFileReader, GetFileReaderError> {
+    pub async fn get_reader(&self) -> Result<FileBufferReader, GetFileReaderError> {
         let inner = self.inner.read().await;
         match &inner.file {
             None => Err(GetFileReaderError::FileExpired(self.id)),
@@ -73,6 +120,19 @@ impl FileRecord {
         }
     }
 
+    /// Gets the path of the temporary file, or `None` if the file was removed
+    /// or is held in memory.
+    pub async fn get_path(&self) -> Option<PathBuf> {
+        let inner = self.inner.read().await;
+        inner.file.as_ref().and_then(FileBuffer::file_path)
+    }
+
+    /// Indicates whether the file is still available to new readers.
+    pub async fn is_open(&self) -> bool {
+        let inner = self.inner.read().await;
+        inner.file.is_some()
+    }
+
     /// Gets the file write summary or `None`, if the file writing hasn't completed yet.
     pub async fn get_summary(&self) -> Option<Arc<WriteSummary>> {
         let inner = self.inner.read().await;
@@ -84,20 +144,34 @@ impl FileRecord {
     /// This method will:
     ///
     /// - Wait until the file is buffered to disk completely,
-    /// - Apply a temporal lease to the file (keeping it alive for a certain time).
+    /// - Apply a temporal lease to the file (keeping it alive for a certain time),
+    ///   extended by reads if sliding expiration is enabled.
+    /// - Keep serving stale reads during the grace period, if one is configured.
     /// - Remove the file from the registry after the time is over.
     async fn lifetime_handler(
         id: ShortGuid,
         mut inner: Arc<RwLock<Inner>>,
+        lease_extension: Arc<Mutex<Option<Instant>>>,
         backbone_command: Sender<BackboneCommand>,
         writer_command: Receiver<WriteResult>,
         duration: Duration,
+        grace: Duration,
     ) {
         // Before starting the timeout, wait for the write to the file to complete.
-        let summary = match writer_command.await {
+        let (id, summary) = match writer_command.await {
             Ok(WriteResult::Success(summary)) => {
                 info!(file_id = %id, "File writing completed: {}", summary.hashes);
-                summary
+                (id, summary)
+            }
+            Ok(WriteResult::Reassigned(content_id, summary)) => {
+                info!(file_id = %id, "File writing completed: {}; the file is now held as {content_id}", summary.hashes);
+                (content_id, summary)
+            }
+            Ok(WriteResult::Deduplicated(existing_id)) => {
+                info!(file_id = %id, "File is identical to file {existing_id}; discarding it");
+                Self::close_file(&mut inner).await;
+                Self::remove_writer(id, backbone_command).await;
+                return;
             }
             Ok(WriteResult::Failed) => {
                 warn!(file_id = %id, "Writing to the file failed");
@@ -133,16 +207,42 @@ impl FileRecord {
         //       alive even if the servers have already shut down.
 
         // Keep the file open for readers.
-        Self::apply_temporal_lease(&id, duration).await;
+        Self::apply_temporal_lease(&id, duration, &lease_extension).await;
+        if let Err(error) = backbone_command
+            .send(BackboneCommand::LeaseExpired(id))
+            .await
+        {
+            warn!(file_id = %id, "The backbone writer channel was closed while indicating the lease expiration of file with ID {id}: {error}");
+        }
+
+        if !grace.is_zero() {
+            info!(file_id = %id, "Read lease timed out for file {id}; serving stale reads for {grace:?}");
+            tokio::time::sleep(grace).await;
+        }
         info!(file_id = %id, "Read lease timed out for file {id}; removing it");
 
         // Gracefully close the file.
         Self::remove_writer(id, backbone_command).await;
     }
 
-    async fn apply_temporal_lease(id: &ShortGuid, duration: Duration) {
+    async fn apply_temporal_lease(
+        id: &ShortGuid,
+        duration: Duration,
+        lease_extension: &Mutex<Option<Instant>>,
+    ) {
         info!(file_id = %id, "File {id} will accept new readers for {duration:?}");
-        tokio::time::sleep(duration).await
+        let mut deadline = Instant::now() + duration;
+        loop {
+            tokio::time::sleep_until(deadline).await;
+            let extension = *lease_extension.lock().unwrap_or_else(|e| e.into_inner());
+            match extension {
+                Some(until) if until > deadline => {
+                    debug!(file_id = %id, "Lease of file {id} was extended by reads for another {extended:?}", extended = until - deadline);
+                    deadline = until;
+                }
+                _ => break,
+            }
+        }
     }
 
     async fn close_file(inner: &mut Arc<RwLock<Inner>>) {
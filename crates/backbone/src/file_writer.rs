@@ -1,15 +1,21 @@
-use file_distribution::hash::{HashMd5, HashSha256};
+use crate::file_buffer::FileBufferWriter;
+use file_distribution::hash::{DigestSet, HashCrc32c, HashMd5, HashSha256};
 use file_distribution::{FileHashes, WriteSummary};
-use shared_files::{prelude::*, SharedTemporaryFileWriter};
+use shared_files::prelude::*;
 use shortguid::ShortGuid;
+use std::collections::BTreeMap;
 use std::io::{Error, ErrorKind};
 use std::sync::Arc;
-use std::time::Duration;
-use tokio::io::AsyncWriteExt;
+use std::time::{Duration, SystemTime};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 use tokio::time::Instant;
 use tracing::debug;
 
-/// A write accessor for a temporary file.
+/// The number of chunks that can be queued for hashing on the blocking thread pool.
+const HASH_QUEUE_CAPACITY: usize = 8;
+
+/// A write accessor for a file buffer.
 ///
 /// ## Remarks
 ///
@@ -17,36 +23,74 @@ use tracing::debug;
 /// ensuring that regardless of whether this writer is finalized or dropped without finalization,
 /// the [`Backbone`](crate::backbone::Backbone) is informed about it.
 pub struct FileWriter {
-    inner: SharedTemporaryFileWriter,
-    md5: HashMd5,
-    sha256: HashSha256,
+    inner: FileBufferWriter,
+    /// The hash states of the file.
+    hashing: Hashing,
     file_name: Option<String>,
+    /// The metadata supplied by the client.
+    metadata: BTreeMap<String, String>,
     file_size: usize,
+    created: SystemTime,
+}
+
+/// Calculates the hashes of a file, either inline or on the blocking thread pool.
+enum Hashing {
+    /// The hashes are updated on the task writing the file.
+    Inline(Box<Hashers>),
+    /// The hashes are updated by a blocking task receiving the chunks,
+    /// keeping the async runtime responsive during large uploads.
+    Blocking {
+        sender: mpsc::Sender<Vec<u8>>,
+        handle: JoinHandle<Hashers>,
+    },
+}
+
+/// The hash states of a file.
+struct Hashers {
+    /// The MD5 state; `None` if the digest is disabled.
+    md5: Option<HashMd5>,
+    /// The SHA-256 state; `None` if the hash is disabled.
+    sha256: Option<HashSha256>,
+    /// The CRC32C state; `None` if the checksum is disabled.
+    crc32c: Option<HashCrc32c>,
 }
 
 impl FileWriter {
-    pub fn new(
+    /// Creates a writer calculating the specified `digests`. If `blocking_hashing` is set,
+    /// the hashes are calculated on the blocking thread pool.
+    pub(crate) fn new(
         id: &ShortGuid,
-        inner: SharedTemporaryFileWriter,
+        inner: FileBufferWriter,
         file_name: Option<String>,
+        metadata: BTreeMap<String, String>,
+        digests: DigestSet,
+        blocking_hashing: bool,
     ) -> Self {
-        debug!(
-            file_id = %id,
-            "Buffering payload for request {id} to {file:?}",
-            file = inner.file_path()
-        );
+        match inner.file_path() {
+            Some(file) => debug!(file_id = %id, "Buffering payload for request {id} to {file:?}"),
+            None => debug!(file_id = %id, "Buffering payload for request {id} in memory"),
+        }
+
+        let hashers = Hashers::new(digests);
+        let hashing = if blocking_hashing && digests != DigestSet::NONE {
+            Hashing::spawn_blocking(hashers)
+        } else {
+            Hashing::Inline(Box::new(hashers))
+        };
 
         Self {
             inner,
-            md5: HashMd5::new(),
-            sha256: HashSha256::new(),
+            hashing,
             file_name,
+            metadata,
             file_size: 0,
+            created: SystemTime::now(),
         }
     }
 
     pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<usize> {
-        self.update_state(chunk);
+        self.file_size += chunk.len();
+        self.hashing.update(chunk).await?;
         self.inner.write(chunk).await
     }
 
@@ -64,23 +108,86 @@ impl FileWriter {
             CompletionMode::NoSync => self.inner.complete_no_sync()?,
         }
 
-        let md5 = self.md5.finalize();
-        let sha256 = self.sha256.finalize();
+        let hashes = self.hashing.finalize().await?;
 
         let summary = Arc::new(WriteSummary {
+            created: self.created,
             expires: Instant::now() + expiration,
-            hashes: FileHashes::new(md5, sha256),
+            hashes,
             file_name: self.file_name,
             file_size_bytes: self.file_size,
+            metadata: self.metadata,
         });
 
         Ok(summary)
     }
+}
+
+impl Hashing {
+    fn spawn_blocking(mut hashers: Hashers) -> Self {
+        let (sender, mut receiver) = mpsc::channel::<Vec<u8>>(HASH_QUEUE_CAPACITY);
+        let handle = tokio::task::spawn_blocking(move || {
+            while let Some(chunk) = receiver.blocking_recv() {
+                hashers.update(&chunk);
+            }
+            hashers
+        });
+        Self::Blocking { sender, handle }
+    }
+
+    async fn update(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        match self {
+            Hashing::Inline(hashers) => {
+                hashers.update(chunk);
+                Ok(())
+            }
+            Hashing::Blocking { sender, .. } => sender
+                .send(chunk.to_vec())
+                .await
+                .map_err(|_| Error::other("The hashing task stopped")),
+        }
+    }
+
+    async fn finalize(self) -> Result<FileHashes, FinalizationError> {
+        let hashers = match self {
+            Hashing::Inline(hashers) => *hashers,
+            Hashing::Blocking { sender, handle } => {
+                // Closing the channel lets the task finish the remaining chunks and return.
+                drop(sender);
+                handle.await.map_err(|_| FinalizationError::HashingFailed)?
+            }
+        };
+        Ok(hashers.finalize())
+    }
+}
+
+impl Hashers {
+    fn new(digests: DigestSet) -> Self {
+        Self {
+            md5: digests.md5.then(HashMd5::new),
+            sha256: digests.sha256.then(HashSha256::new),
+            crc32c: digests.crc32c.then(HashCrc32c::new),
+        }
+    }
+
+    fn update(&mut self, buf: &[u8]) {
+        if let Some(md5) = &mut self.md5 {
+            md5.update(buf);
+        }
+        if let Some(sha256) = &mut self.sha256 {
+            sha256.update(buf);
+        }
+        if let Some(crc32c) = &mut self.crc32c {
+            crc32c.update(buf);
+        }
+    }
 
-    fn update_state(&mut self, buf: &[u8]) {
-        self.file_size += buf.len();
-        self.md5.update(buf);
-        self.sha256.update(buf);
+    fn finalize(self) -> FileHashes {
+        FileHashes::new(
+            self.md5.map(HashMd5::finalize),
+            self.sha256.map(HashSha256::finalize),
+            self.crc32c.map(HashCrc32c::finalize),
+        )
     }
 }
 
@@ -102,8 +209,10 @@ pub enum FinalizationError {
     BackboneCommunicationFailed,
     #[error("Invalid file length: expected {0}, got {1}")]
     InvalidFileLength(u64, u64),
-    #[error("Integrity check failed: expected MD5 {0}, got MD5 {1}")]
-    IntegrityCheckFailed(String, String),
+    #[error("Integrity check failed: expected {0} {1}, got {0} {2}")]
+    IntegrityCheckFailed(&'static str, String, String),
+    #[error("Calculating the file hashes failed")]
+    HashingFailed,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -1,12 +1,23 @@
+use crate::backbone::{ContentIdAssignment, Inner};
+use crate::content_index::ContentIndex;
+use crate::file_id::FileIdFormat;
 use crate::file_writer::{err_broken_pipe, FileWriter, FinalizationError};
+use crate::upload_progress::UploadProgress;
+use crate::write_ahead_log::WalWriter;
+use crate::write_locks::WriteLock;
 use crate::CompletionMode;
+use file_distribution::hash::Sha256Digest;
 use file_distribution::WriteSummary;
 use metrics::transfer::{TransferMethod, TransferMetrics};
+use shortguid::ShortGuid;
 use std::io::ErrorKind;
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::oneshot::Sender;
+use tokio::sync::{watch, RwLock};
+use tracing::warn;
+use uuid::Builder;
 
 /// A writer guard to communicate back to the [`Backbone`](crate::backbone::Backbone);
 ///
@@ -14,6 +25,8 @@ use tokio::sync::oneshot::Sender;
 /// is cancelled) and still have the [`Backbone`](crate::backbone::Backbone) informed
 /// about it.
 pub struct FileWriterGuard {
+    /// The ID of the file.
+    id: ShortGuid,
     /// The file writer; `None` when closed.
     inner: Option<FileWriter>,
     /// The sender to communicate with the backbone.
@@ -22,10 +35,34 @@ pub struct FileWriterGuard {
     expiration: Duration,
     /// The actual file size as per bookkeeping.
     file_size: u64,
-    /// The expected content size as per `Content-Length` header, in bytes.
-    expected_size: Option<u64>,
-    /// The expected MD5 hash of the content, as per `Content-MD5` header.
-    expected_content_md5: Option<[u8; 16]>,
+    /// The expected size and hashes of the content.
+    expected: ExpectedContent,
+    /// The index of files by their content, also used for deduplicating files.
+    content_index: ContentIndex,
+    /// The open files, for assigning the file the ID derived from its content;
+    /// `None` unless IDs are derived from the content.
+    content_addressing: Option<Arc<RwLock<Inner>>>,
+    /// The format of the IDs returned to clients, which content-derived IDs must fit.
+    id_format: FileIdFormat,
+    /// Publishes the progress of the upload.
+    progress: watch::Sender<UploadProgress>,
+    /// Mirrors the content to the write-ahead log; `None` if disabled or failed.
+    wal: Option<WalWriter>,
+    /// The exclusive write access to the ID, held for as long as the file is written.
+    _write_lock: WriteLock,
+}
+
+/// The size and hashes of the content as announced by the client.
+///
+/// The file is rejected if the written content doesn't match.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ExpectedContent {
+    /// The content size in bytes, as per `Content-Length` header.
+    pub size: Option<u64>,
+    /// The MD5 hash of the content, as per `Content-MD5` header.
+    pub md5: Option<[u8; 16]>,
+    /// The SHA-256 hash of the content, as per `yy-expected-sha256` header.
+    pub sha256: Option<[u8; 32]>,
 }
 
 /// A write result.
@@ -33,40 +70,71 @@ pub struct FileWriterGuard {
 pub enum WriteResult {
     /// The writer succeeded.
     Success(Arc<WriteSummary>),
+    /// The writer succeeded and the file is now held under the specified ID
+    /// derived from its content.
+    Reassigned(ShortGuid, Arc<WriteSummary>),
+    /// The writer succeeded, but the file duplicates the file with the specified ID
+    /// and is discarded.
+    Deduplicated(ShortGuid),
     /// The writer failed.
     Failed,
 }
 
+/// The outcome of a successful [`FileWriterGuard::finalize`] call.
+#[derive(Debug)]
+pub struct FinalizedFile {
+    /// The ID under which the file is available. This differs from the ID
+    /// of the written file if the file was deduplicated.
+    pub id: ShortGuid,
+    /// The write summary of the file.
+    pub summary: Arc<WriteSummary>,
+    /// Whether an identical file was already held and is reused instead.
+    pub deduplicated: bool,
+    /// Whether the backbone took over the file. This is `false` if the file was
+    /// written completely, but the backbone could no longer be informed about it,
+    /// e.g. because it is shutting down; the file is then neither served nor distributed.
+    pub tracked: bool,
+}
+
 impl FileWriterGuard {
-    pub fn new(
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        id: ShortGuid,
         writer: FileWriter,
         sender: Sender<WriteResult>,
         expiration: Duration,
-        expected_size: Option<u64>,
-        content_md5: Option<[u8; 16]>,
+        expected: ExpectedContent,
+        content_index: ContentIndex,
+        content_addressing: Option<Arc<RwLock<Inner>>>,
+        id_format: FileIdFormat,
+        progress: watch::Sender<UploadProgress>,
+        wal: Option<WalWriter>,
+        write_lock: WriteLock,
     ) -> Self {
         Self {
+            id,
             inner: Some(writer),
             sender: Some(sender),
             expiration,
             file_size: 0,
-            expected_size,
-            expected_content_md5: content_md5,
+            expected,
+            content_index,
+            content_addressing,
+            id_format,
+            progress,
+            wal,
+            _write_lock: write_lock,
         }
     }
 
     pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<usize> {
         if let Some(ref mut writer) = self.inner {
-            let bytes_written = writer.write(chunk).await?;
-            self.file_size += bytes_written as u64;
-
-            TransferMetrics::track_bytes_transferred(TransferMethod::Store, bytes_written);
-
-            // Ensure we don't store more bytes than anticipated.
+            // Ensure we don't store more bytes than anticipated. The upload fails before
+            // any data past the announced size is written, rather than after the fact.
             // This check only happens when we have a Content-Length header (or similar)
             // available.
-            if let Some(expected_size) = self.expected_size {
-                if self.file_size > expected_size {
+            if let Some(expected_size) = self.expected.size {
+                if self.file_size + chunk.len() as u64 > expected_size {
                     self.fail_if_not_already_closed();
                     return Err(std::io::Error::new(
                         ErrorKind::UnexpectedEof,
@@ -75,6 +143,23 @@ impl FileWriterGuard {
                 }
             }
 
+            let bytes_written = writer.write(chunk).await?;
+            self.file_size += bytes_written as u64;
+
+            // The upload doesn't depend on the write-ahead log; it is only less durable without it.
+            if let Some(wal) = &mut self.wal {
+                if let Err(e) = wal.write(&chunk[..bytes_written]).await {
+                    warn!(file_id = %self.id, "Failed to write file {id} to the write-ahead log; the file won't be recovered after a crash: {e}", id = self.id);
+                    self.wal = None;
+                }
+            }
+
+            self.progress.send_replace(UploadProgress::Pending {
+                buffered: self.file_size,
+                expected: self.expected.size,
+            });
+
+            TransferMetrics::track_bytes_transferred(TransferMethod::Store, bytes_written);
             Ok(bytes_written)
         } else {
             err_broken_pipe()
@@ -84,49 +169,133 @@ impl FileWriterGuard {
     pub async fn finalize(
         mut self,
         mode: CompletionMode,
-    ) -> Result<Arc<WriteSummary>, FinalizationError> {
+    ) -> Result<FinalizedFile, FinalizationError> {
         if let Some(writer) = self.inner.take() {
             let summary = writer.finalize(mode, self.expiration).await?;
 
             // Verify the file length if possible.
-            if let Some(expected_size) = self.expected_size {
+            if let Some(expected_size) = self.expected.size {
                 if self.file_size != expected_size {
                     self.fail_if_not_already_closed();
                     return Err(FinalizationError::InvalidFileLength(
-                        self.file_size,
                         expected_size,
+                        self.file_size,
                     ));
                 }
             }
 
             // Verify integrity if possible.
-            if let Some(md5) = self.expected_content_md5 {
-                if md5.ne(&summary.hashes.md5[..]) {
+            if let Some(md5) = self.expected.md5 {
+                let actual = summary.hashes.md5.map(|actual| actual.0);
+                if actual != Some(md5) {
                     self.fail_if_not_already_closed();
                     return Err(FinalizationError::IntegrityCheckFailed(
+                        "MD5",
                         hex::encode(md5),
-                        hex::encode(&summary.hashes.md5[..]),
+                        actual.map_or_else(|| String::from("none"), hex::encode),
                     ));
                 }
             }
 
-            self.try_signal_success(&summary)?;
-            Ok(summary)
+            if let Some(sha256) = self.expected.sha256 {
+                let actual = summary.hashes.sha256;
+                if actual.as_ref().map(|actual| actual.as_slice()) != Some(&sha256[..]) {
+                    self.fail_if_not_already_closed();
+                    return Err(FinalizationError::IntegrityCheckFailed(
+                        "SHA-256",
+                        hex::encode(sha256),
+                        actual.map_or_else(|| String::from("none"), hex::encode),
+                    ));
+                }
+            }
+
+            // Hold the file under the ID derived from its content if configured.
+            let mut id = self.id;
+            let mut reassigned = false;
+            if let (Some(files), Some(sha256)) =
+                (self.content_addressing.take(), &summary.hashes.sha256)
+            {
+                let content_id = self.id_format.truncate(content_id(sha256));
+                match files.write().await.assign_content_id(id, content_id).await {
+                    ContentIdAssignment::Assigned => {
+                        id = content_id;
+                        reassigned = true;
+                    }
+                    ContentIdAssignment::Existing(existing_summary) => {
+                        self.try_signal(WriteResult::Deduplicated(content_id));
+                        return Ok(FinalizedFile {
+                            id: content_id,
+                            summary: existing_summary,
+                            deduplicated: true,
+                            tracked: true,
+                        });
+                    }
+                    ContentIdAssignment::Unavailable => {
+                        warn!(file_id = %id, "The content-derived ID {content_id} is unavailable; keeping the provisional ID {id}");
+                    }
+                }
+            }
+
+            let result = if reassigned {
+                WriteResult::Reassigned(id, summary.clone())
+            } else {
+                WriteResult::Success(summary.clone())
+            };
+
+            // Index the file by its content, reusing an identical file if possible.
+            let index = self.content_index.clone();
+            if let Some((existing_id, existing_summary)) = index.register(id, &summary) {
+                // The discarded duplicate needs no bookkeeping, so a failed signal is irrelevant.
+                self.try_signal(WriteResult::Deduplicated(existing_id));
+                return Ok(FinalizedFile {
+                    id: existing_id,
+                    summary: existing_summary,
+                    deduplicated: true,
+                    tracked: true,
+                });
+            }
+
+            // The record must be complete before the file is distributed and removed from the log.
+            if let Some(wal) = self.wal.take() {
+                if let Err(e) = wal.commit(id, self.file_size, &summary).await {
+                    warn!(file_id = %id, "Failed to record file {id} in the write-ahead log; the file won't be recovered after a crash: {e}");
+                }
+            }
+
+            // The file is complete and verified at this point; failing to inform the
+            // backbone means it won't be served, but the write itself still succeeded.
+            let tracked = self.try_signal(result);
+            if !tracked {
+                warn!(file_id = %id, "File {id} was written, but the backbone could not be informed about it; the file is not tracked");
+                if let Some(sha256) = &summary.hashes.sha256 {
+                    index.remove(id, sha256);
+                }
+            }
+
+            Ok(FinalizedFile {
+                id,
+                summary,
+                deduplicated: false,
+                tracked,
+            })
         } else {
             Err(FinalizationError::BackboneCommunicationFailed)
         }
     }
 
-    /// Signal a success to the backbone.
-    fn try_signal_success(mut self, summary: &Arc<WriteSummary>) -> Result<(), FinalizationError> {
+    /// Signal a successful write to the backbone.
+    ///
+    /// Returns `false` if the backbone could not be informed, e.g. because
+    /// the lifetime handler of the file already exited during shutdown.
+    fn try_signal(mut self, result: WriteResult) -> bool {
+        // The content was written completely regardless of whether the backbone is informed.
+        self.progress
+            .send_replace(UploadProgress::Completed(self.file_size));
+
         // Send the hashes back to the backbone.
-        match self.sender.take() {
-            None => Err(FinalizationError::BackboneCommunicationFailed),
-            Some(sender) => match sender.send(WriteResult::Success(summary.clone())) {
-                Ok(_) => Ok(()),
-                Err(_) => Err(FinalizationError::BackboneCommunicationFailed),
-            },
-        }
+        self.sender
+            .take()
+            .is_some_and(|sender| sender.send(result).is_ok())
     }
 
     /// Signal a failure to the backbone.
@@ -138,9 +307,10 @@ impl FileWriterGuard {
     /// this method consumes self, [`finalize`](Self::finalize) cannot be
     /// called afterwards.
     fn fail_if_not_already_closed(&mut self) {
-        self.sender
-            .take()
-            .and_then(move |s| s.send(WriteResult::Failed).ok());
+        if let Some(sender) = self.sender.take() {
+            sender.send(WriteResult::Failed).ok();
+            self.progress.send_replace(UploadProgress::Failed);
+        }
     }
 }
 
@@ -165,3 +335,11 @@ impl DerefMut for FileWriterGuard {
         self.inner.as_mut().expect("failed to deref writer")
     }
 }
+
+/// Derives the ID of a file from its SHA-256 hash, using the first 16 bytes
+/// as a custom (version 8) UUID.
+fn content_id(sha256: &Sha256Digest) -> ShortGuid {
+    let mut bytes = [0u8; 16];
+    bytes.copy_from_slice(&sha256[..16]);
+    ShortGuid::from(Builder::from_custom_bytes(bytes).into_uuid())
+}
@@ -3,13 +3,30 @@
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
 mod backbone;
+mod cleanup_guard;
+mod content_index;
 mod file_accessor;
+mod file_buffer;
+mod file_id;
 mod file_reader;
 mod file_record;
 mod file_writer;
 mod file_writer_guard;
+mod reservations;
+mod snapshot;
+mod temp_file_names;
+mod tombstones;
+mod upload_progress;
+mod write_ahead_log;
+mod write_locks;
 
-pub use backbone::{Backbone, NewFileError};
+pub use backbone::{Backbone, NewFileError, RedistributeError};
+pub use cleanup_guard::CleanupGuard;
 pub use file_accessor::FileAccessorBridge;
+pub use file_id::{FileIdFormat, InvalidIdFormat};
 pub use file_reader::FileReader;
-pub use file_writer::CompletionMode;
+pub use file_writer::{CompletionMode, FinalizationError};
+pub use file_writer_guard::{ExpectedContent, FinalizedFile};
+pub use reservations::{MAX_RESERVATIONS, RESERVATION_TTL};
+pub use snapshot::{BackboneSnapshot, FileSnapshot, SNAPSHOT_LOCK_TIMEOUT};
+pub use upload_progress::{UploadProgress, UploadProgressReceiver};
@@ -0,0 +1,111 @@
+use crate::upload_progress::{upload_progress_channel, UploadProgress, UploadProgressReceiver};
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::time::Instant;
+
+/// The time for which a reserved ID can be used to start an upload.
+pub const RESERVATION_TTL: Duration = Duration::from_secs(60);
+
+/// The maximum number of IDs reserved at once, so that clients reserving IDs without
+/// ever uploading can't exhaust the memory.
+pub const MAX_RESERVATIONS: usize = 10_000;
+
+/// The IDs handed out to clients ahead of their upload, so that they can subscribe
+/// to the progress of the upload before streaming it.
+///
+/// The progress of a reserved upload is published from the moment of the reservation;
+/// it reports no buffered bytes until the upload starts.
+#[derive(Debug, Default)]
+pub(crate) struct Reservations {
+    reserved: Mutex<HashMap<ShortGuid, Reservation>>,
+}
+
+#[derive(Debug)]
+struct Reservation {
+    expires: Instant,
+    progress: watch::Sender<UploadProgress>,
+}
+
+impl Reservations {
+    /// Reserves the ID until [`RESERVATION_TTL`] elapsed. Returns the expiration of the
+    /// reservation, or `None` if [`MAX_RESERVATIONS`] IDs are reserved already.
+    pub fn reserve(&self, id: ShortGuid) -> Option<Instant> {
+        let mut reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        if reserved.len() >= MAX_RESERVATIONS {
+            reserved.retain(|_, reservation| reservation.expires > now);
+            if reserved.len() >= MAX_RESERVATIONS {
+                return None;
+            }
+        }
+
+        let expires = now + RESERVATION_TTL;
+        let (progress, _) = upload_progress_channel(None);
+        reserved.insert(id, Reservation { expires, progress });
+        Some(expires)
+    }
+
+    /// Indicates whether the ID is reserved and the reservation hasn't expired.
+    pub fn contains(&self, id: ShortGuid) -> bool {
+        let reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        reserved
+            .get(&id)
+            .is_some_and(|reservation| reservation.expires > Instant::now())
+    }
+
+    /// Subscribes to the progress of the upload to the reserved ID.
+    pub fn subscribe(&self, id: ShortGuid) -> Option<UploadProgressReceiver> {
+        let reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        reserved
+            .get(&id)
+            .filter(|reservation| reservation.expires > Instant::now())
+            .map(|reservation| reservation.progress.subscribe())
+    }
+
+    /// Ends the reservation of the ID as its upload starts, returning the sender of its
+    /// progress so that existing subscribers follow the upload.
+    pub fn take(&self, id: ShortGuid) -> Option<watch::Sender<UploadProgress>> {
+        let mut reserved = self.reserved.lock().unwrap_or_else(|e| e.into_inner());
+        reserved
+            .remove(&id)
+            .filter(|reservation| reservation.expires > Instant::now())
+            .map(|reservation| reservation.progress)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn subscribers_follow_the_upload_to_the_reserved_id() {
+        let reservations = Reservations::default();
+        let id = ShortGuid::new_random();
+        assert!(!reservations.contains(id));
+        assert!(reservations.reserve(id).is_some());
+        assert!(reservations.contains(id));
+
+        let mut receiver = reservations.subscribe(id).expect("the ID is not reserved");
+        let progress = reservations.take(id).expect("the ID is not reserved");
+        assert!(!reservations.contains(id));
+
+        progress.send_replace(UploadProgress::Completed(5));
+        receiver.changed().await.expect("the upload was abandoned");
+        assert_eq!(*receiver.borrow(), UploadProgress::Completed(5));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn reservations_expire() {
+        let reservations = Reservations::default();
+        let id = ShortGuid::new_random();
+        reservations.reserve(id);
+
+        tokio::time::advance(RESERVATION_TTL).await;
+        assert!(!reservations.contains(id));
+        assert!(reservations.subscribe(id).is_none());
+        assert!(reservations.take(id).is_none());
+    }
+}
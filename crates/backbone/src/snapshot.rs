@@ -0,0 +1,36 @@
+use shortguid::ShortGuid;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// The time to wait for the bookkeeping of the files when taking a snapshot.
+pub const SNAPSHOT_LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// A snapshot of the state of the backbone, e.g. for diagnosing a stuck instance.
+#[derive(Debug)]
+pub struct BackboneSnapshot {
+    /// The files held by the backbone, including files that are being written;
+    /// `None` if the bookkeeping was locked for longer than [`SNAPSHOT_LOCK_TIMEOUT`].
+    pub files: Option<Vec<FileSnapshot>>,
+    /// The number of commands waiting to be handled by the command loop.
+    pub pending_commands: usize,
+    /// Whether the command loop is still running. While it is, it holds its rendezvous guard.
+    pub command_loop_running: bool,
+}
+
+/// The state of a single file held by the backbone.
+#[derive(Debug)]
+pub struct FileSnapshot {
+    /// The ID of the file.
+    pub id: ShortGuid,
+    /// The size of the file in bytes; `None` while the file is still being written.
+    pub file_size_bytes: Option<usize>,
+    /// Whether the file still accepts new readers, i.e. whether its lease is running.
+    pub accepts_readers: bool,
+    /// The time since the file was created.
+    pub age: Duration,
+    /// The duration of the lease, including any extensions by reads.
+    pub lease: Duration,
+    /// The path of the temporary file if it is named randomly, since it can't be told
+    /// from the ID then; `None` if files are named after their ID or held in memory.
+    pub temp_file_path: Option<PathBuf>,
+}
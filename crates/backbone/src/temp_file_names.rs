@@ -0,0 +1,62 @@
+use shared_files::SharedTemporaryFile;
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use uuid::Uuid;
+
+/// Names the temporary files of uploads.
+///
+/// By default, temporary files are named after the ID of the file, which makes them easy
+/// to find when debugging. With random names, the on-disk name can't be correlated with
+/// the ID returned to clients; the names are then only kept in memory.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct TempFileNames {
+    /// The paths of the temporary files by file ID; `None` if files are named after their ID.
+    random: Option<Arc<Mutex<HashMap<ShortGuid, PathBuf>>>>,
+}
+
+impl TempFileNames {
+    /// Creates a naming scheme using random names if `random` is `true`,
+    /// or the file IDs otherwise.
+    pub fn new(random: bool) -> Self {
+        Self {
+            random: random.then(Arc::default),
+        }
+    }
+
+    /// Creates the temporary file for the file with the specified ID.
+    pub async fn create(
+        &self,
+        id: ShortGuid,
+    ) -> Result<SharedTemporaryFile, async_tempfile::Error> {
+        let Some(random) = &self.random else {
+            return SharedTemporaryFile::new_with_uuid(id.into()).await;
+        };
+
+        let file = SharedTemporaryFile::new_with_uuid(Uuid::new_v4()).await?;
+        random
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(id, file.file_path().clone());
+        Ok(file)
+    }
+
+    /// Gets the path of the temporary file of the file with the specified ID,
+    /// if it has a random name.
+    pub fn get(&self, id: &ShortGuid) -> Option<PathBuf> {
+        let random = self.random.as_ref()?;
+        random
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .get(id)
+            .cloned()
+    }
+
+    /// Forgets the name of the temporary file of the file with the specified ID.
+    pub fn remove(&self, id: &ShortGuid) {
+        if let Some(random) = &self.random {
+            random.lock().unwrap_or_else(|e| e.into_inner()).remove(id);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+use shortguid::ShortGuid;
+use std::collections::{HashMap, VecDeque};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Remembers the IDs of recently removed files for a limited time, so that requests
+/// for them can be told apart from requests for IDs that were never issued.
+#[derive(Debug)]
+pub(crate) struct Tombstones {
+    /// The time for which a removed ID is remembered.
+    ttl: Duration,
+    /// The expiration times of the tombstones by ID.
+    expirations: HashMap<ShortGuid, Instant>,
+    /// The tombstones in order of their expiration.
+    queue: VecDeque<(Instant, ShortGuid)>,
+}
+
+impl Tombstones {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            expirations: HashMap::default(),
+            queue: VecDeque::default(),
+        }
+    }
+
+    /// Remembers the ID of a removed file. Does nothing if the TTL is zero.
+    pub fn insert(&mut self, id: ShortGuid) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let now = Instant::now();
+        self.prune(now);
+
+        let expiration = now + self.ttl;
+        self.expirations.insert(id, expiration);
+        self.queue.push_back((expiration, id));
+    }
+
+    /// Indicates whether the file with the specified ID was removed recently.
+    pub fn contains(&self, id: &ShortGuid) -> bool {
+        self.expirations
+            .get(id)
+            .is_some_and(|expiration| *expiration > Instant::now())
+    }
+
+    /// Forgets all tombstones that expired by `now`.
+    fn prune(&mut self, now: Instant) {
+        while let Some(&(expiration, id)) = self.queue.front() {
+            if expiration > now {
+                break;
+            }
+
+            self.queue.pop_front();
+            if self.expirations.get(&id) == Some(&expiration) {
+                self.expirations.remove(&id);
+            }
+        }
+    }
+}
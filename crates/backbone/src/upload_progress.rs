@@ -0,0 +1,33 @@
+use tokio::sync::watch;
+
+/// The progress of an upload, as observed by subscribers.
+///
+/// The progress is updated by the [`FileWriterGuard`](crate::file_writer_guard::FileWriterGuard)
+/// whenever it buffers data or the upload completes.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum UploadProgress {
+    /// The upload is in progress.
+    Pending {
+        /// The number of bytes buffered so far.
+        buffered: u64,
+        /// The announced size of the upload, if known.
+        expected: Option<u64>,
+    },
+    /// The upload completed with the specified number of bytes.
+    Completed(u64),
+    /// The upload failed or was aborted.
+    Failed,
+}
+
+/// Receives updates of an [`UploadProgress`].
+pub type UploadProgressReceiver = watch::Receiver<UploadProgress>;
+
+/// Creates the channel over which the progress of a new upload is published.
+pub(crate) fn upload_progress_channel(
+    expected: Option<u64>,
+) -> (watch::Sender<UploadProgress>, UploadProgressReceiver) {
+    watch::channel(UploadProgress::Pending {
+        buffered: 0,
+        expected,
+    })
+}
@@ -0,0 +1,489 @@
+use file_distribution::protobuf::{ItemMetadata, WalRecord};
+use file_distribution::WriteSummary;
+use shortguid::ShortGuid;
+use std::collections::HashSet;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::time::Instant;
+use tracing::{debug, warn};
+
+/// The extension of the files holding the content of logged files.
+const DATA_EXTENSION: &str = "data";
+
+/// The extension of the files holding the [`WalRecord`] of completely written files.
+const RECORD_EXTENSION: &str = "wal";
+
+/// The extension of records that are being written.
+const PARTIAL_RECORD_EXTENSION: &str = "tmp";
+
+/// The extension of the content of files that are being recovered.
+const RECOVERING_EXTENSION: &str = "recovering";
+
+/// A directory mirroring uploads until a backend stored them, so that files buffered
+/// at the time of a crash can be distributed again on startup.
+///
+/// The content of each file is written to `<id>.data` while it is uploaded. Once the
+/// upload completed, the [`WalRecord`] describing it is written to `<id>.wal`. Content
+/// without a record belongs to an upload that never completed and is discarded.
+#[derive(Debug, Clone)]
+pub(crate) struct WriteAheadLog {
+    dir: Arc<PathBuf>,
+}
+
+/// A file left over in the write-ahead log.
+pub(crate) struct PendingEntry {
+    /// The ID of the file.
+    pub id: ShortGuid,
+    /// The record describing the file.
+    pub record: WalRecord,
+    /// The path of the content of the file.
+    pub path: PathBuf,
+}
+
+/// Mirrors the content of an upload to the write-ahead log.
+///
+/// The content is discarded when the writer is dropped without being committed.
+pub(crate) struct WalWriter {
+    log: WriteAheadLog,
+    /// The ID of the file the content was logged under.
+    id: ShortGuid,
+    file: BufWriter<File>,
+    content_type: Option<String>,
+    committed: bool,
+}
+
+impl WriteAheadLog {
+    /// Opens the write-ahead log in the directory, creating it if needed.
+    pub fn open(dir: &Path) -> std::io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+        Ok(Self {
+            dir: Arc::new(dir.to_path_buf()),
+        })
+    }
+
+    /// Gets the directory of the log.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn path(&self, id: &ShortGuid, extension: &str) -> PathBuf {
+        self.dir.join(format!("{id}.{extension}"))
+    }
+
+    /// Starts logging the content of the file with the specified ID.
+    pub async fn create(
+        &self,
+        id: ShortGuid,
+        content_type: Option<String>,
+    ) -> std::io::Result<WalWriter> {
+        let file = File::create(self.path(&id, DATA_EXTENSION)).await?;
+        Ok(WalWriter {
+            log: self.clone(),
+            id,
+            file: BufWriter::new(file),
+            content_type,
+            committed: false,
+        })
+    }
+
+    /// Removes a file from the log, e.g. once a backend stored it.
+    pub async fn remove(&self, id: ShortGuid) {
+        for extension in [RECORD_EXTENSION, DATA_EXTENSION] {
+            match tokio::fs::remove_file(self.path(&id, extension)).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == ErrorKind::NotFound => {}
+                Err(e) => {
+                    warn!(file_id = %id, "Failed to remove file {id} from the write-ahead log: {e}")
+                }
+            }
+        }
+    }
+
+    /// Removes the content of a file that was recovered. Its record is kept if the
+    /// file was logged again while it was recovered.
+    pub async fn remove_recovered(&self, entry: &PendingEntry) {
+        if let Err(e) = tokio::fs::remove_file(&entry.path).await {
+            warn!(file_id = %entry.id, "Failed to remove the recovered file {id} from the write-ahead log: {e}", id = entry.id);
+        }
+    }
+
+    /// Collects the completely written files left over in the log, e.g. after a crash.
+    ///
+    /// Incomplete and expired files are discarded. The content of the returned files is
+    /// moved aside so that they can be logged again while they are recovered; if the
+    /// recovery is interrupted, the moved content is picked up on the next attempt.
+    pub async fn take_pending(&self) -> std::io::Result<Vec<PendingEntry>> {
+        let mut records = Vec::new();
+        let mut contents = HashSet::new();
+        let mut entries = tokio::fs::read_dir(self.dir()).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            let Some(id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| ShortGuid::try_parse(stem).ok())
+            else {
+                continue;
+            };
+
+            match path.extension().and_then(|extension| extension.to_str()) {
+                Some(RECORD_EXTENSION) => records.push(id),
+                Some(DATA_EXTENSION | RECOVERING_EXTENSION | PARTIAL_RECORD_EXTENSION) => {
+                    contents.insert(id);
+                }
+                _ => {}
+            }
+        }
+
+        let mut pending = Vec::with_capacity(records.len());
+        for id in records {
+            contents.remove(&id);
+            if let Some(entry) = self.take_entry(id).await? {
+                pending.push(entry);
+            }
+        }
+
+        for id in contents {
+            debug!(file_id = %id, "Discarding the incomplete file {id} from the write-ahead log");
+            self.discard(id).await;
+        }
+
+        Ok(pending)
+    }
+
+    /// Reads the record of a file and moves its content aside, or discards the file
+    /// if it can't be recovered.
+    async fn take_entry(&self, id: ShortGuid) -> std::io::Result<Option<PendingEntry>> {
+        let buf = tokio::fs::read(self.path(&id, RECORD_EXTENSION)).await?;
+        let record = match WalRecord::deserialize_from_proto(&buf) {
+            Ok(record) => record,
+            Err(e) => {
+                warn!(file_id = %id, "Discarding file {id} from the write-ahead log due to an invalid record: {e}");
+                self.discard(id).await;
+                return Ok(None);
+            }
+        };
+
+        if record.expires() <= SystemTime::now() {
+            warn!(file_id = %id, "Discarding file {id} from the write-ahead log since it expired before any backend stored it");
+            self.discard(id).await;
+            return Ok(None);
+        }
+
+        // Content that was already moved aside is complete, unlike content that was
+        // logged again before the previous recovery was interrupted.
+        let path = self.path(&id, RECOVERING_EXTENSION);
+        if tokio::fs::try_exists(&path).await? {
+            remove_if_exists(&self.path(&id, DATA_EXTENSION)).await?;
+        } else if let Err(e) = tokio::fs::rename(self.path(&id, DATA_EXTENSION), &path).await {
+            warn!(file_id = %id, "Discarding file {id} from the write-ahead log since its content is unavailable: {e}");
+            self.discard(id).await;
+            return Ok(None);
+        }
+
+        Ok(Some(PendingEntry { id, record, path }))
+    }
+
+    /// Removes all files of an entry.
+    async fn discard(&self, id: ShortGuid) {
+        for extension in [
+            RECORD_EXTENSION,
+            PARTIAL_RECORD_EXTENSION,
+            DATA_EXTENSION,
+            RECOVERING_EXTENSION,
+        ] {
+            if let Err(e) = remove_if_exists(&self.path(&id, extension)).await {
+                warn!(file_id = %id, "Failed to remove file {id} from the write-ahead log: {e}");
+            }
+        }
+    }
+}
+
+impl WalWriter {
+    /// Appends a chunk of the content.
+    pub async fn write(&mut self, chunk: &[u8]) -> std::io::Result<()> {
+        self.file.write_all(chunk).await
+    }
+
+    /// Syncs the content to disk and records the completely written file under its
+    /// final ID, which differs from the logged ID if the file was reassigned.
+    pub async fn commit(
+        mut self,
+        id: ShortGuid,
+        size: u64,
+        summary: &Arc<WriteSummary>,
+    ) -> std::io::Result<()> {
+        self.file.flush().await?;
+        self.file.get_ref().sync_data().await?;
+
+        if id != self.id {
+            tokio::fs::rename(
+                self.log.path(&self.id, DATA_EXTENSION),
+                self.log.path(&id, DATA_EXTENSION),
+            )
+            .await?;
+            self.id = id;
+        }
+
+        let expires = SystemTime::now() + summary.expires.saturating_duration_since(Instant::now());
+        let record = WalRecord {
+            item: Some(ItemMetadata::new(id, summary)),
+            content_type: self.content_type.take(),
+            size,
+            expires_unix_ms: expires
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |expires| expires.as_millis() as u64),
+        };
+        let buf = record.serialize_to_proto().map_err(std::io::Error::other)?;
+
+        // The record is only valid once written completely.
+        let partial = self.log.path(&id, PARTIAL_RECORD_EXTENSION);
+        let mut file = File::create(&partial).await?;
+        file.write_all(&buf).await?;
+        file.sync_all().await?;
+        tokio::fs::rename(&partial, self.log.path(&id, RECORD_EXTENSION)).await?;
+
+        self.committed = true;
+        Ok(())
+    }
+}
+
+impl Drop for WalWriter {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+
+        let path = self.log.path(&self.id, DATA_EXTENSION);
+        if let Err(e) = std::fs::remove_file(path) {
+            if e.kind() != ErrorKind::NotFound {
+                warn!(file_id = %self.id, "Failed to discard file {id} from the write-ahead log: {e}", id = self.id);
+            }
+        }
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> std::io::Result<()> {
+    match tokio::fs::remove_file(path).await {
+        Err(e) if e.kind() != ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Backbone, CompletionMode, ExpectedContent};
+    use app_config::backbone::BackboneConfig;
+    use backend_traits::{
+        BackendCommand, BackendCommandSender, DistributionState, DistributionTracker,
+    };
+    use file_distribution::FileHashes;
+    use rendezvous::Rendezvous;
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// The time the tests wait for the background tasks of the backbone.
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    fn summary(size: usize, expires: Instant) -> Arc<WriteSummary> {
+        Arc::new(WriteSummary {
+            created: SystemTime::now(),
+            expires,
+            hashes: FileHashes::new(None, None, None),
+            file_name: None,
+            file_size_bytes: size,
+            metadata: Default::default(),
+        })
+    }
+
+    /// Logs a completely uploaded file, as done while the file is buffered.
+    async fn log_file(wal: &WriteAheadLog, id: ShortGuid, content: &[u8], expires: Instant) {
+        let mut writer = wal.create(id, None).await.unwrap();
+        writer.write(content).await.unwrap();
+        writer
+            .commit(id, content.len() as u64, &summary(content.len(), expires))
+            .await
+            .unwrap();
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<_> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        names
+    }
+
+    fn backbone(
+        dir: &Path,
+    ) -> (
+        Backbone,
+        mpsc::Receiver<BackendCommand>,
+        DistributionTracker,
+        Rendezvous,
+    ) {
+        let (sender, receiver) = mpsc::channel(16);
+        let tracker = DistributionTracker::new(["test"]);
+        let rendezvous = Rendezvous::new();
+        let config = BackboneConfig {
+            wal_dir: Some(dir.to_path_buf()),
+            ..Default::default()
+        };
+        let backbone = Backbone::try_new(
+            BackendCommandSender::new(sender, tracker.clone()),
+            rendezvous.fork_guard(),
+            &config,
+        )
+        .expect("failed to create the backbone");
+        (backbone, receiver, tracker, rendezvous)
+    }
+
+    async fn shut_down(backbone: Backbone, rendezvous: Rendezvous) {
+        drop(backbone);
+        tokio::time::timeout(Duration::from_millis(100), rendezvous.rendezvous_async())
+            .await
+            .ok();
+    }
+
+    #[tokio::test]
+    async fn incomplete_files_are_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::open(dir.path()).unwrap();
+
+        // The upload was interrupted before its record was written.
+        let id = ShortGuid::new_random();
+        std::fs::write(wal.path(&id, DATA_EXTENSION), b"hel").unwrap();
+
+        assert!(wal.take_pending().await.unwrap().is_empty());
+        assert!(file_names(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn committed_files_are_taken_for_recovery() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::open(dir.path()).unwrap();
+        let id = ShortGuid::new_random();
+        log_file(&wal, id, b"hello", Instant::now() + TIMEOUT).await;
+        assert_eq!(
+            file_names(dir.path()),
+            [format!("{id}.data"), format!("{id}.wal")]
+        );
+
+        let pending = wal.take_pending().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].id, id);
+        assert_eq!(pending[0].record.size, 5);
+        assert_eq!(pending[0].path, wal.path(&id, RECOVERING_EXTENSION));
+        assert_eq!(std::fs::read(&pending[0].path).unwrap(), b"hello");
+        assert_eq!(
+            file_names(dir.path()),
+            [format!("{id}.recovering"), format!("{id}.wal")]
+        );
+    }
+
+    #[tokio::test]
+    async fn interrupted_recoveries_are_resumed() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::open(dir.path()).unwrap();
+        let id = ShortGuid::new_random();
+        log_file(&wal, id, b"hello", Instant::now() + TIMEOUT).await;
+        wal.take_pending().await.unwrap();
+
+        // The recovery was interrupted while the file was logged again.
+        std::fs::write(wal.path(&id, DATA_EXTENSION), b"hel").unwrap();
+
+        let pending = wal.take_pending().await.unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].path, wal.path(&id, RECOVERING_EXTENSION));
+        assert_eq!(std::fs::read(&pending[0].path).unwrap(), b"hello");
+        assert_eq!(
+            file_names(dir.path()),
+            [format!("{id}.recovering"), format!("{id}.wal")]
+        );
+    }
+
+    #[tokio::test]
+    async fn expired_files_are_discarded() {
+        let dir = tempfile::tempdir().unwrap();
+        let wal = WriteAheadLog::open(dir.path()).unwrap();
+        log_file(&wal, ShortGuid::new_random(), b"hello", Instant::now()).await;
+
+        assert!(wal.take_pending().await.unwrap().is_empty());
+        assert!(file_names(dir.path()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn files_are_removed_once_a_backend_stored_them() {
+        let dir = tempfile::tempdir().unwrap();
+        let (backbone, mut commands, tracker, rendezvous) = backbone(dir.path());
+
+        let id = backbone.new_id();
+        let mut writer = backbone
+            .new_file(
+                id,
+                ExpectedContent::default(),
+                None,
+                None,
+                Default::default(),
+                None,
+            )
+            .await
+            .unwrap();
+        writer.write(b"hello").await.unwrap();
+        writer.finalize(CompletionMode::Sync).await.unwrap();
+
+        let Some(BackendCommand::DistributeFile(distributed, summary)) = commands.recv().await
+        else {
+            panic!("the file is not distributed");
+        };
+        assert_eq!(distributed, id);
+        assert_eq!(
+            file_names(dir.path()),
+            [format!("{id}.data"), format!("{id}.wal")]
+        );
+
+        tracker.begin(id, summary.expires);
+        tracker.update(id, "test", DistributionState::Stored);
+        tokio::time::timeout(TIMEOUT, async {
+            while !file_names(dir.path()).is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the file is kept in the write-ahead log");
+
+        shut_down(backbone, rendezvous).await;
+    }
+
+    #[tokio::test]
+    async fn pending_files_are_recovered() {
+        let dir = tempfile::tempdir().unwrap();
+        let id = ShortGuid::new_random();
+        let wal = WriteAheadLog::open(dir.path()).unwrap();
+        log_file(&wal, id, b"hello", Instant::now() + TIMEOUT).await;
+
+        let (backbone, mut commands, _tracker, rendezvous) = backbone(dir.path());
+        assert_eq!(backbone.recover_from_wal().await, 1);
+
+        let Some(BackendCommand::DistributeFile(distributed, summary)) = commands.recv().await
+        else {
+            panic!("the recovered file is not distributed");
+        };
+        assert_eq!(distributed, id);
+        assert_eq!(summary.file_size_bytes, 5);
+
+        // The recovered file is logged again until a backend stores it.
+        assert_eq!(
+            file_names(dir.path()),
+            [format!("{id}.data"), format!("{id}.wal")]
+        );
+
+        shut_down(backbone, rendezvous).await;
+    }
+}
@@ -0,0 +1,42 @@
+use shortguid::ShortGuid;
+use std::collections::HashSet;
+use std::sync::{Arc, Mutex};
+
+/// Grants exclusive write access to file IDs.
+///
+/// A [`SharedTemporaryFile`](shared_files::SharedTemporaryFile) supports a single writer
+/// only, and files named after their ID would even be truncated by a second writer. The
+/// lock is taken before the buffer of a new file is created, so that a concurrent writer
+/// to the same ID is rejected before it touches the file of the first one.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WriteLocks {
+    locked: Arc<Mutex<HashSet<ShortGuid>>>,
+}
+
+/// Exclusive write access to a file ID, released when dropped.
+#[derive(Debug)]
+pub(crate) struct WriteLock {
+    locks: WriteLocks,
+    id: ShortGuid,
+}
+
+impl WriteLocks {
+    /// Takes the write lock of the ID, or returns `None` if another writer holds it.
+    pub fn try_lock(&self, id: ShortGuid) -> Option<WriteLock> {
+        let mut locked = self.locked.lock().unwrap_or_else(|e| e.into_inner());
+        locked.insert(id).then(|| WriteLock {
+            locks: self.clone(),
+            id,
+        })
+    }
+}
+
+impl Drop for WriteLock {
+    fn drop(&mut self) {
+        self.locks
+            .locked
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(&self.id);
+    }
+}
@@ -0,0 +1,367 @@
+use crate::file_reader::GcsFileReader;
+use crate::sync_stream::SyncStream;
+use app_config::{gcs::GcsBackendConfig, AppConfig};
+use async_trait::async_trait;
+use backend_traits::{
+    clamp_range, Backend, BackendExpiration, DeleteFileError, DistributeFile, DistributionError,
+};
+use backend_traits::{BackendInfo, ReceiveFile, ReceiveFileError, TryCreateFromConfig};
+use base64::Engine;
+use file_distribution::protobuf::ItemMetadata;
+use file_distribution::{BoxedFileReader, FileProvider, FileReaderTrait, GetFile, WriteSummary};
+use futures::{StreamExt, TryStreamExt};
+use google_cloud_storage::client::google_cloud_auth::credentials::CredentialsFile;
+use google_cloud_storage::client::{Client, ClientConfig};
+use google_cloud_storage::http::objects::delete::DeleteObjectRequest;
+use google_cloud_storage::http::objects::download::Range;
+use google_cloud_storage::http::objects::get::GetObjectRequest;
+use google_cloud_storage::http::objects::upload::{UploadObjectRequest, UploadType};
+use google_cloud_storage::http::objects::Object;
+use google_cloud_storage::sign::SignedURLOptions;
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use time::OffsetDateTime;
+use tokio::sync::OnceCell;
+use tokio::time::Instant;
+use tokio_util::io::ReaderStream;
+use tracing::{trace, warn};
+use url::Url;
+
+/// The object metadata key under which the serialized [`ItemMetadata`] is stored.
+const ITEM_METADATA_KEY: &str = "yy-item-metadata";
+
+pub struct GcsBackend {
+    /// The tag identifying the backend.
+    tag: String,
+    /// The bucket to store the files in.
+    bucket: String,
+    /// The contents of the service account key file, if one was configured.
+    credentials: Option<String>,
+    /// The client, created on first use.
+    client: OnceCell<Client>,
+    /// The expiration time for stored objects.
+    expiration: BackendExpiration,
+    /// The validity of presigned URLs; `None` if presigning is disabled.
+    presign_expiration: Option<Duration>,
+}
+
+impl GcsBackend {
+    pub fn try_new(config: &GcsBackendConfig) -> Result<Self, GcsBackendConstructionError> {
+        let credentials = match &config.credentials_file {
+            Some(path) => Some(std::fs::read_to_string(path).map_err(|e| {
+                GcsBackendConstructionError::FailedToReadCredentials(path.clone(), e)
+            })?),
+            None => None,
+        };
+
+        Ok(Self {
+            tag: config.tag.clone(),
+            bucket: config.bucket.clone(),
+            credentials,
+            client: OnceCell::new(),
+            expiration: BackendExpiration::new(
+                config.expiration(),
+                config.lease_expiration.lease_offset(),
+            ),
+            presign_expiration: config.presign_expiration(),
+        })
+    }
+
+    /// Gets the client, authenticating on first use.
+    async fn client(&self) -> Result<&Client, GcsClientError> {
+        self.client
+            .get_or_try_init(|| async {
+                let config = match &self.credentials {
+                    Some(credentials) => {
+                        let credentials = CredentialsFile::new_from_str(credentials).await?;
+                        ClientConfig::default()
+                            .with_credentials(credentials)
+                            .await?
+                    }
+                    None => ClientConfig::default().with_auth().await?,
+                };
+                Ok(Client::new(config))
+            })
+            .await
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for GcsBackend {
+    /// Streams a stored file back from the bucket.
+    async fn receive_file(&self, id: ShortGuid) -> Result<BoxedFileReader, ReceiveFileError> {
+        self.receive(id, None).await
+    }
+
+    /// Streams a byte range of a stored file back from the bucket, without downloading
+    /// the rest of the object.
+    async fn receive_range(
+        &self,
+        id: ShortGuid,
+        start: u64,
+        end: u64,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        self.receive(id, Some((start, end))).await
+    }
+
+    async fn presign_get(&self, id: ShortGuid) -> Option<Url> {
+        let validity = self.presign_expiration?;
+        match self.try_presign_get(id, validity).await {
+            Ok(url) => url,
+            Err(e) => {
+                warn!(file_id = %id, "Failed to create a presigned URL for object {id} in bucket {bucket}: {e}", bucket = self.bucket);
+                None
+            }
+        }
+    }
+}
+
+impl GcsBackend {
+    /// Streams the object, or the inclusive byte range of it if specified.
+    async fn receive(
+        &self,
+        id: ShortGuid,
+        range: Option<(u64, u64)>,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        let client = self
+            .client()
+            .await
+            .map_err(|e| ReceiveFileError::BackendSpecific(Box::new(e)))?;
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: id.to_string(),
+            ..Default::default()
+        };
+
+        let object = client
+            .get_object(&request)
+            .await
+            .map_err(|e| map_storage_error(id, e))?;
+        let now = OffsetDateTime::now_utc();
+        let expires_in = object
+            .custom_time
+            .map_or(Duration::ZERO, |t| (t - now).try_into().unwrap_or_default());
+        if object.custom_time.is_some() && expires_in.is_zero() {
+            return Err(ReceiveFileError::FileExpired(id));
+        }
+
+        let expires = Instant::now() + expires_in;
+        let file_size = object.size.max(0) as usize;
+        let metadata = object
+            .metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(ITEM_METADATA_KEY))
+            .and_then(|metadata| decode_item_metadata(metadata));
+
+        // Prefer the original creation time of the file over the upload time of the object.
+        let file_age = metadata
+            .as_ref()
+            .and_then(ItemMetadata::file_age)
+            .unwrap_or_else(|| {
+                object
+                    .time_created
+                    .map_or(Duration::ZERO, |t| (now - t).try_into().unwrap_or_default())
+            });
+        let summary = metadata
+            .and_then(|metadata| metadata.to_write_summary(expires, file_size))
+            .map(Arc::new);
+
+        let (download_range, stream_size) = match range {
+            Some((start, end)) => {
+                let (start, end) = clamp_range(id, start, end, file_size as u64)?;
+                (Range(Some(start), Some(end)), (end + 1 - start) as usize)
+            }
+            None => (Range::default(), file_size),
+        };
+
+        let stream = client
+            .download_streamed_object(&request, &download_range)
+            .await
+            .map_err(|e| map_storage_error(id, e))?
+            .map_err(std::io::Error::other)
+            .boxed();
+
+        trace!(file_id = %id, "Streaming {stream_size} bytes from bucket {bucket}", bucket = self.bucket);
+        Ok(BoxedFileReader::new(GcsFileReader::new(
+            stream,
+            object.content_type,
+            stream_size,
+            file_age,
+            expires,
+            summary,
+        )))
+    }
+
+    /// Creates a presigned URL for an object, valid for no longer than the object itself.
+    async fn try_presign_get(
+        &self,
+        id: ShortGuid,
+        validity: Duration,
+    ) -> Result<Option<Url>, Box<dyn std::error::Error + Send + Sync>> {
+        let client = self.client().await?;
+        let request = GetObjectRequest {
+            bucket: self.bucket.clone(),
+            object: id.to_string(),
+            ..Default::default()
+        };
+
+        let object = match client.get_object(&request).await {
+            Ok(object) => object,
+            Err(google_cloud_storage::http::Error::Response(e)) if e.code == 404 => {
+                return Ok(None)
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let expires_in = match object.custom_time {
+            Some(t) => (t - OffsetDateTime::now_utc())
+                .try_into()
+                .unwrap_or_default(),
+            None => validity,
+        };
+        if expires_in.is_zero() {
+            return Ok(None);
+        }
+
+        let options = SignedURLOptions {
+            // Signed URLs must be valid for at least one second.
+            expires: validity.min(expires_in).max(Duration::from_secs(1)),
+            ..Default::default()
+        };
+        let url = client
+            .signed_url(&self.bucket, &object.name, None, None, options)
+            .await?;
+        Ok(Some(Url::parse(&url)?))
+    }
+}
+
+/// Decodes the base64 encoded [`ItemMetadata`] stored with an object.
+fn decode_item_metadata(metadata: &str) -> Option<ItemMetadata> {
+    let buf = base64::engine::general_purpose::STANDARD
+        .decode(metadata)
+        .ok()?;
+    ItemMetadata::deserialize_from_proto(&buf).ok()
+}
+
+fn map_storage_error(id: ShortGuid, error: google_cloud_storage::http::Error) -> ReceiveFileError {
+    match error {
+        google_cloud_storage::http::Error::Response(e) if e.code == 404 => {
+            ReceiveFileError::UnknownFile(id)
+        }
+        e => ReceiveFileError::BackendSpecific(Box::new(e)),
+    }
+}
+
+#[async_trait]
+impl DistributeFile for GcsBackend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<(), DistributionError> {
+        let client = self
+            .client()
+            .await
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+        let file = file_provider.get_file(id).await?;
+
+        let metadata = self
+            .expiration
+            .item_metadata(id, &summary)
+            .serialize_to_proto()
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+        let metadata = base64::engine::general_purpose::STANDARD.encode(metadata);
+
+        let object = Object {
+            name: id.to_string(),
+            content_type: file.content_type().map(|c| c.to_string()),
+            md5_hash: summary
+                .hashes
+                .md5
+                .map(|md5| base64::engine::general_purpose::STANDARD.encode(&md5[..])),
+            custom_time: Some(OffsetDateTime::now_utc() + self.expiration.lifetime(&summary)),
+            metadata: Some(HashMap::from([(ITEM_METADATA_KEY.to_string(), metadata)])),
+            ..Default::default()
+        };
+
+        let request = UploadObjectRequest {
+            bucket: self.bucket.clone(),
+            ..Default::default()
+        };
+
+        let stream = SyncStream::new(ReaderStream::new(file));
+        client
+            .upload_streamed_object(&request, stream, &UploadType::Multipart(Box::new(object)))
+            .await
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        trace!(file_id = %id, "Stored object {id} in bucket {bucket}", bucket = self.bucket);
+        Ok(())
+    }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DeleteFileError> {
+        let client = self
+            .client()
+            .await
+            .map_err(|e| DeleteFileError::BackendSpecific(Box::new(e)))?;
+        let request = DeleteObjectRequest {
+            bucket: self.bucket.clone(),
+            object: id.to_string(),
+            ..Default::default()
+        };
+
+        match client.delete_object(&request).await {
+            Ok(()) => {
+                trace!(file_id = %id, "Deleted object {id} from bucket {bucket}", bucket = self.bucket);
+                Ok(())
+            }
+            Err(google_cloud_storage::http::Error::Response(e)) if e.code == 404 => {
+                Err(DeleteFileError::UnknownFile(id))
+            }
+            Err(e) => Err(DeleteFileError::BackendSpecific(Box::new(e))),
+        }
+    }
+}
+
+impl BackendInfo for GcsBackend {
+    fn backend_name() -> &'static str {
+        "Google Cloud Storage"
+    }
+
+    fn backend_version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl TryCreateFromConfig for GcsBackend {
+    type Error = GcsBackendConstructionError;
+
+    fn try_from_config(config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+        config
+            .backends
+            .gcs
+            .iter()
+            .map(|config| GcsBackend::try_new(config).map(Backend::wrap))
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GcsBackendConstructionError {
+    #[error("Failed to read the credentials file {0}: {1}")]
+    FailedToReadCredentials(PathBuf, std::io::Error),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum GcsClientError {
+    #[error("Failed to authenticate: {0}")]
+    Authentication(#[from] google_cloud_storage::client::google_cloud_auth::error::Error),
+}
@@ -0,0 +1,77 @@
+use bytes::Bytes;
+use file_distribution::{FileReaderTrait, WriteSummary};
+use futures::stream::BoxStream;
+use shared_files::FileSize;
+use std::borrow::Cow;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Instant;
+use tokio_util::io::StreamReader;
+
+/// A read accessor for an object stored in Google Cloud Storage.
+pub struct GcsFileReader {
+    /// The object download stream.
+    inner: StreamReader<BoxStream<'static, std::io::Result<Bytes>>, Bytes>,
+    content_type: Option<String>,
+    file_size: usize,
+    file_age: Duration,
+    expires: Instant,
+    summary: Option<Arc<WriteSummary>>,
+}
+
+impl GcsFileReader {
+    pub(crate) fn new(
+        stream: BoxStream<'static, std::io::Result<Bytes>>,
+        content_type: Option<String>,
+        file_size: usize,
+        file_age: Duration,
+        expires: Instant,
+        summary: Option<Arc<WriteSummary>>,
+    ) -> Self {
+        Self {
+            inner: StreamReader::new(stream),
+            content_type,
+            file_size,
+            file_age,
+            expires,
+            summary,
+        }
+    }
+}
+
+impl FileReaderTrait for GcsFileReader {
+    fn summary(&self) -> &Option<Arc<WriteSummary>> {
+        &self.summary
+    }
+
+    fn expiration_date(&self) -> Instant {
+        self.expires
+    }
+
+    fn file_size(&self) -> FileSize {
+        FileSize::Exactly(self.file_size)
+    }
+
+    fn file_age(&self) -> Duration {
+        self.file_age
+    }
+
+    fn content_type(&self) -> Option<Cow<'_, str>> {
+        self.content_type
+            .as_ref()
+            .map(|content_type| Cow::from(content_type.as_str()))
+    }
+}
+
+impl AsyncRead for GcsFileReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_read(cx, buf)
+    }
+}
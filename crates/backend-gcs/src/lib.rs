@@ -0,0 +1,10 @@
+// only enables the `doc_cfg` feature when
+// the `docsrs` configuration attribute is defined
+#![cfg_attr(docsrs, feature(doc_cfg))]
+
+mod backend;
+mod file_reader;
+mod sync_stream;
+
+pub use backend::{GcsBackend, GcsBackendConstructionError, GcsClientError};
+pub use file_reader::GcsFileReader;
@@ -0,0 +1,31 @@
+use futures::Stream;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+
+/// Makes a [`Send`] stream [`Sync`] by only ever accessing it mutably.
+///
+/// The upload API requires the body stream to be `Sync`, which the file readers are not.
+pub(crate) struct SyncStream<S>(Mutex<S>);
+
+impl<S> SyncStream<S> {
+    pub fn new(stream: S) -> Self {
+        Self(Mutex::new(stream))
+    }
+}
+
+impl<S> Stream for SyncStream<S>
+where
+    S: Stream + Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let stream = self
+            .get_mut()
+            .0
+            .get_mut()
+            .expect("failed to access upload stream");
+        Pin::new(stream).poll_next(cx)
+    }
+}
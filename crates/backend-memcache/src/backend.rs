@@ -1,13 +1,20 @@
 use crate::connection_string::MemcacheConnectionStringWrapper;
 use app_config::{
-    memcache::{MemcacheBackendConfig, DEFAULT_EXPIRATION},
+    memcache::{
+        MemcacheBackendConfig, MemcacheConnectionString, DEFAULT_EXPIRATION, DEFAULT_MAX_POOL_SIZE,
+        DEFAULT_MIN_IDLE,
+    },
     AppConfig,
 };
 use async_trait::async_trait;
-use backend_traits::{Backend, DistributeFile, DistributionError};
-use backend_traits::{BackendInfo, TryCreateFromConfig};
+use backend_traits::{clamp_range, Backend, DeleteFileError, DistributeFile, DistributionError};
+use backend_traits::{
+    BackendExpiration, BackendInfo, ReceiveFile, ReceiveFileError, TryCreateFromConfig,
+};
+use backend_traits::{BackendStats, BackendStatsError};
+use bytes::Bytes;
 use file_distribution::protobuf::ItemMetadata;
-use file_distribution::{BoxedFileReader, FileProvider, GetFile, WriteSummary};
+use file_distribution::{BoxedFileReader, BytesFileReader, FileProvider, GetFile, WriteSummary};
 use map_ok::{BoxOk, MapOk};
 use r2d2::Pool;
 use r2d2_memcache::memcache::{MemcacheError, ToMemcacheValue};
@@ -17,8 +24,13 @@ use std::cell::Cell;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::task::spawn_blocking;
+use tokio::time::Instant;
 use tokio_util::io::SyncIoBridge;
-use tracing::trace;
+use tracing::{debug, trace};
+
+/// The largest file stored, i.e. the default item size limit of Memcached.
+/// Larger files are rejected; the striped backend can store them in chunks.
+const MAX_FILE_SIZE: usize = 1024 * 1024;
 
 pub struct MemcacheBackend {
     /// The tag identifying the backend.
@@ -26,34 +38,132 @@ pub struct MemcacheBackend {
     /// The connection pool
     pool: Pool<MemcacheConnectionManager>,
     /// The expiration time for stored entries.
-    expiration_secs: u32,
+    expiration: BackendExpiration,
 }
 
 impl MemcacheBackend {
     pub fn try_new(
         config: &MemcacheBackendConfig,
     ) -> Result<Self, MemcacheBackendConstructionError> {
-        let manager = MemcacheConnectionManager::new(MemcacheConnectionStringWrapper::from(
-            &config.connection_string,
-        ));
-        let pool = Pool::builder()
-            .min_idle(Some(1))
-            .build(manager)
-            .map_err(MemcacheBackendConstructionError::FailedToCreatePool)?;
-
-        let expiration_secs = config
-            .expiration_sec
-            .map_or(DEFAULT_EXPIRATION, |secs| Duration::from_secs(secs as _))
-            .as_secs()
-            .min(u32::MAX as _) as u32;
+        let pool = create_pool(&config.connection_string, &PoolOptions::from(config))?;
         Ok(Self {
             tag: config.tag.clone(),
             pool,
-            expiration_secs,
+            expiration: expiration(
+                config.expiration_sec,
+                config.lease_expiration.lease_offset(),
+            ),
         })
     }
 }
 
+/// The sizing of a connection pool.
+pub(crate) struct PoolOptions {
+    /// The number of idle connections kept open.
+    min_idle: u32,
+    /// The maximum number of connections.
+    max_size: u32,
+    /// Whether to wait for the idle connections to be opened when creating the pool.
+    warm_up: bool,
+}
+
+impl Default for PoolOptions {
+    fn default() -> Self {
+        Self {
+            min_idle: DEFAULT_MIN_IDLE,
+            max_size: DEFAULT_MAX_POOL_SIZE,
+            warm_up: true,
+        }
+    }
+}
+
+impl From<&MemcacheBackendConfig> for PoolOptions {
+    fn from(config: &MemcacheBackendConfig) -> Self {
+        Self {
+            min_idle: config.min_idle(),
+            max_size: config.max_size(),
+            warm_up: config.warm_up(),
+        }
+    }
+}
+
+/// Creates a connection pool for the Memcached server(s) identified by the connection string.
+///
+/// If warming up, this blocks until the idle connections are opened and fails if that
+/// doesn't succeed within the connection timeout.
+pub(crate) fn create_pool(
+    connection_string: &MemcacheConnectionString,
+    options: &PoolOptions,
+) -> Result<Pool<MemcacheConnectionManager>, MemcacheBackendConstructionError> {
+    let manager =
+        MemcacheConnectionManager::new(MemcacheConnectionStringWrapper::from(connection_string));
+    let builder = Pool::builder()
+        .min_idle(Some(options.min_idle))
+        .max_size(options.max_size);
+
+    if !options.warm_up {
+        return Ok(builder.build_unchecked(manager));
+    }
+
+    let start = Instant::now();
+    let pool = builder
+        .build(manager)
+        .map_err(MemcacheBackendConstructionError::FailedToCreatePool)?;
+    debug!(
+        "Opened {count} connection(s) to {connection_string} in {duration:?}",
+        count = pool.state().connections,
+        duration = start.elapsed()
+    );
+    Ok(pool)
+}
+
+/// Gets the storage statistics of the Memcached server(s) of the pool, summed up.
+pub(crate) async fn server_stats(
+    pool: Pool<MemcacheConnectionManager>,
+) -> Result<BackendStats, BackendStatsError> {
+    let result: Result<BackendStats, BackendStatsError> = spawn_blocking(move || {
+        let client = pool
+            .get()
+            .map_err(|e| BackendStatsError::BackendSpecific(Box::new(e)))?;
+        let servers = client
+            .stats()
+            .map_err(|e| BackendStatsError::BackendSpecific(Box::new(e)))?;
+
+        let mut stats = BackendStats::default();
+        for (_, server) in servers {
+            let value = |name: &str| server.get(name).and_then(|value| value.parse::<u64>().ok());
+            stats.items = add(stats.items, value("curr_items"));
+            stats.bytes_used = add(stats.bytes_used, value("bytes"));
+            stats.bytes_limit = add(stats.bytes_limit, value("limit_maxbytes"));
+        }
+        Ok(stats)
+    })
+    .await?;
+    result
+}
+
+/// Adds up two statistics, either of which may be unknown.
+pub(crate) fn add(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a + b),
+        (a, b) => a.or(b),
+    }
+}
+
+/// Gets the configured expiration, or the [`DEFAULT_EXPIRATION`].
+pub(crate) fn expiration(
+    expiration_sec: Option<u32>,
+    lease_offset: Option<Duration>,
+) -> BackendExpiration {
+    let fixed = expiration_sec.map_or(DEFAULT_EXPIRATION, |secs| Duration::from_secs(secs as _));
+    BackendExpiration::new(fixed, lease_offset)
+}
+
+/// Gets the expiration of an entry in seconds, as passed to Memcached.
+pub(crate) fn expiration_secs(lifetime: Duration) -> u32 {
+    lifetime.as_secs().min(u32::MAX as _) as u32
+}
+
 #[async_trait]
 impl DistributeFile for MemcacheBackend {
     fn tag(&self) -> &str {
@@ -66,13 +176,18 @@ impl DistributeFile for MemcacheBackend {
         summary: Arc<WriteSummary>,
         file_provider: FileProvider,
     ) -> Result<(), DistributionError> {
-        // TODO: Sanity check the file size - don't store if too large.
+        if summary.file_size_bytes > MAX_FILE_SIZE {
+            return Err(DistributionError::BackendRejected(format!(
+                "The file size of {size} bytes exceeds the item size limit of {MAX_FILE_SIZE} bytes",
+                size = summary.file_size_bytes
+            )));
+        }
 
-        let expiration = self.expiration_secs;
+        let expiration = expiration_secs(self.expiration.lifetime(&summary));
         let file = file_provider.get_file(id).await?;
         let client = self.pool.get().unwrap();
 
-        let metadata = ItemMetadata::new(id, &summary);
+        let metadata = self.expiration.item_metadata(id, &summary);
         let metadata_buf = metadata
             .serialize_to_proto()
             .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
@@ -97,6 +212,106 @@ impl DistributeFile for MemcacheBackend {
             Err(e) => Err(DistributionError::BackendSpecific(Box::new(e))),
         }
     }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DeleteFileError> {
+        let client = self
+            .pool
+            .get()
+            .map_err(|e| DeleteFileError::BackendSpecific(Box::new(e)))?;
+
+        let result: Result<bool, MemcacheError> = spawn_blocking(move || {
+            let deleted = client.delete(&format!("data-{}", id))?;
+            client.delete(&format!("meta-{}", id))?;
+            Ok(deleted)
+        })
+        .await?;
+
+        match result {
+            Ok(true) => {
+                trace!(file_id = %id, "Deleted file {id}");
+                Ok(())
+            }
+            Ok(false) => Err(DeleteFileError::UnknownFile(id)),
+            Err(e) => Err(DeleteFileError::BackendSpecific(Box::new(e))),
+        }
+    }
+
+    /// Reports the statistics of the whole server, including items not stored by this service.
+    async fn stats(&self) -> Result<Option<BackendStats>, BackendStatsError> {
+        server_stats(self.pool.clone()).await.map(Some)
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for MemcacheBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<BoxedFileReader, ReceiveFileError> {
+        self.receive(id, None).await
+    }
+
+    async fn receive_range(
+        &self,
+        id: ShortGuid,
+        start: u64,
+        end: u64,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        self.receive(id, Some((start, end))).await
+    }
+}
+
+impl MemcacheBackend {
+    /// Receives the file, or the inclusive byte range of it if specified.
+    async fn receive(
+        &self,
+        id: ShortGuid,
+        range: Option<(u64, u64)>,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        let client = self
+            .pool
+            .get()
+            .map_err(|e| ReceiveFileError::BackendSpecific(Box::new(e)))?;
+
+        let result = spawn_blocking(move || -> Result<_, MemcacheError> {
+            let data: Option<Vec<u8>> = client.get(&format!("data-{}", id))?;
+            let metadata: Option<Vec<u8>> = client.get(&format!("meta-{}", id))?;
+            Ok((data, metadata))
+        })
+        .await?;
+
+        let (data, metadata) = match result {
+            Ok((Some(data), metadata)) => (data, metadata),
+            Ok((None, _)) => return Err(ReceiveFileError::UnknownFile(id)),
+            Err(e) => return Err(ReceiveFileError::BackendSpecific(Box::new(e))),
+        };
+
+        // Memcached doesn't report the remaining lifetime of an entry, so we
+        // assume the item to be valid for no longer than the configured expiration
+        // after the file was originally created, unless its expiration was recorded.
+        let lifetime = self.expiration.fixed();
+        let metadata =
+            metadata.and_then(|metadata| ItemMetadata::deserialize_from_proto(&metadata).ok());
+        let expires = metadata.as_ref().map_or_else(
+            || Instant::now() + lifetime,
+            |metadata| metadata.expires_after(lifetime),
+        );
+        let file_age = metadata
+            .as_ref()
+            .and_then(ItemMetadata::file_age)
+            .unwrap_or_default();
+        let summary = metadata
+            .and_then(|metadata| metadata.to_write_summary(expires, data.len()))
+            .map(Arc::new);
+
+        // Memcached values are fetched as a whole, so ranges are sliced from the full file.
+        let mut data = Bytes::from(data);
+        if let Some((start, end)) = range {
+            let (start, end) = clamp_range(id, start, end, data.len() as u64)?;
+            data = data.slice(start as usize..=end as usize);
+        }
+
+        Ok(BoxedFileReader::new(BytesFileReader::new(
+            data, None, file_age, expires, summary,
+        )))
+    }
 }
 
 struct StreamWrapper {
@@ -130,10 +345,7 @@ where
             std::io::copy(&mut bridge, stream)?;
             Ok(())
         } else {
-            Err(std::io::Error::new(
-                std::io::ErrorKind::Other,
-                "Source already read to end",
-            ))
+            Err(std::io::Error::other("Source already read to end"))
         }
     }
 }
@@ -170,4 +382,6 @@ impl TryCreateFromConfig for MemcacheBackend {
 pub enum MemcacheBackendConstructionError {
     #[error("Failed to create pool")]
     FailedToCreatePool(r2d2::Error),
+    #[error("No servers are configured for backend {0}")]
+    NoServers(String),
 }
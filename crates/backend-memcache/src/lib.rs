@@ -4,5 +4,7 @@
 
 mod backend;
 mod connection_string;
+mod striped;
 
 pub use backend::{MemcacheBackend, MemcacheBackendConstructionError};
+pub use striped::{StripedMemcacheBackend, StripedMemcacheError};
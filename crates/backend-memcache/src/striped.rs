@@ -0,0 +1,435 @@
+use crate::backend::{add, create_pool, expiration, expiration_secs, server_stats, PoolOptions};
+use crate::MemcacheBackendConstructionError;
+use app_config::{memcache::StripedMemcacheBackendConfig, AppConfig};
+use async_trait::async_trait;
+use backend_traits::{clamp_range, Backend, DeleteFileError, DistributeFile, DistributionError};
+use backend_traits::{
+    BackendExpiration, BackendInfo, ReceiveFile, ReceiveFileError, TryCreateFromConfig,
+};
+use backend_traits::{BackendStats, BackendStatsError};
+use bytes::{Bytes, BytesMut};
+use file_distribution::protobuf::{ChunkPlacement, ItemMetadata};
+use file_distribution::{BoxedFileReader, BytesFileReader, FileProvider, GetFile, WriteSummary};
+use r2d2::Pool;
+use r2d2_memcache::memcache::MemcacheError;
+use r2d2_memcache::MemcacheConnectionManager;
+use shortguid::ShortGuid;
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use tokio::task::spawn_blocking;
+use tracing::trace;
+
+/// A backend striping files across several Memcached servers.
+///
+/// Files are split into chunks that are distributed round-robin across the servers.
+/// The placement of the chunks is recorded in the file metadata, which is stored on
+/// every server so that the file can be reassembled as long as its chunks are available.
+pub struct StripedMemcacheBackend {
+    /// The tag identifying the backend.
+    tag: String,
+    /// The servers, identified by their index in the configuration.
+    nodes: Vec<Node>,
+    /// The size of each chunk in bytes.
+    chunk_size: usize,
+    /// The expiration time for stored entries.
+    expiration: BackendExpiration,
+}
+
+struct Node {
+    /// The connection pool.
+    pool: Pool<MemcacheConnectionManager>,
+}
+
+impl StripedMemcacheBackend {
+    pub fn try_new(
+        config: &StripedMemcacheBackendConfig,
+    ) -> Result<Self, MemcacheBackendConstructionError> {
+        if config.connection_strings.is_empty() {
+            return Err(MemcacheBackendConstructionError::NoServers(
+                config.tag.clone(),
+            ));
+        }
+
+        let nodes = config
+            .connection_strings
+            .iter()
+            .map(|connection_string| {
+                Ok(Node {
+                    pool: create_pool(connection_string, &PoolOptions::default())?,
+                })
+            })
+            .collect::<Result<Vec<_>, MemcacheBackendConstructionError>>()?;
+
+        Ok(Self {
+            tag: config.tag.clone(),
+            nodes,
+            chunk_size: config.chunk_size(),
+            expiration: expiration(
+                config.expiration_sec,
+                config.lease_expiration.lease_offset(),
+            ),
+        })
+    }
+
+    /// Stores a value on the specified node, expiring after `expiration` seconds.
+    async fn set(
+        &self,
+        node: &Node,
+        key: String,
+        value: Bytes,
+        expiration: u32,
+    ) -> Result<(), DistributionError> {
+        let pool = node.pool.clone();
+        let result: Result<(), StripedMemcacheError> = spawn_blocking(move || {
+            let client = pool.get()?;
+            client.set(&key, value.as_ref(), expiration)?;
+            trace!(
+                "Stored {len} bytes under key {key} with expiration {expiration}",
+                len = value.len()
+            );
+            Ok(())
+        })
+        .await?;
+
+        result.map_err(|e| DistributionError::BackendSpecific(Box::new(e)))
+    }
+
+    /// Gets a value from the specified node.
+    async fn get(&self, node: &Node, key: String) -> Result<Option<Vec<u8>>, ReceiveFileError> {
+        let pool = node.pool.clone();
+        let result: Result<Option<Vec<u8>>, StripedMemcacheError> = spawn_blocking(move || {
+            let client = pool.get()?;
+            Ok(client.get(&key)?)
+        })
+        .await?;
+
+        result.map_err(|e| ReceiveFileError::BackendSpecific(Box::new(e)))
+    }
+
+    /// Deletes a value from the specified node. Returns `false` if the key was unknown.
+    async fn delete(&self, node: &Node, key: String) -> Result<bool, DeleteFileError> {
+        let pool = node.pool.clone();
+        let result: Result<bool, StripedMemcacheError> = spawn_blocking(move || {
+            let client = pool.get()?;
+            Ok(client.delete(&key)?)
+        })
+        .await?;
+
+        result.map_err(|e| DeleteFileError::BackendSpecific(Box::new(e)))
+    }
+
+    /// Gets the file metadata from the first node holding it.
+    async fn get_metadata(&self, id: ShortGuid) -> Result<ItemMetadata, ReceiveFileError> {
+        for (index, node) in self.nodes.iter().enumerate() {
+            match self.get(node, format!("meta-{id}")).await {
+                Ok(Some(metadata)) => {
+                    return ItemMetadata::deserialize_from_proto(&metadata)
+                        .map_err(|e| ReceiveFileError::BackendSpecific(Box::new(e)));
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    trace!(file_id = %id, "Failed to obtain metadata from server {index}: {e}");
+                }
+            }
+        }
+
+        Err(ReceiveFileError::UnknownFile(id))
+    }
+
+    /// Gets the node holding a chunk.
+    fn node(&self, chunk: &ChunkPlacement) -> Option<&Node> {
+        self.nodes.get(chunk.node_index as usize)
+    }
+}
+
+/// Gets the index of the node a chunk is placed on, distributing the chunks round-robin.
+fn node_index(chunk_index: usize, node_count: usize) -> u32 {
+    (chunk_index % node_count) as u32
+}
+
+/// The part of a chunk covered by a byte range of the file.
+#[derive(Debug, PartialEq, Eq)]
+struct ChunkSlice {
+    /// The index of the chunk.
+    index: usize,
+    /// The start of the slice within the chunk.
+    from: usize,
+    /// The exclusive end of the slice within the chunk.
+    to: usize,
+}
+
+/// Determines the chunks covering the inclusive byte range and the parts of them to read.
+fn chunk_slices(chunks: &[ChunkPlacement], start: u64, end: u64) -> Vec<ChunkSlice> {
+    let mut slices = Vec::new();
+    let mut offset = 0;
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk_start = offset;
+        offset += chunk.size;
+        if offset <= start || chunk_start > end {
+            continue;
+        }
+
+        // Trim the chunks at the edges of the range.
+        slices.push(ChunkSlice {
+            index,
+            from: start.saturating_sub(chunk_start) as usize,
+            to: ((end + 1).min(offset) - chunk_start) as usize,
+        });
+    }
+    slices
+}
+
+/// Reads up to `chunk_size` bytes, returning fewer bytes only at the end of the file.
+async fn read_chunk<R>(reader: &mut R, chunk_size: usize) -> std::io::Result<Bytes>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut chunk = BytesMut::with_capacity(chunk_size);
+    while chunk.len() < chunk_size {
+        let mut limited = (&mut *reader).take((chunk_size - chunk.len()) as u64);
+        if limited.read_buf(&mut chunk).await? == 0 {
+            break;
+        }
+    }
+    Ok(chunk.freeze())
+}
+
+#[async_trait]
+impl DistributeFile for StripedMemcacheBackend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<(), DistributionError> {
+        let expiration = expiration_secs(self.expiration.lifetime(&summary));
+        let mut file = file_provider.get_file(id).await?;
+
+        let mut placement = Vec::new();
+        loop {
+            let chunk = read_chunk(&mut file, self.chunk_size).await?;
+            if chunk.is_empty() {
+                break;
+            }
+
+            let index = placement.len();
+            let node_index = node_index(index, self.nodes.len());
+            let node = &self.nodes[node_index as usize];
+            let size = chunk.len() as u64;
+            self.set(node, format!("data-{id}-{index}"), chunk, expiration)
+                .await?;
+            placement.push(ChunkPlacement { node_index, size });
+        }
+
+        let mut metadata = self.expiration.item_metadata(id, &summary);
+        metadata.chunks = placement;
+        let metadata = metadata
+            .serialize_to_proto()
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        for node in &self.nodes {
+            self.set(node, format!("meta-{id}"), metadata.clone(), expiration)
+                .await?;
+        }
+
+        trace!(file_id = %id, "Striped file {id} across {count} servers", count = self.nodes.len());
+        Ok(())
+    }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DeleteFileError> {
+        let metadata = match self.get_metadata(id).await {
+            Ok(metadata) => metadata,
+            Err(ReceiveFileError::UnknownFile(id)) => return Err(DeleteFileError::UnknownFile(id)),
+            Err(e) => return Err(DeleteFileError::BackendSpecific(Box::new(e))),
+        };
+
+        // Chunks on servers that are no longer configured are left to expire.
+        for (index, chunk) in metadata.chunks.iter().enumerate() {
+            if let Some(node) = self.node(chunk) {
+                self.delete(node, format!("data-{id}-{index}")).await?;
+            }
+        }
+
+        for node in &self.nodes {
+            self.delete(node, format!("meta-{id}")).await?;
+        }
+
+        trace!(file_id = %id, "Deleted striped file {id}");
+        Ok(())
+    }
+
+    /// Reports the statistics of all servers, including items not stored by this service.
+    async fn stats(&self) -> Result<Option<BackendStats>, BackendStatsError> {
+        let mut stats = BackendStats::default();
+        for node in &self.nodes {
+            let node_stats = server_stats(node.pool.clone()).await?;
+            stats.items = add(stats.items, node_stats.items);
+            stats.bytes_used = add(stats.bytes_used, node_stats.bytes_used);
+            stats.bytes_limit = add(stats.bytes_limit, node_stats.bytes_limit);
+        }
+        Ok(Some(stats))
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for StripedMemcacheBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<BoxedFileReader, ReceiveFileError> {
+        self.receive(id, None).await
+    }
+
+    async fn receive_range(
+        &self,
+        id: ShortGuid,
+        start: u64,
+        end: u64,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        self.receive(id, Some((start, end))).await
+    }
+}
+
+impl StripedMemcacheBackend {
+    /// Receives the file, or the inclusive byte range of it if specified.
+    /// For ranges, only the chunks covering the range are fetched.
+    async fn receive(
+        &self,
+        id: ShortGuid,
+        range: Option<(u64, u64)>,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        let metadata = self.get_metadata(id).await?;
+
+        let file_size = metadata.chunks.iter().map(|chunk| chunk.size).sum::<u64>();
+        let (start, end) = match range {
+            Some((start, end)) => clamp_range(id, start, end, file_size)?,
+            None => (0, file_size.saturating_sub(1)),
+        };
+
+        let mut data = BytesMut::with_capacity((end + 1 - start) as usize);
+        for ChunkSlice { index, from, to } in chunk_slices(&metadata.chunks, start, end) {
+            let chunk = &metadata.chunks[index];
+            let node = self.node(chunk).ok_or_else(|| {
+                ReceiveFileError::BackendSpecific(Box::new(StripedMemcacheError::UnknownNode(
+                    chunk.node_index,
+                )))
+            })?;
+
+            // A missing chunk means the file was (partially) evicted.
+            let bytes = self
+                .get(node, format!("data-{id}-{index}"))
+                .await?
+                .ok_or(ReceiveFileError::UnknownFile(id))?;
+            if bytes.len() as u64 != chunk.size {
+                return Err(ReceiveFileError::BackendSpecific(Box::new(
+                    StripedMemcacheError::InvalidChunkSize(index, chunk.size, bytes.len()),
+                )));
+            }
+
+            data.extend_from_slice(&bytes[from..to]);
+        }
+
+        // Memcached doesn't report the remaining lifetime of an entry, so we
+        // assume the item to be valid for no longer than the configured expiration
+        // after the file was originally created, unless its expiration was recorded.
+        let expires = metadata.expires_after(self.expiration.fixed());
+        let file_age = metadata.file_age().unwrap_or_default();
+        let summary = metadata
+            .to_write_summary(expires, file_size as usize)
+            .map(Arc::new);
+
+        Ok(BoxedFileReader::new(BytesFileReader::new(
+            data.freeze(),
+            None,
+            file_age,
+            expires,
+            summary,
+        )))
+    }
+}
+
+impl BackendInfo for StripedMemcacheBackend {
+    fn backend_name() -> &'static str {
+        "Striped Memcached"
+    }
+
+    fn backend_version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl TryCreateFromConfig for StripedMemcacheBackend {
+    type Error = MemcacheBackendConstructionError;
+
+    fn try_from_config(config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+        config
+            .backends
+            .memcache_striped
+            .iter()
+            .map(|config| StripedMemcacheBackend::try_new(config).map(Backend::wrap))
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum StripedMemcacheError {
+    #[error("A chunk is placed on server {0}, which is not configured")]
+    UnknownNode(u32),
+    #[error("Chunk {0} has an invalid size: expected {1} bytes, got {2}")]
+    InvalidChunkSize(usize, u64, usize),
+    #[error("Failed to obtain a connection: {0}")]
+    Pool(#[from] r2d2::Error),
+    #[error(transparent)]
+    Memcache(#[from] MemcacheError),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Places a file of the given size in chunks, as done when distributing it.
+    fn place(file_size: u64, chunk_size: u64, node_count: usize) -> Vec<ChunkPlacement> {
+        (0..file_size.div_ceil(chunk_size))
+            .map(|index| ChunkPlacement {
+                node_index: node_index(index as usize, node_count),
+                size: chunk_size.min(file_size - index * chunk_size),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn chunks_are_placed_round_robin_without_naming_the_servers() {
+        let chunks = place(25, 10, 2);
+        let nodes: Vec<_> = chunks.iter().map(|chunk| chunk.node_index).collect();
+        assert_eq!(nodes, [0, 1, 0]);
+        let sizes: Vec<_> = chunks.iter().map(|chunk| chunk.size).collect();
+        assert_eq!(sizes, [10, 10, 5]);
+
+        let metadata = ItemMetadata {
+            chunks,
+            ..Default::default()
+        };
+        let bytes = metadata.serialize_to_proto().unwrap();
+        let metadata = ItemMetadata::deserialize_from_proto(&bytes).unwrap();
+        assert_eq!(metadata.chunks.len(), 3);
+        assert_eq!(metadata.chunks[1].node_index, 1);
+    }
+
+    #[test]
+    fn ranges_spanning_several_chunks_are_trimmed_at_the_edges() {
+        let chunks = place(25, 10, 2);
+        let slice = |index, from, to| ChunkSlice { index, from, to };
+
+        assert_eq!(
+            chunk_slices(&chunks, 0, 24),
+            [slice(0, 0, 10), slice(1, 0, 10), slice(2, 0, 5)]
+        );
+        assert_eq!(
+            chunk_slices(&chunks, 5, 21),
+            [slice(0, 5, 10), slice(1, 0, 10), slice(2, 0, 2)]
+        );
+        assert_eq!(chunk_slices(&chunks, 10, 19), [slice(1, 0, 10)]);
+        assert_eq!(chunk_slices(&chunks, 24, 24), [slice(2, 4, 5)]);
+    }
+}
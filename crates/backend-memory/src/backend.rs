@@ -0,0 +1,305 @@
+use app_config::{memory::MemoryBackendConfig, AppConfig};
+use async_trait::async_trait;
+use backend_traits::{clamp_range, Backend, DeleteFileError, DistributeFile, DistributionError};
+use backend_traits::{BackendExpiration, BackendInfo, ReceiveFile, ReceiveFileError};
+use backend_traits::{BackendStats, BackendStatsError, TryCreateFromConfig};
+use bytes::Bytes;
+use file_distribution::hash::Sha256Digest;
+use file_distribution::protobuf::ItemMetadata;
+use file_distribution::{
+    BoxedFileReader, BytesFileReader, FileProvider, FileReaderTrait, GetFile, WriteSummary,
+};
+use lru::LruCache;
+use shortguid::ShortGuid;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::AsyncReadExt;
+use tokio::time::Instant;
+use tracing::trace;
+
+/// A backend keeping files in process memory.
+///
+/// Entries are evicted in least recently used order when either the configured number
+/// of entries or the total number of bytes is exceeded, and are dropped after they expire.
+pub struct MemoryBackend {
+    /// The tag identifying the backend.
+    tag: String,
+    /// The maximum number of bytes of file contents to keep.
+    max_total_bytes: u64,
+    /// The maximum number of files to keep.
+    max_entries: usize,
+    /// The expiration time for stored entries.
+    expiration: BackendExpiration,
+    /// The stored files.
+    store: Mutex<Store>,
+}
+
+/// A file held by the [`MemoryBackend`].
+#[derive(Debug, Clone)]
+pub struct StoredFile {
+    /// The file contents.
+    pub data: Bytes,
+    /// The file metadata.
+    pub metadata: ItemMetadata,
+    /// The optional content type.
+    pub content_type: Option<String>,
+}
+
+struct Store {
+    entries: LruCache<ShortGuid, Entry>,
+    total_bytes: u64,
+}
+
+struct Entry {
+    file: StoredFile,
+    created: Instant,
+    expires: Instant,
+}
+
+impl MemoryBackend {
+    pub fn new(config: &MemoryBackendConfig) -> Self {
+        Self {
+            tag: config.tag.clone(),
+            max_total_bytes: config.max_total_bytes(),
+            max_entries: config.max_entries(),
+            expiration: BackendExpiration::new(
+                config.expiration(),
+                config.lease_expiration.lease_offset(),
+            ),
+            store: Mutex::new(Store {
+                entries: LruCache::unbounded(),
+                total_bytes: 0,
+            }),
+        }
+    }
+
+    /// Gets a stored file, or `None` if the file is unknown, was evicted or has expired.
+    pub fn get(&self, id: &ShortGuid) -> Option<StoredFile> {
+        self.get_entry(id).map(|(file, _, _)| file)
+    }
+
+    /// Gets a stored file along with its creation and expiration instants.
+    fn get_entry(&self, id: &ShortGuid) -> Option<(StoredFile, Instant, Instant)> {
+        let mut store = self.store.lock().expect("failed to lock memory backend");
+        match store.entries.get(id) {
+            Some(entry) if entry.expires > Instant::now() => {
+                Some((entry.file.clone(), entry.created, entry.expires))
+            }
+            Some(_) => {
+                store.remove(id);
+                None
+            }
+            None => None,
+        }
+    }
+
+    /// Stores a file for the specified `lifetime`, evicting the least recently used files if needed.
+    fn insert(&self, id: ShortGuid, file: StoredFile, lifetime: Duration) {
+        let size = file.data.len() as u64;
+        let mut store = self.store.lock().expect("failed to lock memory backend");
+        store.remove(&id);
+        store.remove_expired();
+
+        while store.entries.len() >= self.max_entries
+            || store.total_bytes + size > self.max_total_bytes
+        {
+            match store.entries.pop_lru() {
+                Some((evicted, entry)) => {
+                    store.total_bytes -= entry.file.data.len() as u64;
+                    trace!(file_id = %evicted, "Evicted file {evicted} from memory");
+                }
+                None => break,
+            }
+        }
+
+        store.total_bytes += size;
+        let now = Instant::now();
+        store.entries.put(
+            id,
+            Entry {
+                file,
+                created: now,
+                expires: now + lifetime,
+            },
+        );
+    }
+}
+
+impl Store {
+    fn remove(&mut self, id: &ShortGuid) {
+        if let Some(entry) = self.entries.pop(id) {
+            self.total_bytes -= entry.file.data.len() as u64;
+        }
+    }
+
+    fn remove_expired(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<ShortGuid> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expires <= now)
+            .map(|(id, _)| *id)
+            .collect();
+        for id in expired {
+            self.remove(&id);
+        }
+    }
+}
+
+#[async_trait]
+impl DistributeFile for MemoryBackend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<(), DistributionError> {
+        let size = summary.file_size_bytes as u64;
+        if self.max_entries == 0 || size > self.max_total_bytes {
+            return Err(DistributionError::BackendRejected(format!(
+                "The file size of {size} bytes exceeds the capacity of {max} bytes",
+                max = self.max_total_bytes
+            )));
+        }
+
+        let mut file = file_provider.get_file(id).await?;
+        let content_type = file.content_type().map(|c| c.to_string());
+        let mut data = Vec::with_capacity(summary.file_size_bytes);
+        file.read_to_end(&mut data).await?;
+
+        let metadata = self.expiration.item_metadata(id, &summary);
+        self.insert(
+            id,
+            StoredFile {
+                data: Bytes::from(data),
+                metadata,
+                content_type,
+            },
+            self.expiration.lifetime(&summary),
+        );
+
+        trace!(file_id = %id, "Stored {size} bytes in memory");
+        Ok(())
+    }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DeleteFileError> {
+        let mut store = self.store.lock().expect("failed to lock memory backend");
+        if !store.entries.contains(&id) {
+            return Err(DeleteFileError::UnknownFile(id));
+        }
+
+        store.remove(&id);
+        trace!(file_id = %id, "Deleted file {id} from memory");
+        Ok(())
+    }
+
+    async fn stats(&self) -> Result<Option<BackendStats>, BackendStatsError> {
+        let mut store = self.store.lock().expect("failed to lock memory backend");
+        store.remove_expired();
+        Ok(Some(BackendStats {
+            items: Some(store.entries.len() as u64),
+            bytes_used: Some(store.total_bytes),
+            bytes_limit: Some(self.max_total_bytes),
+        }))
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for MemoryBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<BoxedFileReader, ReceiveFileError> {
+        let (file, created, expires) = self
+            .get_entry(&id)
+            .ok_or(ReceiveFileError::UnknownFile(id))?;
+
+        let summary = file
+            .metadata
+            .to_write_summary(expires, file.data.len())
+            .map(Arc::new);
+        // Files distributed to this backend may be older than the entry itself.
+        let file_age = file
+            .metadata
+            .file_age()
+            .unwrap_or_else(|| created.elapsed());
+        Ok(BoxedFileReader::new(BytesFileReader::new(
+            file.data,
+            file.content_type,
+            file_age,
+            expires,
+            summary,
+        )))
+    }
+
+    async fn receive_range(
+        &self,
+        id: ShortGuid,
+        start: u64,
+        end: u64,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        let (file, created, expires) = self
+            .get_entry(&id)
+            .ok_or(ReceiveFileError::UnknownFile(id))?;
+        let (start, end) = clamp_range(id, start, end, file.data.len() as u64)?;
+
+        let summary = file
+            .metadata
+            .to_write_summary(expires, file.data.len())
+            .map(Arc::new);
+        let file_age = file
+            .metadata
+            .file_age()
+            .unwrap_or_else(|| created.elapsed());
+        Ok(BoxedFileReader::new(BytesFileReader::new(
+            file.data.slice(start as usize..=end as usize),
+            file.content_type,
+            file_age,
+            expires,
+            summary,
+        )))
+    }
+
+    async fn find_by_sha256(&self, sha256: &Sha256Digest) -> Option<ShortGuid> {
+        let store = self.store.lock().expect("failed to lock memory backend");
+        let now = Instant::now();
+        store
+            .entries
+            .iter()
+            .find(|(_, entry)| {
+                entry.expires > now
+                    && entry
+                        .file
+                        .metadata
+                        .hashes
+                        .as_ref()
+                        .is_some_and(|hashes| hashes.sha256 == sha256.as_slice())
+            })
+            .map(|(id, _)| *id)
+    }
+}
+
+impl BackendInfo for MemoryBackend {
+    fn backend_name() -> &'static str {
+        "In-Memory"
+    }
+
+    fn backend_version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl TryCreateFromConfig for MemoryBackend {
+    type Error = Infallible;
+
+    fn try_from_config(config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+        Ok(config
+            .backends
+            .memory
+            .iter()
+            .map(|config| Backend::wrap(MemoryBackend::new(config)))
+            .collect())
+    }
+}
@@ -0,0 +1,501 @@
+use crate::file_reader::SftpFileReader;
+use app_config::{sftp::SftpBackendConfig, AppConfig};
+use async_trait::async_trait;
+use backend_traits::{clamp_range, Backend, DeleteFileError, DistributeFile, DistributionError};
+use backend_traits::{
+    BackendExpiration, BackendInfo, ReceiveFile, ReceiveFileError, TryCreateFromConfig,
+};
+use bytes::{Bytes, BytesMut};
+use file_distribution::protobuf::ItemMetadata;
+use file_distribution::{BoxedFileReader, FileProvider, GetFile, WriteSummary};
+use futures::StreamExt;
+use shortguid::ShortGuid;
+use ssh2::{CheckResult, ErrorCode, KnownHostFileKind, Session, Sftp};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::TcpStream;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::task::spawn_blocking;
+use tokio::time::Instant;
+use tokio_util::io::SyncIoBridge;
+use tracing::{trace, warn};
+
+/// The number of bytes read from the remote file at a time.
+const READ_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The number of chunks read ahead of the consumer.
+const READ_AHEAD_CHUNKS: usize = 4;
+
+/// The SFTP status code of missing files (`SSH_FX_NO_SUCH_FILE`).
+const SSH_FX_NO_SUCH_FILE: i32 = 2;
+
+/// A backend storing files on an SFTP server.
+///
+/// Each file is stored under its ID in the configured remote directory, along with a
+/// `.meta` sidecar file holding its metadata. Since the SSH library is blocking, all
+/// transfers run on the blocking thread pool, using a new connection for each transfer.
+pub struct SftpBackend {
+    /// The tag identifying the backend.
+    tag: String,
+    /// The connection settings.
+    connection: Arc<Connection>,
+    /// The remote directory to store the files in.
+    remote_path: PathBuf,
+    /// The expiration time for stored files.
+    expiration: BackendExpiration,
+}
+
+/// The settings for connecting to the SSH server.
+struct Connection {
+    host: String,
+    port: u16,
+    user: String,
+    key_path: PathBuf,
+    key_passphrase: Option<String>,
+    host_key_policy: HostKeyPolicy,
+}
+
+/// How the host key of the server is checked.
+enum HostKeyPolicy {
+    /// The host key must be listed in the `known_hosts` file.
+    Verify(PathBuf),
+    /// Any host key is accepted.
+    AcceptAny,
+}
+
+/// The remote paths of a stored file.
+struct RemotePaths {
+    /// The file contents.
+    data: PathBuf,
+    /// The sidecar file holding the serialized [`ItemMetadata`].
+    metadata: PathBuf,
+    /// The name the contents are uploaded to before being renamed to `data`.
+    partial: PathBuf,
+}
+
+impl SftpBackend {
+    pub fn try_new(config: &SftpBackendConfig) -> Result<Self, SftpBackendConstructionError> {
+        if !config.key_path.is_file() {
+            return Err(SftpBackendConstructionError::KeyNotFound(
+                config.key_path.clone(),
+            ));
+        }
+
+        let host_key_policy = if config.insecure_accept_any_host_key {
+            warn!(
+                "The SFTP backend {tag} accepts any host key; files may be sent to an impostor of {host}",
+                tag = config.tag,
+                host = config.host
+            );
+            HostKeyPolicy::AcceptAny
+        } else {
+            match config.known_hosts_file() {
+                Some(path) if path.is_file() => HostKeyPolicy::Verify(path),
+                path => return Err(SftpBackendConstructionError::KnownHostsNotFound(path)),
+            }
+        };
+
+        Ok(Self {
+            tag: config.tag.clone(),
+            connection: Arc::new(Connection {
+                host: config.host.clone(),
+                port: config.port(),
+                user: config.user.clone(),
+                key_path: config.key_path.clone(),
+                key_passphrase: config.key_passphrase.clone(),
+                host_key_policy,
+            }),
+            remote_path: PathBuf::from(&config.remote_path),
+            expiration: BackendExpiration::new(
+                config.expiration(),
+                config.lease_expiration.lease_offset(),
+            ),
+        })
+    }
+
+    fn paths(&self, id: ShortGuid) -> RemotePaths {
+        RemotePaths {
+            data: self.remote_path.join(id.to_string()),
+            metadata: self.remote_path.join(format!("{id}.meta")),
+            partial: self.remote_path.join(format!("{id}.part")),
+        }
+    }
+}
+
+impl Connection {
+    /// Connects and authenticates to the server and starts an SFTP session.
+    fn open(&self) -> Result<Sftp, SftpError> {
+        let tcp =
+            TcpStream::connect((self.host.as_str(), self.port)).map_err(SftpError::Connect)?;
+        let mut session = Session::new()?;
+        session.set_tcp_stream(tcp);
+        session.handshake()?;
+
+        if let HostKeyPolicy::Verify(known_hosts_file) = &self.host_key_policy {
+            let (key, _) = session
+                .host_key()
+                .ok_or_else(|| SftpError::UnknownHostKey(self.host.clone()))?;
+            self.verify_host_key(&session, known_hosts_file, key)?;
+        }
+
+        session.userauth_pubkey_file(
+            &self.user,
+            None,
+            &self.key_path,
+            self.key_passphrase.as_deref(),
+        )?;
+        Ok(session.sftp()?)
+    }
+
+    /// Verifies the host key of the server against the `known_hosts` file.
+    fn verify_host_key(
+        &self,
+        session: &Session,
+        known_hosts_file: &Path,
+        key: &[u8],
+    ) -> Result<(), SftpError> {
+        let mut known_hosts = session.known_hosts()?;
+        known_hosts.read_file(known_hosts_file, KnownHostFileKind::OpenSSH)?;
+
+        match known_hosts.check_port(&self.host, self.port, key) {
+            CheckResult::Match => Ok(()),
+            CheckResult::Mismatch => Err(SftpError::HostKeyMismatch(self.host.clone())),
+            CheckResult::NotFound | CheckResult::Failure => {
+                Err(SftpError::UnknownHostKey(self.host.clone()))
+            }
+        }
+    }
+}
+
+/// Indicates whether the SFTP operation failed because the file doesn't exist.
+fn is_not_found(error: &ssh2::Error) -> bool {
+    matches!(error.code(), ErrorCode::SFTP(SSH_FX_NO_SUCH_FILE))
+}
+
+#[async_trait]
+impl DistributeFile for SftpBackend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<(), DistributionError> {
+        let file = file_provider.get_file(id).await?;
+        let metadata = self
+            .expiration
+            .item_metadata(id, &summary)
+            .serialize_to_proto()
+            .map_err(|e| DistributionError::BackendSpecific(Box::new(e)))?;
+
+        let connection = self.connection.clone();
+        let paths = self.paths(id);
+        let result: Result<(), SftpError> = spawn_blocking(move || {
+            let sftp = connection.open()?;
+
+            // The contents are uploaded under a temporary name first so that
+            // incomplete uploads are never served.
+            let mut reader = SyncIoBridge::new(file);
+            let mut remote = sftp.create(&paths.partial)?;
+            std::io::copy(&mut reader, &mut remote)?;
+            drop(remote);
+
+            sftp.create(&paths.metadata)?.write_all(&metadata)?;
+            sftp.rename(&paths.partial, &paths.data, None)?;
+            Ok(())
+        })
+        .await?;
+
+        match result {
+            Ok(()) => {
+                trace!(file_id = %id, "Stored file {id} on SFTP server {host}", host = self.connection.host);
+                Ok(())
+            }
+            Err(e) => Err(DistributionError::BackendSpecific(Box::new(e))),
+        }
+    }
+
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DeleteFileError> {
+        let connection = self.connection.clone();
+        let paths = self.paths(id);
+        let result: Result<bool, SftpError> = spawn_blocking(move || {
+            let sftp = connection.open()?;
+            match sftp.unlink(&paths.data) {
+                Ok(()) => {}
+                Err(e) if is_not_found(&e) => return Ok(false),
+                Err(e) => return Err(e.into()),
+            }
+
+            // The file is gone either way, so a missing sidecar is of no concern.
+            let _ = sftp.unlink(&paths.metadata);
+            Ok(true)
+        })
+        .await?;
+
+        match result {
+            Ok(true) => {
+                trace!(file_id = %id, "Deleted file {id} from SFTP server {host}", host = self.connection.host);
+                Ok(())
+            }
+            Ok(false) => Err(DeleteFileError::UnknownFile(id)),
+            Err(e) => Err(DeleteFileError::BackendSpecific(Box::new(e))),
+        }
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for SftpBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<BoxedFileReader, ReceiveFileError> {
+        self.receive(id, None).await
+    }
+
+    async fn receive_range(
+        &self,
+        id: ShortGuid,
+        start: u64,
+        end: u64,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        self.receive(id, Some((start, end))).await
+    }
+}
+
+impl SftpBackend {
+    /// Streams the file, or the inclusive byte range of it if specified, from the server.
+    async fn receive(
+        &self,
+        id: ShortGuid,
+        range: Option<(u64, u64)>,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        let connection = self.connection.clone();
+        let paths = self.paths(id);
+        let result = spawn_blocking(move || -> Result<_, SftpError> {
+            let sftp = connection.open()?;
+            let mut file = match sftp.open(&paths.data) {
+                Ok(file) => file,
+                Err(e) if is_not_found(&e) => return Ok(None),
+                Err(e) => return Err(e.into()),
+            };
+
+            let file_size = file.stat()?.size.unwrap_or_default();
+            let mut metadata = Vec::new();
+            let metadata = match sftp.open(&paths.metadata) {
+                Ok(mut sidecar) => {
+                    sidecar.read_to_end(&mut metadata)?;
+                    ItemMetadata::deserialize_from_proto(&metadata).ok()
+                }
+                Err(_) => None,
+            };
+
+            Ok(Some((sftp, file, file_size, metadata)))
+        })
+        .await?;
+
+        let (sftp, mut file, file_size, metadata) = match result {
+            Ok(Some(opened)) => opened,
+            Ok(None) => return Err(ReceiveFileError::UnknownFile(id)),
+            Err(e) => return Err(ReceiveFileError::BackendSpecific(Box::new(e))),
+        };
+
+        // The server doesn't expire files, so the expiration is derived from the
+        // recorded expiration or the original creation time of the file.
+        let lifetime = self.expiration.fixed();
+        let expires = metadata.as_ref().map_or_else(
+            || Instant::now() + lifetime,
+            |metadata| metadata.expires_after(lifetime),
+        );
+        if expires <= Instant::now() {
+            return Err(ReceiveFileError::FileExpired(id));
+        }
+
+        let file_age = metadata
+            .as_ref()
+            .and_then(ItemMetadata::file_age)
+            .unwrap_or_default();
+        let summary = metadata
+            .and_then(|metadata| metadata.to_write_summary(expires, file_size as usize))
+            .map(Arc::new);
+
+        let (start, end) = match range {
+            Some((start, end)) => clamp_range(id, start, end, file_size)?,
+            None => (0, file_size.saturating_sub(1)),
+        };
+        let length = if file_size == 0 { 0 } else { end + 1 - start };
+
+        // The file is read on the blocking thread pool while the chunks are consumed.
+        let (sender, mut receiver) = mpsc::channel(READ_AHEAD_CHUNKS);
+        spawn_blocking(move || {
+            let _sftp = sftp;
+            if let Err(e) = file.seek(SeekFrom::Start(start)) {
+                let _ = sender.blocking_send(Err(e));
+                return;
+            }
+
+            let mut remaining = length;
+            while remaining > 0 {
+                let mut chunk = BytesMut::zeroed(READ_CHUNK_SIZE.min(remaining as usize));
+                let result = match file.read(&mut chunk) {
+                    Ok(0) => Err(std::io::ErrorKind::UnexpectedEof.into()),
+                    Ok(read) => {
+                        chunk.truncate(read);
+                        remaining -= read as u64;
+                        Ok(Bytes::from(chunk))
+                    }
+                    Err(e) => Err(e),
+                };
+
+                let failed = result.is_err();
+                if sender.blocking_send(result).is_err() || failed {
+                    break;
+                }
+            }
+        });
+
+        let stream = futures::stream::poll_fn(move |cx| receiver.poll_recv(cx)).boxed();
+
+        trace!(file_id = %id, "Streaming {length} bytes from SFTP server {host}", host = self.connection.host);
+        Ok(BoxedFileReader::new(SftpFileReader::new(
+            stream,
+            None,
+            length as usize,
+            file_age,
+            expires,
+            summary,
+        )))
+    }
+}
+
+impl BackendInfo for SftpBackend {
+    fn backend_name() -> &'static str {
+        "SFTP"
+    }
+
+    fn backend_version() -> &'static str {
+        env!("CARGO_PKG_VERSION")
+    }
+}
+
+impl TryCreateFromConfig for SftpBackend {
+    type Error = SftpBackendConstructionError;
+
+    fn try_from_config(config: &AppConfig) -> Result<Vec<Backend>, Self::Error> {
+        config
+            .backends
+            .sftp
+            .iter()
+            .map(|config| SftpBackend::try_new(config).map(Backend::wrap))
+            .collect()
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SftpBackendConstructionError {
+    #[error("The private key {0} does not exist")]
+    KeyNotFound(PathBuf),
+    #[error("The known hosts file {0:?} to verify the host key against does not exist")]
+    KnownHostsNotFound(Option<PathBuf>),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum SftpError {
+    #[error("Failed to connect to the SSH server: {0}")]
+    Connect(std::io::Error),
+    #[error("The host key of {0} does not match the known hosts")]
+    HostKeyMismatch(String),
+    #[error("The host key of {0} is not known")]
+    UnknownHostKey(String),
+    #[error(transparent)]
+    Ssh(#[from] ssh2::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use app_config::sftp::DEFAULT_PORT;
+
+    /// Encodes a fake Ed25519 public key in the SSH wire format.
+    fn host_key(fill: u8) -> Vec<u8> {
+        let mut key = Vec::new();
+        for part in [&b"ssh-ed25519"[..], &[fill; 32]] {
+            key.extend_from_slice(&(part.len() as u32).to_be_bytes());
+            key.extend_from_slice(part);
+        }
+        key
+    }
+
+    fn connection(known_hosts_file: &Path) -> Connection {
+        Connection {
+            host: String::from("archive.example.com"),
+            port: DEFAULT_PORT,
+            user: String::from("yeet"),
+            key_path: PathBuf::from("id_ed25519"),
+            key_passphrase: None,
+            host_key_policy: HostKeyPolicy::Verify(known_hosts_file.to_path_buf()),
+        }
+    }
+
+    #[test]
+    fn host_keys_are_verified_against_the_known_hosts() {
+        let dir = tempfile::tempdir().expect("failed to create a temporary directory");
+        let known_hosts_file = dir.path().join("known_hosts");
+        let session = Session::new().expect("failed to create a session");
+        let mut known_hosts = session.known_hosts().expect("failed to read known hosts");
+        known_hosts
+            .add(
+                "archive.example.com",
+                &host_key(1),
+                "",
+                ssh2::KnownHostKeyFormat::Ed25519,
+            )
+            .expect("failed to add the host key");
+        known_hosts
+            .write_file(&known_hosts_file, KnownHostFileKind::OpenSSH)
+            .expect("failed to write the known hosts");
+
+        let connection = connection(&known_hosts_file);
+        assert!(connection
+            .verify_host_key(&session, &known_hosts_file, &host_key(1))
+            .is_ok());
+        assert!(matches!(
+            connection.verify_host_key(&session, &known_hosts_file, &host_key(2)),
+            Err(SftpError::HostKeyMismatch(host)) if host == "archive.example.com"
+        ));
+
+        let unknown = Connection {
+            host: String::from("impostor.example.com"),
+            ..connection
+        };
+        assert!(matches!(
+            unknown.verify_host_key(&session, &known_hosts_file, &host_key(1)),
+            Err(SftpError::UnknownHostKey(_))
+        ));
+    }
+
+    #[test]
+    fn missing_known_hosts_are_refused() {
+        let dir = tempfile::tempdir().expect("failed to create a temporary directory");
+        let key_path = dir.path().join("id_ed25519");
+        std::fs::write(&key_path, "").expect("failed to write the key");
+        let mut config = SftpBackendConfig {
+            tag: String::from("sftp-1"),
+            host: String::from("archive.example.com"),
+            user: String::from("yeet"),
+            key_path,
+            known_hosts_file: Some(dir.path().join("known_hosts")),
+            remote_path: String::from("/srv/archive"),
+            ..Default::default()
+        };
+        assert!(matches!(
+            SftpBackend::try_new(&config),
+            Err(SftpBackendConstructionError::KnownHostsNotFound(Some(_)))
+        ));
+
+        config.insecure_accept_any_host_key = true;
+        assert!(SftpBackend::try_new(&config).is_ok());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::DistributionTracker;
 use file_distribution::WriteSummary;
 use shortguid::ShortGuid;
 use std::sync::Arc;
@@ -6,21 +7,35 @@ use tokio::sync::mpsc::Sender;
 
 pub enum BackendCommand {
     DistributeFile(ShortGuid, Arc<WriteSummary>),
+    /// Removes the file from all backends.
+    DeleteFile(ShortGuid),
 }
 
+#[derive(Clone)]
 pub struct BackendCommandSender {
     sender: Sender<BackendCommand>,
+    tracker: DistributionTracker,
 }
 
 impl BackendCommandSender {
-    pub async fn send(&self, command: BackendCommand) -> Result<(), BackendCommandSendError> {
-        Ok(self.sender.send(command).await?)
+    /// Creates a new sender for commands to be handled by the backends whose
+    /// distribution outcomes are recorded by `tracker`.
+    pub fn new(sender: Sender<BackendCommand>, tracker: DistributionTracker) -> Self {
+        Self { sender, tracker }
+    }
+
+    /// Indicates whether any backend is registered to handle the commands.
+    pub fn has_backends(&self) -> bool {
+        !self.tracker.backend_tags().is_empty()
     }
-}
 
-impl From<Sender<BackendCommand>> for BackendCommandSender {
-    fn from(value: Sender<BackendCommand>) -> Self {
-        Self { sender: value }
+    /// Gets the tracker recording the per-backend distribution outcomes of files.
+    pub fn distribution_tracker(&self) -> &DistributionTracker {
+        &self.tracker
+    }
+
+    pub async fn send(&self, command: BackendCommand) -> Result<(), BackendCommandSendError> {
+        Ok(self.sender.send(command).await?)
     }
 }
 
@@ -1,13 +1,17 @@
+use crate::{BackendStats, BackendStatsError, ReceiveFile};
 use async_trait::async_trait;
 use file_distribution::{FileAccessorError, FileProvider, WriteSummary};
 use shortguid::ShortGuid;
 use std::error::Error;
 use std::ops::Deref;
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Main trait for file distribution to a backend.
+///
+/// Backends that can read files back additionally implement [`ReceiveFile::receive_file`].
 #[async_trait]
-pub trait DistributeFile: Send + Sync {
+pub trait DistributeFile: ReceiveFile + Send + Sync {
     /// Gets the tag of the backend.
     fn tag(&self) -> &str;
 
@@ -18,6 +22,22 @@ pub trait DistributeFile: Send + Sync {
         summary: Arc<WriteSummary>,
         file_provider: FileProvider,
     ) -> Result<(), DistributionError>;
+
+    /// Removes a file from the backend before it expires.
+    ///
+    /// Backends that can't remove files return [`DeleteFileError::NotSupported`],
+    /// which is what the default implementation does.
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DeleteFileError> {
+        Err(DeleteFileError::NotSupported(id))
+    }
+
+    /// Gets the storage statistics of the backend.
+    ///
+    /// Backends that can't report them return `None`, which is what the default
+    /// implementation does.
+    async fn stats(&self) -> Result<Option<BackendStats>, BackendStatsError> {
+        Ok(None)
+    }
 }
 
 /// [`Backend`] is a wrapper struct that holds a dynamically dispatched [`DistributeFile`] instance.
@@ -28,7 +48,7 @@ pub trait DistributeFile: Send + Sync {
 /// use std::sync::Arc;
 /// use async_trait::async_trait;
 /// use shortguid::ShortGuid;
-/// use backend_traits::{DistributeFile, DistributionError, Backend};
+/// use backend_traits::{DistributeFile, DistributionError, Backend, ReceiveFile};
 /// use file_distribution::{FileProvider, WriteSummary};
 ///
 /// struct PostgresBackend;
@@ -43,6 +63,8 @@ pub trait DistributeFile: Send + Sync {
 ///     }
 /// }
 ///
+/// impl ReceiveFile for PostgresBackend {}
+///
 /// struct MySqlBackend;
 ///
 /// #[async_trait]
@@ -55,6 +77,8 @@ pub trait DistributeFile: Send + Sync {
 ///     }
 /// }
 ///
+/// impl ReceiveFile for MySqlBackend {}
+///
 /// let postgres_backend = Backend::wrap(PostgresBackend);
 /// let my_sql_backend = Backend::wrap(MySqlBackend);
 /// ```
@@ -95,6 +119,8 @@ where
 
 #[derive(Debug, thiserror::Error)]
 pub enum DistributionError {
+    #[error("The backend rejected the file: {0}")]
+    BackendRejected(String),
     #[error(transparent)]
     BackendSpecific(Box<dyn Error>),
     #[error(transparent)]
@@ -103,4 +129,20 @@ pub enum DistributionError {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Join(#[from] tokio::task::JoinError),
+    #[error("The backend did not store the file within {0:?}")]
+    Timeout(Duration),
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum DeleteFileError {
+    #[error("The backend does not support deleting file {0}")]
+    NotSupported(ShortGuid),
+    #[error("No file found for the specified ID {0}")]
+    UnknownFile(ShortGuid),
+    #[error(transparent)]
+    BackendSpecific(Box<dyn Error + Send + Sync>),
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+    #[error("The backend did not delete the file within {0:?}")]
+    Timeout(Duration),
 }
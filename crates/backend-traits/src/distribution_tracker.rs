@@ -0,0 +1,274 @@
+use shortguid::ShortGuid;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tokio::time::Instant;
+
+/// The time for which entries are kept after the file they refer to expired,
+/// so that the outcome can still be inspected when the file is evicted.
+const EXPIRED_RETENTION: Duration = Duration::from_secs(60);
+
+/// The distribution state of a file with respect to a single backend.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum DistributionState {
+    /// The file is scheduled for distribution but was not yet handled by the backend.
+    Pending,
+    /// The backend stored the file.
+    Stored,
+    /// The backend refused to store the file, e.g. because it is too large.
+    Rejected,
+    /// Distributing the file to the backend failed.
+    Failed,
+    /// The file was not distributed to the backend since none of its routing rules matched.
+    NotEligible,
+    /// The file was not distributed to the backend since its circuit was open after
+    /// repeated failures.
+    CircuitOpen,
+}
+
+/// Whether a file was stored on the number of backends required by the write quorum.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum QuorumState {
+    /// Not enough backends stored the file yet, but the quorum can still be met.
+    Pending,
+    /// Enough backends stored the file.
+    Met,
+    /// Too many backends failed or rejected the file for the quorum to be met.
+    Failed,
+}
+
+/// The distribution state of a file for the backend identified by `tag`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BackendDistribution {
+    /// The tag of the backend.
+    pub tag: String,
+    /// The distribution state.
+    pub state: DistributionState,
+}
+
+/// Keeps track of the per-backend distribution outcomes of files.
+///
+/// Entries are reported until the file they refer to expires. Can be cheaply cloned.
+#[derive(Debug, Clone, Default)]
+pub struct DistributionTracker {
+    /// The tags of all registered backends.
+    tags: Arc<[String]>,
+    /// The number of backends that need to store a file; `None` if no quorum is required.
+    write_quorum: Option<usize>,
+    inner: Arc<RwLock<HashMap<ShortGuid, Entry>>>,
+    /// Notifies about changes of any distribution state.
+    changed: Arc<Notify>,
+}
+
+#[derive(Debug)]
+struct Entry {
+    /// The instant at which the file expires.
+    expires: Instant,
+    /// The distribution state per backend.
+    backends: Vec<BackendDistribution>,
+}
+
+impl DistributionTracker {
+    /// Creates a new tracker for the backends identified by the specified tags.
+    pub fn new<I, S>(tags: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            tags: tags.into_iter().map(Into::into).collect(),
+            write_quorum: None,
+            inner: Arc::default(),
+            changed: Arc::default(),
+        }
+    }
+
+    /// Requires files to be stored on at least `quorum` backends; see [`quorum_state`](Self::quorum_state).
+    pub fn with_write_quorum(mut self, quorum: Option<usize>) -> Self {
+        self.write_quorum = quorum;
+        self
+    }
+
+    /// Gets the number of backends that need to store a file, if a write quorum is required.
+    pub fn write_quorum(&self) -> Option<usize> {
+        self.write_quorum
+    }
+
+    /// Gets the tags of all registered backends.
+    pub fn backend_tags(&self) -> &[String] {
+        &self.tags
+    }
+
+    /// Registers a file for distribution and marks all backends as [`DistributionState::Pending`].
+    ///
+    /// Entries of files that have expired a while ago are removed.
+    pub fn begin(&self, id: ShortGuid, expires: Instant) {
+        let mut inner = self
+            .inner
+            .write()
+            .expect("failed to lock distribution tracker");
+
+        let now = Instant::now();
+        inner.retain(|_, entry| entry.expires + EXPIRED_RETENTION > now);
+
+        inner.insert(
+            id,
+            Entry {
+                expires,
+                backends: self.all_pending(),
+            },
+        );
+        self.changed.notify_waiters();
+    }
+
+    /// Updates the distribution state of a file for a specific backend.
+    pub fn update(&self, id: ShortGuid, tag: &str, state: DistributionState) {
+        let mut inner = self
+            .inner
+            .write()
+            .expect("failed to lock distribution tracker");
+        if let Some(entry) = inner.get_mut(&id) {
+            if let Some(backend) = entry.backends.iter_mut().find(|b| b.tag == tag) {
+                backend.state = state;
+            }
+        }
+        drop(inner);
+        self.changed.notify_waiters();
+    }
+
+    /// Gets the per-backend distribution state of a file, or `None` if the file
+    /// was not (yet) registered for distribution or has expired.
+    pub fn get(&self, id: &ShortGuid) -> Option<Vec<BackendDistribution>> {
+        let inner = self
+            .inner
+            .read()
+            .expect("failed to lock distribution tracker");
+        inner
+            .get(id)
+            .filter(|entry| entry.expires > Instant::now())
+            .map(|entry| entry.backends.clone())
+    }
+
+    /// Indicates whether at least one backend stored the file, regardless of
+    /// whether the file has expired in the meantime.
+    pub fn is_stored(&self, id: &ShortGuid) -> bool {
+        let inner = self
+            .inner
+            .read()
+            .expect("failed to lock distribution tracker");
+        inner.get(id).is_some_and(|entry| {
+            entry
+                .backends
+                .iter()
+                .any(|backend| backend.state == DistributionState::Stored)
+        })
+    }
+
+    /// Indicates whether a backend rejected the file while none stored it, regardless of
+    /// whether the distribution completed.
+    pub fn is_rejected(&self, id: &ShortGuid) -> bool {
+        let backends = self.get_or_pending(id);
+        backends
+            .iter()
+            .any(|backend| backend.state == DistributionState::Rejected)
+            && !backends
+                .iter()
+                .any(|backend| backend.state == DistributionState::Stored)
+    }
+
+    /// Like [`get`](Self::get), but reports all backends as pending if the
+    /// file was not yet registered for distribution.
+    pub fn get_or_pending(&self, id: &ShortGuid) -> Vec<BackendDistribution> {
+        self.get(id).unwrap_or_else(|| self.all_pending())
+    }
+
+    /// Gets whether the write quorum was met for a file, or `None` if no quorum is required.
+    ///
+    /// Files that were not yet registered for distribution are reported as pending.
+    pub fn quorum_state(&self, id: &ShortGuid) -> Option<QuorumState> {
+        let quorum = self.write_quorum?;
+        let backends = self.get_or_pending(id);
+        let stored = backends
+            .iter()
+            .filter(|backend| backend.state == DistributionState::Stored)
+            .count();
+        let pending = backends
+            .iter()
+            .filter(|backend| backend.state == DistributionState::Pending)
+            .count();
+
+        Some(if stored >= quorum {
+            QuorumState::Met
+        } else if stored + pending < quorum {
+            QuorumState::Failed
+        } else {
+            QuorumState::Pending
+        })
+    }
+
+    /// Waits until the write quorum was either met or failed for a file.
+    /// Returns `None` immediately if no quorum is required.
+    pub async fn wait_for_quorum(&self, id: &ShortGuid) -> Option<QuorumState> {
+        loop {
+            // Register for notifications before checking to not miss any update.
+            let changed = self.changed.notified();
+            match self.quorum_state(id)? {
+                QuorumState::Pending => changed.await,
+                state => return Some(state),
+            }
+        }
+    }
+
+    /// Waits until no backend is pending for a file anymore and returns the distribution states.
+    ///
+    /// Files that were not yet registered for distribution are waited for as well.
+    pub async fn wait_for_completion(&self, id: &ShortGuid) -> Vec<BackendDistribution> {
+        loop {
+            // Register for notifications before checking to not miss any update.
+            let changed = self.changed.notified();
+            let backends = self.get_or_pending(id);
+            if backends
+                .iter()
+                .all(|backend| backend.state != DistributionState::Pending)
+            {
+                return backends;
+            }
+            changed.await;
+        }
+    }
+
+    fn all_pending(&self) -> Vec<BackendDistribution> {
+        self.tags
+            .iter()
+            .map(|tag| BackendDistribution {
+                tag: tag.clone(),
+                state: DistributionState::Pending,
+            })
+            .collect()
+    }
+}
+
+impl Display for DistributionState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DistributionState::Pending => write!(f, "pending"),
+            DistributionState::Stored => write!(f, "stored"),
+            DistributionState::Rejected => write!(f, "rejected"),
+            DistributionState::Failed => write!(f, "failed"),
+            DistributionState::NotEligible => write!(f, "not-eligible"),
+            DistributionState::CircuitOpen => write!(f, "circuit-open"),
+        }
+    }
+}
+
+impl Display for QuorumState {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QuorumState::Pending => write!(f, "pending"),
+            QuorumState::Met => write!(f, "met"),
+            QuorumState::Failed => write!(f, "failed"),
+        }
+    }
+}
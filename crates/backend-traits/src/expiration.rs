@@ -0,0 +1,59 @@
+use file_distribution::protobuf::ItemMetadata;
+use file_distribution::WriteSummary;
+use shortguid::ShortGuid;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+
+/// The shortest time for which a file is stored relative to its lease, so that backends
+/// don't mistake an expiration of zero for keeping the file indefinitely.
+const MIN_LEASE_LIFETIME: Duration = Duration::from_secs(1);
+
+/// Determines how long a backend keeps the files it stores.
+///
+/// Files either expire a fixed time after they were stored, or a per-backend offset
+/// after the end of their local lease, as given by [`WriteSummary::expires`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct BackendExpiration {
+    /// The fixed expiration, also assumed for files stored without a recorded expiration.
+    fixed: Duration,
+    /// The time for which files are kept after their lease ended; `None` if files
+    /// expire after the fixed expiration.
+    lease_offset: Option<Duration>,
+}
+
+impl BackendExpiration {
+    /// Creates an expiration relative to the lease of each file if `lease_offset` is set,
+    /// or after the `fixed` expiration otherwise.
+    pub fn new(fixed: Duration, lease_offset: Option<Duration>) -> Self {
+        Self {
+            fixed,
+            lease_offset,
+        }
+    }
+
+    /// Gets the fixed expiration, which is also assumed for files that were stored
+    /// without recording their expiration.
+    pub fn fixed(&self) -> Duration {
+        self.fixed
+    }
+
+    /// Gets the time from now for which the file is to be stored.
+    pub fn lifetime(&self, summary: &WriteSummary) -> Duration {
+        match self.lease_offset {
+            Some(offset) => (summary.expires.saturating_duration_since(Instant::now()) + offset)
+                .max(MIN_LEASE_LIFETIME),
+            None => self.fixed,
+        }
+    }
+
+    /// Creates the metadata to store along with the file. If the file expires relative
+    /// to its lease, the expiration is recorded so that it can be told when reading it back.
+    pub fn item_metadata(&self, id: ShortGuid, summary: &Arc<WriteSummary>) -> ItemMetadata {
+        let metadata = ItemMetadata::new(id, summary);
+        match self.lease_offset {
+            Some(_) => metadata.with_expiration(SystemTime::now() + self.lifetime(summary)),
+            None => metadata,
+        }
+    }
+}
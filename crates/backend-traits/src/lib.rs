@@ -5,11 +5,23 @@
 mod backend_command;
 mod backend_info;
 mod distribute_file;
+mod distribution_tracker;
+mod expiration;
 mod from_config;
+mod mirror;
+mod receive_file;
 mod registration;
+mod stats;
 
 pub use backend_command::{BackendCommand, BackendCommandSendError, BackendCommandSender};
 pub use backend_info::BackendInfo;
-pub use distribute_file::{Backend, DistributeFile, DistributionError};
+pub use distribute_file::{Backend, DeleteFileError, DistributeFile, DistributionError};
+pub use distribution_tracker::{
+    BackendDistribution, DistributionState, DistributionTracker, QuorumState,
+};
+pub use expiration::BackendExpiration;
 pub use from_config::TryCreateFromConfig;
+pub use mirror::{MirrorBackend, MirrorRole};
+pub use receive_file::{clamp_range, ReceiveFile, ReceiveFileError};
 pub use registration::{BackendRegistration, RegisterBackendError};
+pub use stats::{BackendStats, BackendStatsError};
@@ -0,0 +1,440 @@
+use crate::{
+    Backend, BackendStats, BackendStatsError, DeleteFileError, DistributeFile, DistributionError,
+    ReceiveFile, ReceiveFileError,
+};
+use async_trait::async_trait;
+use file_distribution::hash::Sha256Digest;
+use file_distribution::{BoxedFileReader, FileProvider, WriteSummary};
+use futures::future::join_all;
+use shortguid::ShortGuid;
+use std::sync::Arc;
+use tracing::warn;
+use url::Url;
+
+/// The role of a backend within a [`MirrorBackend`].
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum MirrorRole {
+    /// The file must be stored on the backend for the distribution to succeed.
+    Primary,
+    /// The file is stored on the backend on a best-effort basis; failures are only reported.
+    Secondary,
+}
+
+/// A backend writing files to several inner backends at once.
+///
+/// Distributing a file succeeds only if all primary backends stored it. Failures of
+/// secondary backends are logged separately and don't fail the distribution. Files are
+/// read back from the first inner backend that holds them, in the order the backends
+/// were added.
+///
+/// # Example
+///
+/// ```
+/// # use async_trait::async_trait;
+/// # use backend_traits::{Backend, DistributeFile, DistributionError, ReceiveFile};
+/// # use file_distribution::{FileProvider, WriteSummary};
+/// # use shortguid::ShortGuid;
+/// # use std::sync::Arc;
+/// use backend_traits::MirrorBackend;
+///
+/// # struct DiskBackend(&'static str);
+/// #
+/// # #[async_trait]
+/// # impl DistributeFile for DiskBackend {
+/// #     fn tag(&self) -> &str { self.0 }
+/// #
+/// #     async fn distribute_file(&self, id: ShortGuid, summary: Arc<WriteSummary>, file_accessor: FileProvider) -> Result<(), DistributionError> {
+/// #         Ok(())
+/// #     }
+/// # }
+/// #
+/// # impl ReceiveFile for DiskBackend {}
+/// #
+/// let mirror = MirrorBackend::new("mirrored")
+///     .with_primary(Backend::wrap(DiskBackend("local")))
+///     .with_secondary(Backend::wrap(DiskBackend("remote")));
+/// let backend = Backend::wrap(mirror);
+/// ```
+pub struct MirrorBackend {
+    /// The tag identifying the mirror.
+    tag: String,
+    /// The inner backends along with their roles, in reading order.
+    backends: Vec<(MirrorRole, Backend)>,
+}
+
+/// The outcome of distributing a file to an inner backend.
+///
+/// Unlike [`DistributionError`], this is `Send` and can be held across the join.
+enum Outcome {
+    Stored,
+    Rejected(String),
+    Failed(String),
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("The primary backend {tag} failed to store the file: {message}")]
+struct PrimaryFailed {
+    tag: String,
+    message: String,
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("Inner backends failed to delete the file: {failures}")]
+struct DeleteFailed {
+    failures: String,
+}
+
+impl MirrorBackend {
+    pub fn new<T: Into<String>>(tag: T) -> Self {
+        Self {
+            tag: tag.into(),
+            backends: Vec::default(),
+        }
+    }
+
+    /// Adds an inner backend with the specified role.
+    pub fn with_backend(mut self, role: MirrorRole, backend: Backend) -> Self {
+        self.backends.push((role, backend));
+        self
+    }
+
+    /// Adds an inner backend that must store each file.
+    pub fn with_primary(self, backend: Backend) -> Self {
+        self.with_backend(MirrorRole::Primary, backend)
+    }
+
+    /// Adds an inner backend that stores files on a best-effort basis.
+    pub fn with_secondary(self, backend: Backend) -> Self {
+        self.with_backend(MirrorRole::Secondary, backend)
+    }
+
+    /// Gets the inner backends with the specified role.
+    fn backends_with_role(&self, role: MirrorRole) -> impl Iterator<Item = &Backend> {
+        self.backends
+            .iter()
+            .filter(move |(r, _)| *r == role)
+            .map(|(_, backend)| backend)
+    }
+}
+
+#[async_trait]
+impl DistributeFile for MirrorBackend {
+    fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    async fn distribute_file(
+        &self,
+        id: ShortGuid,
+        summary: Arc<WriteSummary>,
+        file_provider: FileProvider,
+    ) -> Result<(), DistributionError> {
+        let outcomes = join_all(self.backends.iter().map(|(role, backend)| {
+            let summary = summary.clone();
+            let file_provider = file_provider.clone();
+            async move {
+                let outcome = match backend.distribute_file(id, summary, file_provider).await {
+                    Ok(()) => Outcome::Stored,
+                    Err(DistributionError::BackendRejected(reason)) => Outcome::Rejected(reason),
+                    Err(e) => Outcome::Failed(e.to_string()),
+                };
+                (*role, backend.tag(), outcome)
+            }
+        }))
+        .await;
+
+        let mut result = Ok(());
+        for (role, tag, outcome) in outcomes {
+            match (role, outcome) {
+                (_, Outcome::Stored) => {}
+                (MirrorRole::Secondary, Outcome::Rejected(message) | Outcome::Failed(message)) => {
+                    warn!(file_id = %id, "Secondary backend {tag} of mirror {mirror} failed to store file {id}: {message}", mirror = self.tag);
+                }
+                (MirrorRole::Primary, Outcome::Rejected(reason)) => {
+                    if result.is_ok() {
+                        result = Err(DistributionError::BackendRejected(reason));
+                    }
+                }
+                (MirrorRole::Primary, Outcome::Failed(message)) => {
+                    if result.is_ok() {
+                        result = Err(DistributionError::BackendSpecific(Box::new(
+                            PrimaryFailed {
+                                tag: tag.to_string(),
+                                message,
+                            },
+                        )));
+                    }
+                }
+            }
+        }
+        result
+    }
+
+    /// Deletes the file from all inner backends. Fails if any backend failed to delete it,
+    /// even if others succeeded.
+    async fn delete_file(&self, id: ShortGuid) -> Result<(), DeleteFileError> {
+        let mut deleted = false;
+        let mut unknown = false;
+        let mut failures = Vec::new();
+        for (_, backend) in &self.backends {
+            match backend.delete_file(id).await {
+                Ok(()) => deleted = true,
+                Err(DeleteFileError::NotSupported(_)) => {}
+                Err(DeleteFileError::UnknownFile(_)) => unknown = true,
+                Err(e) => {
+                    warn!(file_id = %id, "Backend {tag} of mirror {mirror} failed to delete file {id}: {e}", tag = backend.tag(), mirror = self.tag);
+                    failures.push(format!("{tag}: {e}", tag = backend.tag()));
+                }
+            }
+        }
+
+        if !failures.is_empty() {
+            Err(DeleteFileError::BackendSpecific(Box::new(DeleteFailed {
+                failures: failures.join("; "),
+            })))
+        } else if deleted {
+            Ok(())
+        } else if unknown {
+            Err(DeleteFileError::UnknownFile(id))
+        } else {
+            Err(DeleteFileError::NotSupported(id))
+        }
+    }
+
+    /// Gets the storage statistics of the first primary backend reporting them.
+    async fn stats(&self) -> Result<Option<BackendStats>, BackendStatsError> {
+        for backend in self.backends_with_role(MirrorRole::Primary) {
+            if let Some(stats) = backend.stats().await? {
+                return Ok(Some(stats));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl ReceiveFile for MirrorBackend {
+    async fn receive_file(&self, id: ShortGuid) -> Result<BoxedFileReader, ReceiveFileError> {
+        let mut result = Err(ReceiveFileError::NotSupported(id));
+        for (_, backend) in &self.backends {
+            match backend.receive_file(id).await {
+                Ok(reader) => return Ok(reader),
+                Err(ReceiveFileError::NotSupported(_)) => {}
+                Err(e) => result = Err(e),
+            }
+        }
+        result
+    }
+
+    async fn receive_range(
+        &self,
+        id: ShortGuid,
+        start: u64,
+        end: u64,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        let mut result = Err(ReceiveFileError::NotSupported(id));
+        for (_, backend) in &self.backends {
+            match backend.receive_range(id, start, end).await {
+                Ok(reader) => return Ok(reader),
+                // An unsatisfiable range is a property of the file, not of the backend.
+                Err(e @ ReceiveFileError::RangeNotSatisfiable(_)) => return Err(e),
+                Err(ReceiveFileError::NotSupported(_)) => {}
+                Err(e) => result = Err(e),
+            }
+        }
+        result
+    }
+
+    async fn presign_get(&self, id: ShortGuid) -> Option<Url> {
+        for (_, backend) in &self.backends {
+            if let Some(url) = backend.presign_get(id).await {
+                return Some(url);
+            }
+        }
+        None
+    }
+
+    async fn find_by_sha256(&self, sha256: &Sha256Digest) -> Option<ShortGuid> {
+        for (_, backend) in &self.backends {
+            if let Some(id) = backend.find_by_sha256(sha256).await {
+                return Some(id);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use file_distribution::{
+        BytesFileReader, FileAccessorError, FileHashes, GetFile, GetFileReaderError,
+    };
+    use std::collections::{BTreeMap, HashSet};
+    use std::sync::Mutex;
+    use std::time::{Duration, SystemTime};
+    use tokio::io::AsyncReadExt;
+    use tokio::time::Instant;
+
+    /// A backend keeping its files in memory, or failing every request.
+    struct TestBackend {
+        tag: &'static str,
+        failing: bool,
+        files: Arc<Mutex<HashSet<ShortGuid>>>,
+    }
+
+    impl TestBackend {
+        fn wrap(tag: &'static str, failing: bool) -> (Backend, Arc<Mutex<HashSet<ShortGuid>>>) {
+            let files = Arc::new(Mutex::new(HashSet::new()));
+            let backend = Backend::wrap(Self {
+                tag,
+                failing,
+                files: files.clone(),
+            });
+            (backend, files)
+        }
+
+        fn failure(&self) -> std::io::Error {
+            std::io::Error::other(format!("{tag} is unavailable", tag = self.tag))
+        }
+    }
+
+    #[async_trait]
+    impl DistributeFile for TestBackend {
+        fn tag(&self) -> &str {
+            self.tag
+        }
+
+        async fn distribute_file(
+            &self,
+            id: ShortGuid,
+            _summary: Arc<WriteSummary>,
+            _file_provider: FileProvider,
+        ) -> Result<(), DistributionError> {
+            if self.failing {
+                return Err(DistributionError::Io(self.failure()));
+            }
+            self.files.lock().unwrap().insert(id);
+            Ok(())
+        }
+
+        async fn delete_file(&self, id: ShortGuid) -> Result<(), DeleteFileError> {
+            if self.failing {
+                return Err(DeleteFileError::BackendSpecific(Box::new(self.failure())));
+            }
+            if self.files.lock().unwrap().remove(&id) {
+                Ok(())
+            } else {
+                Err(DeleteFileError::UnknownFile(id))
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ReceiveFile for TestBackend {
+        async fn receive_file(&self, id: ShortGuid) -> Result<BoxedFileReader, ReceiveFileError> {
+            if self.failing {
+                return Err(ReceiveFileError::BackendSpecific(Box::new(self.failure())));
+            }
+            if !self.files.lock().unwrap().contains(&id) {
+                return Err(ReceiveFileError::UnknownFile(id));
+            }
+            Ok(BoxedFileReader::new(BytesFileReader::new(
+                Bytes::from(self.tag),
+                None,
+                Duration::ZERO,
+                Instant::now() + Duration::from_secs(60),
+                None,
+            )))
+        }
+    }
+
+    struct NoFiles;
+
+    #[async_trait]
+    impl GetFile for NoFiles {
+        async fn get_file(&self, id: ShortGuid) -> Result<BoxedFileReader, FileAccessorError> {
+            Err(GetFileReaderError::UnknownFile(id).into())
+        }
+    }
+
+    async fn distribute(mirror: &MirrorBackend, id: ShortGuid) -> Result<(), DistributionError> {
+        let summary = Arc::new(WriteSummary {
+            created: SystemTime::now(),
+            expires: Instant::now() + Duration::from_secs(60),
+            hashes: FileHashes::new(None, None, None),
+            file_name: None,
+            file_size_bytes: 0,
+            metadata: BTreeMap::default(),
+        });
+        mirror
+            .distribute_file(id, summary, FileProvider::wrap(Arc::new(NoFiles)))
+            .await
+    }
+
+    #[tokio::test]
+    async fn files_are_written_to_all_backends() {
+        let (primary, primary_files) = TestBackend::wrap("primary", false);
+        let (secondary, secondary_files) = TestBackend::wrap("secondary", false);
+        let mirror = MirrorBackend::new("mirror")
+            .with_primary(primary)
+            .with_secondary(secondary);
+
+        let id = ShortGuid::new_random();
+        distribute(&mirror, id)
+            .await
+            .expect("failed to store the file");
+        assert!(primary_files.lock().unwrap().contains(&id));
+        assert!(secondary_files.lock().unwrap().contains(&id));
+
+        mirror
+            .delete_file(id)
+            .await
+            .expect("failed to delete the file");
+        assert!(primary_files.lock().unwrap().is_empty());
+        assert!(secondary_files.lock().unwrap().is_empty());
+        assert!(matches!(
+            mirror.delete_file(id).await,
+            Err(DeleteFileError::UnknownFile(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn files_are_read_from_the_secondary_if_the_primary_fails() {
+        let (primary, _) = TestBackend::wrap("primary", true);
+        let (secondary, secondary_files) = TestBackend::wrap("secondary", false);
+        let mirror = MirrorBackend::new("mirror")
+            .with_primary(primary)
+            .with_secondary(secondary);
+
+        // The primary failing fails the distribution, but the secondary holds the file.
+        let id = ShortGuid::new_random();
+        assert!(distribute(&mirror, id).await.is_err());
+        assert!(secondary_files.lock().unwrap().contains(&id));
+
+        let mut reader = mirror
+            .receive_file(id)
+            .await
+            .expect("no backend has the file");
+        let mut content = String::new();
+        reader.read_to_string(&mut content).await.unwrap();
+        assert_eq!(content, "secondary");
+    }
+
+    #[tokio::test]
+    async fn failed_deletions_are_not_hidden_by_successful_ones() {
+        let (failing, _) = TestBackend::wrap("failing", true);
+        let (working, working_files) = TestBackend::wrap("working", false);
+        let mirror = MirrorBackend::new("mirror")
+            .with_primary(failing)
+            .with_secondary(working);
+
+        let id = ShortGuid::new_random();
+        working_files.lock().unwrap().insert(id);
+        let error = mirror.delete_file(id).await.unwrap_err();
+        assert!(matches!(error, DeleteFileError::BackendSpecific(_)));
+        assert!(error.to_string().contains("failing is unavailable"));
+        assert!(working_files.lock().unwrap().is_empty());
+    }
+}
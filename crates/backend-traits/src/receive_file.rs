@@ -0,0 +1,82 @@
+use async_trait::async_trait;
+use file_distribution::hash::Sha256Digest;
+use file_distribution::BoxedFileReader;
+use shortguid::ShortGuid;
+use std::error::Error;
+use url::Url;
+
+/// Trait for reading files back from a backend.
+#[async_trait]
+pub trait ReceiveFile: Send + Sync {
+    /// Gets a reader for a file previously distributed to the backend.
+    ///
+    /// Backends that can't read files back return [`ReceiveFileError::NotSupported`],
+    /// which is what the default implementation does.
+    async fn receive_file(&self, id: ShortGuid) -> Result<BoxedFileReader, ReceiveFileError> {
+        Err(ReceiveFileError::NotSupported(id))
+    }
+
+    /// Gets a reader for the bytes `start..=end` of a file previously distributed to the
+    /// backend, reading as little of the file as the backend allows. An `end` past the end
+    /// of the file is clamped to the last byte. The summary of the reader, if any,
+    /// describes the complete file.
+    ///
+    /// Backends that can't read ranges return [`ReceiveFileError::NotSupported`],
+    /// which is what the default implementation does; callers may fall back to
+    /// [`receive_file`](Self::receive_file) then.
+    async fn receive_range(
+        &self,
+        id: ShortGuid,
+        _start: u64,
+        _end: u64,
+    ) -> Result<BoxedFileReader, ReceiveFileError> {
+        Err(ReceiveFileError::NotSupported(id))
+    }
+
+    /// Gets a time-limited URL from which clients can download a file directly,
+    /// without it being proxied by the service.
+    ///
+    /// Returns `None` if the backend doesn't hold the file or doesn't support
+    /// presigned URLs, which is what the default implementation does.
+    async fn presign_get(&self, _id: ShortGuid) -> Option<Url> {
+        None
+    }
+
+    /// Gets the ID of a file held by the backend by its SHA-256 hash.
+    ///
+    /// Returns `None` if the backend doesn't hold such a file or doesn't support
+    /// hash-keyed lookups, which is what the default implementation does.
+    async fn find_by_sha256(&self, _sha256: &Sha256Digest) -> Option<ShortGuid> {
+        None
+    }
+}
+
+/// Clamps the range `start..=end` requested from [`ReceiveFile::receive_range`] to a file
+/// of `file_size` bytes, failing if the range starts past the end of the file.
+pub fn clamp_range(
+    id: ShortGuid,
+    start: u64,
+    end: u64,
+    file_size: u64,
+) -> Result<(u64, u64), ReceiveFileError> {
+    if start > end || start >= file_size {
+        return Err(ReceiveFileError::RangeNotSatisfiable(id));
+    }
+    Ok((start, end.min(file_size - 1)))
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ReceiveFileError {
+    #[error("The backend does not support reading back file {0}")]
+    NotSupported(ShortGuid),
+    #[error("No file found for the specified ID {0}")]
+    UnknownFile(ShortGuid),
+    #[error("The file with ID {0} has expired")]
+    FileExpired(ShortGuid),
+    #[error("The requested range of the file with ID {0} is not satisfiable")]
+    RangeNotSatisfiable(ShortGuid),
+    #[error(transparent)]
+    BackendSpecific(Box<dyn Error + Send + Sync>),
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+}
@@ -12,4 +12,6 @@ pub trait BackendRegistration {
 pub enum RegisterBackendError {
     #[error(transparent)]
     TryCreateFromConfig(Box<dyn Error>),
+    #[error("The mirror {mirror} refers to the backend {tag}, which is not registered")]
+    UnknownMirroredBackend { mirror: String, tag: String },
 }
@@ -0,0 +1,24 @@
+use std::error::Error;
+use std::time::Duration;
+
+/// Storage statistics reported by a backend, e.g. for capacity monitoring.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct BackendStats {
+    /// The number of items held, if known. Depending on the backend, this includes
+    /// items not stored by this service, e.g. on a shared Memcached server.
+    pub items: Option<u64>,
+    /// The number of bytes used, if known.
+    pub bytes_used: Option<u64>,
+    /// The maximum number of bytes the backend holds, if known.
+    pub bytes_limit: Option<u64>,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum BackendStatsError {
+    #[error(transparent)]
+    BackendSpecific(Box<dyn Error + Send + Sync>),
+    #[error(transparent)]
+    Join(#[from] tokio::task::JoinError),
+    #[error("The backend did not report its statistics within {0:?}")]
+    Timeout(Duration),
+}
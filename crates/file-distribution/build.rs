@@ -1,8 +1,12 @@
 fn main() {
     let proto_includes = ["../../proto"];
 
+    // The definitions live outside of the crate, so changes to them aren't picked up otherwise.
+    println!("cargo:rerun-if-changed=../../proto/metadata.proto");
+
     let mut config = prost_build::Config::new();
     config.protoc_arg("--experimental_allow_proto3_optional");
+    config.btree_map(["."]);
 
     config
         .compile_protos(&["../../proto/metadata.proto"], &proto_includes)
@@ -0,0 +1,76 @@
+use crate::{FileReaderTrait, WriteSummary};
+use bytes::{Buf, Bytes};
+use shared_files::FileSize;
+use std::borrow::Cow;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::time::Instant;
+
+/// A read accessor for a file that is held in memory entirely.
+pub struct BytesFileReader {
+    data: Bytes,
+    file_size: usize,
+    content_type: Option<String>,
+    file_age: Duration,
+    expires: Instant,
+    summary: Option<Arc<WriteSummary>>,
+}
+
+impl BytesFileReader {
+    pub fn new(
+        data: Bytes,
+        content_type: Option<String>,
+        file_age: Duration,
+        expires: Instant,
+        summary: Option<Arc<WriteSummary>>,
+    ) -> Self {
+        Self {
+            file_size: data.len(),
+            data,
+            content_type,
+            file_age,
+            expires,
+            summary,
+        }
+    }
+}
+
+impl FileReaderTrait for BytesFileReader {
+    fn summary(&self) -> &Option<Arc<WriteSummary>> {
+        &self.summary
+    }
+
+    fn expiration_date(&self) -> Instant {
+        self.expires
+    }
+
+    fn file_size(&self) -> FileSize {
+        FileSize::Exactly(self.file_size)
+    }
+
+    fn file_age(&self) -> Duration {
+        self.file_age
+    }
+
+    fn content_type(&self) -> Option<Cow<'_, str>> {
+        self.content_type
+            .as_ref()
+            .map(|content_type| Cow::from(content_type.as_str()))
+    }
+}
+
+impl AsyncRead for BytesFileReader {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let n = self.data.len().min(buf.remaining());
+        buf.put_slice(&self.data[..n]);
+        self.data.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
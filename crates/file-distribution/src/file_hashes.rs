@@ -1,18 +1,30 @@
-use crate::hash::{Md5Digest, Sha256Digest};
+use crate::hash::{Crc32cDigest, Md5Digest, Sha256Digest};
 use std::fmt::{Debug, Display, Formatter};
 
 /// The calculated hashes of a file.
+///
+/// Digests that were not enabled for the file are `None`.
 #[derive(Clone)]
 pub struct FileHashes {
     /// The MD5 digest.
-    pub md5: Md5Digest,
+    pub md5: Option<Md5Digest>,
     /// The SHA-256 hash.
-    pub sha256: Sha256Digest,
+    pub sha256: Option<Sha256Digest>,
+    /// The CRC32C checksum.
+    pub crc32c: Option<Crc32cDigest>,
 }
 
 impl FileHashes {
-    pub fn new(md5: Md5Digest, sha256: Sha256Digest) -> Self {
-        Self { md5, sha256 }
+    pub fn new(
+        md5: Option<Md5Digest>,
+        sha256: Option<Sha256Digest>,
+        crc32c: Option<Crc32cDigest>,
+    ) -> Self {
+        Self {
+            md5,
+            sha256,
+            crc32c,
+        }
     }
 }
 
@@ -24,11 +36,19 @@ impl Debug for FileHashes {
 
 impl Display for FileHashes {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "MD5 {md5:x}, SHA256 {sha256:x}",
-            md5 = self.md5,
-            sha256 = self.sha256
-        )
+        let hashes = [
+            self.md5.map(|md5| format!("MD5 {md5:x}")),
+            self.sha256.map(|sha256| format!("SHA256 {sha256:x}")),
+            self.crc32c.map(|crc32c| format!("CRC32C {crc32c:08x}")),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>();
+
+        if hashes.is_empty() {
+            write!(f, "no hashes")
+        } else {
+            write!(f, "{}", hashes.join(", "))
+        }
     }
 }
@@ -1,6 +1,7 @@
 use crate::WriteSummary;
 use shared_files::FileSize;
 use std::borrow::Cow;
+use std::path::Path;
 use std::pin::Pin;
 use std::sync::Arc;
 use std::task::{Context, Poll};
@@ -13,7 +14,21 @@ pub trait FileReaderTrait: AsyncRead + Send + Unpin {
     fn expiration_date(&self) -> Instant;
     fn file_size(&self) -> FileSize;
     fn file_age(&self) -> Duration;
-    fn content_type(&self) -> Option<Cow<str>>;
+    fn content_type(&self) -> Option<Cow<'_, str>>;
+
+    /// Gets the size the file will have once it is completely written, if known.
+    /// For files that are still being written, this is the size declared on upload.
+    fn final_file_size(&self) -> Option<usize> {
+        match self.file_size() {
+            FileSize::Exactly(size) => Some(size),
+            FileSize::AtLeast(_) | FileSize::Error => None,
+        }
+    }
+
+    /// Gets the path of the file on the local disk, if the file is stored there.
+    fn local_path(&self) -> Option<&Path> {
+        None
+    }
 }
 
 pub struct BoxedFileReader(Box<dyn FileReaderTrait>);
@@ -31,9 +46,15 @@ impl FileReaderTrait for BoxedFileReader {
     fn file_age(&self) -> Duration {
         self.0.file_age()
     }
-    fn content_type(&self) -> Option<Cow<str>> {
+    fn content_type(&self) -> Option<Cow<'_, str>> {
         self.0.content_type()
     }
+    fn final_file_size(&self) -> Option<usize> {
+        self.0.final_file_size()
+    }
+    fn local_path(&self) -> Option<&Path> {
+        self.0.local_path()
+    }
 }
 
 impl AsyncRead for BoxedFileReader {
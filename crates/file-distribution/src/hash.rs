@@ -1,6 +1,8 @@
 use sha2::digest::consts::U32;
 use sha2::digest::generic_array::GenericArray;
 use sha2::Digest;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
 
 /// An MD5 hash.
 pub struct HashMd5(md5::Context);
@@ -8,12 +10,102 @@ pub struct HashMd5(md5::Context);
 /// A SHA-256 hash.
 pub struct HashSha256(sha2::Sha256);
 
+/// A CRC32C (Castagnoli) checksum.
+pub struct HashCrc32c(u32);
+
 /// Alias for a SHA-256 hash digest.
 pub type Md5Digest = md5::Digest;
 
 /// Alias for a SHA-256 hash digest.
 pub type Sha256Digest = GenericArray<u8, U32>;
 
+/// Alias for a CRC32C checksum.
+pub type Crc32cDigest = u32;
+
+/// The set of digests to calculate for a file.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct DigestSet {
+    /// Whether to calculate the MD5 digest.
+    pub md5: bool,
+    /// Whether to calculate the SHA-256 hash.
+    pub sha256: bool,
+    /// Whether to calculate the CRC32C checksum.
+    pub crc32c: bool,
+}
+
+impl DigestSet {
+    /// All supported digests.
+    pub const ALL: Self = Self {
+        md5: true,
+        sha256: true,
+        crc32c: true,
+    };
+
+    /// No digests.
+    pub const NONE: Self = Self {
+        md5: false,
+        sha256: false,
+        crc32c: false,
+    };
+
+    /// The digests calculated unless configured otherwise, i.e. MD5 and SHA-256.
+    pub const DEFAULT: Self = Self {
+        md5: true,
+        sha256: true,
+        crc32c: false,
+    };
+}
+
+impl Default for DigestSet {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+impl Display for DigestSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let names = [
+            (self.md5, "md5"),
+            (self.sha256, "sha256"),
+            (self.crc32c, "crc32c"),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, name)| enabled.then_some(name))
+        .collect::<Vec<_>>();
+
+        if names.is_empty() {
+            write!(f, "none")
+        } else {
+            write!(f, "{}", names.join(", "))
+        }
+    }
+}
+
+/// Parses a comma separated list of digest names, e.g. `md5, sha256`.
+/// The name `none` selects no digests.
+impl FromStr for DigestSet {
+    type Err = UnknownDigestError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut set = Self::NONE;
+        for name in s.split(',').map(str::trim).filter(|name| !name.is_empty()) {
+            match name.to_ascii_lowercase().as_str() {
+                "md5" => set.md5 = true,
+                "sha256" | "sha-256" => set.sha256 = true,
+                "crc32c" => set.crc32c = true,
+                "none" => {}
+                _ => return Err(UnknownDigestError(name.to_string())),
+            }
+        }
+        Ok(set)
+    }
+}
+
+/// An unsupported digest name was specified.
+#[derive(Debug, thiserror::Error)]
+#[error("Unknown digest: {0}")]
+pub struct UnknownDigestError(pub String);
+
 impl HashMd5 {
     pub fn new() -> Self {
         Self(md5::Context::new())
@@ -44,6 +136,20 @@ impl HashSha256 {
     }
 }
 
+impl HashCrc32c {
+    pub fn new() -> Self {
+        Self(0)
+    }
+
+    pub fn update(&mut self, chunk: &[u8]) {
+        self.0 = crc32c::crc32c_append(self.0, chunk)
+    }
+
+    pub fn finalize(self) -> Crc32cDigest {
+        self.0
+    }
+}
+
 impl Default for HashMd5 {
     fn default() -> Self {
         Self::new()
@@ -55,3 +161,9 @@ impl Default for HashSha256 {
         Self::new()
     }
 }
+
+impl Default for HashCrc32c {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -2,6 +2,7 @@
 // the `docsrs` configuration attribute is defined
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+mod bytes_reader;
 mod file_hashes;
 mod file_provider;
 mod file_reader;
@@ -9,6 +10,7 @@ pub mod hash;
 pub mod protobuf;
 mod write_summary;
 
+pub use bytes_reader::BytesFileReader;
 pub use file_hashes::FileHashes;
 pub use file_provider::{FileAccessorError, FileProvider, GetFile, GetFileReaderError};
 pub use file_reader::{BoxedFileReader, FileReaderTrait};
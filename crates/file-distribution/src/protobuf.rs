@@ -1,8 +1,10 @@
-use crate::WriteSummary;
+use crate::{FileHashes, WriteSummary};
 use bytes::{Bytes, BytesMut};
 use prost::Message;
 use shortguid::ShortGuid;
 use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::time::Instant;
 
 include!(concat!(env!("OUT_DIR"), "/types.rs"));
 
@@ -12,15 +14,126 @@ impl ItemMetadata {
             id: Vec::from(id.as_bytes()),
             file_name: summary.file_name.clone(),
             hashes: Some(Hashes {
-                md5: Vec::from(summary.hashes.md5.as_slice()),
-                sha256: Vec::from(summary.hashes.sha256.as_slice()),
+                md5: summary
+                    .hashes
+                    .md5
+                    .map_or_else(Vec::new, |md5| Vec::from(md5.as_slice())),
+                sha256: summary
+                    .hashes
+                    .sha256
+                    .map_or_else(Vec::new, |sha256| Vec::from(sha256.as_slice())),
+                crc32c: summary.hashes.crc32c,
             }),
+            chunks: Vec::default(),
+            created_unix_ms: summary
+                .created
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|created| created.as_millis() as u64),
+            metadata: summary.metadata.clone(),
+            expires_unix_ms: None,
         }
     }
 
+    /// Records the time at which the backend expires the file.
+    pub fn with_expiration(mut self, expires: SystemTime) -> Self {
+        self.expires_unix_ms = expires
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|expires| expires.as_millis() as u64);
+        self
+    }
+
     pub fn serialize_to_proto(&self) -> Result<Bytes, prost::EncodeError> {
         let mut metadata_buf = BytesMut::new();
         self.encode(&mut metadata_buf)?;
         Ok(metadata_buf.freeze())
     }
+
+    pub fn deserialize_from_proto(buf: &[u8]) -> Result<Self, prost::DecodeError> {
+        Self::decode(buf)
+    }
+
+    /// Gets the time at which the file was originally created, if known.
+    pub fn created(&self) -> Option<SystemTime> {
+        self.created_unix_ms
+            .map(|millis| UNIX_EPOCH + Duration::from_millis(millis))
+    }
+
+    /// Gets the age of the file based on its original creation time, if known.
+    pub fn file_age(&self) -> Option<Duration> {
+        let created = self.created()?;
+        Some(
+            SystemTime::now()
+                .duration_since(created)
+                .unwrap_or_default(),
+        )
+    }
+
+    /// Gets the instant at which a file stored for `lifetime` after its original
+    /// creation expires. If the creation time is unknown, the lifetime starts now.
+    /// A recorded expiration time takes precedence over the lifetime.
+    pub fn expires_after(&self, lifetime: Duration) -> Instant {
+        if let Some(millis) = self.expires_unix_ms {
+            let expires = UNIX_EPOCH + Duration::from_millis(millis);
+            let remaining = expires
+                .duration_since(SystemTime::now())
+                .unwrap_or_default();
+            return Instant::now() + remaining;
+        }
+
+        Instant::now() + lifetime.saturating_sub(self.file_age().unwrap_or_default())
+    }
+
+    /// Restores the [`WriteSummary`] of a stored file, or `None` if the hashes are missing or invalid.
+    pub fn to_write_summary(
+        &self,
+        expires: Instant,
+        file_size_bytes: usize,
+    ) -> Option<WriteSummary> {
+        Some(WriteSummary {
+            created: self.created().unwrap_or_else(SystemTime::now),
+            expires,
+            hashes: self.hashes.as_ref()?.to_file_hashes()?,
+            file_name: self.file_name.clone(),
+            file_size_bytes,
+            metadata: self.metadata.clone(),
+        })
+    }
+}
+
+impl WalRecord {
+    pub fn serialize_to_proto(&self) -> Result<Bytes, prost::EncodeError> {
+        let mut record_buf = BytesMut::new();
+        self.encode(&mut record_buf)?;
+        Ok(record_buf.freeze())
+    }
+
+    pub fn deserialize_from_proto(buf: &[u8]) -> Result<Self, prost::DecodeError> {
+        Self::decode(buf)
+    }
+
+    /// Gets the time at which the file expires.
+    pub fn expires(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(self.expires_unix_ms)
+    }
+}
+
+impl Hashes {
+    /// Converts the hashes into [`FileHashes`], or `None` if any digest has an invalid length.
+    /// Empty digests were not calculated for the file.
+    pub fn to_file_hashes(&self) -> Option<FileHashes> {
+        let md5 = match self.md5.len() {
+            0 => None,
+            _ => Some(md5::Digest(self.md5.as_slice().try_into().ok()?)),
+        };
+
+        let sha256 = match self.sha256.len() {
+            0 => None,
+            32 => Some(crate::hash::Sha256Digest::clone_from_slice(&self.sha256)),
+            _ => return None,
+        };
+
+        Some(FileHashes::new(md5, sha256, self.crc32c))
+    }
 }
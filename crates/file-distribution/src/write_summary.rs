@@ -1,9 +1,13 @@
 use crate::FileHashes;
+use std::collections::BTreeMap;
+use std::time::SystemTime;
 use tokio::time::Instant;
 
 /// A write result.
 #[derive(Debug)]
 pub struct WriteSummary {
+    /// The time at which the file was created.
+    pub created: SystemTime,
     /// The instant at which the file will expire.
     pub expires: Instant,
     /// The file hashes.
@@ -12,4 +16,6 @@ pub struct WriteSummary {
     pub file_name: Option<String>,
     /// The file size in bytes.
     pub file_size_bytes: usize,
+    /// The metadata supplied by the client on upload, keyed by name.
+    pub metadata: BTreeMap<String, String>,
 }
@@ -0,0 +1,40 @@
+//! Contains backbone related metrics, notably [`BackboneMetrics`].
+
+use lazy_static::lazy_static;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+lazy_static! {
+    static ref COMMAND_QUEUE_DEPTH: Gauge = Gauge::default();
+    static ref OPEN_FILES: Gauge = Gauge::default();
+}
+
+/// Register the backbone metrics with the registry.
+pub(crate) fn register_backbone_metrics(registry: &mut Registry) {
+    registry.register(
+        "backbone_command_queue_depth",
+        "Number of commands waiting to be processed by the backbone",
+        COMMAND_QUEUE_DEPTH.clone(),
+    );
+    registry.register(
+        "backbone_open_files",
+        "Number of files currently held by the backbone, including uploads in progress",
+        OPEN_FILES.clone(),
+    );
+}
+
+/// Backbone metrics.
+#[derive(Default)]
+pub struct BackboneMetrics;
+
+impl BackboneMetrics {
+    /// Sets the number of commands waiting to be processed.
+    pub fn set_command_queue_depth(depth: usize) {
+        COMMAND_QUEUE_DEPTH.set(depth as _);
+    }
+
+    /// Sets the number of files currently held.
+    pub fn set_open_files(count: usize) {
+        OPEN_FILES.set(count as _);
+    }
+}
@@ -0,0 +1,73 @@
+//! Contains backend storage related metrics, notably [`BackendMetrics`].
+
+use lazy_static::lazy_static;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+lazy_static! {
+    static ref ITEMS: Family<BackendLabels, Gauge> = Family::default();
+    static ref BYTES_USED: Family<BackendLabels, Gauge> = Family::default();
+    static ref BYTES_LIMIT: Family<BackendLabels, Gauge> = Family::default();
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct BackendLabels {
+    /// The tag of the backend.
+    backend: String,
+}
+
+/// Register the backend metrics with the registry.
+pub(crate) fn register_backend_metrics(registry: &mut Registry) {
+    registry.register(
+        "backend_items",
+        "Number of items held by the backend, as last reported by it",
+        ITEMS.clone(),
+    );
+    registry.register(
+        "backend_bytes_used",
+        "Number of bytes used by the backend, as last reported by it",
+        BYTES_USED.clone(),
+    );
+    registry.register(
+        "backend_bytes_limit",
+        "Maximum number of bytes held by the backend, as last reported by it",
+        BYTES_LIMIT.clone(),
+    );
+}
+
+/// Backend storage metrics.
+#[derive(Default)]
+pub struct BackendMetrics;
+
+impl BackendMetrics {
+    /// Sets the storage statistics reported by a backend. Unknown values are left unchanged.
+    ///
+    /// ## Arguments
+    /// * `backend` - The tag of the backend.
+    /// * `items` - The number of items held.
+    /// * `bytes_used` - The number of bytes used.
+    /// * `bytes_limit` - The maximum number of bytes held.
+    pub fn set_stats<B: Into<String>>(
+        backend: B,
+        items: Option<u64>,
+        bytes_used: Option<u64>,
+        bytes_limit: Option<u64>,
+    ) {
+        let labels = BackendLabels {
+            backend: backend.into(),
+        };
+        for (family, value) in [
+            (&*ITEMS, items),
+            (&*BYTES_USED, bytes_used),
+            (&*BYTES_LIMIT, bytes_limit),
+        ] {
+            if let Some(value) = value {
+                family
+                    .get_or_create(&labels)
+                    .set(value.try_into().unwrap_or(i64::MAX));
+            }
+        }
+    }
+}
@@ -0,0 +1,130 @@
+//! Contains connection related metrics, notably [`ConnectionMetrics`].
+
+use lazy_static::lazy_static;
+use prometheus_client::encoding::LabelValueEncoder;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+use std::fmt::{Display, Formatter, Write};
+
+lazy_static! {
+    static ref ACCEPTED: Counter = Counter::default();
+    static ref ACTIVE: Gauge = Gauge::default();
+    static ref CLOSED: Family<Labels, Counter> = Family::default();
+    static ref THROTTLED: Family<ThrottleLabels, Counter> = Family::default();
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct Labels {
+    outcome: CloseOutcome,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct ThrottleLabels {
+    reason: ThrottleReason,
+}
+
+/// The limit that paused accepting connections.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum ThrottleReason {
+    /// The accept rate limit was exceeded.
+    Rate,
+    /// The maximum number of concurrent connections was reached.
+    MaxConnections,
+}
+
+/// The way a connection was closed.
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub enum CloseOutcome {
+    /// The connection was closed without an I/O error.
+    Clean,
+    /// The connection was closed after an I/O error.
+    Error,
+}
+
+impl EncodeLabelValue for CloseOutcome {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for CloseOutcome {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CloseOutcome::Clean => write!(f, "clean"),
+            CloseOutcome::Error => write!(f, "error"),
+        }
+    }
+}
+
+impl EncodeLabelValue for ThrottleReason {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for ThrottleReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ThrottleReason::Rate => write!(f, "rate"),
+            ThrottleReason::MaxConnections => write!(f, "max_connections"),
+        }
+    }
+}
+
+/// Register the connection metrics with the registry.
+pub(crate) fn register_connection_metrics(registry: &mut Registry) {
+    registry.register(
+        "connections_accepted",
+        "Number of connections accepted",
+        ACCEPTED.clone(),
+    );
+
+    registry.register(
+        "connections_active",
+        "Number of connections that are currently open",
+        ACTIVE.clone(),
+    );
+
+    registry.register(
+        "connections_closed",
+        "Number of connections closed",
+        CLOSED.clone(),
+    );
+
+    registry.register(
+        "connections_throttled",
+        "Number of times accepting connections was paused by a limit",
+        THROTTLED.clone(),
+    );
+}
+
+/// Connection metrics.
+#[derive(Default)]
+pub struct ConnectionMetrics;
+
+impl ConnectionMetrics {
+    /// Tracks a newly accepted connection.
+    pub fn track_accepted() {
+        ACCEPTED.inc();
+        ACTIVE.inc();
+    }
+
+    /// Tracks a closed connection.
+    pub fn track_closed(outcome: CloseOutcome) {
+        ACTIVE.dec();
+        CLOSED.get_or_create(&Labels { outcome }).inc();
+    }
+
+    /// Gets the number of currently open connections.
+    pub fn active() -> i64 {
+        ACTIVE.get()
+    }
+
+    /// Tracks that accepting connections was paused by a limit.
+    pub fn track_throttled(reason: ThrottleReason) {
+        THROTTLED.get_or_create(&ThrottleLabels { reason }).inc();
+    }
+}
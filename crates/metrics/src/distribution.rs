@@ -0,0 +1,115 @@
+//! Contains backend distribution related metrics, notably [`DistributionMetrics`].
+
+use lazy_static::lazy_static;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::registry::Registry;
+
+lazy_static! {
+    static ref TIMEOUTS: Family<BackendLabels, Counter> = Family::default();
+    static ref QUORUM_FAILURES: Counter = Counter::default();
+    static ref CIRCUIT_OPEN_SKIPS: Family<BackendLabels, Counter> = Family::default();
+    static ref BACKEND_HEALTHY: Family<BackendLabels, Gauge> = Family::default();
+    static ref UNSTORED_REJECTIONS: Counter = Counter::default();
+    static ref CANCELLED: Counter = Counter::default();
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct BackendLabels {
+    /// The tag of the backend.
+    backend: String,
+}
+
+/// Register the distribution metrics with the registry.
+pub(crate) fn register_distribution_metrics(registry: &mut Registry) {
+    registry.register(
+        "distribution_timeouts",
+        "Number of file distributions aborted because the backend did not complete in time",
+        TIMEOUTS.clone(),
+    );
+    registry.register(
+        "write_quorum_failures",
+        "Number of files that were not stored on enough backends to meet the write quorum",
+        QUORUM_FAILURES.clone(),
+    );
+    registry.register(
+        "distribution_circuit_open_skips",
+        "Number of file distributions skipped because the circuit of the backend was open",
+        CIRCUIT_OPEN_SKIPS.clone(),
+    );
+    registry.register(
+        "backend_healthy",
+        "Whether distributions to the backend are attempted (1) or skipped due to an open circuit (0)",
+        BACKEND_HEALTHY.clone(),
+    );
+    registry.register(
+        "distribution_unstored_rejections",
+        "Number of files that no backend stored because a backend rejected them",
+        UNSTORED_REJECTIONS.clone(),
+    );
+    registry.register(
+        "distributions_cancelled",
+        "Number of delayed file distributions cancelled because the file was removed in the meantime",
+        CANCELLED.clone(),
+    );
+}
+
+/// Backend distribution metrics.
+#[derive(Default)]
+pub struct DistributionMetrics;
+
+impl DistributionMetrics {
+    /// Tracks a distribution to a backend that timed out.
+    ///
+    /// ## Arguments
+    /// * `backend` - The tag of the backend.
+    pub fn track_timeout<B: Into<String>>(backend: B) {
+        TIMEOUTS
+            .get_or_create(&BackendLabels {
+                backend: backend.into(),
+            })
+            .inc();
+    }
+
+    /// Tracks a file that no backend stored because a backend rejected it.
+    pub fn track_unstored_rejection() {
+        UNSTORED_REJECTIONS.inc();
+    }
+
+    /// Tracks a delayed distribution that was cancelled because the file was removed.
+    pub fn track_cancelled() {
+        CANCELLED.inc();
+    }
+
+    /// Tracks a file that was not stored on enough backends to meet the write quorum.
+    pub fn track_quorum_failure() {
+        QUORUM_FAILURES.inc();
+    }
+
+    /// Tracks a distribution that was skipped because the circuit of the backend was open.
+    ///
+    /// ## Arguments
+    /// * `backend` - The tag of the backend.
+    pub fn track_circuit_open_skip<B: Into<String>>(backend: B) {
+        CIRCUIT_OPEN_SKIPS
+            .get_or_create(&BackendLabels {
+                backend: backend.into(),
+            })
+            .inc();
+    }
+
+    /// Sets whether distributions to a backend are attempted.
+    ///
+    /// ## Arguments
+    /// * `backend` - The tag of the backend.
+    /// * `healthy` - `false` if the circuit of the backend is open.
+    pub fn set_backend_healthy<B: Into<String>>(backend: B, healthy: bool) {
+        BACKEND_HEALTHY
+            .get_or_create(&BackendLabels {
+                backend: backend.into(),
+            })
+            .set(i64::from(healthy));
+    }
+}
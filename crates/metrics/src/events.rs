@@ -0,0 +1,40 @@
+//! Contains transfer event related metrics, notably [`EventMetrics`].
+
+use lazy_static::lazy_static;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::registry::Registry;
+
+lazy_static! {
+    static ref EMITTED: Counter = Counter::default();
+    static ref DROPPED: Counter = Counter::default();
+}
+
+/// Register the event metrics with the registry.
+pub(crate) fn register_event_metrics(registry: &mut Registry) {
+    registry.register(
+        "transfer_events_emitted",
+        "Number of transfer events delivered to the event sink",
+        EMITTED.clone(),
+    );
+    registry.register(
+        "transfer_events_dropped",
+        "Number of transfer events dropped because the event sink was saturated or failed",
+        DROPPED.clone(),
+    );
+}
+
+/// Transfer event metrics.
+#[derive(Default)]
+pub struct EventMetrics;
+
+impl EventMetrics {
+    /// Tracks an event delivered to the sink.
+    pub fn track_emitted() {
+        EMITTED.inc();
+    }
+
+    /// Tracks an event that was dropped.
+    pub fn track_dropped() {
+        DROPPED.inc();
+    }
+}
@@ -0,0 +1,68 @@
+//! Contains file lifetime related metrics, notably [`FileMetrics`].
+
+use lazy_static::lazy_static;
+use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::registry::Registry;
+
+lazy_static! {
+    static ref EXPIRED_UNDISTRIBUTED: Counter = Counter::default();
+    static ref CREATION_FAILURES: Family<CreationFailureLabels, Counter> = Family::default();
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CreationFailureLabels {
+    /// The operation that failed.
+    operation: FileOperation,
+    /// The kind of failure, e.g. `StorageFull` for I/O errors.
+    kind: String,
+}
+
+/// The temporary file operation that failed.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelValue)]
+pub enum FileOperation {
+    /// Creating the temporary file.
+    CreateFile,
+    /// Creating a writer to the temporary file.
+    CreateWriter,
+}
+
+/// Register the file metrics with the registry.
+pub(crate) fn register_file_metrics(registry: &mut Registry) {
+    registry.register(
+        "files_expired_undistributed",
+        "Number of files that expired before any backend stored them",
+        EXPIRED_UNDISTRIBUTED.clone(),
+    );
+    registry.register(
+        "temp_file_creation_failures",
+        "Number of failures creating temporary files or their writers",
+        CREATION_FAILURES.clone(),
+    );
+}
+
+/// File lifetime metrics.
+#[derive(Default)]
+pub struct FileMetrics;
+
+impl FileMetrics {
+    /// Tracks a file that expired before any backend stored it.
+    pub fn track_expired_undistributed() {
+        EXPIRED_UNDISTRIBUTED.inc();
+    }
+
+    /// Tracks a failure to create a temporary file or its writer.
+    ///
+    /// ## Arguments
+    /// * `operation` - The operation that failed.
+    /// * `kind` - The kind of failure, e.g. the I/O error kind.
+    pub fn track_creation_failure<K: Into<String>>(operation: FileOperation, kind: K) {
+        CREATION_FAILURES
+            .get_or_create(&CreationFailureLabels {
+                operation,
+                kind: kind.into(),
+            })
+            .inc();
+    }
+}
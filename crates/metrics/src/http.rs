@@ -7,8 +7,12 @@ use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
 use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::{Registry, Unit};
+use std::collections::HashSet;
 use std::fmt::{Display, Formatter, Write};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::RwLock;
 use std::time::Duration;
 
 lazy_static! {
@@ -16,7 +20,72 @@ lazy_static! {
     // type, representing the number of HTTP requests received.
     static ref TRACK_ENDPOINT: Family<Labels, Counter> = Family::default();
     static ref TRACK_DURATION: Family<Labels, Counter<f64>> = Family::default();
+    static ref TRACK_DURATION_HISTOGRAM: Family<Labels, Histogram> =
+        Family::new_with_constructor(|| {
+            let buckets = DURATION_BUCKETS.read().unwrap_or_else(|e| e.into_inner());
+            Histogram::new(buckets.iter().copied())
+        });
     static ref TRACK_IN_FLIGHT: Family<InFlightLabels, Gauge> = Family::default();
+    static ref TRACK_SHED: Family<InFlightLabels, Counter> = Family::default();
+    /// The upper bounds of the request duration histogram buckets, in seconds.
+    static ref DURATION_BUCKETS: RwLock<Vec<f64>> =
+        RwLock::new(exponential_buckets(0.005, 2.0, 14).collect());
+    /// The `path` label values tracked so far.
+    static ref PATH_LABELS: RwLock<PathLabels> =
+        RwLock::new(PathLabels::new(DEFAULT_MAX_PATH_LABELS));
+}
+
+/// The number of requests in flight across all paths.
+static IN_FLIGHT_TOTAL: AtomicI64 = AtomicI64::new(0);
+
+/// The default maximum number of distinct `path` label values.
+pub const DEFAULT_MAX_PATH_LABELS: usize = 256;
+
+/// The `path` label value of requests to paths beyond the maximum number of distinct values.
+pub const OVERFLOW_PATH_LABEL: &str = "other";
+
+/// Limits the number of distinct `path` label values, so that requests to arbitrary
+/// paths can't blow up the cardinality of the metrics.
+struct PathLabels {
+    /// The maximum number of distinct values.
+    max: usize,
+    /// The values tracked so far. Values are never removed, so a path keeps its label.
+    known: HashSet<String>,
+}
+
+impl PathLabels {
+    fn new(max: usize) -> Self {
+        Self {
+            max,
+            known: HashSet::new(),
+        }
+    }
+
+    /// Gets the `path` label value for a path, which is [`OVERFLOW_PATH_LABEL`] if the path
+    /// was not tracked before and the maximum number of distinct values was reached.
+    fn label(labels: &RwLock<Self>, path: &str) -> String {
+        {
+            let labels = labels.read().unwrap_or_else(|e| e.into_inner());
+            if labels.known.contains(path) {
+                return path.to_string();
+            }
+            if labels.known.len() >= labels.max {
+                return OVERFLOW_PATH_LABEL.to_string();
+            }
+        }
+
+        let mut labels = labels.write().unwrap_or_else(|e| e.into_inner());
+        if labels.known.len() >= labels.max && !labels.known.contains(path) {
+            return OVERFLOW_PATH_LABEL.to_string();
+        }
+        labels.known.insert(path.to_string());
+        path.to_string()
+    }
+}
+
+/// Gets the `path` label value for a path; see [`PathLabels::label`].
+fn path_label(path: &str) -> String {
+    PathLabels::label(&PATH_LABELS, path)
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
@@ -114,11 +183,24 @@ pub(crate) fn register_http_requests(registry: &mut Registry) {
         TRACK_DURATION.clone(),
     );
 
+    registry.register_with_unit(
+        "http_request_duration",
+        "Distribution of the duration of HTTP requests executed",
+        Unit::Seconds,
+        TRACK_DURATION_HISTOGRAM.clone(),
+    );
+
     registry.register(
         "http_requests_in_flight",
         "Number of requests that are currently in flight",
         TRACK_IN_FLIGHT.clone(),
     );
+
+    registry.register(
+        "http_requests_shed",
+        "Number of requests rejected because too many requests were in flight",
+        TRACK_SHED.clone(),
+    );
 }
 
 /// HTTP call metrics. Can be cheaply cloned.
@@ -133,37 +215,137 @@ impl HttpMetrics {
         P: AsRef<str>,
         M: Into<HttpMethod>,
     {
-        let method = method.into();
-        TRACK_ENDPOINT
-            .get_or_create(&Labels {
-                method: method.clone(),
-                path: path.as_ref().to_string(),
-                status,
-            })
-            .inc();
-
+        let labels = Labels {
+            method: method.into(),
+            path: path_label(path.as_ref()),
+            status,
+        };
+        TRACK_ENDPOINT.get_or_create(&labels).inc();
         TRACK_DURATION
-            .get_or_create(&Labels {
-                method,
-                path: path.as_ref().to_string(),
-                status,
-            })
+            .get_or_create(&labels)
             .inc_by(elapsed.as_secs_f64());
+        TRACK_DURATION_HISTOGRAM
+            .get_or_create(&labels)
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Sets the upper bounds of the request duration histogram buckets, in seconds.
+    /// Must be called before the first request is tracked to take effect for all requests.
+    ///
+    /// By default, 14 exponential buckets from 5 ms to about 41 s are used.
+    pub fn set_duration_buckets(buckets: &[f64]) -> Result<(), InvalidBuckets> {
+        if !valid_buckets(buckets) {
+            return Err(InvalidBuckets);
+        }
+
+        *DURATION_BUCKETS.write().unwrap_or_else(|e| e.into_inner()) = buckets.to_vec();
+        Ok(())
+    }
+
+    /// Sets the maximum number of distinct `path` label values. Requests to further paths
+    /// are tracked with the [`OVERFLOW_PATH_LABEL`]. Paths tracked before keep their label.
+    ///
+    /// Defaults to [`DEFAULT_MAX_PATH_LABELS`].
+    pub fn set_max_path_labels(max: usize) {
+        PATH_LABELS.write().unwrap_or_else(|e| e.into_inner()).max = max;
     }
 
     pub fn inc_in_flight<P: AsRef<str>>(path: P) {
+        IN_FLIGHT_TOTAL.fetch_add(1, Ordering::Relaxed);
         TRACK_IN_FLIGHT
             .get_or_create(&InFlightLabels {
-                path: path.as_ref().to_string(),
+                path: path_label(path.as_ref()),
             })
             .inc();
     }
 
     pub fn dec_in_flight<P: AsRef<str>>(path: P) {
+        IN_FLIGHT_TOTAL.fetch_sub(1, Ordering::Relaxed);
         TRACK_IN_FLIGHT
             .get_or_create(&InFlightLabels {
-                path: path.as_ref().to_string(),
+                path: path_label(path.as_ref()),
             })
             .dec();
     }
+
+    /// Gets the number of requests in flight across all paths.
+    pub fn in_flight() -> i64 {
+        IN_FLIGHT_TOTAL.load(Ordering::Relaxed)
+    }
+
+    /// Tracks a request to the specified HTTP path that was rejected due to load shedding.
+    pub fn track_shed<P: AsRef<str>>(path: P) {
+        TRACK_SHED
+            .get_or_create(&InFlightLabels {
+                path: path_label(path.as_ref()),
+            })
+            .inc();
+    }
+}
+
+/// Determines whether the histogram bucket bounds are non-empty, positive, finite
+/// and strictly increasing.
+pub fn valid_buckets(buckets: &[f64]) -> bool {
+    !buckets.is_empty()
+        && buckets
+            .iter()
+            .all(|bound| bound.is_finite() && *bound > 0.0)
+        && buckets.windows(2).all(|pair| pair[0] < pair[1])
+}
+
+/// The histogram bucket bounds are not non-empty, positive, finite and strictly increasing.
+#[derive(Debug, Copy, Clone)]
+pub struct InvalidBuckets;
+
+impl Display for InvalidBuckets {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "the bucket bounds must be positive, finite and strictly increasing"
+        )
+    }
+}
+
+impl std::error::Error for InvalidBuckets {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_beyond_the_maximum_share_the_overflow_label() {
+        let labels = RwLock::new(PathLabels::new(2));
+        assert_eq!(PathLabels::label(&labels, "/yeet"), "/yeet");
+        assert_eq!(PathLabels::label(&labels, "/yoink"), "/yoink");
+        assert_eq!(PathLabels::label(&labels, "/meta"), OVERFLOW_PATH_LABEL);
+        assert_eq!(PathLabels::label(&labels, "/admin"), OVERFLOW_PATH_LABEL);
+
+        // Paths tracked before the maximum was reached keep their label.
+        assert_eq!(PathLabels::label(&labels, "/yeet"), "/yeet");
+        assert_eq!(PathLabels::label(&labels, "/yoink"), "/yoink");
+    }
+
+    #[test]
+    fn in_flight_requests_are_paired_on_the_same_label() {
+        let gauge = |path: &str| {
+            TRACK_IN_FLIGHT
+                .get_or_create(&InFlightLabels {
+                    path: path.to_string(),
+                })
+                .get()
+        };
+
+        HttpMetrics::set_max_path_labels(1);
+        HttpMetrics::inc_in_flight("/known");
+        HttpMetrics::inc_in_flight("/unknown");
+        assert_eq!(gauge("/known"), 1);
+        assert_eq!(gauge(OVERFLOW_PATH_LABEL), 1);
+        assert_eq!(gauge("/unknown"), 0);
+
+        HttpMetrics::dec_in_flight("/unknown");
+        HttpMetrics::dec_in_flight("/known");
+        assert_eq!(gauge("/known"), 0);
+        assert_eq!(gauge(OVERFLOW_PATH_LABEL), 0);
+        assert_eq!(gauge("/unknown"), 0);
+    }
 }
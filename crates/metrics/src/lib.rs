@@ -4,6 +4,12 @@
 // the `docsrs` configuration attribute is defined
 #![cfg_attr(docsrs, feature(doc_cfg))]
 
+pub mod backbone;
+pub mod backends;
+pub mod connections;
+pub mod distribution;
+pub mod events;
+pub mod files;
 pub mod http;
 pub mod transfer;
 
@@ -48,6 +54,12 @@ impl Metrics {
     /// Creates a new metrics registry.
     fn new() -> Self {
         let mut metrics = <Registry>::default();
+        backbone::register_backbone_metrics(&mut metrics);
+        backends::register_backend_metrics(&mut metrics);
+        connections::register_connection_metrics(&mut metrics);
+        distribution::register_distribution_metrics(&mut metrics);
+        events::register_event_metrics(&mut metrics);
+        files::register_file_metrics(&mut metrics);
         http::register_http_requests(&mut metrics);
         transfer::register_transfer_metrics(&mut metrics);
 
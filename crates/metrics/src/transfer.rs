@@ -5,12 +5,17 @@ use prometheus_client::encoding::LabelValueEncoder;
 use prometheus_client::encoding::{EncodeLabelSet, EncodeLabelValue};
 use prometheus_client::metrics::counter::Counter;
 use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
 use prometheus_client::registry::{Registry, Unit};
 use std::fmt::{Display, Formatter, Write};
+use std::time::Duration;
 
 lazy_static! {
     static ref TRANSFER_SIZES: Family<Labels, Counter> = Family::default();
     static ref TRANSFER_COUNT: Family<Labels, Counter> = Family::default();
+    static ref UPLOAD_DURATION: Family<UploadLabels, Histogram> =
+        Family::new_with_constructor(|| Histogram::new(exponential_buckets(0.01, 4.0, 9)));
+    static ref RATE_LIMITED: Counter = Counter::default();
 }
 
 #[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
@@ -18,6 +23,58 @@ struct Labels {
     method: TransferMethod,
 }
 
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct UploadLabels {
+    size: UploadSizeBucket,
+}
+
+/// The size range of an upload, labeled by its inclusive upper bound.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum UploadSizeBucket {
+    UpTo64KiB,
+    UpTo1MiB,
+    UpTo16MiB,
+    UpTo256MiB,
+    Larger,
+}
+
+impl UploadSizeBucket {
+    /// Gets the bucket of an upload of the specified size.
+    pub fn of(bytes: usize) -> Self {
+        const KIB: usize = 1024;
+        const MIB: usize = 1024 * KIB;
+        if bytes <= 64 * KIB {
+            Self::UpTo64KiB
+        } else if bytes <= MIB {
+            Self::UpTo1MiB
+        } else if bytes <= 16 * MIB {
+            Self::UpTo16MiB
+        } else if bytes <= 256 * MIB {
+            Self::UpTo256MiB
+        } else {
+            Self::Larger
+        }
+    }
+}
+
+impl EncodeLabelValue for UploadSizeBucket {
+    fn encode(&self, encoder: &mut LabelValueEncoder) -> Result<(), std::fmt::Error> {
+        encoder.write_str(self.to_string().as_str())
+    }
+}
+
+impl Display for UploadSizeBucket {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            UploadSizeBucket::UpTo64KiB => write!(f, "64KiB"),
+            UploadSizeBucket::UpTo1MiB => write!(f, "1MiB"),
+            UploadSizeBucket::UpTo16MiB => write!(f, "16MiB"),
+            UploadSizeBucket::UpTo256MiB => write!(f, "256MiB"),
+            UploadSizeBucket::Larger => write!(f, "+Inf"),
+        }
+    }
+}
+
 /// The HTTP method to track.
 #[derive(Clone, Debug, Hash, PartialEq, Eq)]
 pub enum TransferMethod {
@@ -54,6 +111,19 @@ pub(crate) fn register_transfer_metrics(registry: &mut Registry) {
         "Number of transfers initiated",
         TRANSFER_COUNT.clone(),
     );
+
+    registry.register_with_unit(
+        "upload_duration",
+        "Time from receiving the first chunk of an upload until it was finalized, by upload size",
+        Unit::Seconds,
+        UPLOAD_DURATION.clone(),
+    );
+
+    registry.register(
+        "uploads_rate_limited",
+        "Number of uploads rejected because the client exceeded its upload rate limit",
+        RATE_LIMITED.clone(),
+    );
 }
 
 /// HTTP call metrics. Can be cheaply cloned.
@@ -79,4 +149,18 @@ impl TransferMetrics {
             })
             .inc_by(bytes as _);
     }
+
+    /// Tracks the duration of a completed upload of the specified size.
+    pub fn track_upload_duration(bytes: usize, duration: Duration) {
+        UPLOAD_DURATION
+            .get_or_create(&UploadLabels {
+                size: UploadSizeBucket::of(bytes),
+            })
+            .observe(duration.as_secs_f64());
+    }
+
+    /// Tracks an upload rejected due to the client's upload rate limit.
+    pub fn track_rate_limited() {
+        RATE_LIMITED.inc();
+    }
 }